@@ -0,0 +1,245 @@
+// Writes the asset bundle read by `src/assets/bundle.rs`
+//
+// Layout, numbers are little endian:
+//   magic "TBDL", u32 version, u32 entry count
+//   for each entry: u16 path length, path, u32 offset, u32 compressed size, u32 size, u32 crc32
+//   the deflate streams of the entries, offsets start after the index
+
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::crc32::crc32;
+
+pub const MAGIC: &[u8; 4] = b"TBDL";
+pub const VERSION: u32 = 1;
+
+/// Bundles every file under `folder`, paths are relative to it and use '/'
+pub fn build_bundle(folder: &Path) -> io::Result<Vec<u8>> {
+    let mut files = vec![];
+    list_files(folder, "", &mut files)?;
+    // sorted so that the bundle is reproducible
+    files.sort();
+
+    let mut index = vec![];
+    let mut data = vec![];
+    index.extend(MAGIC);
+    index.extend(VERSION.to_le_bytes());
+    index.extend((files.len() as u32).to_le_bytes());
+    for (path, name) in files {
+        let content = fs::read(&path)?;
+        let compressed = deflate(&content);
+        index.extend((name.len() as u16).to_le_bytes());
+        index.extend(name.as_bytes());
+        index.extend((data.len() as u32).to_le_bytes());
+        index.extend((compressed.len() as u32).to_le_bytes());
+        index.extend((content.len() as u32).to_le_bytes());
+        index.extend(crc32(&content).to_le_bytes());
+        data.extend(compressed);
+    }
+    index.extend(data);
+    Ok(index)
+}
+
+fn list_files(dir: &Path, prefix: &str, files: &mut Vec<(PathBuf, String)>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+        if entry.file_type()?.is_dir() {
+            list_files(&entry.path(), &format!("{}/", name), files)?;
+        } else {
+            files.push((entry.path(), name));
+        }
+    }
+    Ok(())
+}
+
+/// Raw deflate stream, lz77 with the fixed huffman codes.
+/// Stored blocks are used when that doesn't make the data smaller.
+/// Specification: https://datatracker.ietf.org/doc/html/rfc1951
+pub fn deflate(data: &[u8]) -> Vec<u8> {
+    let compressed = deflate_fixed(data);
+    if compressed.len() < data.len() {
+        compressed
+    } else {
+        deflate_stored(data)
+    }
+}
+
+fn deflate_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![];
+    let mut chunks = data.chunks(0xffff).peekable();
+    if chunks.peek().is_none() {
+        // an empty final block
+        out.extend([1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(chunk) = chunks.next() {
+        let bfinal = chunks.peek().is_none() as u8;
+        let len = chunk.len() as u16;
+        out.push(bfinal);
+        out.extend(len.to_le_bytes());
+        out.extend((!len).to_le_bytes());
+        out.extend(chunk);
+    }
+    out
+}
+
+const WINDOW_SIZE: usize = 32768;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+// how many previous positions with the same hash are compared
+const MAX_CHAIN: usize = 64;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+fn deflate_fixed(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter::default();
+    writer.write(1, 1); // bfinal
+    writer.write(1, 2); // fixed huffman codes
+
+    // last position of each 3 byte sequence, and the previous one with the same bytes
+    let mut head: HashMap<[u8; 3], usize> = HashMap::new();
+    let mut previous = vec![usize::MAX; data.len()];
+
+    let mut i = 0;
+    while i < data.len() {
+        let mut best = (0, 0);
+        if i + MIN_MATCH <= data.len() {
+            let key = [data[i], data[i + 1], data[i + 2]];
+            let mut candidate = head.get(&key).copied();
+            let mut chain = 0;
+            while let Some(start) = candidate {
+                if i - start > WINDOW_SIZE || chain >= MAX_CHAIN {
+                    break;
+                }
+                let length = data[start..]
+                    .iter()
+                    .zip(&data[i..])
+                    .take(MAX_MATCH)
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if length > best.0 {
+                    best = (length, i - start);
+                }
+                candidate = Some(previous[start]).filter(|&p| p != usize::MAX);
+                chain += 1;
+            }
+        }
+
+        if best.0 >= MIN_MATCH {
+            let (length, distance) = best;
+            write_length(&mut writer, length);
+            write_distance(&mut writer, distance);
+            for j in i..i + length {
+                insert_position(data, j, &mut head, &mut previous);
+            }
+            i += length;
+        } else {
+            write_symbol(&mut writer, data[i] as u16);
+            insert_position(data, i, &mut head, &mut previous);
+            i += 1;
+        }
+    }
+    write_symbol(&mut writer, 256);
+    writer.finish()
+}
+
+fn insert_position(
+    data: &[u8],
+    i: usize,
+    head: &mut HashMap<[u8; 3], usize>,
+    previous: &mut [usize],
+) {
+    if i + MIN_MATCH <= data.len() {
+        let key = [data[i], data[i + 1], data[i + 2]];
+        if let Some(last) = head.insert(key, i) {
+            previous[i] = last;
+        }
+    }
+}
+
+/// Literal, end of block or length symbol with its fixed code
+fn write_symbol(writer: &mut BitWriter, symbol: u16) {
+    let (code, bits) = match symbol {
+        0..=143 => (0x30 + symbol, 8),
+        144..=255 => (0x190 + symbol - 144, 9),
+        256..=279 => (symbol - 256, 7),
+        _ => (0xc0 + symbol - 280, 8),
+    };
+    writer.write_huffman(code as u32, bits);
+}
+
+fn write_length(writer: &mut BitWriter, length: usize) {
+    let code = LENGTH_BASE
+        .iter()
+        .rposition(|&base| base as usize <= length)
+        .unwrap();
+    write_symbol(writer, 257 + code as u16);
+    writer.write(
+        (length - LENGTH_BASE[code] as usize) as u32,
+        LENGTH_EXTRA[code] as u32,
+    );
+}
+
+fn write_distance(writer: &mut BitWriter, distance: usize) {
+    let code = DISTANCE_BASE
+        .iter()
+        .rposition(|&base| base as usize <= distance)
+        .unwrap();
+    writer.write_huffman(code as u32, 5);
+    writer.write(
+        (distance - DISTANCE_BASE[code] as usize) as u32,
+        DISTANCE_EXTRA[code] as u32,
+    );
+}
+
+/// Packs bits starting from the least significant bit of each byte
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    count: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u32, bits: u32) {
+        for bit in 0..bits {
+            self.buffer |= ((value >> bit) & 1) << self.count;
+            self.count += 1;
+            if self.count == 8 {
+                self.bytes.push(self.buffer as u8);
+                self.buffer = 0;
+                self.count = 0;
+            }
+        }
+    }
+
+    /// Huffman codes are packed starting from the most significant bit
+    fn write_huffman(&mut self, code: u32, bits: u32) {
+        let reversed = code.reverse_bits() >> (32 - bits);
+        self.write(reversed, bits);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
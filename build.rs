@@ -0,0 +1,41 @@
+use std::{env, fs, path::Path, process::Command};
+
+mod asset_bundle;
+#[allow(dead_code)]
+#[path = "src/assets/crc32.rs"]
+mod crc32;
+
+// Stamps the binary with the git state it was built from
+fn main() {
+    let describe = git(&["describe", "--tags", "--always", "--dirty"]);
+    let commit = git(&["rev-parse", "--short", "HEAD"]);
+    let date = git(&["log", "-1", "--format=%cs"]);
+    println!("cargo:rustc-env=TILING_GIT_DESCRIBE={}", describe);
+    println!("cargo:rustc-env=TILING_GIT_COMMIT={}", commit);
+    println!("cargo:rustc-env=TILING_GIT_DATE={}", date);
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+    println!("cargo:rerun-if-changed=.git/index");
+
+    // The assets are bundled only when they are embedded
+    if env::var_os("CARGO_FEATURE_EMBED_ASSETS").is_some() {
+        let bundle = asset_bundle::build_bundle(Path::new("assets")).unwrap();
+        let out_dir = env::var("OUT_DIR").unwrap();
+        fs::write(Path::new(&out_dir).join("assets.bundle"), bundle).unwrap();
+        println!("cargo:rerun-if-changed=assets");
+        println!("cargo:rerun-if-changed=asset_bundle.rs");
+    }
+}
+
+// Falls back to "unknown" when building outside of a git checkout
+fn git(args: &[&str]) -> String {
+    Command::new("git")
+        .args(args)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .unwrap_or("unknown".to_string())
+}
@@ -1,11 +1,20 @@
 #!/usr/bin/env -S cargo +nightly -Zscript --quiet
 
 use std::{
-    env, io,
+    env, fs, io,
+    path::{Path, PathBuf},
     process::{Command, Stdio},
 };
 
+#[path = "asset_bundle.rs"]
+mod asset_bundle;
+#[path = "src/assets/crc32.rs"]
+mod crc32;
+
 const OUT_FOLDER: &str = "out";
+/// Read next to the executable by `src/assets/bundle.rs`
+const BUNDLE_FILE: &str = "assets.bundle";
+const DEFAULT_ITCH_USER: &str = "zjikra";
 
 fn main() -> Result<(), Error> {
     // get platforms to package from command line arguments
@@ -30,43 +39,51 @@ fn main() -> Result<(), Error> {
 fn package_platform(args: &Args, platform: &Platform) -> Result<(), Error> {
     let v: Verbosity = args.into();
     let crate_name = get_crate_name();
-    let package = format!("{}-{}", get_crate_name(), platform.to_string());
+    let package = get_package_name(platform);
 
     println!("packaging: {}", package);
 
     // basically cargo run --release
-    platform.build(&v)?;
+    // with `--skip-build` the artifacts of a previous build are packaged again
+    if args.check(&Arg::SkipBuild) {
+        let executable = platform.path_to_executable();
+        if v != Verbosity::DryRun && !Path::new(&executable).exists() {
+            return Err(format!("missing {}, can't skip the build", executable).into());
+        }
+    } else {
+        platform.build(&v)?;
+    }
 
     // remove old root dir
-    run_silent_fail(&v, &["rm", "-r", &format!("./{}/{}", OUT_FOLDER, package)])?;
+    remove_path(&v, &Path::new(OUT_FOLDER).join(&package))?;
 
-    // make package root directory
-    run(
-        &v,
-        &["mkdir", "-p", &format!("./{}/{}/", OUT_FOLDER, package)],
-    )?;
+    // make package root directory, for bundled platforms the bundle inside it
+    let bundle_path = format!("./{}/{}/{}", OUT_FOLDER, package, platform.bundle_dir());
+    create_dir(&v, Path::new(&bundle_path))?;
 
     // copy executable
-    run(
+    let executable = PathBuf::from(platform.path_to_executable());
+    let executable_name = executable
+        .file_name()
+        .ok_or(format!("no executable name"))?;
+    copy_path(
         &v,
-        &[
-            "cp",
-            "-r",
-            &platform.path_to_executable(),
-            &format!("./{}/{}/", OUT_FOLDER, package),
-        ],
+        &executable,
+        &Path::new(&bundle_path).join(executable_name),
     )?;
 
-    // copy new assets
-    if platform.needs_copy_assets() {
-        run(
+    // copy new assets, or bundle them next to the executable
+    if platform.ships_bundle() {
+        write_bundle(
             &v,
-            &[
-                "cp",
-                "-r",
-                "assets",
-                &format!("./{}/{}/assets/", OUT_FOLDER, package),
-            ],
+            Path::new("assets"),
+            &Path::new(&bundle_path).join(BUNDLE_FILE),
+        )?;
+    } else if platform.needs_copy_assets() {
+        copy_path(
+            &v,
+            Path::new("assets"),
+            &Path::new(&bundle_path).join("assets"),
         )?;
     }
 
@@ -78,13 +95,18 @@ fn package_platform(args: &Args, platform: &Platform) -> Result<(), Error> {
 
     // if `-i` is passed, push to itch the package
     if args.check(&Arg::ItchDeploy) {
+        let user = args.itch_user();
+        let project = args.itch_project().unwrap_or(crate_name);
+        let channel = args.channel(platform);
         run(
             &v,
             &[
                 "butler",
                 "push",
                 &package_path,
-                &format!("zjikra/{}:{}", crate_name, platform.to_string()),
+                &format!("{}/{}:{}", user, project, channel),
+                "--userversion",
+                &get_crate_version()?,
             ],
         )?;
     }
@@ -100,6 +122,7 @@ enum Platform {
     Wasm,
     Linux,
     Android,
+    Ios,
 }
 
 impl ToString for Platform {
@@ -109,6 +132,7 @@ impl ToString for Platform {
             Self::Wasm => "wasm",
             Self::Linux => "linux",
             Self::Android => "android",
+            Self::Ios => "ios",
         }
         .to_string()
     }
@@ -116,6 +140,7 @@ impl ToString for Platform {
 
 impl Platform {
     fn all() -> Vec<Platform> {
+        // ios is left out since it can only be built from macos
         vec![
             Platform::Windows,
             Platform::Wasm,
@@ -130,6 +155,7 @@ impl Platform {
             "wasm" => Ok(Self::Wasm),
             "linux" => Ok(Self::Linux),
             "android" => Ok(Self::Android),
+            "ios" => Ok(Self::Ios),
             _ => Err(format!("unknown platform {}", s).into()),
         }
     }
@@ -140,6 +166,7 @@ impl Platform {
             Self::Wasm => Some("wasm32-unknown-unknown"),
             Self::Linux => Some("x86_64-unknown-linux-gnu"),
             Self::Android => None,
+            Self::Ios => Some("aarch64-apple-ios"),
         }
     }
 
@@ -172,6 +199,14 @@ impl Platform {
                     ("NDK_HOME", "/home/j/.android-dev/android-ndk-r25"),
                 ],
             ),
+            Self::Ios => {
+                // needs xcode and `rustup target add aarch64-apple-ios`
+                let target = self.target().unwrap();
+                run(
+                    &v,
+                    &["cargo", "+stable", "build", "--release", "--target", target],
+                )
+            }
         }
     }
 
@@ -195,6 +230,19 @@ impl Platform {
                     get_crate_name()
                 )
             }
+            Self::Ios => {
+                let target = self.target().unwrap();
+                format!("target/{target}/release/{}", get_crate_name())
+            }
+        }
+    }
+
+    /// Folder inside the package root where the executable and the assets go
+    fn bundle_dir(&self) -> String {
+        match self {
+            // the .ipa is just a zip of the Payload folder
+            Self::Ios => format!("Payload/{}.app/", get_crate_name()),
+            _ => String::new(),
         }
     }
 
@@ -205,50 +253,313 @@ impl Platform {
         }
     }
 
+    /// The web build fetches the files one by one, android packs them in the apk
+    fn ships_bundle(&self) -> bool {
+        match self {
+            Self::Windows | Self::Linux | Self::Ios => true,
+            _ => false,
+        }
+    }
+
     fn copy_resources(&self, v: &Verbosity) -> Result<(), Error> {
         match self {
             Self::Wasm => {
-                let package = format!("{}-{}", get_crate_name(), self.to_string());
-                let out_path = &format!("./{}/{}/index.html", OUT_FOLDER, package);
-                run(
-                    &v,
-                    &[
-                        "cp",
-                        "-r",
-                        &format!("resources/wasm-res/index.html"),
-                        out_path,
-                    ],
-                )?;
-                run(
-                    &v,
-                    &[
-                        "sed",
-                        "-i",
-                        &format!("s/CRATENAME/{}/g", get_crate_name()),
-                        out_path,
-                    ],
-                )
+                let package = get_package_name(self);
+                let out_path = Path::new(OUT_FOLDER).join(package).join("index.html");
+                copy_path(&v, Path::new("resources/wasm-res/index.html"), &out_path)?;
+                replace_in_file(&v, &out_path, "CRATENAME", &get_crate_name())
+            }
+            Self::Ios => {
+                let package = get_package_name(self);
+                let bundle_path = format!("./{}/{}/{}", OUT_FOLDER, package, self.bundle_dir());
+                let plist_path = Path::new(&bundle_path).join("Info.plist");
+                copy_path(&v, Path::new("resources/ios-res/Info.plist"), &plist_path)?;
+                replace_in_file(&v, &plist_path, "CRATENAME", &get_crate_name())?;
+                replace_in_file(&v, &plist_path, "CRATEVERSION", &get_crate_version()?)?;
+                sign_ios_bundle(v, &bundle_path)
             }
             _ => Ok(()),
         }
     }
 
     fn package_post_process(&self, v: &Verbosity) -> Result<String, Error> {
-        let package = format!("{}-{}", get_crate_name(), self.to_string());
+        let package = get_package_name(self);
         match self {
-            Self::Android => Ok(format!("{}/{}.apk", package, get_crate_name())),
+            Self::Android => Ok(format!(
+                "{}/{}/{}.apk",
+                OUT_FOLDER,
+                package,
+                get_crate_name()
+            )),
+            Self::Ios => {
+                let ipa_path = format!("{}/{}.ipa", OUT_FOLDER, package);
+                remove_path(&v, Path::new(&ipa_path))?;
+                let package_root = Path::new(OUT_FOLDER).join(&package);
+                zip_dir(&v, &package_root, "Payload", Path::new(&ipa_path))?;
+                Ok(ipa_path)
+            }
             _ => {
-                env::set_current_dir(OUT_FOLDER)?;
-                let zipped_path = format!("{}.zip", package);
-                run_silent_fail(&v, &["rm", &zipped_path])?;
-                run(&v, &["zip", "-r", &zipped_path, &format!("{}", package)])?;
-                env::set_current_dir("..")?;
-                Ok(format!("{}/{}", OUT_FOLDER, zipped_path))
+                let zipped_path = format!("{}/{}.zip", OUT_FOLDER, package);
+                remove_path(&v, Path::new(&zipped_path))?;
+                zip_dir(&v, Path::new(OUT_FOLDER), &package, Path::new(&zipped_path))?;
+                Ok(zipped_path)
             }
         }
     }
 }
 
+/// Signing hook points for the ios bundle, configured through env vars:
+/// - `IOS_PROVISIONING_PROFILE`: path to a .mobileprovision, embedded in the bundle
+/// - `IOS_CODESIGN_IDENTITY`: identity passed to `codesign`, without it the bundle is unsigned
+/// - `IOS_ENTITLEMENTS`: optional path to an entitlements .plist for `codesign`
+fn sign_ios_bundle(v: &Verbosity, bundle_path: &str) -> Result<(), Error> {
+    if let Ok(profile) = env::var("IOS_PROVISIONING_PROFILE") {
+        copy_path(
+            &v,
+            Path::new(&profile),
+            &Path::new(bundle_path).join("embedded.mobileprovision"),
+        )?;
+    }
+    let Ok(identity) = env::var("IOS_CODESIGN_IDENTITY") else {
+        println!("   ios: bundle is unsigned, set IOS_CODESIGN_IDENTITY to sign it");
+        return Ok(());
+    };
+    let mut args = vec![
+        "codesign",
+        "--force",
+        "--timestamp=none",
+        "--sign",
+        &identity,
+    ];
+    let entitlements = env::var("IOS_ENTITLEMENTS");
+    if let Ok(ref entitlements) = entitlements {
+        args.extend(["--entitlements", entitlements]);
+    }
+    args.push(bundle_path);
+    run(&v, &args)
+}
+
+/// Removes a file or a whole directory, succeeds if there is nothing to remove
+fn remove_path(v: &Verbosity, path: &Path) -> Result<(), Error> {
+    if !path.exists() {
+        return Ok(());
+    }
+    log(v, format!("removing: {}", path.display()));
+    if v == &Verbosity::DryRun {
+        return Ok(());
+    }
+    if path.is_dir() {
+        fs::remove_dir_all(path)?;
+    } else {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+fn create_dir(v: &Verbosity, path: &Path) -> Result<(), Error> {
+    log(v, format!("creating: {}", path.display()));
+    if v == &Verbosity::DryRun {
+        return Ok(());
+    }
+    fs::create_dir_all(path)?;
+    Ok(())
+}
+
+/// Copies a file or recursively a directory to the destination path
+fn copy_path(v: &Verbosity, from: &Path, to: &Path) -> Result<(), Error> {
+    log(
+        v,
+        format!("copying: {} -> {}", from.display(), to.display()),
+    );
+    if v == &Verbosity::DryRun {
+        return Ok(());
+    }
+    copy_recursive(from, to)
+}
+
+/// Packs every file of the `assets` folder in one bundle
+fn write_bundle(v: &Verbosity, assets: &Path, to: &Path) -> Result<(), Error> {
+    log(
+        v,
+        format!("bundling: {} -> {}", assets.display(), to.display()),
+    );
+    if v == &Verbosity::DryRun {
+        return Ok(());
+    }
+    fs::write(to, asset_bundle::build_bundle(assets)?)?;
+    Ok(())
+}
+
+fn copy_recursive(from: &Path, to: &Path) -> Result<(), Error> {
+    if from.is_dir() {
+        fs::create_dir_all(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy_recursive(&entry.path(), &to.join(entry.file_name()))?;
+        }
+    } else {
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(from, to)?;
+    }
+    Ok(())
+}
+
+/// Replaces every occurrence of `pattern` in a text file
+fn replace_in_file(v: &Verbosity, path: &Path, pattern: &str, value: &str) -> Result<(), Error> {
+    log(
+        v,
+        format!("replacing: {} -> {} in {}", pattern, value, path.display()),
+    );
+    if v == &Verbosity::DryRun {
+        return Ok(());
+    }
+    let text = fs::read_to_string(path)?;
+    fs::write(path, text.replace(pattern, value))?;
+    Ok(())
+}
+
+/// Zips `base/dir` into `zip_path`, entries are named starting from `dir`.
+/// Entries are stored without compression, which every unzipper understands.
+fn zip_dir(v: &Verbosity, base: &Path, dir: &str, zip_path: &Path) -> Result<(), Error> {
+    log(
+        v,
+        format!(
+            "zipping: {} -> {}",
+            base.join(dir).display(),
+            zip_path.display()
+        ),
+    );
+    if v == &Verbosity::DryRun {
+        return Ok(());
+    }
+    let mut files = vec![];
+    list_files(&base.join(dir), dir, &mut files)?;
+    files.sort();
+
+    let mut zip = ZipWriter::default();
+    for (path, name) in files {
+        let data = fs::read(&path)?;
+        let mode = file_mode(&fs::metadata(&path)?);
+        zip.add_stored(&name, &data, mode);
+    }
+    fs::write(zip_path, zip.finish())?;
+    Ok(())
+}
+
+/// Collects all the files under `path` paired with their zip entry name
+fn list_files(path: &Path, name: &str, files: &mut Vec<(PathBuf, String)>) -> Result<(), Error> {
+    if path.is_dir() {
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            let entry_name = format!("{}/{}", name, entry.file_name().to_string_lossy());
+            list_files(&entry.path(), &entry_name, files)?;
+        }
+    } else {
+        files.push((path.to_path_buf(), name.to_string()));
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn file_mode(metadata: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode()
+}
+
+#[cfg(not(unix))]
+fn file_mode(_metadata: &fs::Metadata) -> u32 {
+    // windows doesn't know about the executable bit, mark everything as runnable
+    0o100755
+}
+
+/// Minimal zip archive writer
+/// Specification: https://pkware.cachefly.net/webdocs/casestudies/APPNOTE.TXT
+#[derive(Default)]
+struct ZipWriter {
+    data: Vec<u8>,
+    central_directory: Vec<u8>,
+    entries: u16,
+}
+
+impl ZipWriter {
+    // version 2.0, made on unix so that the file mode is kept
+    const VERSION_NEEDED: u16 = 20;
+    const VERSION_MADE_BY: u16 = (3 << 8) | 20;
+    // names are utf-8
+    const FLAGS: u16 = 1 << 11;
+    // 1980-01-01 00:00, the minimum dos date, keeps the archive reproducible
+    const DOS_TIME: u16 = 0;
+    const DOS_DATE: u16 = (1 << 5) | 1;
+
+    fn add_stored(&mut self, name: &str, content: &[u8], mode: u32) {
+        let crc = crc32::crc32(content);
+        let offset = self.data.len() as u32;
+
+        // local file header
+        self.data.extend(0x04034b50_u32.to_le_bytes());
+        self.data.extend(Self::VERSION_NEEDED.to_le_bytes());
+        self.data.extend(Self::FLAGS.to_le_bytes());
+        self.data.extend(0_u16.to_le_bytes()); // stored
+        self.data.extend(Self::DOS_TIME.to_le_bytes());
+        self.data.extend(Self::DOS_DATE.to_le_bytes());
+        self.data.extend(crc.to_le_bytes());
+        self.data.extend((content.len() as u32).to_le_bytes());
+        self.data.extend((content.len() as u32).to_le_bytes());
+        self.data.extend((name.len() as u16).to_le_bytes());
+        self.data.extend(0_u16.to_le_bytes()); // extra field length
+        self.data.extend(name.as_bytes());
+        self.data.extend(content);
+
+        // central directory header
+        let cd = &mut self.central_directory;
+        cd.extend(0x02014b50_u32.to_le_bytes());
+        cd.extend(Self::VERSION_MADE_BY.to_le_bytes());
+        cd.extend(Self::VERSION_NEEDED.to_le_bytes());
+        cd.extend(Self::FLAGS.to_le_bytes());
+        cd.extend(0_u16.to_le_bytes()); // stored
+        cd.extend(Self::DOS_TIME.to_le_bytes());
+        cd.extend(Self::DOS_DATE.to_le_bytes());
+        cd.extend(crc.to_le_bytes());
+        cd.extend((content.len() as u32).to_le_bytes());
+        cd.extend((content.len() as u32).to_le_bytes());
+        cd.extend((name.len() as u16).to_le_bytes());
+        cd.extend(0_u16.to_le_bytes()); // extra field length
+        cd.extend(0_u16.to_le_bytes()); // comment length
+        cd.extend(0_u16.to_le_bytes()); // disk number
+        cd.extend(0_u16.to_le_bytes()); // internal attributes
+        cd.extend((mode << 16).to_le_bytes()); // external attributes
+        cd.extend(offset.to_le_bytes());
+        cd.extend(name.as_bytes());
+
+        self.entries += 1;
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let cd_offset = self.data.len() as u32;
+        let cd_size = self.central_directory.len() as u32;
+        self.data.append(&mut self.central_directory);
+
+        // end of central directory record
+        self.data.extend(0x06054b50_u32.to_le_bytes());
+        self.data.extend(0_u16.to_le_bytes()); // disk number
+        self.data.extend(0_u16.to_le_bytes()); // disk with the central directory
+        self.data.extend(self.entries.to_le_bytes());
+        self.data.extend(self.entries.to_le_bytes());
+        self.data.extend(cd_size.to_le_bytes());
+        self.data.extend(cd_offset.to_le_bytes());
+        self.data.extend(0_u16.to_le_bytes()); // comment length
+        self.data
+    }
+}
+
+fn log(verbosity: &Verbosity, message: String) {
+    if verbosity != &Verbosity::Silent {
+        println!("{}", message);
+    }
+}
+
 fn run(v: &Verbosity, args: &[&str]) -> Result<(), Error> {
     run_complete(v, args, &[], false)
 }
@@ -257,16 +568,20 @@ fn run_env(v: &Verbosity, args: &[&str], envs: &[(&str, &str)]) -> Result<(), Er
     run_complete(v, args, envs, false)
 }
 
-fn run_silent_fail(v: &Verbosity, args: &[&str]) -> Result<(), Error> {
-    run_complete(v, args, &[], true)
-}
-
 fn run_complete(
     verbosity: &Verbosity,
     args: &[&str],
     envs: &[(&str, &str)],
     silent_fail: bool,
 ) -> Result<(), Error> {
+    if verbosity == &Verbosity::DryRun {
+        println!(
+            "running command:{}",
+            args.iter()
+                .fold(String::new(), |acc, i| format!("{} {}", acc, i))
+        );
+        return Ok(());
+    }
     let mut stdout = Stdio::piped();
     let mut stderr = Stdio::piped();
     if verbosity == &Verbosity::Verbose {
@@ -320,6 +635,23 @@ fn get_crate_name() -> String {
         .to_string()
 }
 
+fn get_crate_version() -> Result<String, Error> {
+    let manifest = fs::read_to_string("Cargo.toml")?;
+    manifest
+        .lines()
+        .find_map(|line| {
+            let value = line.strip_prefix("version")?.trim().strip_prefix('=')?;
+            Some(value.trim().trim_matches('"').to_string())
+        })
+        .ok_or(format!("no version in Cargo.toml").into())
+}
+
+/// Name of the package folder and zip, ex: tiling-0.1.0-linux
+fn get_package_name(platform: &Platform) -> String {
+    let version = get_crate_version().unwrap_or("unknown".to_string());
+    format!("{}-{}-{}", get_crate_name(), version, platform.to_string())
+}
+
 #[derive(Clone, Debug)]
 struct Args {
     args: Vec<Arg>,
@@ -353,12 +685,48 @@ impl Args {
     fn check(&self, arg: &Arg) -> bool {
         self.args.contains(arg)
     }
+
+    fn itch_user(&self) -> String {
+        self.args
+            .iter()
+            .find_map(|arg| match arg {
+                Arg::ItchUser(user) => Some(user.clone()),
+                _ => None,
+            })
+            .unwrap_or(DEFAULT_ITCH_USER.to_string())
+    }
+
+    fn itch_project(&self) -> Option<String> {
+        self.args.iter().find_map(|arg| match arg {
+            Arg::ItchProject(project) => Some(project.clone()),
+            _ => None,
+        })
+    }
+
+    /// The itch channel of the platform, defaults to the platform name
+    fn channel(&self, platform: &Platform) -> String {
+        self.args
+            .iter()
+            .find_map(|arg| match arg {
+                Arg::Channels(channels) => channels
+                    .iter()
+                    .find(|(p, _)| p == platform)
+                    .map(|(_, channel)| channel.clone()),
+                _ => None,
+            })
+            .unwrap_or(platform.to_string())
+    }
 }
 
 #[derive(Clone, Debug, PartialEq)]
 enum Arg {
     ItchDeploy,
+    ItchUser(String),
+    ItchProject(String),
+    Channels(Vec<(Platform, String)>),
     Verbose,
+    DryRun,
+    SkipBuild,
     Platforms(Vec<Platform>),
 }
 
@@ -378,6 +746,18 @@ impl Arg {
                     platforms.push(p);
                 }
             }
+            Self::ItchUser(user) => *user = s.clone(),
+            Self::ItchProject(project) => *project = s.clone(),
+            Self::Channels(channels) => {
+                // ex: windows=win-beta,linux=linux-beta
+                for token in s.split(',') {
+                    let (p, channel) = token.split_once('=').ok_or(format!(
+                        "invalid channel {}, expected platform=channel",
+                        token
+                    ))?;
+                    channels.push((Platform::from_str(p)?, channel.to_string()));
+                }
+            }
             _ => {}
         }
         Ok(())
@@ -386,20 +766,37 @@ impl Arg {
     fn representations(&self) -> Vec<String> {
         match self {
             Self::ItchDeploy => vec![format!("-i"), format!("--itch-deploy")],
+            Self::ItchUser(_) => vec![format!("-u"), format!("--itch-user")],
+            Self::ItchProject(_) => vec![format!("--itch-project")],
+            Self::Channels(_) => vec![format!("-c"), format!("--channels")],
             Self::Platforms(_) => vec![format!("-p"), format!("--platforms")],
             Self::Verbose => vec![format!("-v"), format!("--verbose")],
+            Self::DryRun => vec![format!("-n"), format!("--dry-run")],
+            Self::SkipBuild => vec![format!("-s"), format!("--skip-build")],
         }
     }
 
     fn require_next(&self) -> bool {
         match self {
             Self::Platforms(_) => true,
+            Self::ItchUser(_) => true,
+            Self::ItchProject(_) => true,
+            Self::Channels(_) => true,
             _ => false,
         }
     }
 
     fn all() -> Vec<Arg> {
-        vec![Arg::ItchDeploy, Arg::Verbose, Arg::Platforms(vec![])]
+        vec![
+            Arg::ItchDeploy,
+            Arg::ItchUser(String::new()),
+            Arg::ItchProject(String::new()),
+            Arg::Channels(vec![]),
+            Arg::Verbose,
+            Arg::DryRun,
+            Arg::SkipBuild,
+            Arg::Platforms(vec![]),
+        ]
     }
 }
 
@@ -407,11 +804,15 @@ impl Arg {
 enum Verbosity {
     Verbose,
     Silent,
+    /// Prints what would be done without touching anything
+    DryRun,
 }
 
 impl From<&Args> for Verbosity {
     fn from(args: &Args) -> Self {
-        if args.check(&Arg::Verbose) {
+        if args.check(&Arg::DryRun) {
+            Self::DryRun
+        } else if args.check(&Arg::Verbose) {
             Self::Verbose
         } else {
             Self::Silent
@@ -0,0 +1,66 @@
+use std::collections::HashMap;
+
+use glam::Vec2;
+
+/// Pixels of a named sprite in the atlas image, from the top left
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AtlasRegion {
+    pub pos: Vec2,
+    pub size: Vec2,
+}
+
+/// Names of the sprites of an image, parsed from `key=value` lines.
+/// Lines starting with '#' are comments, `image` is the asset path of the image
+/// and every other key is a sprite with its pixel rect written as "x y width height".
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SpriteAtlas {
+    pub image: String,
+    pub regions: HashMap<String, AtlasRegion>,
+}
+
+impl SpriteAtlas {
+    pub fn from_atlas(s: &[u8]) -> Result<Self, String> {
+        let text = std::str::from_utf8(s).map_err(|e| format!("Atlas: {}", e))?;
+        let mut atlas = Self::default();
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or(format!("Atlas: missing '=' at line {}", line_number + 1))?;
+            let (key, value) = (key.trim(), value.trim());
+            if key == "image" {
+                atlas.image = value.to_string();
+                continue;
+            }
+            let numbers = value
+                .split_whitespace()
+                .map(|n| n.parse::<f32>())
+                .collect::<Result<Vec<f32>, _>>()
+                .map_err(|e| format!("Atlas: {} at line {}", e, line_number + 1))?;
+            let [x, y, width, height] = numbers[..] else {
+                return Err(format!(
+                    "Atlas: expected 'x y width height' at line {}",
+                    line_number + 1
+                ));
+            };
+            atlas.regions.insert(
+                key.to_string(),
+                AtlasRegion {
+                    pos: Vec2::new(x, y),
+                    size: Vec2::new(width, height),
+                },
+            );
+        }
+        if atlas.image.is_empty() {
+            return Err("Atlas: missing image".to_string());
+        }
+        Ok(atlas)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&AtlasRegion> {
+        self.regions.get(name)
+    }
+}
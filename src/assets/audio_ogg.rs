@@ -0,0 +1,1306 @@
+//! Ogg Vorbis decoder.
+//! Specification: https://xiph.org/vorbis/doc/Vorbis_I_spec.html
+//! Supports floor 1 and residues 0, 1 and 2, which is what encoders produce since 2002.
+
+use std::{f64::consts::PI, sync::Arc};
+
+use crate::quad_snd::MusicSource;
+
+/// One packet of the logical stream
+pub struct OggPacket {
+    pub data: Vec<u8>,
+    /// Samples decoded up to the end of this packet, if it's the last one of its page
+    pub granule: Option<u64>,
+    /// Last packet of the stream
+    pub last: bool,
+}
+
+/// Splits the pages of an Ogg file into packets, only the first logical stream is read
+#[derive(Clone, Default)]
+pub struct OggReader {
+    cursor: usize,
+    serial: Option<u32>,
+    lacing: Vec<u8>,
+    lacing_index: usize,
+    data_cursor: usize,
+    granule: u64,
+    last_page: bool,
+}
+
+impl OggReader {
+    /// Returns false at the end of the file
+    fn next_page(&mut self, s: &[u8]) -> Result<bool, String> {
+        loop {
+            if self.cursor >= s.len() {
+                return Ok(false);
+            }
+            let header = s
+                .get(self.cursor..self.cursor + 27)
+                .ok_or("Truncated ogg page header")?;
+            if &header[0..4] != b"OggS" {
+                return Err(format!("Not an ogg page at {}", self.cursor));
+            }
+            let header_type = header[5];
+            let granule = u64::from_le_bytes(header[6..14].try_into().unwrap());
+            let serial = u32::from_le_bytes(header[14..18].try_into().unwrap());
+            let segments = header[26] as usize;
+            let lacing_start = self.cursor + 27;
+            let lacing = s
+                .get(lacing_start..lacing_start + segments)
+                .ok_or("Truncated ogg segment table")?;
+            let data_len: usize = lacing.iter().map(|&l| l as usize).sum();
+            let data_cursor = lacing_start + segments;
+            if data_cursor + data_len > s.len() {
+                return Err(format!("Truncated ogg page at {}", self.cursor));
+            }
+            self.cursor = data_cursor + data_len;
+            if *self.serial.get_or_insert(serial) != serial {
+                continue;
+            }
+            self.lacing = lacing.to_vec();
+            self.lacing_index = 0;
+            self.data_cursor = data_cursor;
+            self.granule = granule;
+            self.last_page = header_type & 4 != 0;
+            return Ok(true);
+        }
+    }
+
+    /// Returns None at the end of the stream
+    pub fn next_packet(&mut self, s: &[u8]) -> Result<Option<OggPacket>, String> {
+        let mut data = vec![];
+        loop {
+            if self.lacing_index >= self.lacing.len() {
+                if !self.next_page(s)? {
+                    return Ok(None);
+                }
+                continue;
+            }
+            let len = self.lacing[self.lacing_index] as usize;
+            data.extend_from_slice(&s[self.data_cursor..self.data_cursor + len]);
+            self.data_cursor += len;
+            self.lacing_index += 1;
+            if len < 255 {
+                // the granule position of the page counts up to the last packet that ends in it
+                let ends_page = self.lacing[self.lacing_index..].iter().all(|&l| l == 255);
+                return Ok(Some(OggPacket {
+                    data,
+                    granule: (ends_page && self.granule != u64::MAX).then_some(self.granule),
+                    last: ends_page && self.last_page,
+                }));
+            }
+        }
+    }
+}
+
+/// Reads the bits of a packet, least significant first.
+/// Returns None at the end of the packet.
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    bit: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, bit: 0 }
+    }
+
+    fn read(&mut self, count: u32) -> Option<u32> {
+        let mut value = 0;
+        for i in 0..count {
+            let byte = *self.bytes.get(self.bit / 8)?;
+            value |= (((byte >> (self.bit % 8)) & 1) as u32) << i;
+            self.bit += 1;
+        }
+        Some(value)
+    }
+
+    /// For the headers, where the end of the packet is an error
+    fn bits(&mut self, count: u32) -> Result<u32, String> {
+        self.read(count)
+            .ok_or_else(|| "Truncated vorbis header".to_string())
+    }
+
+    fn flag(&mut self) -> Result<bool, String> {
+        Ok(self.bits(1)? == 1)
+    }
+}
+
+/// Bits needed to store the value
+fn ilog(value: u32) -> u32 {
+    32 - value.leading_zeros()
+}
+
+fn float32_unpack(value: u32) -> f32 {
+    let mantissa = (value & 0x1fffff) as f64;
+    let exponent = ((value & 0x7fe00000) >> 21) as i32;
+    let signed = if value & 0x80000000 != 0 {
+        -mantissa
+    } else {
+        mantissa
+    };
+    (signed * 2f64.powi(exponent - 788)) as f32
+}
+
+/// Greatest value whose `dimensions` power is not above `entries`
+fn lookup1_values(entries: usize, dimensions: usize) -> usize {
+    let fits = |r: usize| {
+        (r as u64)
+            .checked_pow(dimensions as u32)
+            .is_some_and(|p| p <= entries as u64)
+    };
+    let mut r = (entries as f64).powf(1. / dimensions as f64).floor() as usize;
+    while fits(r + 1) {
+        r += 1;
+    }
+    while r > 0 && !fits(r) {
+        r -= 1;
+    }
+    r
+}
+
+struct Codebook {
+    dimensions: usize,
+    /// Huffman tree: children are node indices, leaves are `-(entry + 1)`, 0 is missing
+    tree: Vec<[i32; 2]>,
+    /// `dimensions` values for each entry, empty if the codebook is only scalar
+    vectors: Vec<f32>,
+}
+
+impl Codebook {
+    fn parse(r: &mut BitReader) -> Result<Self, String> {
+        if r.bits(24)? != 0x564342 {
+            return Err("Invalid vorbis codebook sync".to_string());
+        }
+        let dimensions = r.bits(16)? as usize;
+        let entries = r.bits(24)? as usize;
+        let mut lengths = vec![0u32; entries];
+        if r.flag()? {
+            // ordered
+            let mut entry = 0;
+            let mut length = r.bits(5)? + 1;
+            while entry < entries {
+                let count = r.bits(ilog((entries - entry) as u32))? as usize;
+                // codewords are at most 32 bits
+                if entry + count > entries || length > 32 {
+                    return Err("Invalid vorbis codebook lengths".to_string());
+                }
+                lengths[entry..entry + count].fill(length);
+                entry += count;
+                length += 1;
+            }
+        } else {
+            let sparse = r.flag()?;
+            for length in lengths.iter_mut() {
+                if !sparse || r.flag()? {
+                    *length = r.bits(5)? + 1;
+                }
+            }
+        }
+
+        let lookup_type = r.bits(4)?;
+        let vectors = match lookup_type {
+            0 => vec![],
+            1 | 2 => {
+                let minimum = float32_unpack(r.bits(32)?);
+                let delta = float32_unpack(r.bits(32)?);
+                let value_bits = r.bits(4)? + 1;
+                let sequence_p = r.flag()?;
+                let lookup_values = if lookup_type == 1 {
+                    lookup1_values(entries, dimensions)
+                } else {
+                    entries * dimensions
+                };
+                let multiplicands = (0..lookup_values)
+                    .map(|_| r.bits(value_bits))
+                    .collect::<Result<Vec<u32>, String>>()?;
+                let mut vectors = Vec::with_capacity(entries * dimensions);
+                for entry in 0..entries {
+                    let mut last = 0.;
+                    let mut divisor = 1;
+                    for i in 0..dimensions {
+                        let offset = if lookup_type == 1 {
+                            entry / divisor % lookup_values
+                        } else {
+                            entry * dimensions + i
+                        };
+                        let value = multiplicands[offset] as f32 * delta + minimum + last;
+                        if sequence_p {
+                            last = value;
+                        }
+                        vectors.push(value);
+                        divisor *= lookup_values;
+                    }
+                }
+                vectors
+            }
+            _ => return Err(format!("Invalid vorbis codebook lookup type {lookup_type}")),
+        };
+
+        Ok(Self {
+            dimensions,
+            tree: Self::build_tree(&lengths)?,
+            vectors,
+        })
+    }
+
+    /// Codewords are assigned in entry order, each taking the lowest free one of its length
+    fn build_tree(lengths: &[u32]) -> Result<Vec<[i32; 2]>, String> {
+        let used: Vec<usize> = (0..lengths.len()).filter(|&e| lengths[e] > 0).collect();
+        if let [entry] = used[..] {
+            // a single entry is decoded from one bit, whatever its value
+            let leaf = -(entry as i32) - 1;
+            return Ok(vec![[leaf, leaf]]);
+        }
+        let mut tree = vec![[0, 0]];
+        let mut marker = [0u64; 33];
+        for entry in used {
+            let length = lengths[entry] as usize;
+            let mut code = marker[length];
+            if code >> length != 0 {
+                return Err("Overspecified vorbis codebook".to_string());
+            }
+            let mut node = 0;
+            for bit in (0..length).rev() {
+                let side = (code >> bit & 1) as usize;
+                if bit == 0 {
+                    tree[node][side] = -(entry as i32) - 1;
+                } else {
+                    if tree[node][side] == 0 {
+                        tree.push([0, 0]);
+                        tree[node][side] = tree.len() as i32 - 1;
+                    }
+                    if tree[node][side] < 0 {
+                        return Err("Overspecified vorbis codebook".to_string());
+                    }
+                    node = tree[node][side] as usize;
+                }
+            }
+            // move the markers of this length and the shorter ones past the taken codeword
+            for j in (1..=length).rev() {
+                if marker[j] & 1 == 1 {
+                    marker[j] = if j == 1 {
+                        marker[1] + 1
+                    } else {
+                        marker[j - 1] << 1
+                    };
+                    break;
+                }
+                marker[j] += 1;
+            }
+            // and the longer ones that were below it
+            for j in length + 1..33 {
+                if marker[j] >> 1 != code {
+                    break;
+                }
+                code = marker[j];
+                marker[j] = marker[j - 1] << 1;
+            }
+        }
+        Ok(tree)
+    }
+
+    fn decode(&self, r: &mut BitReader) -> Option<usize> {
+        let mut node = 0;
+        loop {
+            let next = self.tree[node][r.read(1)? as usize];
+            if next < 0 {
+                return Some((-next - 1) as usize);
+            }
+            if next == 0 {
+                return None;
+            }
+            node = next as usize;
+        }
+    }
+
+    fn decode_vector(&self, r: &mut BitReader) -> Option<&[f32]> {
+        let entry = self.decode(r)?;
+        self.vectors
+            .get(entry * self.dimensions..(entry + 1) * self.dimensions)
+    }
+}
+
+const FLOOR1_RANGES: [u32; 4] = [256, 128, 86, 64];
+
+struct Floor1 {
+    partition_classes: Vec<usize>,
+    class_dimensions: Vec<usize>,
+    class_subclasses: Vec<u32>,
+    class_masterbooks: Vec<usize>,
+    /// -1 for no codebook
+    subclass_books: Vec<Vec<i32>>,
+    multiplier: i32,
+    x_list: Vec<i32>,
+    /// Indices of `x_list` by increasing x
+    sorted: Vec<usize>,
+    /// Closest lower and higher x among the previous points
+    neighbors: Vec<(usize, usize)>,
+}
+
+impl Floor1 {
+    fn parse(r: &mut BitReader, codebooks: usize) -> Result<Self, String> {
+        let book = |index: u32| {
+            if (index as usize) < codebooks {
+                Ok(index as usize)
+            } else {
+                Err(format!("Invalid vorbis codebook {index}"))
+            }
+        };
+        let partitions = r.bits(5)?;
+        let partition_classes = (0..partitions)
+            .map(|_| Ok(r.bits(4)? as usize))
+            .collect::<Result<Vec<usize>, String>>()?;
+        let classes = partition_classes.iter().max().map_or(0, |c| c + 1);
+        let mut class_dimensions = vec![];
+        let mut class_subclasses = vec![];
+        let mut class_masterbooks = vec![];
+        let mut subclass_books = vec![];
+        for _ in 0..classes {
+            class_dimensions.push(r.bits(3)? as usize + 1);
+            let subclasses = r.bits(2)?;
+            class_subclasses.push(subclasses);
+            class_masterbooks.push(if subclasses > 0 { book(r.bits(8)?)? } else { 0 });
+            let mut books = vec![];
+            for _ in 0..1 << subclasses {
+                let index = r.bits(8)? as i32 - 1;
+                if index >= 0 {
+                    book(index as u32)?;
+                }
+                books.push(index);
+            }
+            subclass_books.push(books);
+        }
+        let multiplier = r.bits(2)? as i32 + 1;
+        let range_bits = r.bits(4)?;
+        let mut x_list = vec![0, 1 << range_bits];
+        for &class in &partition_classes {
+            for _ in 0..class_dimensions[class] {
+                x_list.push(r.bits(range_bits)? as i32);
+            }
+        }
+        if x_list.len() > 65 {
+            return Err("Too many vorbis floor points".to_string());
+        }
+
+        let mut sorted: Vec<usize> = (0..x_list.len()).collect();
+        sorted.sort_by_key(|&i| x_list[i]);
+        let mut neighbors = vec![(0, 0); x_list.len()];
+        for i in 2..x_list.len() {
+            let x = x_list[i];
+            let low = (0..i).filter(|&j| x_list[j] < x).max_by_key(|&j| x_list[j]);
+            let high = (0..i).filter(|&j| x_list[j] > x).min_by_key(|&j| x_list[j]);
+            let (Some(low), Some(high)) = (low, high) else {
+                return Err("Repeated vorbis floor point".to_string());
+            };
+            neighbors[i] = (low, high);
+        }
+
+        Ok(Self {
+            partition_classes,
+            class_dimensions,
+            class_subclasses,
+            class_masterbooks,
+            subclass_books,
+            multiplier,
+            x_list,
+            sorted,
+            neighbors,
+        })
+    }
+
+    /// The y of the points, None if the channel is silent in this packet
+    fn decode(&self, r: &mut BitReader, codebooks: &[Codebook]) -> Option<Vec<i32>> {
+        if r.read(1)? == 0 {
+            return None;
+        }
+        let bits = ilog(FLOOR1_RANGES[self.multiplier as usize - 1] - 1);
+        let mut y = vec![r.read(bits)? as i32, r.read(bits)? as i32];
+        for &class in &self.partition_classes {
+            let subclass_bits = self.class_subclasses[class];
+            let mut subclass = 0;
+            if subclass_bits > 0 {
+                subclass = codebooks[self.class_masterbooks[class]].decode(r)?;
+            }
+            for _ in 0..self.class_dimensions[class] {
+                let book = self.subclass_books[class][subclass & ((1 << subclass_bits) - 1)];
+                subclass >>= subclass_bits;
+                y.push(if book >= 0 {
+                    codebooks[book as usize].decode(r)? as i32
+                } else {
+                    0
+                });
+            }
+        }
+        Some(y)
+    }
+
+    /// Multiplies the spectrum by the curve through the points
+    fn apply(&self, y: &[i32], spectrum: &mut [f32]) {
+        let range = FLOOR1_RANGES[self.multiplier as usize - 1] as i32;
+        let mut final_y = vec![0; y.len()];
+        let mut step2 = vec![false; y.len()];
+        final_y[0] = y[0];
+        final_y[1] = y[1];
+        step2[0] = true;
+        step2[1] = true;
+        for i in 2..y.len() {
+            let (low, high) = self.neighbors[i];
+            let predicted = render_point(
+                self.x_list[low],
+                final_y[low],
+                self.x_list[high],
+                final_y[high],
+                self.x_list[i],
+            );
+            let value = y[i];
+            let high_room = range - predicted;
+            let low_room = predicted;
+            let room = high_room.min(low_room) * 2;
+            if value == 0 {
+                final_y[i] = predicted;
+                continue;
+            }
+            step2[low] = true;
+            step2[high] = true;
+            step2[i] = true;
+            final_y[i] = if value >= room {
+                if high_room > low_room {
+                    value - low_room + predicted
+                } else {
+                    predicted - value + high_room - 1
+                }
+            } else if value % 2 == 1 {
+                predicted - (value + 1) / 2
+            } else {
+                predicted + value / 2
+            };
+        }
+
+        let mut curve = vec![0; spectrum.len()];
+        let (mut lx, mut ly) = (0, final_y[0] * self.multiplier);
+        for &i in &self.sorted[1..] {
+            if step2[i] {
+                let (hx, hy) = (self.x_list[i], final_y[i] * self.multiplier);
+                render_line(lx, ly, hx, hy, &mut curve);
+                (lx, ly) = (hx, hy);
+            }
+        }
+        if (lx as usize) < curve.len() {
+            render_line(lx, ly, curve.len() as i32, ly, &mut curve);
+        }
+        for (value, &index) in spectrum.iter_mut().zip(&curve) {
+            *value *= inverse_db(index);
+        }
+    }
+}
+
+fn render_point(x0: i32, y0: i32, x1: i32, y1: i32, x: i32) -> i32 {
+    let dy = y1 - y0;
+    let offset = dy.abs() * (x - x0) / (x1 - x0);
+    if dy < 0 {
+        y0 - offset
+    } else {
+        y0 + offset
+    }
+}
+
+/// Bresenham line, the points past the end of `v` are dropped
+fn render_line(x0: i32, y0: i32, x1: i32, y1: i32, v: &mut [i32]) {
+    let dy = y1 - y0;
+    let adx = x1 - x0;
+    if adx <= 0 {
+        return;
+    }
+    let base = dy / adx;
+    let step = if dy < 0 { base - 1 } else { base + 1 };
+    let ady = dy.abs() - base.abs() * adx;
+    let mut y = y0;
+    let mut err = 0;
+    for x in x0..x1.min(v.len() as i32) {
+        if x > x0 {
+            err += ady;
+            if err >= adx {
+                err -= adx;
+                y += step;
+            } else {
+                y += base;
+            }
+        }
+        v[x as usize] = y;
+    }
+}
+
+/// The floor values are steps of about 0.5 dB, from -140 dB to 0 dB
+fn inverse_db(index: i32) -> f32 {
+    let index = index.clamp(0, 255) as f32;
+    1.0649863e-7_f32.powf((255. - index) / 255.)
+}
+
+struct Residue {
+    kind: u16,
+    begin: usize,
+    end: usize,
+    partition_size: usize,
+    classifications: usize,
+    classbook: usize,
+    /// Codebook of each pass for each classification, -1 for none
+    books: Vec<[i32; 8]>,
+}
+
+impl Residue {
+    fn parse(r: &mut BitReader, kind: u16, codebooks: &[Codebook]) -> Result<Self, String> {
+        let begin = r.bits(24)? as usize;
+        let end = r.bits(24)? as usize;
+        let partition_size = r.bits(24)? as usize + 1;
+        let classifications = r.bits(6)? as usize + 1;
+        let classbook = r.bits(8)? as usize;
+        if classbook >= codebooks.len() {
+            return Err(format!("Invalid vorbis codebook {classbook}"));
+        }
+        let mut cascades = vec![];
+        for _ in 0..classifications {
+            let low = r.bits(3)?;
+            let high = if r.flag()? { r.bits(5)? } else { 0 };
+            cascades.push(high << 3 | low);
+        }
+        let mut books = vec![];
+        for cascade in cascades {
+            let mut passes = [-1; 8];
+            for (pass, book) in passes.iter_mut().enumerate() {
+                if cascade >> pass & 1 == 1 {
+                    let index = r.bits(8)? as usize;
+                    match codebooks.get(index) {
+                        Some(codebook) if !codebook.vectors.is_empty() => *book = index as i32,
+                        _ => return Err(format!("Invalid vorbis residue codebook {index}")),
+                    }
+                }
+            }
+            books.push(passes);
+        }
+        Ok(Self {
+            kind,
+            begin,
+            end,
+            partition_size,
+            classifications,
+            classbook,
+            books,
+        })
+    }
+
+    /// Adds the residue of the channels to their spectrum of length `n`
+    fn decode(
+        &self,
+        r: &mut BitReader,
+        codebooks: &[Codebook],
+        spectra: &mut [Vec<f32>],
+        skip: &[bool],
+        n: usize,
+    ) {
+        if self.kind != 2 {
+            self.decode_partitions(r, codebooks, spectra, skip, n);
+            return;
+        }
+        // type 2 is type 1 on the channels interleaved in a single vector
+        if skip.iter().all(|&s| s) {
+            return;
+        }
+        let channels = spectra.len();
+        let mut interleaved = vec![vec![0.; n * channels]];
+        self.decode_partitions(r, codebooks, &mut interleaved, &[false], n * channels);
+        for (i, value) in interleaved[0].iter().enumerate() {
+            spectra[i % channels][i / channels] = *value;
+        }
+    }
+
+    fn decode_partitions(
+        &self,
+        r: &mut BitReader,
+        codebooks: &[Codebook],
+        spectra: &mut [Vec<f32>],
+        skip: &[bool],
+        n: usize,
+    ) {
+        let begin = self.begin.min(n);
+        let end = self.end.min(n);
+        let partitions = end.saturating_sub(begin) / self.partition_size;
+        if partitions == 0 {
+            return;
+        }
+        let classbook = &codebooks[self.classbook];
+        let per_word = classbook.dimensions.max(1);
+        let mut classes = vec![vec![0; partitions + per_word]; spectra.len()];
+        for pass in 0..8 {
+            let mut partition = 0;
+            while partition < partitions {
+                if pass == 0 {
+                    for (channel, _) in skip.iter().enumerate().filter(|(_, &s)| !s) {
+                        let Some(mut word) = classbook.decode(r) else {
+                            return;
+                        };
+                        for i in (0..per_word).rev() {
+                            classes[channel][partition + i] = word % self.classifications;
+                            word /= self.classifications;
+                        }
+                    }
+                }
+                for _ in 0..per_word {
+                    if partition >= partitions {
+                        break;
+                    }
+                    for (channel, _) in skip.iter().enumerate().filter(|(_, &s)| !s) {
+                        let book = self.books[classes[channel][partition]][pass];
+                        if book < 0 {
+                            continue;
+                        }
+                        let offset = begin + partition * self.partition_size;
+                        let v = &mut spectra[channel][offset..offset + self.partition_size];
+                        if !self.decode_partition(r, &codebooks[book as usize], v) {
+                            return;
+                        }
+                    }
+                    partition += 1;
+                }
+            }
+        }
+    }
+
+    /// False at the end of the packet
+    fn decode_partition(&self, r: &mut BitReader, book: &Codebook, v: &mut [f32]) -> bool {
+        if self.kind == 0 {
+            // the values of a vector are spread over the partition
+            let step = v.len() / book.dimensions.max(1);
+            for i in 0..step {
+                let Some(values) = book.decode_vector(r) else {
+                    return false;
+                };
+                for (j, value) in values.iter().enumerate() {
+                    v[i + j * step] += value;
+                }
+            }
+        } else {
+            let mut i = 0;
+            while i < v.len() {
+                let Some(values) = book.decode_vector(r) else {
+                    return false;
+                };
+                for value in values {
+                    if let Some(sample) = v.get_mut(i) {
+                        *sample += value;
+                    }
+                    i += 1;
+                }
+            }
+        }
+        true
+    }
+}
+
+struct Mapping {
+    /// Magnitude and angle channels
+    coupling: Vec<(usize, usize)>,
+    /// Submap of each channel
+    mux: Vec<usize>,
+    /// Floor and residue of each submap
+    submaps: Vec<(usize, usize)>,
+}
+
+#[derive(Clone, Copy)]
+struct Mode {
+    long: bool,
+    mapping: usize,
+}
+
+/// Inverse MDCT computed with a complex FFT of a quarter of the size
+struct Imdct {
+    n: usize,
+    /// Twiddles before and after the FFT
+    pre: Vec<(f32, f32)>,
+    post: Vec<(f32, f32)>,
+    fft_twiddles: Vec<(f32, f32)>,
+    bit_reverse: Vec<usize>,
+}
+
+impl Imdct {
+    fn new(n: usize) -> Self {
+        let m = n / 2;
+        let l = n / 4;
+        let cis = |angle: f64| (angle.cos() as f32, angle.sin() as f32);
+        let bits = l.trailing_zeros();
+        Self {
+            n,
+            pre: (0..l).map(|q| cis(-PI * q as f64 / m as f64)).collect(),
+            post: (0..l)
+                .map(|p| cis(-PI * (p as f64 + 0.25) / m as f64))
+                .collect(),
+            fft_twiddles: (0..l / 2)
+                .map(|k| cis(-2. * PI * k as f64 / l as f64))
+                .collect(),
+            bit_reverse: (0..l)
+                .map(|i| i.reverse_bits() >> (usize::BITS - bits))
+                .collect(),
+        }
+    }
+
+    fn fft(&self, data: &mut [(f32, f32)]) {
+        let l = data.len();
+        for i in 0..l {
+            let j = self.bit_reverse[i];
+            if i < j {
+                data.swap(i, j);
+            }
+        }
+        let mut size = 2;
+        while size <= l {
+            let stride = l / size;
+            for start in (0..l).step_by(size) {
+                for k in 0..size / 2 {
+                    let (wr, wi) = self.fft_twiddles[k * stride];
+                    let (ar, ai) = data[start + k];
+                    let (br, bi) = data[start + k + size / 2];
+                    let (tr, ti) = (br * wr - bi * wi, br * wi + bi * wr);
+                    data[start + k] = (ar + tr, ai + ti);
+                    data[start + k + size / 2] = (ar - tr, ai - ti);
+                }
+            }
+            size *= 2;
+        }
+    }
+
+    /// `n / 2` coefficients to `n` samples
+    fn inverse(&self, coefficients: &[f32]) -> Vec<f32> {
+        let m = self.n / 2;
+        // DCT-IV of the coefficients, from the FFT of their even and reversed odd values
+        let mut data: Vec<(f32, f32)> = (0..m / 2)
+            .map(|q| {
+                let (a, b) = (coefficients[2 * q], coefficients[m - 1 - 2 * q]);
+                let (wr, wi) = self.pre[q];
+                (a * wr - b * wi, a * wi + b * wr)
+            })
+            .collect();
+        self.fft(&mut data);
+        let mut dct = vec![0.; m];
+        for (p, &(zr, zi)) in data.iter().enumerate() {
+            let (wr, wi) = self.post[p];
+            dct[2 * p] = zr * wr - zi * wi;
+            dct[m - 1 - 2 * p] = -(zr * wi + zi * wr);
+        }
+        // unfold the DCT-IV into the symmetries of the MDCT
+        (0..self.n)
+            .map(|i| {
+                if i < m / 2 {
+                    dct[i + m / 2]
+                } else if i < m * 3 / 2 {
+                    -dct[m * 3 / 2 - 1 - i]
+                } else {
+                    -dct[i - m * 3 / 2]
+                }
+            })
+            .collect()
+    }
+}
+
+/// Decodes the audio packets of a Vorbis stream
+pub struct VorbisDecoder {
+    pub channels: usize,
+    pub frequency: u32,
+    blocksizes: [usize; 2],
+    codebooks: Vec<Codebook>,
+    floors: Vec<Floor1>,
+    residues: Vec<Residue>,
+    mappings: Vec<Mapping>,
+    modes: Vec<Mode>,
+    /// Rising half of the window of each blocksize
+    slopes: [Vec<f32>; 2],
+    imdct: [Imdct; 2],
+    /// Windowed samples of the last block, overlapped with the next one
+    previous: Option<Vec<Vec<f32>>>,
+}
+
+impl VorbisDecoder {
+    /// From the identification and setup header packets
+    pub fn new(identification: &[u8], setup: &[u8]) -> Result<Self, String> {
+        let mut r = BitReader::new(identification);
+        if r.bits(8)? != 1 || identification.get(1..7) != Some(b"vorbis") {
+            return Err("Not a vorbis identification header".to_string());
+        }
+        r.bit += 6 * 8;
+        let version = r.bits(32)?;
+        let channels = r.bits(8)? as usize;
+        let frequency = r.bits(32)?;
+        let _bitrates = (r.bits(32)?, r.bits(32)?, r.bits(32)?);
+        let blocksizes = [1 << r.bits(4)?, 1 << r.bits(4)?];
+        if version != 0 || channels == 0 || frequency == 0 {
+            return Err(format!(
+                "Unsupported vorbis stream: version {version}, {channels} channels, {frequency} Hz"
+            ));
+        }
+        if blocksizes[0] < 64 || blocksizes[1] > 8192 || blocksizes[0] > blocksizes[1] {
+            return Err(format!("Invalid vorbis blocksizes {blocksizes:?}"));
+        }
+
+        let mut r = BitReader::new(setup);
+        if r.bits(8)? != 5 || setup.get(1..7) != Some(b"vorbis") {
+            return Err("Not a vorbis setup header".to_string());
+        }
+        r.bit += 6 * 8;
+
+        let codebooks = (0..r.bits(8)? + 1)
+            .map(|_| Codebook::parse(&mut r))
+            .collect::<Result<Vec<_>, String>>()?;
+
+        for _ in 0..r.bits(6)? + 1 {
+            if r.bits(16)? != 0 {
+                return Err("Invalid vorbis time domain transform".to_string());
+            }
+        }
+
+        let mut floors = vec![];
+        for _ in 0..r.bits(6)? + 1 {
+            match r.bits(16)? {
+                0 => return Err("Vorbis floor 0 is not supported".to_string()),
+                1 => floors.push(Floor1::parse(&mut r, codebooks.len())?),
+                kind => return Err(format!("Invalid vorbis floor type {kind}")),
+            }
+        }
+
+        let mut residues = vec![];
+        for _ in 0..r.bits(6)? + 1 {
+            let kind = r.bits(16)? as u16;
+            if kind > 2 {
+                return Err(format!("Invalid vorbis residue type {kind}"));
+            }
+            residues.push(Residue::parse(&mut r, kind, &codebooks)?);
+        }
+
+        let mut mappings = vec![];
+        for _ in 0..r.bits(6)? + 1 {
+            if r.bits(16)? != 0 {
+                return Err("Invalid vorbis mapping type".to_string());
+            }
+            let submap_count = if r.flag()? { r.bits(4)? + 1 } else { 1 } as usize;
+            let mut coupling = vec![];
+            if r.flag()? {
+                let bits = ilog(channels as u32 - 1);
+                for _ in 0..r.bits(8)? + 1 {
+                    let magnitude = r.bits(bits)? as usize;
+                    let angle = r.bits(bits)? as usize;
+                    if magnitude == angle || magnitude >= channels || angle >= channels {
+                        return Err("Invalid vorbis channel coupling".to_string());
+                    }
+                    coupling.push((magnitude, angle));
+                }
+            }
+            if r.bits(2)? != 0 {
+                return Err("Invalid vorbis mapping".to_string());
+            }
+            let mut mux = vec![0; channels];
+            if submap_count > 1 {
+                for submap in mux.iter_mut() {
+                    *submap = r.bits(4)? as usize;
+                    if *submap >= submap_count {
+                        return Err("Invalid vorbis mapping submap".to_string());
+                    }
+                }
+            }
+            let mut submaps = vec![];
+            for _ in 0..submap_count {
+                let _time = r.bits(8)?;
+                let floor = r.bits(8)? as usize;
+                let residue = r.bits(8)? as usize;
+                if floor >= floors.len() || residue >= residues.len() {
+                    return Err("Invalid vorbis mapping submap".to_string());
+                }
+                submaps.push((floor, residue));
+            }
+            mappings.push(Mapping {
+                coupling,
+                mux,
+                submaps,
+            });
+        }
+
+        let mut modes = vec![];
+        for _ in 0..r.bits(6)? + 1 {
+            let long = r.flag()?;
+            let window = r.bits(16)?;
+            let transform = r.bits(16)?;
+            let mapping = r.bits(8)? as usize;
+            if window != 0 || transform != 0 || mapping >= mappings.len() {
+                return Err("Invalid vorbis mode".to_string());
+            }
+            modes.push(Mode { long, mapping });
+        }
+        if !r.flag()? {
+            return Err("Missing vorbis setup framing bit".to_string());
+        }
+
+        let slope = |half: usize| -> Vec<f32> {
+            (0..half)
+                .map(|i| {
+                    let x = (i as f64 + 0.5) / half as f64 * PI / 2.;
+                    (PI / 2. * x.sin().powi(2)).sin() as f32
+                })
+                .collect()
+        };
+        Ok(Self {
+            channels,
+            frequency,
+            blocksizes,
+            codebooks,
+            floors,
+            residues,
+            mappings,
+            modes,
+            slopes: [slope(blocksizes[0] / 2), slope(blocksizes[1] / 2)],
+            imdct: [Imdct::new(blocksizes[0]), Imdct::new(blocksizes[1])],
+            previous: None,
+        })
+    }
+
+    /// Forgets the last block, call before decoding from another position
+    pub fn reset(&mut self) {
+        self.previous = None;
+    }
+
+    /// Samples of each channel, the first packet doesn't return any
+    pub fn decode_packet(&mut self, packet: &[u8]) -> Vec<Vec<f32>> {
+        let Some(block) = self.decode_block(packet) else {
+            return vec![vec![]; self.channels];
+        };
+        let n = block[0].len();
+        let output = match &self.previous {
+            // from the center of the last block to the center of this one
+            Some(previous) => {
+                let previous_n = previous[0].len();
+                let len = previous_n / 4 + n / 4;
+                previous
+                    .iter()
+                    .zip(&block)
+                    .map(|(previous, current)| {
+                        (0..len)
+                            .map(|j| {
+                                let p = previous.get(previous_n / 2 + j).unwrap_or(&0.);
+                                let c = (j + n / 4)
+                                    .checked_sub(previous_n / 4)
+                                    .and_then(|c| current.get(c))
+                                    .unwrap_or(&0.);
+                                p + c
+                            })
+                            .collect()
+                    })
+                    .collect()
+            }
+            None => vec![vec![]; self.channels],
+        };
+        self.previous = Some(block);
+        output
+    }
+
+    /// Windowed samples of the packet, None if it's not an audio packet
+    fn decode_block(&self, packet: &[u8]) -> Option<Vec<Vec<f32>>> {
+        let mut r = BitReader::new(packet);
+        if r.read(1)? != 0 {
+            return None;
+        }
+        let mode = *self
+            .modes
+            .get(r.read(ilog(self.modes.len() as u32 - 1))? as usize)?;
+        let (previous_long, next_long) = if mode.long {
+            (r.read(1)? == 1, r.read(1)? == 1)
+        } else {
+            (false, false)
+        };
+        let n = self.blocksizes[mode.long as usize];
+        let half = n / 2;
+        let mapping = &self.mappings[mode.mapping];
+
+        // an end of packet from here on leaves the rest silent
+        let mut floors: Vec<Option<Vec<i32>>> = (0..self.channels)
+            .map(|channel| {
+                let floor = &self.floors[mapping.submaps[mapping.mux[channel]].0];
+                floor.decode(&mut r, &self.codebooks)
+            })
+            .collect();
+        let mut skip: Vec<bool> = floors.iter().map(|f| f.is_none()).collect();
+        for &(magnitude, angle) in &mapping.coupling {
+            if !skip[magnitude] || !skip[angle] {
+                skip[magnitude] = false;
+                skip[angle] = false;
+            }
+        }
+
+        let mut spectra = vec![vec![0.; half]; self.channels];
+        for (submap, &(_, residue)) in mapping.submaps.iter().enumerate() {
+            let channels: Vec<usize> = (0..self.channels)
+                .filter(|&c| mapping.mux[c] == submap)
+                .collect();
+            let mut submap_spectra = vec![vec![0.; half]; channels.len()];
+            let submap_skip: Vec<bool> = channels.iter().map(|&c| skip[c]).collect();
+            self.residues[residue].decode(
+                &mut r,
+                &self.codebooks,
+                &mut submap_spectra,
+                &submap_skip,
+                half,
+            );
+            for (&channel, spectrum) in channels.iter().zip(submap_spectra) {
+                spectra[channel] = spectrum;
+            }
+        }
+
+        for &(magnitude, angle) in mapping.coupling.iter().rev() {
+            let mut angles = std::mem::take(&mut spectra[angle]);
+            for (m, a) in spectra[magnitude].iter_mut().zip(angles.iter_mut()) {
+                (*m, *a) = match (*m > 0., *a > 0.) {
+                    (true, true) => (*m, *m - *a),
+                    (true, false) => (*m + *a, *m),
+                    (false, true) => (*m, *m + *a),
+                    (false, false) => (*m - *a, *m),
+                };
+            }
+            spectra[angle] = angles;
+        }
+
+        let mut block = vec![];
+        for (channel, spectrum) in spectra.iter_mut().enumerate() {
+            match floors[channel].take() {
+                Some(y) => {
+                    let floor = &self.floors[mapping.submaps[mapping.mux[channel]].0];
+                    floor.apply(&y, spectrum);
+                }
+                None => spectrum.fill(0.),
+            }
+            let mut samples = self.imdct[mode.long as usize].inverse(spectrum);
+            self.window(&mut samples, mode.long, previous_long, next_long);
+            block.push(samples);
+        }
+        Some(block)
+    }
+
+    fn window(&self, samples: &mut [f32], long: bool, previous_long: bool, next_long: bool) {
+        let n = samples.len();
+        let short_half = self.blocksizes[0] / 2;
+        let (left_start, left) = if long && !previous_long {
+            (n / 4 - short_half / 2, &self.slopes[0])
+        } else {
+            (0, &self.slopes[long as usize])
+        };
+        let (right_start, right) = if long && !next_long {
+            (n * 3 / 4 - short_half / 2, &self.slopes[0])
+        } else {
+            (n / 2, &self.slopes[long as usize])
+        };
+        samples[..left_start].fill(0.);
+        for (sample, w) in samples[left_start..].iter_mut().zip(left) {
+            *sample *= w;
+        }
+        for (sample, w) in samples[right_start..].iter_mut().zip(right.iter().rev()) {
+            *sample *= w;
+        }
+        samples[right_start + right.len()..].fill(0.);
+    }
+}
+
+/// Left and right channel of a frame, for more channels it's the front pair
+fn stereo_frame(channels: &[Vec<f32>], i: usize) -> [f32; 2] {
+    let right = match channels.len() {
+        1 => 0,
+        2 | 4 => 1,
+        _ => 2,
+    };
+    [channels[0][i], channels[right][i]]
+}
+
+/// Opens the stream, the reader is left at the first audio packet
+pub fn open_ogg(s: &[u8]) -> Result<(OggReader, VorbisDecoder), String> {
+    let mut reader = OggReader::default();
+    let mut header = || {
+        reader
+            .next_packet(s)?
+            .ok_or_else(|| "Missing vorbis header".to_string())
+    };
+    let identification = header()?;
+    let _comment = header()?;
+    let setup = header()?;
+    let decoder = VorbisDecoder::new(&identification.data, &setup.data)?;
+    Ok((reader, decoder))
+}
+
+/// Interleaved stereo samples of the whole file, at its own frequency
+pub fn decode_ogg(s: &[u8]) -> Result<(Vec<f32>, u32), String> {
+    let (mut reader, mut decoder) = open_ogg(s)?;
+    let mut samples = vec![];
+    while let Some(packet) = reader.next_packet(s)? {
+        let channels = decoder.decode_packet(&packet.data);
+        let mut frames = channels[0].len();
+        if let (true, Some(granule)) = (packet.last, packet.granule) {
+            // the last packet is padded up to a full block
+            let decoded = (samples.len() / 2) as u64;
+            frames = frames.min(granule.saturating_sub(decoded) as usize);
+        }
+        for i in 0..frames {
+            samples.extend(stereo_frame(&channels, i));
+        }
+        if packet.last {
+            break;
+        }
+    }
+    Ok((samples, decoder.frequency))
+}
+
+/// Decodes a packet at a time while playing, resampling linearly
+pub struct OggStream {
+    bytes: Arc<[u8]>,
+    start: OggReader,
+    reader: OggReader,
+    decoder: VorbisDecoder,
+    /// Decoded stereo frames not played yet
+    frames: Vec<[f32; 2]>,
+    decoded: u64,
+    finished: bool,
+    // in source frames, from the start of `frames`
+    position: f64,
+    output_frequency: u32,
+}
+
+impl OggStream {
+    pub fn new(bytes: Arc<[u8]>, output_frequency: u32) -> Result<Self, String> {
+        let (reader, decoder) = open_ogg(&bytes)?;
+        Ok(Self {
+            bytes,
+            start: reader.clone(),
+            reader,
+            decoder,
+            frames: vec![],
+            decoded: 0,
+            finished: false,
+            position: 0.,
+            output_frequency,
+        })
+    }
+
+    fn decode_next(&mut self) {
+        let packet = match self.reader.next_packet(&self.bytes) {
+            Ok(Some(packet)) => packet,
+            Ok(None) => {
+                self.finished = true;
+                return;
+            }
+            Err(err) => {
+                eprintln!("Music stopped: {err}");
+                self.finished = true;
+                return;
+            }
+        };
+        let channels = self.decoder.decode_packet(&packet.data);
+        let mut frames = channels[0].len();
+        if let (true, Some(granule)) = (packet.last, packet.granule) {
+            frames = frames.min(granule.saturating_sub(self.decoded) as usize);
+        }
+        self.frames
+            .extend((0..frames).map(|i| stereo_frame(&channels, i)));
+        self.decoded += frames as u64;
+        self.finished = packet.last;
+    }
+}
+
+impl MusicSource for OggStream {
+    fn read(&mut self, out: &mut [f32]) -> usize {
+        let step = self.decoder.frequency as f64 / self.output_frequency as f64;
+        let mut written = 0;
+        for frame in out.chunks_exact_mut(2) {
+            let index = self.position as usize;
+            while index + 1 >= self.frames.len() && !self.finished {
+                self.decode_next();
+            }
+            let Some(&a) = self.frames.get(index) else {
+                break;
+            };
+            let b = *self.frames.get(index + 1).unwrap_or(&a);
+            let t = (self.position - index as f64) as f32;
+            frame[0] = a[0] + (b[0] - a[0]) * t;
+            frame[1] = a[1] + (b[1] - a[1]) * t;
+            self.position += step;
+            written += 1;
+        }
+        let played = (self.position as usize).min(self.frames.len());
+        self.frames.drain(..played);
+        self.position -= played as f64;
+        written
+    }
+
+    fn rewind(&mut self) {
+        self.reader = self.start.clone();
+        self.decoder.reset();
+        self.frames.clear();
+        self.decoded = 0;
+        self.finished = false;
+        self.position = 0.;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The bits of the (value, bit count) fields, least significant first
+    fn pack(fields: &[(u32, u32)]) -> Vec<u8> {
+        let mut bytes = vec![];
+        let mut bit = 0;
+        for &(value, count) in fields {
+            for i in 0..count {
+                if bit % 8 == 0 {
+                    bytes.push(0);
+                }
+                *bytes.last_mut().unwrap() |= ((value >> i & 1) as u8) << (bit % 8);
+                bit += 1;
+            }
+        }
+        bytes
+    }
+
+    #[test]
+    fn tone_fixture() {
+        // mono 8000 Hz, blocks of 256 with a single residue value on bin 20
+        let ogg = include_bytes!("fixtures/tone_bin20.ogg");
+        let (samples, frequency) = decode_ogg(ogg).unwrap();
+        assert_eq!(frequency, 8000);
+        // the granule of the last page cuts the padding of the last block
+        assert_eq!(samples.len(), 600 * 2);
+        assert!(samples.chunks_exact(2).all(|frame| frame[0] == frame[1]));
+
+        let left: Vec<f64> = samples.iter().step_by(2).map(|&s| s as f64).collect();
+        let power = |bin: f64| {
+            let (re, im) = left.iter().enumerate().fold((0., 0.), |(re, im), (i, s)| {
+                let angle = 2. * PI * bin / 256. * i as f64;
+                (re + s * angle.cos(), im - s * angle.sin())
+            });
+            re * re + im * im
+        };
+        let tone = power(20.5);
+        assert!(tone > 0.);
+        for bin in [5.5, 12.5, 17.5, 23.5, 30.5, 60.5] {
+            assert!(power(bin) * 10. < tone, "bin {bin}");
+        }
+
+        assert!(decode_ogg(&ogg[..ogg.len() - 10]).is_err());
+    }
+
+    #[test]
+    fn codeword_over_32_bits_is_an_error() {
+        // ordered lengths from 32: none of 32 bits, then all 40 entries of 33 bits
+        let header = pack(&[
+            (0x564342, 24),
+            (1, 16),
+            (40, 24),
+            (1, 1),
+            (31, 5),
+            (0, 6),
+            (40, 6),
+            (0, 4),
+        ]);
+        assert!(Codebook::parse(&mut BitReader::new(&header)).is_err());
+    }
+}
@@ -1,71 +1,398 @@
-use crate::ByteDecoder;
+use std::{ops::Range, sync::Arc};
+
+use crate::quad_snd::MusicSource;
+use crate::{decode_ogg, open_ogg, ByteDecoder, OggStream};
 
 #[derive(Debug)]
 pub struct AudioPcm {
+    /// Interleaved stereo
     pub samples: Vec<f32>,
-    // TODO: expose the frequency too
+    pub frequency: u32,
 }
 
-impl AudioPcm {
-    pub fn from_wav(s: &[u8]) -> Result<Self, String> {
+/// Format and location of the samples of a .wav
+#[derive(Debug, Clone)]
+pub struct WavInfo {
+    pub audio_format: u16,
+    pub interleaved_channels: u16,
+    pub frequency: u32,
+    pub bits_per_sample: u16,
+    pub data: Range<usize>,
+}
+
+impl WavInfo {
+    pub fn parse(s: &[u8]) -> Result<Self, String> {
+        if s.len() < 12 {
+            return Err("Not a .wav (too short)".to_string());
+        }
         let mut decoder = ByteDecoder::new(s);
         decoder.check_bytes(b"RIFF")?;
         let _file_size_minus_8 = decoder.decode_u32_le();
         decoder.check_bytes(b"WAVE")?;
 
-        decoder.check_bytes(b"fmt ")?;
-        let _chunk_size_minus_8 = decoder.decode_u32_le();
-        let audio_format = decoder.decode_u16_le();
-        let interleaved_channels = decoder.decode_u16_le();
-        let frequency = decoder.decode_u32_le();
-        let _byte_per_sec = decoder.decode_u32_le();
-        let _byte_per_chunk = decoder.decode_u16_le();
-        let bits_per_sample = decoder.decode_u16_le();
-
-        // ignore optional list chunk
-        if let Ok(()) = decoder.check_bytes(b"LIST") {
-            let list_chunk_size_minus_8 = decoder.decode_u32_le();
-            decoder.cursor += list_chunk_size_minus_8 as usize;
+        // chunks can come in any order, the unknown ones are skipped
+        let mut format = None;
+        let mut data = None;
+        while decoder.cursor + 8 <= s.len() {
+            let id = &s[decoder.cursor..decoder.cursor + 4];
+            decoder.cursor += 4;
+            let size = decoder.decode_u32_le() as usize;
+            let start = decoder.cursor;
+            match id {
+                b"fmt " => {
+                    if size < 16 || start + 16 > s.len() {
+                        return Err(format!("Truncated .wav fmt chunk ({size} bytes)"));
+                    }
+                    let mut audio_format = decoder.decode_u16_le();
+                    let interleaved_channels = decoder.decode_u16_le();
+                    let frequency = decoder.decode_u32_le();
+                    let _byte_per_sec = decoder.decode_u32_le();
+                    let _byte_per_chunk = decoder.decode_u16_le();
+                    let bits_per_sample = decoder.decode_u16_le();
+                    // WAVE_FORMAT_EXTENSIBLE, the format is the start of the subformat guid
+                    if audio_format == 0xFFFE && size >= 26 && start + 26 <= s.len() {
+                        decoder.cursor = start + 24;
+                        audio_format = decoder.decode_u16_le();
+                    }
+                    format = Some((
+                        audio_format,
+                        interleaved_channels,
+                        frequency,
+                        bits_per_sample,
+                    ));
+                }
+                b"data" => data = Some(start..(start + size).min(s.len())),
+                _ => {}
+            }
+            // chunks are padded to an even size
+            decoder.cursor = start + size + size % 2;
+        }
+
+        let (audio_format, interleaved_channels, frequency, bits_per_sample) =
+            format.ok_or("Not a .wav (no fmt chunk)")?;
+        let data = data.ok_or("Not a .wav (no data chunk)")?;
+        match (audio_format, bits_per_sample) {
+            (1, 8 | 16 | 24 | 32) | (3, 32 | 64) => {}
+            _ => {
+                return Err(format!(
+                    "Unsupported .wav format: {bits_per_sample} bit {}",
+                    match audio_format {
+                        1 => "pcm".to_string(),
+                        3 => "float".to_string(),
+                        other => format!("format {other}"),
+                    }
+                ))
+            }
+        }
+        if interleaved_channels == 0 || frequency == 0 {
+            return Err(format!(
+                "Invalid .wav: {interleaved_channels} channels at {frequency} Hz"
+            ));
         }
 
-        decoder.check_bytes(b"data")?;
-        let data_size = decoder.decode_u32_le();
+        Ok(Self {
+            audio_format,
+            interleaved_channels,
+            frequency,
+            bits_per_sample,
+            data,
+        })
+    }
+
+    pub fn frames(&self) -> usize {
+        self.data.len() / (self.bits_per_sample as usize / 8) / self.interleaved_channels as usize
+    }
 
-        let mut samples: Vec<f32> = vec![];
-        if bits_per_sample == 16 && audio_format == 1 {
-            for _i in 0..(data_size / (bits_per_sample as u32 / 8)) {
-                let sample = decoder.decode_i16_le();
-                samples.push((sample as f32) / 32768.);
+    /// A sample as a float between -1 and 1
+    fn sample(&self, s: &[u8], frame: usize, channel: usize) -> f32 {
+        let size = self.bits_per_sample as usize / 8;
+        let i = self.data.start + (frame * self.interleaved_channels as usize + channel) * size;
+        let bytes = &s[i..i + size];
+        match (self.audio_format, self.bits_per_sample) {
+            // 8 bit is unsigned
+            (1, 8) => (bytes[0] as f32 - 128.) / 128.,
+            (1, 16) => i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 32768.,
+            (1, 24) => i32::from_le_bytes([0, bytes[0], bytes[1], bytes[2]]) as f32 / 2147483648.,
+            (1, 32) => i32::from_le_bytes(bytes.try_into().unwrap()) as f32 / 2147483648.,
+            (3, 32) => f32::from_le_bytes(bytes.try_into().unwrap()),
+            (3, 64) => f64::from_le_bytes(bytes.try_into().unwrap()) as f32,
+            _ => 0.,
+        }
+    }
+
+    /// Left and right of a frame, more channels are mixed down
+    pub fn frame(&self, s: &[u8], frame: usize) -> [f32; 2] {
+        match self.interleaved_channels {
+            1 => [self.sample(s, frame, 0); 2],
+            2 => [self.sample(s, frame, 0), self.sample(s, frame, 1)],
+            channels => {
+                // front left and right, then center in both and the rest alternating,
+                // the low frequency channel is dropped
+                let mut mix = [0.; 2];
+                let mut weights = [0.; 2];
+                for channel in 0..channels as usize {
+                    let (sides, weight): (&[usize], f32) = match channel {
+                        0 => (&[0], 1.),
+                        1 => (&[1], 1.),
+                        2 => (&[0, 1], 0.7),
+                        3 => (&[], 0.),
+                        c => (&[c % 2], 0.7),
+                    };
+                    let sample = self.sample(s, frame, channel);
+                    for &side in sides {
+                        mix[side] += sample * weight;
+                        weights[side] += weight;
+                    }
+                }
+                [mix[0] / weights[0], mix[1] / weights[1]]
             }
         }
+    }
+}
 
-        if interleaved_channels == 1 {
-            // mono to stereo
-            samples = samples
-                .into_iter()
-                .flat_map(|sample| [sample, sample])
-                .collect();
+impl AudioPcm {
+    pub fn from_wav(s: &[u8]) -> Result<Self, String> {
+        let info = WavInfo::parse(s)?;
+        let samples = (0..info.frames())
+            .flat_map(|frame| info.frame(s, frame))
+            .collect();
+        Ok(Self {
+            samples,
+            frequency: info.frequency,
+        })
+    }
+
+    pub fn from_ogg(s: &[u8]) -> Result<Self, String> {
+        let (samples, frequency) = decode_ogg(s)?;
+        Ok(Self { samples, frequency })
+    }
+
+    /// The samples at another frequency, interpolated linearly
+    pub fn resampled(&self, frequency: u32) -> Vec<f32> {
+        if frequency == self.frequency || self.samples.len() < 4 {
+            return self.samples.clone();
         }
+        let frames = self.samples.len() / 2;
+        let step = self.frequency as f64 / frequency as f64;
+        let new_frames = ((frames - 1) as f64 / step) as usize + 1;
+        let mut resampled = Vec::with_capacity(new_frames * 2);
+        for n in 0..new_frames {
+            let position = n as f64 * step;
+            let index = (position as usize).min(frames - 1);
+            let next = (index + 1).min(frames - 1);
+            let t = (position - index as f64) as f32;
+            for channel in 0..2 {
+                let a = self.samples[index * 2 + channel];
+                let b = self.samples[next * 2 + channel];
+                resampled.push(a + (b - a) * t);
+            }
+        }
+        resampled
+    }
+}
 
-        // TODO: move this resampling downstream when i know to what frequency to resample
-        // stupid nearest-neighbor resampler taken from quad_snd
-        if frequency != 48000 {
-            let mut new_length =
-                ((48000 as f32 / frequency as f32) * samples.len() as f32) as usize;
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MusicFormat {
+    Wav,
+    Ogg,
+}
 
-            // `new_length` must be an even number
-            new_length -= new_length % 2;
+/// Music kept as the bytes of the file, decoded while playing
+#[derive(Debug, Clone)]
+pub struct MusicData {
+    pub bytes: Arc<[u8]>,
+    pub format: MusicFormat,
+}
 
-            let mut resampled = vec![0.0; new_length];
+impl MusicData {
+    pub fn from_wav(s: &[u8]) -> Result<Self, String> {
+        WavInfo::parse(s)?;
+        Ok(Self {
+            bytes: s.into(),
+            format: MusicFormat::Wav,
+        })
+    }
 
-            for (n, sample) in resampled.chunks_exact_mut(2).enumerate() {
-                let ix = 2 * ((n as f32 / new_length as f32) * samples.len() as f32) as usize;
-                sample[0] = samples[ix];
-                sample[1] = samples[ix + 1];
+    pub fn from_ogg(s: &[u8]) -> Result<Self, String> {
+        // check the headers now rather than when it starts playing
+        open_ogg(s)?;
+        Ok(Self {
+            bytes: s.into(),
+            format: MusicFormat::Ogg,
+        })
+    }
+
+    /// Decoder resampling to the output `frequency`
+    pub fn stream(&self, frequency: u32) -> Result<Box<dyn MusicSource>, String> {
+        Ok(match self.format {
+            MusicFormat::Wav => Box::new(WavStream::new(self.bytes.clone(), frequency)?),
+            MusicFormat::Ogg => Box::new(OggStream::new(self.bytes.clone(), frequency)?),
+        })
+    }
+}
+
+/// Reads the samples of a .wav on demand, resampling linearly
+pub struct WavStream {
+    bytes: Arc<[u8]>,
+    info: WavInfo,
+    frames: usize,
+    // in source frames
+    position: f64,
+    output_frequency: u32,
+}
+
+impl WavStream {
+    pub fn new(bytes: Arc<[u8]>, output_frequency: u32) -> Result<Self, String> {
+        let info = WavInfo::parse(&bytes)?;
+        let frames = info.frames();
+        Ok(Self {
+            bytes,
+            info,
+            frames,
+            position: 0.,
+            output_frequency,
+        })
+    }
+}
+
+impl MusicSource for WavStream {
+    fn read(&mut self, out: &mut [f32]) -> usize {
+        let step = self.info.frequency as f64 / self.output_frequency as f64;
+        let mut written = 0;
+        for frame in out.chunks_exact_mut(2) {
+            let index = self.position as usize;
+            if index >= self.frames {
+                break;
+            }
+            let next = (index + 1).min(self.frames - 1);
+            let t = (self.position - index as f64) as f32;
+            let a = self.info.frame(&self.bytes, index);
+            let b = self.info.frame(&self.bytes, next);
+            for (channel, sample) in frame.iter_mut().enumerate() {
+                *sample = a[channel] + (b[channel] - a[channel]) * t;
+            }
+            self.position += step;
+            written += 1;
+        }
+        written
+    }
+
+    fn rewind(&mut self) {
+        self.position = 0.;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::RandLCG;
+
+    /// A .wav of the samples in -1..1, with an unknown chunk before the data
+    fn encode_wav(samples: &[f32], channels: u16, audio_format: u16, bits: u16) -> Vec<u8> {
+        let mut data = vec![];
+        for sample in samples {
+            let int = |scale: f32| (sample * scale).round().clamp(-scale, scale - 1.) as i64;
+            match (audio_format, bits) {
+                (1, 8) => data.push((int(128.) + 128) as u8),
+                (1, 16) => data.extend((int(32768.) as i16).to_le_bytes()),
+                (1, 24) => data.extend(&(int(8388608.) as i32).to_le_bytes()[..3]),
+                (1, 32) => data.extend((int(2147483648.) as i32).to_le_bytes()),
+                (3, 32) => data.extend(sample.to_le_bytes()),
+                _ => data.extend((*sample as f64).to_le_bytes()),
             }
-            return Ok(Self { samples: resampled });
         }
+        let block = channels * bits / 8;
+        let mut fmt = vec![];
+        fmt.extend(audio_format.to_le_bytes());
+        fmt.extend(channels.to_le_bytes());
+        fmt.extend(22050u32.to_le_bytes());
+        fmt.extend((22050 * block as u32).to_le_bytes());
+        fmt.extend(block.to_le_bytes());
+        fmt.extend(bits.to_le_bytes());
+
+        let mut wav = b"RIFF\0\0\0\0WAVE".to_vec();
+        for (id, chunk) in [(b"fmt ", &fmt), (b"junk", &vec![1; 5]), (b"data", &data)] {
+            wav.extend(id);
+            wav.extend((chunk.len() as u32).to_le_bytes());
+            wav.extend(chunk);
+            if chunk.len() % 2 == 1 {
+                wav.push(0);
+            }
+        }
+        let size = wav.len() as u32 - 8;
+        wav[4..8].copy_from_slice(&size.to_le_bytes());
+        wav
+    }
+
+    const FORMATS: [(u16, u16); 6] = [(1, 8), (1, 16), (1, 24), (1, 32), (3, 32), (3, 64)];
+
+    #[test]
+    fn random_wavs_round_trip() {
+        let mut rand = RandLCG::new(11);
+        for _ in 0..100 {
+            let (audio_format, bits) = FORMATS[rand.next_u32() as usize % FORMATS.len()];
+            let channels = 1 + rand.next_u32() as u16 % 2;
+            let frames = rand.next_u32() as usize % 200;
+            let samples: Vec<f32> = (0..frames * channels as usize)
+                .map(|_| rand.next_f32() * 2. - 1.)
+                .collect();
+            let wav = encode_wav(&samples, channels, audio_format, bits);
+            let pcm = AudioPcm::from_wav(&wav).unwrap();
+            assert_eq!(pcm.frequency, 22050);
+            assert_eq!(pcm.samples.len(), frames * 2);
+            // a step of the integer formats, or what an f32 keeps of it
+            let tolerance = match audio_format {
+                1 => (1. / (1u64 << (bits - 1)) as f32).max(f32::EPSILON),
+                _ => 0.,
+            };
+            for (frame, decoded) in pcm.samples.chunks_exact(2).enumerate() {
+                let source = &samples[frame * channels as usize..][..channels as usize];
+                let expected = [source[0], *source.last().unwrap()];
+                for (decoded, expected) in decoded.iter().zip(expected) {
+                    assert!(
+                        (decoded - expected).abs() <= tolerance,
+                        "{bits} bit format {audio_format}: {decoded} for {expected}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn truncated_never_panics() {
+        let mut rand = RandLCG::new(13);
+        for (audio_format, bits) in FORMATS {
+            let samples: Vec<f32> = (0..21).map(|_| rand.next_f32() * 2. - 1.).collect();
+            let wav = encode_wav(&samples, 3, audio_format, bits);
+            for end in 0..wav.len() {
+                if let Ok(pcm) = AudioPcm::from_wav(&wav[..end]) {
+                    assert!(pcm.samples.len() <= 14);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn unsigned_8_bit_fixture() {
+        let pcm = AudioPcm::from_wav(include_bytes!("fixtures/mono_u8.wav")).unwrap();
+        assert_eq!(pcm.frequency, 8000);
+        let expected = [-1., -0.5, 0., 0.5, 127. / 128.];
+        let left: Vec<f32> = pcm.samples.iter().step_by(2).copied().collect();
+        let right: Vec<f32> = pcm.samples.iter().skip(1).step_by(2).copied().collect();
+        assert_eq!(left, expected);
+        assert_eq!(right, expected);
+    }
 
-        Ok(Self { samples })
+    #[test]
+    fn extensible_fixture() {
+        // WAVE_FORMAT_EXTENSIBLE, then a chunk of 3 bytes and its padding before the data
+        let wav = include_bytes!("fixtures/stereo_s16_extensible.wav");
+        let info = WavInfo::parse(wav).unwrap();
+        assert_eq!(info.audio_format, 1);
+        assert_eq!(info.frames(), 3);
+        let pcm = AudioPcm::from_wav(wav).unwrap();
+        assert_eq!(pcm.frequency, 44100);
+        let expected = [-32768, 32767, 0, 16384, -1, 1].map(|v| v as f32 / 32768.);
+        assert_eq!(pcm.samples, expected);
     }
 }
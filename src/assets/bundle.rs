@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+
+use crate::{crc32, zlib_inflate};
+
+/// Name of the bundle, next to the executable or built into it
+pub const BUNDLE_FILE: &str = "assets.bundle";
+
+const MAGIC: &[u8; 4] = b"TBDL";
+const VERSION: u32 = 1;
+
+/// The files of `ASSET_FOLDER` packed into one archive, built by `asset_bundle.rs`.
+/// Each file is a deflate stream, the index table at the start maps the paths to them.
+#[derive(Debug)]
+pub struct AssetBundle {
+    data: Vec<u8>,
+    entries: HashMap<String, BundleEntry>,
+}
+
+#[derive(Clone, Debug)]
+struct BundleEntry {
+    /// Range of `AssetBundle::data`
+    offset: usize,
+    compressed_size: usize,
+    size: usize,
+    crc: u32,
+}
+
+impl AssetBundle {
+    pub fn parse(data: Vec<u8>) -> Result<Self, String> {
+        let mut cursor = 0;
+        let mut next = |len: usize| -> Result<&[u8], String> {
+            let bytes = data
+                .get(cursor..cursor + len)
+                .ok_or(format!("Bundle: truncated index at byte {}", cursor))?;
+            cursor += len;
+            Ok(bytes)
+        };
+        let u32_le = |bytes: &[u8]| u32::from_le_bytes(bytes.try_into().unwrap());
+
+        if next(4)? != MAGIC {
+            return Err("Bundle: not an asset bundle".to_string());
+        }
+        let version = u32_le(next(4)?);
+        if version != VERSION {
+            return Err(format!("Bundle: unsupported version {}", version));
+        }
+        let count = u32_le(next(4)?);
+        let mut entries = HashMap::new();
+        for _ in 0..count {
+            let path_len = u16::from_le_bytes(next(2)?.try_into().unwrap());
+            let path = std::str::from_utf8(next(path_len as usize)?)
+                .map_err(|e| format!("Bundle: {}", e))?
+                .to_string();
+            let entry = BundleEntry {
+                offset: u32_le(next(4)?) as usize,
+                compressed_size: u32_le(next(4)?) as usize,
+                size: u32_le(next(4)?) as usize,
+                crc: u32_le(next(4)?),
+            };
+            entries.insert(path, entry);
+        }
+
+        // offsets start after the index
+        let data = data[cursor..].to_vec();
+        if let Some((path, _)) = entries
+            .iter()
+            .find(|(_, entry)| entry.offset + entry.compressed_size > data.len())
+        {
+            return Err(format!("Bundle: {} is past the end of the bundle", path));
+        }
+        Ok(Self { data, entries })
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        self.entries.contains_key(path)
+    }
+
+    /// Paths of the bundled files, relative to `ASSET_FOLDER`
+    pub fn paths(&self) -> impl Iterator<Item = &String> {
+        self.entries.keys()
+    }
+
+    /// Decompressed content of the file
+    pub fn read(&self, path: &str) -> Result<Vec<u8>, String> {
+        let entry = self
+            .entries
+            .get(path)
+            .ok_or(format!("Bundle: missing {}", path))?;
+        let compressed = &self.data[entry.offset..entry.offset + entry.compressed_size];
+        let content = zlib_inflate::decompress(compressed)
+            .map_err(|e| format!("Bundle: {} is corrupt: {:?}", path, e))?;
+        if content.len() != entry.size || crc32::crc32(&content) != entry.crc {
+            return Err(format!("Bundle: {} is corrupt", path));
+        }
+        Ok(content)
+    }
+
+    /// The bundle built into the binary with the `embed-assets` feature
+    #[cfg(feature = "embed-assets")]
+    pub fn find() -> Option<Self> {
+        let data = include_bytes!(concat!(env!("OUT_DIR"), "/assets.bundle"));
+        Some(Self::parse(data.to_vec()).unwrap())
+    }
+
+    /// Otherwise the `BUNDLE_FILE` next to the executable, if there is one
+    #[cfg(not(feature = "embed-assets"))]
+    pub fn find() -> Option<Self> {
+        if cfg!(any(target_arch = "wasm32", target_os = "android")) {
+            return None;
+        }
+        let path = std::env::current_exe().ok()?.with_file_name(BUNDLE_FILE);
+        let data = std::fs::read(&path).ok()?;
+        match Self::parse(data) {
+            Ok(bundle) => Some(bundle),
+            Err(error) => {
+                eprintln!("Ignoring {}: {}", path.display(), error);
+                None
+            }
+        }
+    }
+}
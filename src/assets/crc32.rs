@@ -0,0 +1,35 @@
+/// Crc-32 of the zlib, png and gzip formats
+/// https://www.w3.org/TR/2003/REC-PNG-20031110/#D-CRCAppendix
+pub fn crc32(data: &[u8]) -> u32 {
+    crc32_update(0, data)
+}
+
+/// Continues the crc of the data before `data`, starting from 0
+pub fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+    let mut c = !crc;
+    for &byte in data {
+        c = CRC_TABLE[((c ^ byte as u32) & 0xff) as usize] ^ (c >> 8);
+    }
+    !c
+}
+
+/// Crc of every byte value
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                0xedb88320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+};
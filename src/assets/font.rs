@@ -0,0 +1,843 @@
+use std::{collections::HashMap, ops::RangeInclusive};
+
+use glam::Vec2;
+
+use crate::Image;
+
+/// TrueType font, the glyph outlines are read when they are rasterized
+/// Specification: https://learn.microsoft.com/en-us/typography/opentype/spec/
+#[derive(Clone, Debug)]
+pub struct Font {
+    data: Vec<u8>,
+    /// Start of the glyf table
+    glyf: usize,
+    /// Offset of each glyph in the glyf table, one more than the glyphs
+    loca: Vec<usize>,
+    pub units_per_em: f32,
+    pub ascent: f32,
+    pub descent: f32,
+    pub line_gap: f32,
+    /// Advance width and left side bearing of each glyph
+    h_metrics: Vec<(u16, i16)>,
+    cmap: HashMap<char, u16>,
+    kerning: HashMap<(u16, u16), i16>,
+}
+
+/// Coverage of a glyph, rows from the top
+#[derive(Clone, Debug, Default)]
+pub struct GlyphBitmap {
+    pub width: usize,
+    pub height: usize,
+    /// Pixels from the pen position to the left column
+    pub left: i32,
+    /// Pixels from the baseline up to the top row
+    pub top: i32,
+    pub coverage: Vec<u8>,
+}
+
+impl Font {
+    pub fn from_ttf(s: &[u8]) -> Result<Self, String> {
+        let version = read_u32(s, 0)?;
+        if version != 0x00010000 && version != u32::from_be_bytes(*b"true") {
+            return Err(format!("Ttf: unsupported version {:#x}", version));
+        }
+        let num_tables = read_u16(s, 4)? as usize;
+        let mut tables = HashMap::new();
+        for i in 0..num_tables {
+            let record = 12 + i * 16;
+            let tag = s
+                .get(record..record + 4)
+                .ok_or("Ttf: truncated table records")?;
+            let offset = read_u32(s, record + 8)? as usize;
+            tables.insert(tag.to_vec(), offset);
+        }
+        let table = |tag: &[u8; 4]| {
+            tables.get(tag.as_slice()).copied().ok_or(format!(
+                "Ttf: missing {} table",
+                String::from_utf8_lossy(tag)
+            ))
+        };
+
+        let head = table(b"head")?;
+        let units_per_em = read_u16(s, head + 18)? as f32;
+        let long_loca = read_i16(s, head + 50)? != 0;
+        let num_glyphs = read_u16(s, table(b"maxp")? + 4)? as usize;
+
+        let hhea = table(b"hhea")?;
+        let ascent = read_i16(s, hhea + 4)? as f32;
+        let descent = read_i16(s, hhea + 6)? as f32;
+        let line_gap = read_i16(s, hhea + 8)? as f32;
+        let num_h_metrics = read_u16(s, hhea + 34)? as usize;
+
+        // glyphs after the last metric repeat its advance
+        let hmtx = table(b"hmtx")?;
+        let mut h_metrics = Vec::with_capacity(num_glyphs);
+        for i in 0..num_glyphs {
+            if i < num_h_metrics {
+                h_metrics.push((read_u16(s, hmtx + i * 4)?, read_i16(s, hmtx + i * 4 + 2)?));
+            } else {
+                let advance = h_metrics.last().map_or(0, |m: &(u16, i16)| m.0);
+                let lsb_at = hmtx + num_h_metrics * 4 + (i - num_h_metrics) * 2;
+                h_metrics.push((advance, read_i16(s, lsb_at)?));
+            }
+        }
+
+        let loca_at = table(b"loca")?;
+        let loca = (0..=num_glyphs)
+            .map(|i| match long_loca {
+                true => read_u32(s, loca_at + i * 4).map(|o| o as usize),
+                false => read_u16(s, loca_at + i * 2).map(|o| o as usize * 2),
+            })
+            .collect::<Result<Vec<usize>, String>>()?;
+
+        let cmap = parse_cmap(s, table(b"cmap")?)?;
+        let kerning = match table(b"kern") {
+            Ok(kern) => parse_kern(s, kern)?,
+            Err(_) => HashMap::new(),
+        };
+
+        Ok(Font {
+            data: s.to_vec(),
+            glyf: table(b"glyf")?,
+            loca,
+            units_per_em,
+            ascent,
+            descent,
+            line_gap,
+            h_metrics,
+            cmap,
+            kerning,
+        })
+    }
+
+    /// Glyph of the character, 0 is the missing glyph
+    pub fn glyph_index(&self, c: char) -> u16 {
+        self.cmap.get(&c).copied().unwrap_or(0)
+    }
+
+    /// Horizontal advance in font units
+    pub fn advance(&self, glyph: u16) -> f32 {
+        self.h_metrics
+            .get(glyph as usize)
+            .map_or(0., |m| m.0 as f32)
+    }
+
+    /// Adjustment of the advance between two glyphs in font units
+    pub fn kerning(&self, left: u16, right: u16) -> f32 {
+        self.kerning.get(&(left, right)).map_or(0., |&k| k as f32)
+    }
+
+    /// Kerning pairs that move the glyphs, in font units
+    pub fn kerning_pairs(&self) -> impl Iterator<Item = (&(u16, u16), &i16)> {
+        self.kerning.iter()
+    }
+
+    /// Antialiased coverage of the glyph with an em of `pixel_size` pixels
+    pub fn rasterize(&self, glyph: u16, pixel_size: f32) -> Result<GlyphBitmap, String> {
+        let scale = pixel_size / self.units_per_em;
+        let contours = self.outline(glyph, 0)?;
+        let points = contours.iter().flatten().map(|(p, _)| *p);
+        let Some((min, max)) = points.fold(None, |bounds: Option<(Vec2, Vec2)>, p| {
+            Some(bounds.map_or((p, p), |(min, max)| (min.min(p), max.max(p))))
+        }) else {
+            return Ok(GlyphBitmap::default());
+        };
+
+        // pixel rows grow downwards
+        let left = (min.x * scale).floor();
+        let top = (max.y * scale).ceil();
+        let width = ((max.x * scale).ceil() - left) as usize + 1;
+        let height = (top - (min.y * scale).floor()) as usize + 1;
+        let to_pixel = |p: Vec2| Vec2::new(p.x * scale - left, top - p.y * scale);
+
+        let mut rasterizer = Rasterizer::new(width, height);
+        for contour in contours.iter() {
+            let contour: Vec<(Vec2, bool)> =
+                contour.iter().map(|&(p, on)| (to_pixel(p), on)).collect();
+            for_each_line(&contour, |p0, p1| rasterizer.draw_line(p0, p1));
+        }
+        Ok(GlyphBitmap {
+            width,
+            height,
+            left: left as i32,
+            top: top as i32,
+            coverage: rasterizer.coverage(),
+        })
+    }
+
+    /// Contours of points in font units, with their on curve flag
+    fn outline(&self, glyph: u16, depth: u32) -> Result<Vec<Vec<(Vec2, bool)>>, String> {
+        // composite glyphs nest only a few levels in valid fonts
+        if depth > 8 {
+            return Err(format!("Ttf: glyph {} nests too deep", glyph));
+        }
+        let s = &self.data;
+        let (Some(&start), Some(&end)) = (
+            self.loca.get(glyph as usize),
+            self.loca.get(glyph as usize + 1),
+        ) else {
+            return Err(format!("Ttf: glyph {} out of range", glyph));
+        };
+        if start == end {
+            // no outline, like the space
+            return Ok(vec![]);
+        }
+        let at = self.glyf + start;
+        let num_contours = read_i16(s, at)?;
+        if num_contours >= 0 {
+            self.simple_outline(at, num_contours as usize)
+        } else {
+            self.composite_outline(at, depth)
+        }
+    }
+
+    fn simple_outline(
+        &self,
+        at: usize,
+        num_contours: usize,
+    ) -> Result<Vec<Vec<(Vec2, bool)>>, String> {
+        const ON_CURVE: u8 = 1;
+        const X_SHORT: u8 = 2;
+        const Y_SHORT: u8 = 4;
+        const REPEAT: u8 = 8;
+        const X_SAME_OR_POSITIVE: u8 = 16;
+        const Y_SAME_OR_POSITIVE: u8 = 32;
+
+        let s = &self.data;
+        let mut cursor = at + 10;
+        let mut contour_ends = vec![];
+        for _ in 0..num_contours {
+            contour_ends.push(read_u16(s, cursor)? as usize);
+            cursor += 2;
+        }
+        let num_points = contour_ends.last().map_or(0, |end| end + 1);
+        let instructions_len = read_u16(s, cursor)? as usize;
+        cursor += 2 + instructions_len;
+
+        let mut flags = Vec::with_capacity(num_points);
+        while flags.len() < num_points {
+            let flag = read_u8(s, cursor)?;
+            cursor += 1;
+            flags.push(flag);
+            if flag & REPEAT != 0 {
+                let repeat = read_u8(s, cursor)?;
+                cursor += 1;
+                flags.extend(std::iter::repeat_n(flag, repeat as usize));
+            }
+        }
+        flags.truncate(num_points);
+
+        // coordinates are deltas from the previous point
+        let mut read_coordinates = |short: u8, same_or_positive: u8| {
+            let mut value = 0_i32;
+            let mut values = Vec::with_capacity(num_points);
+            for flag in flags.iter() {
+                if flag & short != 0 {
+                    let delta = read_u8(s, cursor)? as i32;
+                    cursor += 1;
+                    value += if flag & same_or_positive != 0 {
+                        delta
+                    } else {
+                        -delta
+                    };
+                } else if flag & same_or_positive == 0 {
+                    value += read_i16(s, cursor)? as i32;
+                    cursor += 2;
+                }
+                values.push(value as f32);
+            }
+            Ok::<Vec<f32>, String>(values)
+        };
+        let xs = read_coordinates(X_SHORT, X_SAME_OR_POSITIVE)?;
+        let ys = read_coordinates(Y_SHORT, Y_SAME_OR_POSITIVE)?;
+
+        let mut contours = vec![];
+        let mut start = 0;
+        for end in contour_ends {
+            if end < start || end >= num_points {
+                return Err(format!("Ttf: contour end {} out of order", end));
+            }
+            contours.push(
+                (start..=end)
+                    .map(|i| (Vec2::new(xs[i], ys[i]), flags[i] & ON_CURVE != 0))
+                    .collect(),
+            );
+            start = end + 1;
+        }
+        Ok(contours)
+    }
+
+    /// Outlines of other glyphs, each one transformed
+    fn composite_outline(&self, at: usize, depth: u32) -> Result<Vec<Vec<(Vec2, bool)>>, String> {
+        const ARGS_ARE_WORDS: u16 = 1;
+        const ARGS_ARE_XY: u16 = 2;
+        const HAS_SCALE: u16 = 8;
+        const MORE_COMPONENTS: u16 = 0x20;
+        const HAS_XY_SCALE: u16 = 0x40;
+        const HAS_2X2: u16 = 0x80;
+
+        let s = &self.data;
+        let mut cursor = at + 10;
+        let mut contours = vec![];
+        loop {
+            let flags = read_u16(s, cursor)?;
+            let component = read_u16(s, cursor + 2)?;
+            cursor += 4;
+            let (arg1, arg2) = if flags & ARGS_ARE_WORDS != 0 {
+                cursor += 4;
+                (
+                    read_i16(s, cursor - 4)? as f32,
+                    read_i16(s, cursor - 2)? as f32,
+                )
+            } else {
+                cursor += 2;
+                (
+                    read_u8(s, cursor - 2)? as i8 as f32,
+                    read_u8(s, cursor - 1)? as i8 as f32,
+                )
+            };
+            // matching points instead of an offset are rare, the component is not moved
+            let offset = match flags & ARGS_ARE_XY != 0 {
+                true => Vec2::new(arg1, arg2),
+                false => Vec2::ZERO,
+            };
+            let f2dot14 = |at: usize| read_i16(s, at).map(|v| v as f32 / 16384.);
+            let (x_axis, y_axis) = if flags & HAS_SCALE != 0 {
+                let scale = f2dot14(cursor)?;
+                cursor += 2;
+                (Vec2::new(scale, 0.), Vec2::new(0., scale))
+            } else if flags & HAS_XY_SCALE != 0 {
+                let (x, y) = (f2dot14(cursor)?, f2dot14(cursor + 2)?);
+                cursor += 4;
+                (Vec2::new(x, 0.), Vec2::new(0., y))
+            } else if flags & HAS_2X2 != 0 {
+                let x_axis = Vec2::new(f2dot14(cursor)?, f2dot14(cursor + 2)?);
+                let y_axis = Vec2::new(f2dot14(cursor + 4)?, f2dot14(cursor + 6)?);
+                cursor += 8;
+                (x_axis, y_axis)
+            } else {
+                (Vec2::X, Vec2::Y)
+            };
+
+            for contour in self.outline(component, depth + 1)? {
+                contours.push(
+                    contour
+                        .into_iter()
+                        .map(|(p, on)| (x_axis * p.x + y_axis * p.y + offset, on))
+                        .collect(),
+                );
+            }
+            if flags & MORE_COMPONENTS == 0 {
+                break;
+            }
+        }
+        Ok(contours)
+    }
+}
+
+/// Unicode to glyph mapping, from the formats 4 and 12 subtables
+fn parse_cmap(s: &[u8], cmap: usize) -> Result<HashMap<char, u16>, String> {
+    let num_tables = read_u16(s, cmap + 2)? as usize;
+    let mut subtables = vec![];
+    for i in 0..num_tables {
+        let record = cmap + 4 + i * 8;
+        let platform = read_u16(s, record)?;
+        let encoding = read_u16(s, record + 2)?;
+        let at = cmap + read_u32(s, record + 4)? as usize;
+        // unicode platform, or windows unicode bmp and full repertoire
+        if platform == 0 || (platform == 3 && (encoding == 1 || encoding == 10)) {
+            subtables.push((read_u16(s, at)?, at));
+        }
+    }
+    // the full repertoire is a superset of the bmp
+    subtables.sort_by_key(|(format, _)| std::cmp::Reverse(*format == 12));
+
+    let mut map = HashMap::new();
+    match subtables.first() {
+        Some(&(12, at)) => {
+            let groups = read_u32(s, at + 12)? as usize;
+            for i in 0..groups {
+                let group = at + 16 + i * 12;
+                let (first, last) = (read_u32(s, group)?, read_u32(s, group + 4)?);
+                let first_glyph = read_u32(s, group + 8)?;
+                for code in first..=last.min(0x10ffff) {
+                    if let Some(c) = char::from_u32(code) {
+                        map.insert(c, (first_glyph + code - first) as u16);
+                    }
+                }
+            }
+        }
+        Some(&(4, at)) => {
+            let segments = read_u16(s, at + 6)? as usize / 2;
+            let ends = at + 14;
+            let starts = ends + segments * 2 + 2;
+            let deltas = starts + segments * 2;
+            let range_offsets = deltas + segments * 2;
+            for i in 0..segments {
+                let end = read_u16(s, ends + i * 2)?;
+                let start = read_u16(s, starts + i * 2)?;
+                let delta = read_u16(s, deltas + i * 2)?;
+                let range_offset_at = range_offsets + i * 2;
+                let range_offset = read_u16(s, range_offset_at)? as usize;
+                for code in start..=end {
+                    if code == 0xffff {
+                        break;
+                    }
+                    let glyph = if range_offset == 0 {
+                        code.wrapping_add(delta)
+                    } else {
+                        let at = range_offset_at + range_offset + (code - start) as usize * 2;
+                        match read_u16(s, at)? {
+                            0 => 0,
+                            glyph => glyph.wrapping_add(delta),
+                        }
+                    };
+                    if let Some(c) = char::from_u32(code as u32) {
+                        map.insert(c, glyph);
+                    }
+                }
+            }
+        }
+        Some((format, _)) => return Err(format!("Ttf: unsupported cmap format {}", format)),
+        None => return Err("Ttf: no unicode cmap".to_string()),
+    }
+    Ok(map)
+}
+
+/// Pairs of the horizontal format 0 subtables
+fn parse_kern(s: &[u8], kern: usize) -> Result<HashMap<(u16, u16), i16>, String> {
+    let mut pairs = HashMap::new();
+    let num_tables = read_u16(s, kern + 2)?;
+    let mut at = kern + 4;
+    for _ in 0..num_tables {
+        let length = read_u16(s, at + 2)? as usize;
+        let coverage = read_u16(s, at + 4)?;
+        let horizontal = coverage & 1 != 0;
+        let format = coverage >> 8;
+        if horizontal && format == 0 {
+            let num_pairs = read_u16(s, at + 6)? as usize;
+            for i in 0..num_pairs {
+                let pair = at + 14 + i * 6;
+                let left = read_u16(s, pair)?;
+                let right = read_u16(s, pair + 2)?;
+                pairs.insert((left, right), read_i16(s, pair + 4)?);
+            }
+        }
+        at += length;
+    }
+    Ok(pairs)
+}
+
+/// Calls `line` for every segment of the contour, curves are flattened
+fn for_each_line(contour: &[(Vec2, bool)], mut line: impl FnMut(Vec2, Vec2)) {
+    let n = contour.len();
+    if n < 2 {
+        return;
+    }
+    let mut quad = |p0: Vec2, control: Vec2, p1: Vec2| {
+        // about a segment every few pixels
+        let length = p0.distance(control) + control.distance(p1);
+        let segments = (length / 3.).ceil().clamp(1., 16.) as usize;
+        let mut previous = p0;
+        for i in 1..=segments {
+            let t = i as f32 / segments as f32;
+            let next = p0.lerp(control, t).lerp(control.lerp(p1, t), t);
+            line(previous, next);
+            previous = next;
+        }
+    };
+
+    // starts on a point on the curve, or between two control points if there are none
+    let (first, start) = match contour.iter().position(|(_, on)| *on) {
+        Some(i) => (i + 1, contour[i].0),
+        None => (0, (contour[n - 1].0 + contour[0].0) * 0.5),
+    };
+    let mut current = start;
+    let mut control: Option<Vec2> = None;
+    for k in first..first + n {
+        let (p, on) = contour[k % n];
+        match (on, control) {
+            (true, Some(c)) => {
+                quad(current, c, p);
+                current = p;
+                control = None;
+            }
+            (true, None) => {
+                quad(current, current, p);
+                current = p;
+            }
+            (false, Some(c)) => {
+                let middle = (c + p) * 0.5;
+                quad(current, c, middle);
+                current = middle;
+                control = Some(p);
+            }
+            (false, None) => control = Some(p),
+        }
+    }
+    match control {
+        Some(c) => quad(current, c, start),
+        None => quad(current, current, start),
+    }
+}
+
+/// Signed area accumulation, the coverage of a pixel is the sum of the row before it
+/// Algorithm: https://medium.com/@raphlinus/inside-the-fastest-font-renderer-in-the-world-75ae5270c445
+struct Rasterizer {
+    width: usize,
+    height: usize,
+    accumulation: Vec<f32>,
+}
+
+impl Rasterizer {
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            accumulation: vec![0.; width * height + 4],
+        }
+    }
+
+    fn draw_line(&mut self, p0: Vec2, p1: Vec2) {
+        if (p0.y - p1.y).abs() <= f32::EPSILON {
+            return;
+        }
+        let (direction, p0, p1) = if p0.y < p1.y {
+            (1., p0, p1)
+        } else {
+            (-1., p1, p0)
+        };
+        let dxdy = (p1.x - p0.x) / (p1.y - p0.y);
+        let mut x = p0.x;
+        if p0.y < 0. {
+            x -= p0.y * dxdy;
+        }
+        let y_start = p0.y.max(0.) as usize;
+        let y_end = self.height.min(p1.y.ceil() as usize);
+        for y in y_start..y_end {
+            let row = y * self.width;
+            let dy = ((y + 1) as f32).min(p1.y) - (y as f32).max(p0.y);
+            let x_next = x + dxdy * dy;
+            let d = dy * direction;
+            let (x0, x1) = if x < x_next { (x, x_next) } else { (x_next, x) };
+            let x0_floor = x0.floor();
+            let x0i = x0_floor.max(0.) as usize;
+            let x1_ceil = x1.ceil();
+            let x1i = x1_ceil.max(0.) as usize;
+            if x1i <= x0i + 1 {
+                // the line stays in a pixel
+                let xmf = 0.5 * (x + x_next) - x0_floor;
+                self.accumulation[row + x0i] += d - d * xmf;
+                self.accumulation[row + x0i + 1] += d * xmf;
+            } else {
+                let s = (x1 - x0).recip();
+                let x0f = x0 - x0_floor;
+                let a0 = 0.5 * s * (1. - x0f) * (1. - x0f);
+                let x1f = x1 - x1_ceil + 1.;
+                let am = 0.5 * s * x1f * x1f;
+                self.accumulation[row + x0i] += d * a0;
+                if x1i == x0i + 2 {
+                    self.accumulation[row + x0i + 1] += d * (1. - a0 - am);
+                } else {
+                    let a1 = s * (1.5 - x0f);
+                    self.accumulation[row + x0i + 1] += d * (a1 - a0);
+                    for xi in x0i + 2..x1i - 1 {
+                        self.accumulation[row + xi] += d * s;
+                    }
+                    let a2 = a1 + (x1i - x0i - 3) as f32 * s;
+                    self.accumulation[row + x1i - 1] += d * (1. - a2 - am);
+                }
+                self.accumulation[row + x1i] += d * am;
+            }
+            x = x_next;
+        }
+    }
+
+    fn coverage(&self) -> Vec<u8> {
+        let mut sum = 0.;
+        self.accumulation[..self.width * self.height]
+            .iter()
+            .map(|a| {
+                sum += a;
+                (sum.abs().min(1.) * 255.).round() as u8
+            })
+            .collect()
+    }
+}
+
+/// Glyphs rasterized at one size into an image, scaled when drawn
+#[derive(Clone, Debug, Default)]
+pub struct FontAtlas {
+    /// Em size the glyphs were rasterized at
+    pub pixel_size: f32,
+    pub image_size: Vec2,
+    /// From the top of a line to the baseline, in atlas pixels
+    pub ascent: f32,
+    pub line_height: f32,
+    pub glyphs: HashMap<char, AtlasGlyph>,
+    /// The missing glyph of the font, usually a box
+    pub missing: AtlasGlyph,
+    pub kerning: HashMap<(char, char), f32>,
+}
+
+/// A glyph in the atlas, in atlas pixels
+#[derive(Clone, Debug, Default)]
+pub struct AtlasGlyph {
+    pub atlas_pos: Vec2,
+    pub size: Vec2,
+    /// From the pen position on the baseline to the top left corner
+    pub offset: Vec2,
+    pub advance: f32,
+}
+
+impl FontAtlas {
+    /// Ascii, latin-1, latin extended-a and the common punctuation
+    pub const DEFAULT_CHARS: [RangeInclusive<char>; 3] =
+        [' '..='~', '\u{a0}'..='\u{17f}', '\u{2010}'..='\u{2027}'];
+    const WIDTH: usize = 1024;
+    /// Empty pixels around each glyph, so that filtering doesn't bleed the neighbours
+    const PADDING: usize = 2;
+
+    /// Rasterizes the characters and packs them in rows, white with the coverage as alpha.
+    /// Characters that the font doesn't have are left out, they are drawn as `missing`.
+    pub fn bake(
+        font: &Font,
+        pixel_size: f32,
+        chars: impl Iterator<Item = char>,
+    ) -> Result<(Self, Image), String> {
+        let scale = pixel_size / font.units_per_em;
+        let mut bitmaps = vec![(None, 0, font.rasterize(0, pixel_size)?)];
+        for c in chars {
+            let glyph = font.glyph_index(c);
+            if glyph != 0 {
+                bitmaps.push((Some(c), glyph, font.rasterize(glyph, pixel_size)?));
+            }
+        }
+
+        // shelf packing, the tallest glyphs first so that the rows are even
+        let mut order: Vec<usize> = (0..bitmaps.len()).collect();
+        order.sort_by_key(|&i| std::cmp::Reverse(bitmaps[i].2.height));
+        let mut positions = vec![(0, 0); bitmaps.len()];
+        let (mut x, mut y, mut row_height) = (Self::PADDING, Self::PADDING, 0);
+        for i in order {
+            let bitmap = &bitmaps[i].2;
+            if bitmap.width + Self::PADDING * 2 > Self::WIDTH {
+                return Err(format!("Font: glyph of {:?} is too big", bitmaps[i].0));
+            }
+            if x + bitmap.width + Self::PADDING > Self::WIDTH {
+                x = Self::PADDING;
+                y += row_height + Self::PADDING;
+                row_height = 0;
+            }
+            positions[i] = (x, y);
+            x += bitmap.width + Self::PADDING;
+            row_height = row_height.max(bitmap.height);
+        }
+        let height = (y + row_height + Self::PADDING).next_power_of_two();
+
+        let mut raw = vec![255; Self::WIDTH * height * 4];
+        for px in raw.chunks_exact_mut(4) {
+            px[3] = 0;
+        }
+        let mut glyphs = HashMap::new();
+        let mut missing = AtlasGlyph::default();
+        for ((c, glyph, bitmap), (x, y)) in bitmaps.iter().zip(positions) {
+            for row in 0..bitmap.height {
+                for column in 0..bitmap.width {
+                    let alpha = bitmap.coverage[row * bitmap.width + column];
+                    raw[((y + row) * Self::WIDTH + x + column) * 4 + 3] = alpha;
+                }
+            }
+            let atlas_glyph = AtlasGlyph {
+                atlas_pos: Vec2::new(x as f32, y as f32),
+                size: Vec2::new(bitmap.width as f32, bitmap.height as f32),
+                offset: Vec2::new(bitmap.left as f32, -bitmap.top as f32),
+                advance: font.advance(*glyph) * scale,
+            };
+            match c {
+                Some(c) => {
+                    glyphs.insert(*c, atlas_glyph);
+                }
+                None => missing = atlas_glyph,
+            }
+        }
+
+        let glyph_chars: HashMap<u16, char> = bitmaps
+            .iter()
+            .filter_map(|(c, g, _)| Some((*g, (*c)?)))
+            .collect();
+        let kerning = font
+            .kerning_pairs()
+            .filter_map(|((left, right), k)| {
+                let pair = (*glyph_chars.get(left)?, *glyph_chars.get(right)?);
+                Some((pair, *k as f32 * scale))
+            })
+            .collect();
+
+        let atlas = FontAtlas {
+            pixel_size,
+            image_size: Vec2::new(Self::WIDTH as f32, height as f32),
+            ascent: font.ascent * scale,
+            line_height: (font.ascent - font.descent + font.line_gap) * scale,
+            glyphs,
+            missing,
+            kerning,
+        };
+        let image = Image {
+            width: Self::WIDTH as u32,
+            height: height as u32,
+            raw,
+        };
+        Ok((atlas, image))
+    }
+
+    /// The glyph drawn for a character: its own, the one of its base letter, or the missing one
+    pub fn glyph(&self, c: char) -> &AtlasGlyph {
+        self.glyphs
+            .get(&c)
+            .or_else(|| self.glyphs.get(&fallback_char(c)?))
+            .unwrap_or(&self.missing)
+    }
+}
+
+/// Characters that look close enough to others, like the letters with diacritics
+const FALLBACKS: &[(&str, char)] = &[
+    ("ÀÁÂÃÄÅĀĂĄ", 'A'),
+    ("àáâãäåāăą", 'a'),
+    ("ÇĆĈĊČ", 'C'),
+    ("çćĉċč", 'c'),
+    ("ĎĐÐ", 'D'),
+    ("ďđ", 'd'),
+    ("ÈÉÊËĒĔĖĘĚ", 'E'),
+    ("èéêëēĕėęě", 'e'),
+    ("ĜĞĠĢ", 'G'),
+    ("ĝğġģ", 'g'),
+    ("ĤĦ", 'H'),
+    ("ĥħ", 'h'),
+    ("ÌÍÎÏĨĪĬĮİ", 'I'),
+    ("ìíîïĩīĭįı", 'i'),
+    ("Ĵ", 'J'),
+    ("ĵ", 'j'),
+    ("Ķ", 'K'),
+    ("ķ", 'k'),
+    ("ĹĻĽĿŁ", 'L'),
+    ("ĺļľŀł", 'l'),
+    ("ÑŃŅŇ", 'N'),
+    ("ñńņň", 'n'),
+    ("ÒÓÔÕÖØŌŎŐ", 'O'),
+    ("òóôõöøōŏő", 'o'),
+    ("ŔŖŘ", 'R'),
+    ("ŕŗř", 'r'),
+    ("ŚŜŞŠ", 'S'),
+    ("śŝşšß", 's'),
+    ("ŢŤŦ", 'T'),
+    ("ţťŧ", 't'),
+    ("ÙÚÛÜŨŪŬŮŰŲ", 'U'),
+    ("ùúûüũūŭůűų", 'u'),
+    ("Ŵ", 'W'),
+    ("ŵ", 'w'),
+    ("ÝŶŸ", 'Y'),
+    ("ýÿŷ", 'y'),
+    ("ŹŻŽ", 'Z'),
+    ("źżž", 'z'),
+    ("‘’‚‛′`", '\''),
+    ("“”„‟″«»", '"'),
+    ("‐‑‒–—―−", '-'),
+    ("×", 'x'),
+    ("\u{a0}\u{2007}\u{202f}", ' '),
+];
+
+/// Similar looking character for fonts that don't have `c`
+pub fn fallback_char(c: char) -> Option<char> {
+    FALLBACKS
+        .iter()
+        .find(|(from, _)| from.contains(c))
+        .map(|(_, to)| *to)
+}
+
+fn read_u8(s: &[u8], at: usize) -> Result<u8, String> {
+    s.get(at)
+        .copied()
+        .ok_or(format!("Ttf: truncated at byte {}", at))
+}
+
+fn read_u16(s: &[u8], at: usize) -> Result<u16, String> {
+    let bytes = s
+        .get(at..at + 2)
+        .ok_or(format!("Ttf: truncated at byte {}", at))?;
+    Ok(u16::from_be_bytes(bytes.try_into().unwrap()))
+}
+
+fn read_i16(s: &[u8], at: usize) -> Result<i16, String> {
+    read_u16(s, at).map(|v| v as i16)
+}
+
+fn read_u32(s: &[u8], at: usize) -> Result<u32, String> {
+    let bytes = s
+        .get(at..at + 4)
+        .ok_or(format!("Ttf: truncated at byte {}", at))?;
+    Ok(u32::from_be_bytes(bytes.try_into().unwrap()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // An em of 1000 units: the missing glyph is a 400x600 box with a 50 unit border,
+    // 'A' a 500 unit square, 'B' two 'A' at half scale on a diagonal, composite,
+    // and 'O' a square turned into a circle of four quadratic curves, 'Ω' is drawn with it
+    const SQUARES: &[u8] = include_bytes!("fixtures/squares.ttf");
+
+    /// Pixels covered, partly covered ones count by their coverage
+    fn area(bitmap: &GlyphBitmap) -> f32 {
+        bitmap.coverage.iter().map(|c| *c as f32 / 255.).sum()
+    }
+
+    #[test]
+    fn cmap_lookup() {
+        let font = Font::from_ttf(SQUARES).unwrap();
+        assert_eq!(font.units_per_em, 1000.);
+        assert_eq!((font.ascent, font.descent), (800., -200.));
+        let glyphs = ['A', 'B', 'O', 'Ω', 'C', 'Z', 'é'].map(|c| font.glyph_index(c));
+        assert_eq!(glyphs, [1, 2, 3, 3, 0, 0, 0]);
+        assert_eq!(font.advance(0), 450.);
+        assert_eq!(font.advance(1), 600.);
+    }
+
+    #[test]
+    fn rasterized_coverage() {
+        let font = Font::from_ttf(SQUARES).unwrap();
+        // 10 pixels every 100 units
+        let square = font.rasterize(1, 100.).unwrap();
+        assert_eq!((square.left, square.top), (0, 50));
+        assert!((area(&square) - 2500.).abs() < 1.);
+        let inside = square.coverage[25 * square.width + 25];
+        assert_eq!(inside, 255);
+        // the hole of the box isn't covered
+        let missing = font.rasterize(0, 100.).unwrap();
+        assert!((area(&missing) - 900.).abs() < 1.);
+        assert_eq!(missing.coverage[30 * missing.width + 20], 0);
+        // a diamond and the four curves past its sides, 2/3 of their triangle each
+        let circle = font.rasterize(3, 100.).unwrap();
+        let expected = 1250. + 4. * 2. / 3. * 312.5;
+        assert!((area(&circle) - expected).abs() < 10., "{}", area(&circle));
+    }
+
+    #[test]
+    fn composite_glyph() {
+        let font = Font::from_ttf(SQUARES).unwrap();
+        let diagonal = font.rasterize(2, 100.).unwrap();
+        assert!((area(&diagonal) - 1250.).abs() < 1.);
+        let at = |x: usize, y: usize| diagonal.coverage[y * diagonal.width + x];
+        // rows go down: the top right and bottom left quarters are covered
+        assert_eq!((at(37, 12), at(12, 37)), (255, 255));
+        assert_eq!((at(12, 12), at(37, 37)), (0, 0));
+    }
+
+    #[test]
+    fn truncated_is_an_error() {
+        for end in [0, 11, 60, 200, SQUARES.len() - 100] {
+            assert!(Font::from_ttf(&SQUARES[..end]).is_err(), "{} bytes", end);
+        }
+    }
+}
@@ -0,0 +1,65 @@
+//! Reloads the assets changed on disk while the game runs, for development.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::assets::{AssetId, AssetState, Assets, ASSET_FOLDER};
+
+/// Time between two checks of the files
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Modification times of the loaded asset files
+#[derive(Default, Debug)]
+pub struct AssetWatcher {
+    modified: HashMap<String, SystemTime>,
+    last_poll: Option<Instant>,
+}
+
+impl Assets {
+    /// Parses again the loaded assets whose file changed, returns their ids.
+    /// A file that fails to parse keeps the previous asset.
+    pub fn reload_changed(&mut self) -> Vec<AssetId> {
+        if let Some(last_poll) = self.watcher.last_poll {
+            if last_poll.elapsed() < POLL_INTERVAL {
+                return vec![];
+            }
+        }
+        self.watcher.last_poll = Some(Instant::now());
+
+        let loaded: Vec<(String, AssetId)> = self
+            .states
+            .iter()
+            .filter_map(|(path, state)| match state {
+                AssetState::Loaded(id) => Some((path.clone(), id.clone())),
+                _ => None,
+            })
+            .collect();
+
+        let mut reloaded = vec![];
+        for (path, id) in loaded {
+            let file = format!("{}{}", ASSET_FOLDER, path);
+            let Ok(modified) = std::fs::metadata(&file).and_then(|m| m.modified()) else {
+                continue;
+            };
+            // The first time it's only recorded
+            let Some(previous) = self.watcher.modified.insert(path.clone(), modified) else {
+                continue;
+            };
+            if previous == modified {
+                continue;
+            }
+            let Ok(data) = std::fs::read(&file) else {
+                continue;
+            };
+            // Editors can save a half written file, the next save fixes it
+            match self.parse_asset(&id, &path, &data) {
+                Ok(()) => reloaded.push(id),
+                Err(error) => eprintln!("Hot reload of {path} failed: {error}"),
+            }
+        }
+        self.bake_lang_glyphs(&mut reloaded);
+        reloaded
+    }
+}
@@ -1,4 +1,4 @@
-use crate::zlib_inflate;
+use crate::{crc32, zlib_inflate};
 
 #[derive(Default, Clone, Debug)]
 pub struct Image {
@@ -7,11 +7,21 @@ pub struct Image {
     pub raw: Vec<u8>,
 }
 
+#[derive(Default, Clone, Debug)]
+pub struct PngOptions {
+    /// Decode chunks with a wrong crc instead of failing, with a warning
+    pub ignore_crc: bool,
+}
+
 impl Image {
-    // Specification: https://www.w3.org/TR/2003/REC-PNG-20031110/
     pub fn from_png(s: &[u8]) -> Result<Self, String> {
+        Self::from_png_with(s, &PngOptions::default())
+    }
+
+    // Specification: https://www.w3.org/TR/2003/REC-PNG-20031110/
+    pub fn from_png_with(s: &[u8], options: &PngOptions) -> Result<Self, String> {
         // Check file signature
-        if &s[0..8] != [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A] {
+        if s.get(0..8) != Some(&[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]) {
             return Err(format!("Not a png"));
         }
 
@@ -20,35 +30,92 @@ impl Image {
         let mut image_data_filtered_compressed: Vec<u8> = vec![];
         let mut c: usize = 8;
 
-        let mut transparency = false;
+        let mut bit_depth = 8;
+        let mut color_type = 6;
+        let mut interlaced = false;
+        let mut palette: Vec<[u8; 3]> = vec![];
+        // alpha of the palette entries, or the color key of the other types
+        let mut transparency: Vec<u8> = vec![];
 
         // Read png chunks
         for _ in 0..10000 {
+            if c + 8 > s.len() {
+                return Err(format!("Png truncated at byte {c}, no IEND chunk"));
+            }
             let length = u32::from_be_bytes(s[c..c + 4].try_into().unwrap());
             let block_type: &[u8; 4] = s[c + 4..c + 8].try_into().unwrap();
+            let name = String::from_utf8_lossy(block_type);
+            if c + 12 + length as usize > s.len() {
+                return Err(format!(
+                    "Png chunk {name} at byte {c} truncated: {length} bytes declared, {} left",
+                    s.len().saturating_sub(c + 12)
+                ));
+            }
+
+            // The crc covers the type and the data
+            let crc_at = c + 8 + length as usize;
+            let crc = u32::from_be_bytes(s[crc_at..crc_at + 4].try_into().unwrap());
+            let calculated_crc = crc32::crc32(&s[c + 4..crc_at]);
+            if crc != calculated_crc {
+                let err = format!(
+                    "Png chunk {name} at byte {c} is corrupt: crc {crc:08x}, calculated {calculated_crc:08x}"
+                );
+                if !options.ignore_crc {
+                    return Err(err);
+                }
+                eprintln!("{err}, ignored");
+            }
             c += 8;
             match block_type {
                 // Header
                 b"IHDR" => {
-                    assert_eq!(length, 13);
+                    if length != 13 {
+                        return Err(format!("Png header of {length} bytes, expected 13"));
+                    }
                     width = u32::from_be_bytes(s[c..c + 4].try_into().unwrap());
                     height = u32::from_be_bytes(s[c + 4..c + 8].try_into().unwrap());
 
-                    let bit_depth = s[c + 8];
-                    let color_space = s[c + 9];
+                    bit_depth = s[c + 8];
+                    color_type = s[c + 9];
                     let compression_method = s[c + 10];
                     let filter_method = s[c + 11];
                     let interlacing = s[c + 12];
 
-                    assert_eq!(bit_depth, 8);
-                    assert_eq!(compression_method, 0);
-                    assert_eq!(filter_method, 0);
-                    assert_eq!(interlacing, 0);
-                    match color_space {
-                        2 => transparency = false,
-                        6 => transparency = true,
-                        _ => return Err(format!("Color space {color_space} not supported")),
+                    if compression_method != 0 {
+                        return Err(format!(
+                            "Compression method {compression_method} not supported"
+                        ));
+                    }
+                    if filter_method != 0 {
+                        return Err(format!("Filter method {filter_method} not supported"));
+                    }
+                    interlaced = match interlacing {
+                        0 => false,
+                        1 => true,
+                        _ => return Err(format!("Interlace method {interlacing} not supported")),
                     };
+                    let valid_depths: &[u8] = match color_type {
+                        0 => &[1, 2, 4, 8, 16],
+                        3 => &[1, 2, 4, 8],
+                        2 | 4 | 6 => &[8, 16],
+                        _ => return Err(format!("Color type {color_type} not supported")),
+                    };
+                    if !valid_depths.contains(&bit_depth) {
+                        return Err(format!(
+                            "Bit depth {bit_depth} not supported for color type {color_type}"
+                        ));
+                    }
+                }
+                // Palette
+                b"PLTE" => {
+                    palette = s[c..c + length as usize]
+                        .chunks_exact(3)
+                        .map(|rgb| [rgb[0], rgb[1], rgb[2]])
+                        .collect();
+                }
+                // Transparency
+                b"tRNS" => {
+                    transparency = s[c..c + length as usize].to_vec();
                 }
                 // Image data
                 b"IDAT" => {
@@ -61,108 +128,590 @@ impl Image {
                 _ => {}
             };
 
-            // TODO: check crc
-            let _calculated_crc = 0;
-
-            c += length as usize;
-            let _crc = u32::from_be_bytes(s[c..c + 4].try_into().unwrap());
-            c += 4;
+            c += length as usize + 4;
         }
 
         // Decompress
         let image_data_filtered = zlib_inflate::decompress_zlib(&image_data_filtered_compressed)
             .map_err(|e| format!("{:?}", e))?;
 
-        // Revert filters
-        let px_size = if transparency { 4 } else { 3 };
-        let row_size = (width * px_size) as i32;
-        let mut image_data = vec![0; (width * height * px_size) as usize];
-
-        let xy_idx = |(x, y): (i32, i32)| (x + y * row_size) as usize;
-        let bound_check =
-            |(x, y): (i32, i32)| (0..row_size).contains(&x) && (0..height as i32).contains(&y);
-        let get_at = |xy, image: &Vec<u8>| {
-            if bound_check(xy) {
-                image[xy_idx(xy)]
-            } else {
-                0
-            }
+        let channels = match color_type {
+            0 | 3 => 1,
+            4 => 2,
+            2 => 3,
+            _ => 4,
         };
+        let bits_per_px = channels * bit_depth as u32;
 
-        for y in 0..height as i32 {
-            let j = (y * row_size + y) as usize;
-            let filter = image_data_filtered[j];
-
-            match filter {
+        // Samples of a pixel converted to 8 bit rgba, the color key compares the full samples
+        let max = ((1u64 << bit_depth) - 1) as u32;
+        let key: Option<Vec<u32>> = match color_type {
+            0 | 2 if transparency.len() >= channels as usize * 2 => Some(
+                transparency
+                    .chunks_exact(2)
+                    .take(channels as usize)
+                    .map(|k| u16::from_be_bytes([k[0], k[1]]) as u32)
+                    .collect(),
+            ),
+            _ => None,
+        };
+        let to_rgba = |row: &[u8], x: usize| -> Result<[u8; 4], String> {
+            let sample = |c: usize| unpack_sample(row, x * channels as usize + c, bit_depth);
+            let to_8 = |v: u32| (v * 255 / max) as u8;
+            let keyed = |samples: &[u32]| key.as_deref() == Some(samples);
+            Ok(match color_type {
                 0 => {
-                    for x in 0..row_size {
-                        image_data[xy_idx((x, y))] = image_data_filtered[j + 1 + x as usize];
-                    }
-                }
-                1 => {
-                    for x in 0..row_size {
-                        let a = get_at((x - px_size as i32, y), &image_data);
-                        let recon = image_data_filtered[j + 1 + x as usize];
-                        image_data[xy_idx((x, y))] = recon.wrapping_add(a);
-                    }
+                    let v = sample(0);
+                    let alpha = if keyed(&[v]) { 0 } else { 255 };
+                    [to_8(v), to_8(v), to_8(v), alpha]
                 }
                 2 => {
-                    for x in 0..row_size {
-                        let b = get_at((x, y - 1), &image_data);
-                        let recon = image_data_filtered[j + 1 + x as usize];
-                        image_data[xy_idx((x, y))] = recon.wrapping_add(b);
-                    }
+                    let rgb = [sample(0), sample(1), sample(2)];
+                    let alpha = if keyed(&rgb) { 0 } else { 255 };
+                    [to_8(rgb[0]), to_8(rgb[1]), to_8(rgb[2]), alpha]
                 }
                 3 => {
-                    for x in 0..row_size {
-                        let a = get_at((x - px_size as i32, y), &image_data);
-                        let b = get_at((x, y - 1), &image_data);
-                        let recon = image_data_filtered[j + 1 + x as usize];
-                        let avg = ((a as u32 + b as u32) / 2) as u8;
-                        image_data[xy_idx((x, y))] = recon.wrapping_add(avg);
-                    }
+                    let index = sample(0) as usize;
+                    let [r, g, b] = *palette
+                        .get(index)
+                        .ok_or(format!("Palette index {index} out of range"))?;
+                    [r, g, b, *transparency.get(index).unwrap_or(&255)]
                 }
                 4 => {
-                    for x in 0..row_size {
-                        let a = get_at((x - px_size as i32, y), &image_data);
-                        let b = get_at((x, y - 1), &image_data);
-                        let c = get_at((x - px_size as i32, y - 1), &image_data);
-                        let recon = image_data_filtered[j + 1 + x as usize];
-                        let p = a as i32 + b as i32 - c as i32;
-                        let pa = (p - a as i32).abs();
-                        let pb = (p - b as i32).abs();
-                        let pc = (p - c as i32).abs();
-                        let pr = if pa <= pb && pa <= pc {
-                            a
-                        } else if pb <= pc {
-                            b
-                        } else {
-                            c
-                        };
-                        image_data[xy_idx((x, y))] = recon.wrapping_add(pr);
-                    }
+                    let v = to_8(sample(0));
+                    [v, v, v, to_8(sample(1))]
                 }
-                _ => return Err(format!("Unsupported filter {filter} at row {y}")),
-            };
+                _ => [
+                    to_8(sample(0)),
+                    to_8(sample(1)),
+                    to_8(sample(2)),
+                    to_8(sample(3)),
+                ],
+            })
+        };
+
+        // Adam7 splits the image in 7 reduced images: (x start, y start, x step, y step)
+        let passes: &[(u32, u32, u32, u32)] = if interlaced {
+            &[
+                (0, 0, 8, 8),
+                (4, 0, 8, 8),
+                (0, 4, 4, 8),
+                (2, 0, 4, 4),
+                (0, 2, 2, 4),
+                (1, 0, 2, 2),
+                (0, 1, 1, 2),
+            ]
+        } else {
+            &[(0, 0, 1, 1)]
+        };
+
+        let mut raw = vec![0; (width * height * 4) as usize];
+        let mut cursor = 0;
+        for &(x0, y0, dx, dy) in passes {
+            let pass_width = width.saturating_sub(x0).div_ceil(dx);
+            let pass_height = height.saturating_sub(y0).div_ceil(dy);
+            if pass_width == 0 || pass_height == 0 {
+                continue;
+            }
+            let row_size = (pass_width * bits_per_px).div_ceil(8) as usize;
+            let size = (row_size + 1) * pass_height as usize;
+            let filtered = image_data_filtered
+                .get(cursor..cursor + size)
+                .ok_or(format!(
+                    "Image data too short: {} bytes for {width}x{height}",
+                    image_data_filtered.len()
+                ))?;
+            cursor += size;
+
+            let image_data = unfilter(filtered, row_size, pass_height, bits_per_px)?;
+            for (py, row) in image_data.chunks_exact(row_size).enumerate() {
+                for px in 0..pass_width as usize {
+                    let x = x0 as usize + px * dx as usize;
+                    let y = y0 as usize + py * dy as usize;
+                    let i = (x + y * width as usize) * 4;
+                    raw[i..i + 4].copy_from_slice(&to_rgba(row, px)?);
+                }
+            }
+        }
+
+        Ok(Self { width, height, raw })
+    }
+    /// Copy with every pixel color remapped, alpha is kept
+    pub fn recolored(&self, palette: impl Fn([u8; 3]) -> [u8; 3]) -> Self {
+        let mut raw = self.raw.clone();
+        for px in raw.chunks_exact_mut(4) {
+            let [r, g, b] = palette([px[0], px[1], px[2]]);
+            px[0] = r;
+            px[1] = g;
+            px[2] = b;
+        }
+        Self {
+            width: self.width,
+            height: self.height,
+            raw,
+        }
+    }
+
+    /// Half the size, each pixel the average of the four it covers.
+    /// Odd sides repeat their last row or column.
+    pub fn downsampled(&self) -> Self {
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+        let pixel = |x: u32, y: u32| {
+            let i = ((y.min(self.height - 1) * self.width + x.min(self.width - 1)) * 4) as usize;
+            &self.raw[i..i + 4]
+        };
+        let mut raw = Vec::with_capacity((width * height * 4) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let quad = [
+                    pixel(x * 2, y * 2),
+                    pixel(x * 2 + 1, y * 2),
+                    pixel(x * 2, y * 2 + 1),
+                    pixel(x * 2 + 1, y * 2 + 1),
+                ];
+                for channel in 0..4 {
+                    let sum: u32 = quad.iter().map(|px| px[channel] as u32).sum();
+                    raw.push(((sum + 2) / 4) as u8);
+                }
+            }
         }
+        Self { width, height, raw }
+    }
 
-        // Add the transparency channel
-        let image_data = if !transparency {
-            let image_size = (width * height * 4) as usize;
-            let mut image_data_with_transparency: Vec<u8> = vec![0; image_size];
-            for i in 0..image_size {
-                let j = i - i / 4;
-                image_data_with_transparency[i] = if i % 4 == 3 { 255 } else { image_data[j] };
+    /// The levels after this one, down to 1x1
+    pub fn mipmaps(&self) -> Vec<Self> {
+        let mut levels: Vec<Self> = vec![];
+        loop {
+            let level = levels.last().unwrap_or(self);
+            if level.width <= 1 && level.height <= 1 {
+                return levels;
             }
-            image_data_with_transparency
+            levels.push(level.downsampled());
+        }
+    }
+}
+
+/// The `index`th sample of a row, samples under 8 bits are packed from the high bits
+fn unpack_sample(row: &[u8], index: usize, bit_depth: u8) -> u32 {
+    match bit_depth {
+        8 => return row[index] as u32,
+        16 => return u16::from_be_bytes([row[index * 2], row[index * 2 + 1]]) as u32,
+        _ => {}
+    }
+    let bit = index * bit_depth as usize;
+    let shift = 8 - bit_depth as usize - bit % 8;
+    (row[bit / 8] as u32 >> shift) & ((1 << bit_depth) - 1)
+}
+
+/// Reverts the filters of the `height` rows of `filtered`, each one starts with the filter type.
+/// Filters work on bytes, with the previous pixel at `px_size` bytes.
+fn unfilter(
+    filtered: &[u8],
+    row_size: usize,
+    height: u32,
+    bits_per_px: u32,
+) -> Result<Vec<u8>, String> {
+    let px_size = (bits_per_px / 8).max(1);
+    let row_size = row_size as i32;
+    let mut image_data = vec![0; row_size as usize * height as usize];
+
+    let xy_idx = |(x, y): (i32, i32)| (x + y * row_size) as usize;
+    let bound_check =
+        |(x, y): (i32, i32)| (0..row_size).contains(&x) && (0..height as i32).contains(&y);
+    let get_at = |xy, image: &Vec<u8>| {
+        if bound_check(xy) {
+            image[xy_idx(xy)]
         } else {
-            image_data
+            0
+        }
+    };
+
+    for y in 0..height as i32 {
+        let j = (y * row_size + y) as usize;
+        let filter = filtered[j];
+
+        match filter {
+            0 => {
+                for x in 0..row_size {
+                    image_data[xy_idx((x, y))] = filtered[j + 1 + x as usize];
+                }
+            }
+            1 => {
+                for x in 0..row_size {
+                    let a = get_at((x - px_size as i32, y), &image_data);
+                    let recon = filtered[j + 1 + x as usize];
+                    image_data[xy_idx((x, y))] = recon.wrapping_add(a);
+                }
+            }
+            2 => {
+                for x in 0..row_size {
+                    let b = get_at((x, y - 1), &image_data);
+                    let recon = filtered[j + 1 + x as usize];
+                    image_data[xy_idx((x, y))] = recon.wrapping_add(b);
+                }
+            }
+            3 => {
+                for x in 0..row_size {
+                    let a = get_at((x - px_size as i32, y), &image_data);
+                    let b = get_at((x, y - 1), &image_data);
+                    let recon = filtered[j + 1 + x as usize];
+                    let avg = ((a as u32 + b as u32) / 2) as u8;
+                    image_data[xy_idx((x, y))] = recon.wrapping_add(avg);
+                }
+            }
+            4 => {
+                for x in 0..row_size {
+                    let a = get_at((x - px_size as i32, y), &image_data);
+                    let b = get_at((x, y - 1), &image_data);
+                    let c = get_at((x - px_size as i32, y - 1), &image_data);
+                    let recon = filtered[j + 1 + x as usize];
+                    let p = a as i32 + b as i32 - c as i32;
+                    let pa = (p - a as i32).abs();
+                    let pb = (p - b as i32).abs();
+                    let pc = (p - c as i32).abs();
+                    let pr = if pa <= pb && pa <= pc {
+                        a
+                    } else if pb <= pc {
+                        b
+                    } else {
+                        c
+                    };
+                    image_data[xy_idx((x, y))] = recon.wrapping_add(pr);
+                }
+            }
+            _ => return Err(format!("Unsupported filter {filter} at row {y}")),
         };
+    }
+
+    Ok(image_data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assets::asset_bundle::deflate, rng::RandLCG};
+
+    const ADAM7: [(u32, u32, u32, u32); 7] = [
+        (0, 0, 8, 8),
+        (4, 0, 8, 8),
+        (0, 4, 4, 8),
+        (2, 0, 4, 4),
+        (0, 2, 2, 4),
+        (1, 0, 2, 2),
+        (0, 1, 1, 2),
+    ];
+
+    /// An image as the samples of its color type, `channels` of them per pixel
+    struct Samples {
+        width: u32,
+        height: u32,
+        color_type: u8,
+        bit_depth: u8,
+        interlaced: bool,
+        samples: Vec<u32>,
+        palette: Vec<[u8; 3]>,
+        transparency: Vec<u8>,
+    }
+
+    impl Samples {
+        fn channels(&self) -> usize {
+            match self.color_type {
+                0 | 3 => 1,
+                4 => 2,
+                2 => 3,
+                _ => 4,
+            }
+        }
+
+        fn sample(&self, x: u32, y: u32, c: usize) -> u32 {
+            self.samples[(x + y * self.width) as usize * self.channels() + c]
+        }
+
+        /// What the decoder should make of it
+        fn rgba(&self) -> Vec<u8> {
+            let max = (1u32 << self.bit_depth) - 1;
+            let to_8 = |v: u32| (v * 255 / max) as u8;
+            let key: Option<Vec<u32>> = (!self.transparency.is_empty() && self.color_type != 3)
+                .then(|| {
+                    self.transparency
+                        .chunks_exact(2)
+                        .map(|k| u16::from_be_bytes([k[0], k[1]]) as u32)
+                        .collect()
+                });
+            let mut raw = vec![];
+            for y in 0..self.height {
+                for x in 0..self.width {
+                    let s: Vec<u32> = (0..self.channels()).map(|c| self.sample(x, y, c)).collect();
+                    let alpha = if key.as_ref() == Some(&s) { 0 } else { 255 };
+                    raw.extend(match self.color_type {
+                        0 => [to_8(s[0]), to_8(s[0]), to_8(s[0]), alpha],
+                        2 => [to_8(s[0]), to_8(s[1]), to_8(s[2]), alpha],
+                        3 => {
+                            let [r, g, b] = self.palette[s[0] as usize];
+                            let alpha = *self.transparency.get(s[0] as usize).unwrap_or(&255);
+                            [r, g, b, alpha]
+                        }
+                        4 => [to_8(s[0]), to_8(s[0]), to_8(s[0]), to_8(s[1])],
+                        _ => [to_8(s[0]), to_8(s[1]), to_8(s[2]), to_8(s[3])],
+                    });
+                }
+            }
+            raw
+        }
 
-        Ok(Self {
-            width,
-            height,
-            raw: image_data,
-        })
+        /// The png file, each row with a random filter
+        fn encode(&self, rand: &mut RandLCG) -> Vec<u8> {
+            let bits_per_px = self.channels() * self.bit_depth as usize;
+            let px_size = (bits_per_px / 8).max(1);
+            let passes: &[(u32, u32, u32, u32)] = if self.interlaced {
+                &ADAM7
+            } else {
+                &[(0, 0, 1, 1)]
+            };
+            let mut filtered = vec![];
+            for &(x0, y0, dx, dy) in passes {
+                let xs: Vec<u32> = (x0..self.width).step_by(dx as usize).collect();
+                let mut previous: Option<Vec<u8>> = None;
+                for y in (y0..self.height).step_by(dy as usize) {
+                    if xs.is_empty() {
+                        break;
+                    }
+                    let mut row = vec![0u8; (xs.len() * bits_per_px).div_ceil(8)];
+                    let mut bit = 0;
+                    for x in xs.iter() {
+                        for c in 0..self.channels() {
+                            let v = self.sample(*x, y, c);
+                            match self.bit_depth {
+                                16 => row[bit / 8..bit / 8 + 2]
+                                    .copy_from_slice(&(v as u16).to_be_bytes()),
+                                depth => {
+                                    row[bit / 8] |= (v << (8 - depth as usize - bit % 8)) as u8
+                                }
+                            }
+                            bit += self.bit_depth as usize;
+                        }
+                    }
+                    let filter = (rand.next_u32() % 5) as u8;
+                    filtered.push(filter);
+                    for i in 0..row.len() {
+                        let a = if i >= px_size { row[i - px_size] } else { 0 };
+                        let b = previous.as_ref().map_or(0, |p| p[i]);
+                        let c = match &previous {
+                            Some(p) if i >= px_size => p[i - px_size],
+                            _ => 0,
+                        };
+                        let prediction = match filter {
+                            0 => 0,
+                            1 => a,
+                            2 => b,
+                            3 => ((a as u32 + b as u32) / 2) as u8,
+                            _ => {
+                                let p = a as i32 + b as i32 - c as i32;
+                                let (pa, pb, pc) = (
+                                    (p - a as i32).abs(),
+                                    (p - b as i32).abs(),
+                                    (p - c as i32).abs(),
+                                );
+                                if pa <= pb && pa <= pc {
+                                    a
+                                } else if pb <= pc {
+                                    b
+                                } else {
+                                    c
+                                }
+                            }
+                        };
+                        filtered.push(row[i].wrapping_sub(prediction));
+                    }
+                    previous = Some(row);
+                }
+            }
+
+            let mut zlib = vec![0x78, 0x01];
+            zlib.extend(deflate(&filtered));
+            zlib.extend(adler32(&filtered).to_be_bytes());
+
+            let mut header = vec![];
+            header.extend(self.width.to_be_bytes());
+            header.extend(self.height.to_be_bytes());
+            header.extend([self.bit_depth, self.color_type, 0, 0, self.interlaced as u8]);
+            let mut png = vec![0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+            png.extend(chunk(b"IHDR", &header));
+            if !self.palette.is_empty() {
+                png.extend(chunk(b"PLTE", &self.palette.concat()));
+            }
+            if !self.transparency.is_empty() {
+                png.extend(chunk(b"tRNS", &self.transparency));
+            }
+            // split in two, the data of the chunks is concatenated
+            let half = zlib.len() / 2;
+            png.extend(chunk(b"IDAT", &zlib[..half]));
+            png.extend(chunk(b"IDAT", &zlib[half..]));
+            png.extend(chunk(b"IEND", &[]));
+            png
+        }
+    }
+
+    fn chunk(kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+        chunk.extend(kind);
+        chunk.extend(data);
+        chunk.extend(crc32::crc32(&chunk[4..]).to_be_bytes());
+        chunk
+    }
+
+    fn adler32(data: &[u8]) -> u32 {
+        let (mut a, mut b) = (1u32, 0u32);
+        for byte in data {
+            a = (a + *byte as u32) % 65521;
+            b = (b + a) % 65521;
+        }
+        b << 16 | a
+    }
+
+    fn random_samples(rand: &mut RandLCG) -> Samples {
+        let (color_type, depths): (u8, &[u8]) = [
+            (0, &[1, 2, 4, 8, 16][..]),
+            (2, &[8, 16]),
+            (3, &[1, 2, 4, 8]),
+            (4, &[8, 16]),
+            (6, &[8, 16]),
+        ][rand.next_u32() as usize % 5];
+        let bit_depth = depths[rand.next_u32() as usize % depths.len()];
+        let mut image = Samples {
+            width: 1 + rand.next_u32() % 19,
+            height: 1 + rand.next_u32() % 19,
+            color_type,
+            bit_depth,
+            interlaced: rand.next_u32().is_multiple_of(2),
+            samples: vec![],
+            palette: vec![],
+            transparency: vec![],
+        };
+        let max = (1u32 << bit_depth) - 1;
+        if color_type == 3 {
+            let len = 1 + rand.next_u32() % (max + 1);
+            image.palette = (0..len)
+                .map(|_| [0; 3].map(|_| rand.next_u32() as u8))
+                .collect();
+            image.transparency = (0..rand.next_u32() % (len + 1))
+                .map(|_| rand.next_u32() as u8)
+                .collect();
+        }
+        let values = match color_type {
+            3 => image.palette.len() as u32,
+            _ => max + 1,
+        };
+        let len = (image.width * image.height) as usize * image.channels();
+        image.samples = (0..len).map(|_| rand.next_u32() % values).collect();
+        // a color key that some pixels match
+        if matches!(color_type, 0 | 2) && rand.next_u32().is_multiple_of(2) {
+            let channels = image.channels();
+            image.transparency = image.samples[..channels]
+                .iter()
+                .flat_map(|v| (*v as u16).to_be_bytes())
+                .collect();
+        }
+        image
+    }
+
+    #[test]
+    fn random_images_round_trip() {
+        let mut rand = RandLCG::new(42);
+        for _ in 0..300 {
+            let samples = random_samples(&mut rand);
+            let image = Image::from_png(&samples.encode(&mut rand)).unwrap();
+            assert_eq!((image.width, image.height), (samples.width, samples.height));
+            assert_eq!(
+                image.raw,
+                samples.rgba(),
+                "color type {} at {} bits, interlaced {}",
+                samples.color_type,
+                samples.bit_depth,
+                samples.interlaced
+            );
+        }
+    }
+
+    #[test]
+    fn truncated_is_an_error() {
+        let mut rand = RandLCG::new(7);
+        let png = random_samples(&mut rand).encode(&mut rand);
+        for end in 0..png.len() {
+            assert!(Image::from_png(&png[..end]).is_err(), "{} bytes", end);
+        }
+    }
+
+    #[test]
+    fn malformed_header_is_an_error() {
+        let header = |length: usize, compression: u8, filter: u8| {
+            let mut data = [0, 0, 0, 1, 0, 0, 0, 1, 8, 6, compression, filter, 0, 0].to_vec();
+            data.truncate(length);
+            let mut png = vec![0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+            png.extend(chunk(b"IHDR", &data));
+            png.extend(chunk(b"IEND", &[]));
+            png
+        };
+        assert!(Image::from_png(&header(12, 0, 0)).is_err());
+        assert!(Image::from_png(&header(14, 0, 0)).is_err());
+        assert!(Image::from_png(&header(13, 1, 0)).is_err());
+        assert!(Image::from_png(&header(13, 0, 1)).is_err());
+    }
+
+    #[test]
+    fn corrupt_crc() {
+        let mut rand = RandLCG::new(5);
+        let samples = random_samples(&mut rand);
+        let mut png = samples.encode(&mut rand);
+        // the last byte of the crc of IEND
+        let last = png.len() - 1;
+        png[last] ^= 0xff;
+        assert!(Image::from_png(&png).is_err());
+        let options = PngOptions { ignore_crc: true };
+        let image = Image::from_png_with(&png, &options).unwrap();
+        assert_eq!(image.raw, samples.rgba());
+    }
+
+    #[test]
+    fn palette_fixture() {
+        // 2 bit indices (x + 2y) % 4 of red, green, blue and white,
+        // the first two with alpha 0 and 128
+        let image = Image::from_png(include_bytes!("fixtures/palette_2bit.png")).unwrap();
+        assert_eq!((image.width, image.height), (5, 3));
+        let colors = [
+            [255, 0, 0, 0],
+            [0, 255, 0, 128],
+            [0, 0, 255, 255],
+            [255, 255, 255, 255],
+        ];
+        for (i, px) in image.raw.chunks_exact(4).enumerate() {
+            let (x, y) = (i % 5, i / 5);
+            assert_eq!(px, colors[(x + 2 * y) % 4], "at {x},{y}");
+        }
+    }
+
+    #[test]
+    fn interlaced_fixture() {
+        // rgba 8 bit, its rows go through the five filters
+        let image = Image::from_png(include_bytes!("fixtures/rgba_adam7.png")).unwrap();
+        assert_eq!((image.width, image.height), (11, 9));
+        for (i, px) in image.raw.chunks_exact(4).enumerate() {
+            let (x, y) = (i as u32 % 11, i as u32 / 11);
+            let expected = [x * 23, y * 27, x * y * 5, 255 - x * 10].map(|v| (v & 255) as u8);
+            assert_eq!(px, expected, "at {x},{y}");
+        }
+    }
+
+    #[test]
+    fn color_key_fixture() {
+        // 16 bit gray, 0x1234 is transparent
+        let image = Image::from_png(include_bytes!("fixtures/gray16_key.png")).unwrap();
+        let values: [u32; 8] = [0, 0x1234, 0xffff, 0x8000, 0x1234, 0x00ff, 0xff00, 0x1235];
+        for (px, value) in image.raw.chunks_exact(4).zip(values) {
+            let v = (value * 255 / 65535) as u8;
+            let alpha = if value == 0x1234 { 0 } else { 255 };
+            assert_eq!(px, [v, v, v, alpha]);
+        }
     }
 }
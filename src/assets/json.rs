@@ -0,0 +1,277 @@
+/// Parsed json value
+/// Specification: https://www.json.org/json-en.html
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    /// Keys in file order
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn parse(s: &str) -> Result<Json, String> {
+        let mut parser = JsonParser {
+            bytes: s.as_bytes(),
+            cursor: 0,
+        };
+        let value = parser.value()?;
+        parser.whitespace();
+        if parser.cursor < parser.bytes.len() {
+            return Err(format!("Json: trailing data at byte {}", parser.cursor));
+        }
+        Ok(value)
+    }
+
+    /// Value of a key of an object
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Element of an array
+    pub fn at(&self, index: usize) -> Option<&Json> {
+        self.as_array()?.get(index)
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_usize(&self) -> Option<usize> {
+        let n = self.as_f64()?;
+        (n >= 0. && n.fract() == 0.).then_some(n as usize)
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+}
+
+struct JsonParser<'a> {
+    bytes: &'a [u8],
+    cursor: usize,
+}
+
+impl<'a> JsonParser<'a> {
+    fn whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.bytes.get(self.cursor) {
+            self.cursor += 1;
+        }
+    }
+
+    fn expect(&mut self, literal: &str) -> Result<(), String> {
+        if self.bytes[self.cursor..].starts_with(literal.as_bytes()) {
+            self.cursor += literal.len();
+            Ok(())
+        } else {
+            Err(format!("Json: expected {literal} at byte {}", self.cursor))
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.whitespace();
+        match self.bytes.get(self.cursor) {
+            Some(b'{') => self.object(),
+            Some(b'[') => self.array(),
+            Some(b'"') => Ok(Json::String(self.string()?)),
+            Some(b't') => self.expect("true").map(|_| Json::Bool(true)),
+            Some(b'f') => self.expect("false").map(|_| Json::Bool(false)),
+            Some(b'n') => self.expect("null").map(|_| Json::Null),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(c) => Err(format!(
+                "Json: unexpected {:?} at byte {}",
+                *c as char, self.cursor
+            )),
+            None => Err("Json: unexpected end".to_string()),
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, String> {
+        self.expect("{")?;
+        let mut entries = vec![];
+        self.whitespace();
+        if self.expect("}").is_ok() {
+            return Ok(Json::Object(entries));
+        }
+        loop {
+            self.whitespace();
+            let key = self.string()?;
+            self.whitespace();
+            self.expect(":")?;
+            entries.push((key, self.value()?));
+            self.whitespace();
+            if self.expect(",").is_err() {
+                self.expect("}")?;
+                return Ok(Json::Object(entries));
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, String> {
+        self.expect("[")?;
+        let mut values = vec![];
+        self.whitespace();
+        if self.expect("]").is_ok() {
+            return Ok(Json::Array(values));
+        }
+        loop {
+            values.push(self.value()?);
+            self.whitespace();
+            if self.expect(",").is_err() {
+                self.expect("]")?;
+                return Ok(Json::Array(values));
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect("\"")?;
+        let mut s = String::new();
+        loop {
+            let start = self.cursor;
+            while !matches!(self.bytes.get(self.cursor), Some(b'"' | b'\\') | None) {
+                self.cursor += 1;
+            }
+            s.push_str(
+                std::str::from_utf8(&self.bytes[start..self.cursor]).map_err(|e| format!("{e}"))?,
+            );
+            match self.bytes.get(self.cursor) {
+                Some(b'"') => {
+                    self.cursor += 1;
+                    return Ok(s);
+                }
+                Some(b'\\') => {
+                    let escape = *self
+                        .bytes
+                        .get(self.cursor + 1)
+                        .ok_or("Json: unexpected end")?;
+                    self.cursor += 2;
+                    match escape {
+                        b'"' => s.push('"'),
+                        b'\\' => s.push('\\'),
+                        b'/' => s.push('/'),
+                        b'b' => s.push('\u{8}'),
+                        b'f' => s.push('\u{c}'),
+                        b'n' => s.push('\n'),
+                        b'r' => s.push('\r'),
+                        b't' => s.push('\t'),
+                        b'u' => {
+                            let hex = self
+                                .bytes
+                                .get(self.cursor..self.cursor + 4)
+                                .and_then(|hex| std::str::from_utf8(hex).ok())
+                                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                                .ok_or(format!("Json: bad \\u escape at byte {}", self.cursor))?;
+                            self.cursor += 4;
+                            // surrogate pairs are replaced
+                            s.push(char::from_u32(hex).unwrap_or(char::REPLACEMENT_CHARACTER));
+                        }
+                        _ => return Err(format!("Json: bad escape at byte {}", self.cursor)),
+                    }
+                }
+                _ => return Err("Json: unterminated string".to_string()),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let start = self.cursor;
+        while let Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') = self.bytes.get(self.cursor)
+        {
+            self.cursor += 1;
+        }
+        let text = std::str::from_utf8(&self.bytes[start..self.cursor]).unwrap();
+        text.parse()
+            .map(Json::Number)
+            .map_err(|e| format!("Json: {e} at byte {start}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values() {
+        let json = Json::parse(
+            r#" { "a": [1, -2.5e2, true, false, null], "b": {}, "c": "x\"\\\/\n\u00e9", "d": [] } "#,
+        )
+        .unwrap();
+        let a = json.get("a").unwrap();
+        assert_eq!(a.at(0).and_then(Json::as_usize), Some(1));
+        assert_eq!(a.at(1).and_then(Json::as_f64), Some(-250.));
+        assert_eq!(a.at(1).and_then(Json::as_usize), None);
+        assert_eq!(a.at(2).and_then(Json::as_bool), Some(true));
+        assert_eq!(a.at(4), Some(&Json::Null));
+        assert_eq!(json.get("b"), Some(&Json::Object(vec![])));
+        assert_eq!(json.get("c").and_then(Json::as_str), Some("x\"\\/\né"));
+        assert_eq!(json.get("d").and_then(Json::as_array), Some(&[][..]));
+        assert_eq!(json.get("e"), None);
+    }
+
+    #[test]
+    fn the_glb_json_chunk_round_trips() {
+        let glb = include_bytes!("fixtures/quad.glb");
+        let length = u32::from_le_bytes(glb[12..16].try_into().unwrap()) as usize;
+        let text = std::str::from_utf8(&glb[20..20 + length]).unwrap();
+        let gltf = Json::parse(text).unwrap();
+        let generator = gltf.get("asset").and_then(|asset| asset.get("generator"));
+        assert_eq!(generator.and_then(Json::as_str), Some("fixture \"quad\""));
+        let keys: Vec<&str> = match &gltf {
+            Json::Object(entries) => entries.iter().map(|(key, _)| key.as_str()).collect(),
+            _ => vec![],
+        };
+        assert_eq!(
+            keys,
+            [
+                "asset",
+                "buffers",
+                "bufferViews",
+                "accessors",
+                "materials",
+                "meshes"
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_is_an_error() {
+        for text in [
+            "",
+            "[1,]",
+            "{\"a\" 1}",
+            "\"open",
+            "tru",
+            "1 2",
+            "\"\\x\"",
+            "[1",
+        ] {
+            assert!(Json::parse(text).is_err(), "{text}");
+        }
+    }
+}
@@ -0,0 +1,61 @@
+/// Loaded first, it lists the rest
+pub const MANIFEST_PATH: &str = "manifest.txt";
+
+/// Files of a group of the manifest
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ManifestGroup {
+    pub name: String,
+    pub paths: Vec<String>,
+}
+
+/// How far the loading of a group of the manifest is
+#[derive(Clone, Debug, PartialEq)]
+pub struct GroupProgress<'a> {
+    pub name: &'a str,
+    pub loaded: usize,
+    pub total: usize,
+    /// The first file of the group not loaded yet
+    pub pending: Option<&'a String>,
+}
+
+/// The files loaded before the game starts, parsed from lines like
+/// "[<group>]", starting a group, followed by the asset paths in it.
+/// Lines starting with '#' are comments
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Manifest {
+    pub groups: Vec<ManifestGroup>,
+}
+
+impl Manifest {
+    pub fn from_manifest(s: &[u8]) -> Result<Self, String> {
+        let text = std::str::from_utf8(s).map_err(|e| format!("Manifest: {}", e))?;
+        let mut manifest = Self::default();
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                manifest.groups.push(ManifestGroup {
+                    name: name.trim().to_string(),
+                    paths: vec![],
+                });
+                continue;
+            }
+            let Some(group) = manifest.groups.last_mut() else {
+                return Err(format!(
+                    "Manifest: '{}' outside of a group at line {}",
+                    line,
+                    line_number + 1
+                ));
+            };
+            group.paths.push(line.to_string());
+        }
+        Ok(manifest)
+    }
+
+    /// Every path, in the order of the groups
+    pub fn paths(&self) -> impl Iterator<Item = &String> {
+        self.groups.iter().flat_map(|group| group.paths.iter())
+    }
+}
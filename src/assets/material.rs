@@ -0,0 +1,55 @@
+/// Material of an .obj, only the texture is used
+#[derive(Default, Clone, Debug)]
+pub struct Material {
+    pub name: String,
+    /// Asset path of the `map_Kd` image
+    pub diffuse_texture: Option<String>,
+}
+
+/// Materials of a .mtl file
+#[derive(Default, Clone, Debug)]
+pub struct MaterialLibrary {
+    pub materials: Vec<Material>,
+}
+
+impl MaterialLibrary {
+    // Specification: https://paulbourke.net/dataformats/mtl/
+    pub fn from_mtl(s: &[u8]) -> Result<Self, String> {
+        let mut materials: Vec<Material> = vec![];
+        let text = std::str::from_utf8(s).map_err(|e| format!("{}", e))?;
+        for (line_number, line) in text.lines().enumerate() {
+            let mut tokens = line.split_whitespace();
+            let Some(kind_token) = tokens.next() else {
+                continue;
+            };
+            match kind_token {
+                "newmtl" => {
+                    let name = tokens.next().ok_or(format!(
+                        "Mtl: missing material name at line {}",
+                        line_number
+                    ))?;
+                    materials.push(Material {
+                        name: name.to_string(),
+                        ..Default::default()
+                    });
+                }
+                "map_Kd" => {
+                    let material = materials
+                        .last_mut()
+                        .ok_or(format!("Mtl: map_Kd before newmtl at line {}", line_number))?;
+                    // the options come before the file name
+                    let path = tokens
+                        .last()
+                        .ok_or(format!("Mtl: missing map_Kd file at line {}", line_number))?;
+                    material.diffuse_texture = Some(path.to_string());
+                }
+                _ => {}
+            }
+        }
+        Ok(Self { materials })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Material> {
+        self.materials.iter().find(|material| material.name == name)
+    }
+}
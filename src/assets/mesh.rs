@@ -1,18 +1,68 @@
+use std::{collections::HashMap, ops::Range};
+
 use glam::{Vec2, Vec3};
 
 #[derive(Default, Clone, Debug)]
 pub struct Mesh {
     pub vertices: Vec<Vec3>,
     pub uvs: Vec<Vec2>,
-    pub indices: Vec<u16>,
+    pub normals: Vec<Vec3>,
+    pub indices: Vec<u32>,
+    /// Path of the .mtl file
+    pub material_library: Option<String>,
+    pub submeshes: Vec<Submesh>,
+}
+
+/// Triangles of a mesh drawn with the same material
+#[derive(Clone, Debug)]
+pub struct Submesh {
+    pub material: Option<String>,
+    /// Range of `Mesh::indices`
+    pub indices: Range<usize>,
 }
 
 impl Mesh {
+    /// Mesh of generated geometry: triangles of `indices` into `vertices`.
+    /// The uvs are one per vertex or none, the normals are smoothed by the renderer
+    pub fn from_data(
+        vertices: Vec<Vec3>,
+        uvs: Vec<Vec2>,
+        indices: Vec<u32>,
+    ) -> Result<Self, String> {
+        if !indices.len().is_multiple_of(3) {
+            return Err(format!("Mesh: {} indices aren't triangles", indices.len()));
+        }
+        if let Some(index) = indices.iter().find(|i| **i as usize >= vertices.len()) {
+            return Err(format!(
+                "Mesh: index {} of {} vertices",
+                index,
+                vertices.len()
+            ));
+        }
+        if !uvs.is_empty() && uvs.len() != vertices.len() {
+            return Err(format!(
+                "Mesh: {} uvs for {} vertices",
+                uvs.len(),
+                vertices.len()
+            ));
+        }
+        Ok(Self {
+            vertices,
+            uvs,
+            indices,
+            ..Default::default()
+        })
+    }
+
     // Specification: https://en.wikipedia.org/wiki/Wavefront_.obj_file
     pub fn from_obj(s: &[u8]) -> Result<Self, String> {
         let mut vertices = vec![];
         let mut uvs = vec![];
-        let mut faces: Vec<[Option<[u16; 3]>; 3]> = vec![];
+        let mut normals = vec![];
+        let mut faces: Vec<[Option<[u32; 3]>; 3]> = vec![];
+        let mut material_library = None;
+        // first face of each material
+        let mut material_starts: Vec<(usize, Option<String>)> = vec![(0, None)];
         let text = std::str::from_utf8(s).map_err(|e| format!("{}", e))?;
         for (line_number, line) in text.lines().enumerate() {
             let mut tokens = line.split_whitespace();
@@ -42,41 +92,46 @@ impl Mesh {
                     vertices.push(Vec3::new(v1, v2, v3));
                 }
                 "f" => {
-                    let f1 = tokens
-                        .next()
-                        .ok_or(format!("Obj: missing face index 0 at line {}", line_number))?;
-                    let [f1_vertex, f1_texture, f1_normal] =
-                        obj_parse_face_indices(f1, line_number)?;
-                    let f2 = tokens
-                        .next()
-                        .ok_or(format!("Obj: missing face index 1 at line {}", line_number))?;
-                    let [f2_vertex, f2_texture, f2_normal] =
-                        obj_parse_face_indices(f2, line_number)?;
-                    let f3 = tokens
-                        .next()
-                        .ok_or(format!("Obj: missing face index 2 at line {}", line_number))?;
-                    let [f3_vertex, f3_texture, f3_normal] =
-                        obj_parse_face_indices(f3, line_number)?;
-                    assert!(
-                        tokens.next().is_none(),
-                        "Obj: only triangles supported, line {}",
-                        line_number
-                    );
-                    let mut face = [None, None, None];
-                    if let (Some(f1), Some(f2), Some(f3)) = (f1_vertex, f2_vertex, f3_vertex) {
-                        face[0] = Some([f1 - 1, f2 - 1, f3 - 1]);
-                    } else {
-                        panic!(
-                            "Obj: face at line {line_number} doesn't have all vertex indices set"
-                        );
-                    }
-                    if let (Some(f1), Some(f2), Some(f3)) = (f1_texture, f2_texture, f3_texture) {
-                        face[1] = Some([f1 - 1, f2 - 1, f3 - 1]);
+                    let corners = tokens
+                        .map(|corner| obj_parse_face_indices(corner, line_number))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    if corners.len() < 3 {
+                        return Err(format!(
+                            "Obj: face with {} indices at line {line_number}",
+                            corners.len()
+                        ));
                     }
-                    if let (Some(f1), Some(f2), Some(f3)) = (f1_normal, f2_normal, f3_normal) {
-                        face[2] = Some([f1 - 1, f2 - 1, f3 - 1]);
+                    let positions = corners
+                        .iter()
+                        .map(|[vertex, _, _]| {
+                            let vertex = vertex.ok_or(format!(
+                                "Obj: face at line {line_number} doesn't have all vertex indices set"
+                            ))?;
+                            (vertex as usize)
+                                .checked_sub(1)
+                                .and_then(|i| vertices.get(i).copied())
+                                .ok_or(format!(
+                                    "Obj: vertex index {vertex} out of range at line {line_number}"
+                                ))
+                        })
+                        .collect::<Result<Vec<Vec3>, String>>()?;
+                    for triangle in triangulate(&positions) {
+                        // texture and normal indices are used only if every corner has them
+                        let indices = |attribute: usize| -> Result<Option<[u32; 3]>, String> {
+                            let [Some(f1), Some(f2), Some(f3)] =
+                                triangle.map(|i| corners[i][attribute])
+                            else {
+                                return Ok(None);
+                            };
+                            let index = |f: u32| {
+                                f.checked_sub(1).ok_or(format!(
+                                    "Obj: index {f} out of range at line {line_number}"
+                                ))
+                            };
+                            Ok(Some([index(f1)?, index(f2)?, index(f3)?]))
+                        };
+                        faces.push([indices(0)?, indices(1)?, indices(2)?]);
                     }
-                    faces.push(face);
                 }
                 "vt" => {
                     let u = tokens
@@ -93,38 +148,115 @@ impl Mesh {
                         .map_err(|e| format!("Obj: {} at {line_number}", e))?;
                     uvs.push(Vec2::new(u, 1. - v));
                 }
+                "mtllib" => {
+                    let path = tokens
+                        .next()
+                        .ok_or(format!("Obj: missing mtllib file at line {}", line_number))?;
+                    material_library = Some(path.to_string());
+                }
+                "usemtl" => {
+                    let name = tokens
+                        .next()
+                        .ok_or(format!("Obj: missing material at line {}", line_number))?;
+                    material_starts.push((faces.len(), Some(name.to_string())));
+                }
+                "vn" => {
+                    let n1 = tokens
+                        .next()
+                        .ok_or(format!("Obj: missing normal.x at line {}", line_number))?;
+                    let n2 = tokens
+                        .next()
+                        .ok_or(format!("Obj: missing normal.y at line {}", line_number))?;
+                    let n3 = tokens
+                        .next()
+                        .ok_or(format!("Obj: missing normal.z at line {}", line_number))?;
+                    let n1: f32 = n1
+                        .parse()
+                        .map_err(|e| format!("Obj: {} at {line_number}", e))?;
+                    let n2: f32 = n2
+                        .parse()
+                        .map_err(|e| format!("Obj: {} at {line_number}", e))?;
+                    let n3: f32 = n3
+                        .parse()
+                        .map_err(|e| format!("Obj: {} at {line_number}", e))?;
+                    normals.push(Vec3::new(n1, n2, n3).normalize_or_zero());
+                }
                 _ => {}
             }
         }
 
         // Generate the triangles from the faces
+        let faces_len = faces.len();
         let mut packed_vertices = vec![];
         let mut packed_uvs = vec![];
+        let mut packed_normals = vec![];
         let mut packed_indices = vec![];
-        let mut index = 0;
-        for [vert_index, uv_index, _] in faces {
+        // Corners with the same position, uv and normal share the vertex
+        let mut welded: HashMap<[u32; 8], u32> = HashMap::new();
+        for [vert_index, uv_index, normal_index] in faces {
             let Some(vert_index) = vert_index else {
                 continue;
             };
-            packed_vertices.push(vertices[vert_index[0] as usize]);
-            packed_vertices.push(vertices[vert_index[1] as usize]);
-            packed_vertices.push(vertices[vert_index[2] as usize]);
-            packed_indices.push(index + 0);
-            packed_indices.push(index + 1);
-            packed_indices.push(index + 2);
-            index += 3;
-            let Some(uv_index) = uv_index else {
-                continue;
+            let triangle = vert_index.map(|i| vertices[i as usize]);
+            // The face normal when the file has none
+            let face_normal = {
+                let [v0, v1, v2]: [Vec3; 3] = triangle;
+                (v1 - v0).cross(v2 - v0).normalize_or_zero()
             };
-            packed_uvs.push(uvs[uv_index[0] as usize]);
-            packed_uvs.push(uvs[uv_index[1] as usize]);
-            packed_uvs.push(uvs[uv_index[2] as usize]);
+            for corner in 0..3 {
+                let pos = triangle[corner];
+                let uv = match uv_index {
+                    Some(uv_index) => *uvs.get(uv_index[corner] as usize).ok_or(format!(
+                        "Obj: uv index {} out of range",
+                        uv_index[corner] + 1
+                    ))?,
+                    None => Vec2::ZERO,
+                };
+                let normal = match normal_index {
+                    Some(normal_index) => {
+                        *normals.get(normal_index[corner] as usize).ok_or(format!(
+                            "Obj: normal index {} out of range",
+                            normal_index[corner] + 1
+                        ))?
+                    }
+                    None => face_normal,
+                };
+                // adding 0 makes -0 into 0
+                let key = [
+                    pos.x, pos.y, pos.z, uv.x, uv.y, normal.x, normal.y, normal.z,
+                ]
+                .map(|v| (v + 0.).to_bits());
+                let index = *welded.entry(key).or_insert_with(|| {
+                    packed_vertices.push(pos);
+                    packed_uvs.push(uv);
+                    packed_normals.push(normal);
+                    packed_vertices.len() as u32 - 1
+                });
+                packed_indices.push(index);
+            }
         }
 
+        // Every face is three indices
+        let submeshes = material_starts
+            .iter()
+            .enumerate()
+            .map(|(i, (start, material))| {
+                let end = material_starts.get(i + 1).map_or(faces_len, |next| next.0);
+                Submesh {
+                    material: material.clone(),
+                    indices: start * 3..end * 3,
+                }
+            })
+            .filter(|submesh| !submesh.indices.is_empty())
+            .collect();
+
         Ok(Mesh {
             vertices: packed_vertices,
             uvs: packed_uvs,
+            normals: packed_normals,
             indices: packed_indices,
+            material_library,
+            submeshes,
         })
     }
 }
@@ -132,7 +264,7 @@ impl Mesh {
 fn obj_parse_face_indices(
     face_indices: &str,
     line_number: usize,
-) -> Result<[Option<u16>; 3], String> {
+) -> Result<[Option<u32>; 3], String> {
     let mut tokens = face_indices.split("/");
     let i0 = obj_parse_index(&mut tokens, line_number).ok();
     let i1 = obj_parse_index(&mut tokens, line_number).ok();
@@ -143,9 +275,193 @@ fn obj_parse_face_indices(
 fn obj_parse_index<'a>(
     iter: &mut impl Iterator<Item = &'a str>,
     line_number: usize,
-) -> Result<u16, String> {
+) -> Result<u32, String> {
     let f = iter
         .next()
         .ok_or(format!("Obj: missing face index at line {}", line_number))?;
     f.parse().map_err(|e| format!("{} at {line_number}", e))
 }
+
+/// Triangles of a polygon as indices of its corners, keeping the winding.
+/// Ear clipping, so concave polygons work too.
+fn triangulate(polygon: &[Vec3]) -> Vec<[usize; 3]> {
+    let n = polygon.len();
+    // Newell's normal, the corners of a convex ear turn around it
+    let mut normal = Vec3::ZERO;
+    for i in 0..n {
+        normal += polygon[i].cross(polygon[(i + 1) % n]);
+    }
+
+    let mut remaining: Vec<usize> = (0..n).collect();
+    let mut triangles = vec![];
+    while remaining.len() > 3 {
+        let len = remaining.len();
+        let corner = |i: usize| {
+            [
+                remaining[(i + len - 1) % len],
+                remaining[i],
+                remaining[(i + 1) % len],
+            ]
+        };
+        let ear = (0..len).find(|&i| {
+            let [a, b, c] = corner(i);
+            let [pa, pb, pc] = [polygon[a], polygon[b], polygon[c]];
+            let convex = (pb - pa).cross(pc - pb).dot(normal) > 0.;
+            convex
+                && !remaining.iter().any(|&j| {
+                    j != a && j != b && j != c && inside_triangle(polygon[j], [pa, pb, pc], normal)
+                })
+        });
+        // A degenerate polygon has no ears, it's cut as a fan
+        let ear = ear.unwrap_or(0);
+        triangles.push(corner(ear));
+        remaining.remove(ear);
+    }
+    triangles.push([remaining[0], remaining[1], remaining[2]]);
+    triangles
+}
+
+fn inside_triangle(p: Vec3, [a, b, c]: [Vec3; 3], normal: Vec3) -> bool {
+    [(a, b), (b, c), (c, a)]
+        .iter()
+        .all(|&(from, to)| (to - from).cross(p - from).dot(normal) >= 0.)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::RandLCG;
+
+    /// The corners of each triangle of a mesh
+    fn triangles(mesh: &Mesh) -> Vec<[(Vec3, Vec2, Vec3); 3]> {
+        mesh.indices
+            .chunks_exact(3)
+            .map(|t| {
+                [0, 1, 2].map(|c| {
+                    let i = t[c] as usize;
+                    (mesh.vertices[i], mesh.uvs[i], mesh.normals[i])
+                })
+            })
+            .collect()
+    }
+
+    fn area(triangle: [Vec3; 3]) -> f32 {
+        (triangle[1] - triangle[0])
+            .cross(triangle[2] - triangle[0])
+            .length()
+            / 2.
+    }
+
+    #[test]
+    fn two_materials_fixture() {
+        let mesh = Mesh::from_obj(include_bytes!("fixtures/two_materials.obj")).unwrap();
+        assert_eq!(mesh.material_library.as_deref(), Some("two_materials.mtl"));
+        let materials: Vec<(Option<&str>, Range<usize>)> = mesh
+            .submeshes
+            .iter()
+            .map(|submesh| (submesh.material.as_deref(), submesh.indices.clone()))
+            .collect();
+        assert_eq!(materials, [(Some("first"), 0..9), (Some("second"), 9..15)]);
+        // the corners of the pentagon have the same uv and normal, they are shared
+        assert_eq!(mesh.vertices.len(), 9);
+
+        let triangles = triangles(&mesh);
+        let (pentagon, quad) = triangles.split_at(3);
+        // the 2x2 square less the notch of the concave corner
+        let pentagon_area: f32 = pentagon.iter().map(|t| area(t.map(|c| c.0))).sum();
+        assert!((pentagon_area - 3.).abs() < 1e-6);
+        for (position, uv, normal) in pentagon.iter().flatten() {
+            assert_eq!(position.z, 0.);
+            assert_eq!(*uv, Vec2::ZERO);
+            assert_eq!(*normal, Vec3::Z);
+        }
+        let quad_area: f32 = quad.iter().map(|t| area(t.map(|c| c.0))).sum();
+        assert!((quad_area - 1.).abs() < 1e-6);
+        // the vs are flipped, the normal is normalized
+        for (position, uv, normal) in quad.iter().flatten() {
+            assert_eq!(*uv, Vec2::new(position.x, 1. - position.y));
+            assert_eq!(*normal, Vec3::Z);
+        }
+    }
+
+    #[test]
+    fn zero_index_is_an_error() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nvn 0 0 1\n";
+        assert!(Mesh::from_obj(format!("{obj}f 1/1/1 2/1/1 3/1/1").as_bytes()).is_ok());
+        assert!(Mesh::from_obj(format!("{obj}f 1/0/1 2/1/1 3/1/1").as_bytes()).is_err());
+        assert!(Mesh::from_obj(format!("{obj}f 1/1/1 2/1/0 3/1/1").as_bytes()).is_err());
+        assert!(Mesh::from_obj(format!("{obj}f 0/1/1 2/1/1 3/1/1").as_bytes()).is_err());
+    }
+
+    #[test]
+    fn more_vertices_than_u16() {
+        // a grid of quads, with more corners than a 16 bit index can reach
+        let (w, h) = (257, 256);
+        let mut obj = String::new();
+        for y in 0..h {
+            for x in 0..w {
+                obj += &format!("v {} {} 0\n", x, y);
+            }
+        }
+        for y in 0..h - 1 {
+            for x in 0..w - 1 {
+                let i = y * w + x + 1;
+                obj += &format!("f {} {} {} {}\n", i, i + 1, i + 1 + w, i + w);
+            }
+        }
+        let mesh = Mesh::from_obj(obj.as_bytes()).unwrap();
+        assert_eq!(mesh.vertices.len(), (w * h) as usize);
+        assert_eq!(mesh.indices.len(), ((w - 1) * (h - 1) * 6) as usize);
+        let last = *mesh.indices.iter().max().unwrap();
+        assert!(last > u16::MAX as u32);
+        assert_eq!(mesh.vertices[last as usize], Vec3::new(256., 255., 0.));
+    }
+
+    #[test]
+    fn random_meshes_round_trip() {
+        let mut rand = RandLCG::new(9);
+        let value = |rand: &mut RandLCG| (rand.next_f32() - 0.5) * 100.;
+        for _ in 0..50 {
+            let count = 3 + rand.next_u32() as usize % 30;
+            let positions: Vec<Vec3> = (0..count)
+                .map(|_| Vec3::new(value(&mut rand), value(&mut rand), value(&mut rand)))
+                .collect();
+            let uvs: Vec<Vec2> = (0..count)
+                .map(|_| Vec2::new(rand.next_f32(), rand.next_f32()))
+                .collect();
+            let normals: Vec<Vec3> = (0..count)
+                .map(|_| Vec3::new(value(&mut rand), value(&mut rand), value(&mut rand)))
+                .collect();
+            let faces: Vec<[usize; 3]> = (0..1 + rand.next_u32() % 40)
+                .map(|_| [0; 3].map(|_| rand.next_u32() as usize % count))
+                .collect();
+
+            let mut obj = String::new();
+            for p in &positions {
+                obj += &format!("v {} {} {}\n", p.x, p.y, p.z);
+            }
+            for uv in &uvs {
+                obj += &format!("vt {} {}\n", uv.x, uv.y);
+            }
+            for n in &normals {
+                obj += &format!("vn {} {} {}\n", n.x, n.y, n.z);
+            }
+            for face in &faces {
+                let corners = face.map(|i| format!("{0}/{0}/{0}", i + 1));
+                obj += &format!("f {}\n", corners.join(" "));
+            }
+
+            let mesh = Mesh::from_obj(obj.as_bytes()).unwrap();
+            let expected: Vec<[(Vec3, Vec2, Vec3); 3]> = faces
+                .iter()
+                .map(|face| {
+                    face.map(|i| {
+                        let uv = Vec2::new(uvs[i].x, 1. - uvs[i].y);
+                        (positions[i], uv, normals[i].normalize_or_zero())
+                    })
+                })
+                .collect();
+            assert_eq!(triangles(&mesh), expected);
+        }
+    }
+}
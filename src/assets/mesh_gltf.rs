@@ -0,0 +1,262 @@
+use glam::{Vec2, Vec3};
+
+use crate::{Json, Mesh, Submesh};
+
+const GLB_CHUNK_JSON: u32 = 0x4E4F534A;
+const GLB_CHUNK_BIN: u32 = 0x004E4942;
+
+impl Mesh {
+    /// Every triangle primitive of every mesh of a binary gltf, as submeshes.
+    /// The node transforms are not applied.
+    // Specification: https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html
+    pub fn from_glb(s: &[u8]) -> Result<Self, String> {
+        let u32_at = |at: usize| -> Result<u32, String> {
+            let bytes = s.get(at..at + 4).ok_or("Glb: truncated")?;
+            Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+        };
+        if s.get(0..4) != Some(b"glTF") {
+            return Err("Not a glb".to_string());
+        }
+        let version = u32_at(4)?;
+        if version != 2 {
+            return Err(format!("Glb: version {version} not supported"));
+        }
+
+        let mut json = None;
+        let mut bin: &[u8] = &[];
+        let mut c = 12;
+        while c + 8 <= s.len() {
+            let length = u32_at(c)? as usize;
+            let chunk_type = u32_at(c + 4)?;
+            let data = s.get(c + 8..c + 8 + length).ok_or("Glb: truncated chunk")?;
+            match chunk_type {
+                GLB_CHUNK_JSON => {
+                    let text = std::str::from_utf8(data).map_err(|e| format!("Glb: {e}"))?;
+                    json = Some(Json::parse(text)?);
+                }
+                GLB_CHUNK_BIN => bin = data,
+                _ => {}
+            }
+            c += 8 + length;
+        }
+        let gltf = json.ok_or("Glb: missing json chunk")?;
+
+        let mut vertices: Vec<Vec3> = vec![];
+        let mut uvs: Vec<Vec2> = vec![];
+        let mut normals: Vec<Vec3> = vec![];
+        let mut indices: Vec<u32> = vec![];
+        let mut submeshes = vec![];
+        let meshes = gltf.get("meshes").and_then(Json::as_array).unwrap_or(&[]);
+        for mesh in meshes {
+            let primitives = mesh.get("primitives").and_then(Json::as_array);
+            for primitive in primitives.unwrap_or(&[]) {
+                // 4 is triangles
+                let mode = primitive.get("mode").and_then(Json::as_usize).unwrap_or(4);
+                if mode != 4 {
+                    return Err(format!("Glb: primitive mode {mode} not supported"));
+                }
+                let attributes = primitive.get("attributes").ok_or("Glb: no attributes")?;
+                let attribute = |name: &str| attributes.get(name).and_then(Json::as_usize);
+
+                let position = attribute("POSITION").ok_or("Glb: no POSITION attribute")?;
+                let positions = read_accessor(&gltf, bin, position, 3)?;
+                let base = vertices.len();
+                let count = positions.len() / 3;
+                vertices.extend(
+                    positions
+                        .chunks_exact(3)
+                        .map(|p| Vec3::new(p[0] as f32, p[1] as f32, p[2] as f32)),
+                );
+
+                if let Some(uv) = attribute("TEXCOORD_0") {
+                    uvs.extend(
+                        read_accessor(&gltf, bin, uv, 2)?
+                            .chunks_exact(2)
+                            .map(|uv| Vec2::new(uv[0] as f32, uv[1] as f32)),
+                    );
+                }
+                uvs.resize(vertices.len(), Vec2::ZERO);
+
+                let primitive_indices: Vec<usize> =
+                    match primitive.get("indices").and_then(Json::as_usize) {
+                        Some(accessor) => read_accessor(&gltf, bin, accessor, 1)?
+                            .iter()
+                            .map(|&i| i as usize)
+                            .collect(),
+                        None => (0..count).collect(),
+                    };
+                if !primitive_indices.len().is_multiple_of(3) {
+                    return Err(format!(
+                        "Glb: {} indices, not whole triangles",
+                        primitive_indices.len()
+                    ));
+                }
+                if let Some(out_of_range) = primitive_indices.iter().find(|&&i| i >= count) {
+                    return Err(format!("Glb: index {out_of_range} out of range"));
+                }
+
+                match attribute("NORMAL") {
+                    Some(normal) => normals.extend(
+                        read_accessor(&gltf, bin, normal, 3)?
+                            .chunks_exact(3)
+                            .map(|n| Vec3::new(n[0] as f32, n[1] as f32, n[2] as f32)),
+                    ),
+                    None => {
+                        // smooth normals, the faces around a vertex are averaged
+                        let mut smooth = vec![Vec3::ZERO; count];
+                        for triangle in primitive_indices.chunks_exact(3) {
+                            let [v0, v1, v2] = [0, 1, 2].map(|i| vertices[base + triangle[i]]);
+                            let normal = (v1 - v0).cross(v2 - v0);
+                            for &i in triangle {
+                                smooth[i] += normal;
+                            }
+                        }
+                        normals.extend(smooth.iter().map(|n| n.normalize_or_zero()));
+                    }
+                }
+                normals.resize(vertices.len(), Vec3::Z);
+
+                let start = indices.len();
+                indices.extend(primitive_indices.iter().map(|&i| (base + i) as u32));
+                let material = primitive
+                    .get("material")
+                    .and_then(Json::as_usize)
+                    .and_then(|material| gltf.get("materials")?.at(material)?.get("name"))
+                    .and_then(Json::as_str)
+                    .map(str::to_string);
+                submeshes.push(Submesh {
+                    material,
+                    indices: start..indices.len(),
+                });
+            }
+        }
+
+        Ok(Mesh {
+            vertices,
+            uvs,
+            normals,
+            indices,
+            material_library: None,
+            submeshes,
+        })
+    }
+}
+
+/// Components of the elements of an accessor, normalized integers are mapped to 0..1 or -1..1
+fn read_accessor(
+    gltf: &Json,
+    bin: &[u8],
+    index: usize,
+    components: usize,
+) -> Result<Vec<f64>, String> {
+    let accessor = gltf
+        .get("accessors")
+        .and_then(|accessors| accessors.at(index))
+        .ok_or(format!("Glb: missing accessor {index}"))?;
+    let field = |name: &str| accessor.get(name).and_then(Json::as_usize);
+    let count = field("count").ok_or("Glb: accessor without count")?;
+    let component_type = field("componentType").ok_or("Glb: accessor without type")?;
+    let normalized = accessor
+        .get("normalized")
+        .and_then(Json::as_bool)
+        .unwrap_or(false);
+    let element_type = accessor.get("type").and_then(Json::as_str).unwrap_or("");
+    let expected = match components {
+        1 => "SCALAR",
+        2 => "VEC2",
+        3 => "VEC3",
+        _ => "VEC4",
+    };
+    if element_type != expected {
+        return Err(format!(
+            "Glb: accessor {index} is {element_type}, expected {expected}"
+        ));
+    }
+    // sparse accessors and accessors without buffer view are not supported
+    let view_index = field("bufferView").ok_or(format!("Glb: accessor {index} has no view"))?;
+    let view = gltf
+        .get("bufferViews")
+        .and_then(|views| views.at(view_index))
+        .ok_or(format!("Glb: missing buffer view {view_index}"))?;
+    let view_field = |name: &str| view.get(name).and_then(Json::as_usize);
+    if view_field("buffer").unwrap_or(0) != 0 {
+        return Err("Glb: only the binary chunk buffer is supported".to_string());
+    }
+
+    let size = match component_type {
+        5120 | 5121 => 1,
+        5122 | 5123 => 2,
+        5125 | 5126 => 4,
+        _ => {
+            return Err(format!(
+                "Glb: component type {component_type} not supported"
+            ))
+        }
+    };
+    let stride = view_field("byteStride").unwrap_or(size * components);
+    let offset = view_field("byteOffset").unwrap_or(0) + field("byteOffset").unwrap_or(0);
+    if count > 0 && offset + stride * (count - 1) + size * components > bin.len() {
+        return Err(format!("Glb: accessor {index} out of the buffer"));
+    }
+
+    let mut values = Vec::with_capacity(count * components);
+    for element in 0..count {
+        for component in 0..components {
+            let at = offset + element * stride + component * size;
+            let b = &bin[at..at + size];
+            let value = match component_type {
+                5120 => b[0] as i8 as f64,
+                5121 => b[0] as f64,
+                5122 => i16::from_le_bytes([b[0], b[1]]) as f64,
+                5123 => u16::from_le_bytes([b[0], b[1]]) as f64,
+                5125 => u32::from_le_bytes(b.try_into().unwrap()) as f64,
+                _ => f32::from_le_bytes(b.try_into().unwrap()) as f64,
+            };
+            let value = match (normalized, component_type) {
+                (true, 5120) => (value / 127.).max(-1.),
+                (true, 5121) => value / 255.,
+                (true, 5122) => (value / 32767.).max(-1.),
+                (true, 5123) => value / 65535.,
+                _ => value,
+            };
+            values.push(value);
+        }
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quad_fixture() {
+        let mesh = Mesh::from_glb(include_bytes!("fixtures/quad.glb")).unwrap();
+        let materials: Vec<(Option<&str>, std::ops::Range<usize>)> = mesh
+            .submeshes
+            .iter()
+            .map(|submesh| (submesh.material.as_deref(), submesh.indices.clone()))
+            .collect();
+        assert_eq!(materials, [(Some("top"), 0..6), (None, 6..9)]);
+        // the triangle without indices follows the quad
+        assert_eq!(mesh.indices, [0, 1, 2, 0, 2, 3, 4, 5, 6]);
+        assert_eq!(mesh.vertices[2], Vec3::new(1., 1., 0.));
+        assert_eq!(mesh.vertices[6], Vec3::new(0., 0., 2.));
+        // normalized bytes, and zeros for the triangle without uvs
+        assert_eq!(mesh.uvs[1], Vec2::new(1., 0.));
+        assert_eq!(mesh.uvs[6], Vec2::ZERO);
+        assert_eq!(mesh.normals[0], Vec3::Z);
+        // the triangle lies on x = 0, the smooth normals point along x
+        assert!(mesh.normals[4..].iter().all(|&n| n == Vec3::X));
+    }
+
+    #[test]
+    fn damaged_is_an_error() {
+        let glb = include_bytes!("fixtures/quad.glb");
+        assert!(Mesh::from_glb(&glb[..glb.len() - 8]).is_err());
+        assert!(Mesh::from_glb(&glb[4..]).is_err());
+        let mut version = glb.to_vec();
+        version[4] = 1;
+        assert!(Mesh::from_glb(&version).is_err());
+    }
+}
@@ -1,30 +1,102 @@
-use std::{cell::RefCell, collections::HashMap, sync::Arc};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
+use crate::locale::LangTable;
+
+// The bundle writer of the build script, its deflate checks the inflate of the tests
+#[cfg(test)]
+#[allow(dead_code)]
+#[path = "../../asset_bundle.rs"]
+mod asset_bundle;
+pub mod atlas;
+pub mod audio_ogg;
 pub mod audio_pcm;
+pub mod bundle;
+pub mod crc32;
+pub mod font;
+#[cfg(all(
+    feature = "hot-reload",
+    not(any(target_arch = "wasm32", target_os = "android"))
+))]
+pub mod hot_reload;
 pub mod image;
+pub mod json;
+pub mod manifest;
+pub mod material;
 pub mod mesh;
+pub mod mesh_gltf;
+pub mod theme;
+pub mod tilepack;
+pub mod tileset;
 pub mod zlib_inflate;
 
+pub use atlas::*;
+pub use audio_ogg::*;
 pub use audio_pcm::*;
+pub use bundle::*;
+pub use font::*;
 pub use image::*;
+pub use json::*;
+pub use manifest::*;
+pub use material::*;
 pub use mesh::*;
+pub use theme::*;
+pub use tilepack::*;
+pub use tileset::*;
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct AssetId(pub u64);
 
-const ASSET_FOLDER: &str = "assets/";
+pub(crate) const ASSET_FOLDER: &str = "assets/";
+const MUSIC_FOLDER: &str = "music/";
+/// Em size of the glyphs of the font atlases, in pixels
+const FONT_BAKE_SIZE: f32 = 48.;
+/// Reads of a file before it's given up on
+pub const LOAD_ATTEMPTS: u32 = 3;
+/// Seconds before a file that couldn't be read is read again
+const LOAD_RETRY_DELAY: f64 = 1.;
 
-/// Loads assets from the local `ASSET_FOLDER`.
+/// Loads assets from the local `ASSET_FOLDER`, or from the `bundle` when there is one.
 /// Provides access to assets via `AssetId`.
 /// Assets are parsed into game-ready formats into the `states` value.
-/// Raw data is not retained after parsing.
+/// Raw data is not retained after parsing, except for music that is streamed.
 #[derive(Default, Debug)]
 pub struct Assets {
     pub states: HashMap<String, AssetState>,
     pub images: HashMap<AssetId, Image>,
     pub meshes: HashMap<AssetId, Mesh>,
+    pub materials: HashMap<AssetId, MaterialLibrary>,
+    /// The atlas image of a font is in `images` with the same id
+    pub fonts: HashMap<AssetId, FontAtlas>,
+    /// Parsed ttf files, to add glyphs to their atlas
+    pub font_sources: HashMap<AssetId, Font>,
+    /// Fonts with new glyphs since the last update
+    pub rebaked_fonts: Vec<AssetId>,
+    /// Removed since the last update, to free their gpu and audio copies
+    pub unloaded: Vec<AssetId>,
     pub audio_pcm: HashMap<AssetId, AudioPcm>,
+    pub lang_tables: HashMap<AssetId, LangTable>,
+    pub themes: HashMap<AssetId, Theme>,
+    pub tile_sets: HashMap<AssetId, TileSet>,
+    pub tile_packs: HashMap<AssetId, TilePack>,
+    /// The image of an atlas is loaded as its own asset
+    pub atlases: HashMap<AssetId, SpriteAtlas>,
+    pub music: HashMap<AssetId, MusicData>,
+    /// The files to load before the game starts, once `MANIFEST_PATH` is loaded
+    pub manifest: Option<Manifest>,
     pub id_sequential: u64,
+    /// Bundled files are read from it instead of the `ASSET_FOLDER`
+    pub bundle: Option<AssetBundle>,
+    /// How forgiving the png decoder is with the images loaded
+    pub png_options: PngOptions,
+    #[cfg(all(
+        feature = "hot-reload",
+        not(any(target_arch = "wasm32", target_os = "android"))
+    ))]
+    pub watcher: hot_reload::AssetWatcher,
 }
 
 #[derive(Clone, Debug)]
@@ -32,11 +104,23 @@ pub enum AssetState {
     Idle,
     Requested(AssetRequest),
     Loaded(AssetId),
+    /// The file couldn't be read, it's read again at `retry_time` until `LOAD_ATTEMPTS`
+    Failed {
+        error: String,
+        attempts: u32,
+        retry_time: f64,
+    },
 }
 
-/// A pointer to a [u8] that can be shared.
+/// The bytes of a file or why it couldn't be read
+type ReadResult = Result<Vec<u8>, String>;
+
+/// A pointer to the read [u8] or the error that can be shared, and the reads before it.
 #[derive(Clone, Debug)]
-pub struct AssetRequest(Arc<RefCell<Option<Vec<u8>>>>);
+pub struct AssetRequest {
+    data: Arc<RefCell<Option<ReadResult>>>,
+    attempts: u32,
+}
 
 impl Assets {
     pub fn new() -> Self {
@@ -44,8 +128,27 @@ impl Assets {
             states: HashMap::new(),
             images: HashMap::new(),
             meshes: HashMap::new(),
+            materials: HashMap::new(),
+            fonts: HashMap::new(),
+            font_sources: HashMap::new(),
+            rebaked_fonts: vec![],
+            unloaded: vec![],
             audio_pcm: HashMap::new(),
+            lang_tables: HashMap::new(),
+            themes: HashMap::new(),
+            tile_sets: HashMap::new(),
+            tile_packs: HashMap::new(),
+            atlases: HashMap::new(),
+            music: HashMap::new(),
+            manifest: None,
             id_sequential: 1,
+            bundle: None,
+            png_options: PngOptions::default(),
+            #[cfg(all(
+                feature = "hot-reload",
+                not(any(target_arch = "wasm32", target_os = "android"))
+            ))]
+            watcher: hot_reload::AssetWatcher::default(),
         }
     }
 
@@ -70,33 +173,51 @@ impl Assets {
         }
     }
 
-    /// Loads the requested assets
-    pub fn update(&mut self) -> Vec<AssetId> {
+    /// Loads the requested assets, reads again the files that failed when it's time
+    pub fn update(&mut self, current_time: f64) -> Vec<AssetId> {
         let mut loaded: Vec<(String, Vec<u8>)> = vec![];
         for (path, state) in &mut self.states {
             match state {
-                AssetState::Idle => {
-                    let request = Arc::new(RefCell::new(None));
-                    // Save a pointer to the request, which will be inspected every frame
-                    *state = AssetState::Requested(AssetRequest(request.clone()));
-                    let actual_path = if cfg!(target_os = "android") {
-                        // Android expects assets to be in the same folder as the apk
-                        path.to_string()
-                    } else {
-                        format!("{}{}", ASSET_FOLDER, path)
-                    };
-                    miniquad::fs::load_file(&actual_path.clone(), move |data| {
-                        if let Ok(data) = data {
-                            *request.borrow_mut() = Some(data);
-                        } else {
-                            panic!("Failed to load: {}", actual_path);
-                        };
-                    });
+                AssetState::Idle if self.bundle.as_ref().is_some_and(|b| b.contains(path)) => {
+                    // the bundle won't read differently the next time
+                    match self.bundle.as_ref().unwrap().read(path) {
+                        Ok(data) => loaded.push((path.clone(), data)),
+                        Err(error) => {
+                            eprintln!("Failed to load {}: {}", path, error);
+                            *state = AssetState::Failed {
+                                error,
+                                attempts: LOAD_ATTEMPTS,
+                                retry_time: current_time,
+                            };
+                        }
+                    }
+                }
+                AssetState::Idle => *state = Self::read_file(path, 0),
+                AssetState::Failed {
+                    attempts,
+                    retry_time,
+                    ..
+                } if *attempts < LOAD_ATTEMPTS && current_time >= *retry_time => {
+                    *state = Self::read_file(path, *attempts);
                 }
-                AssetState::Requested(AssetRequest(request)) => {
-                    if request.borrow().is_some() {
-                        let data: Vec<u8> = request.borrow_mut().take().unwrap();
-                        loaded.push((path.clone(), data));
+                AssetState::Failed { .. } => {}
+                AssetState::Requested(request) => {
+                    let attempts = request.attempts + 1;
+                    let result = request.data.borrow_mut().take();
+                    match result {
+                        Some(Ok(data)) => loaded.push((path.clone(), data)),
+                        Some(Err(error)) => {
+                            eprintln!(
+                                "Failed to load {} ({}/{}): {}",
+                                path, attempts, LOAD_ATTEMPTS, error
+                            );
+                            *state = AssetState::Failed {
+                                error,
+                                attempts,
+                                retry_time: current_time + LOAD_RETRY_DELAY,
+                            };
+                        }
+                        None => {}
                     }
                 }
                 AssetState::Loaded(_) => {}
@@ -104,32 +225,266 @@ impl Assets {
         }
         let mut loaded_assets = vec![];
         for (path, data) in loaded {
-            let id = self.process_asset(&path, &data);
-            loaded_assets.push(id.clone());
-            self.states.insert(path, AssetState::Loaded(id));
+            match self.process_asset(&path, &data) {
+                Ok(id) => {
+                    loaded_assets.push(id.clone());
+                    self.states.insert(path, AssetState::Loaded(id));
+                }
+                // the same bytes won't parse the next time
+                Err(error) => {
+                    eprintln!("Failed to parse {}: {}", path, error);
+                    let state = AssetState::Failed {
+                        error,
+                        attempts: LOAD_ATTEMPTS,
+                        retry_time: current_time,
+                    };
+                    self.states.insert(path, state);
+                }
+            }
         }
+        self.bake_lang_glyphs(&mut loaded_assets);
         loaded_assets
     }
 
+    /// Starts reading the file of the asset, after `attempts` that failed
+    fn read_file(path: &str, attempts: u32) -> AssetState {
+        let data = Arc::new(RefCell::new(None));
+        // Save a pointer to the request, which will be inspected every frame
+        let state = AssetState::Requested(AssetRequest {
+            data: data.clone(),
+            attempts,
+        });
+        let actual_path = if cfg!(target_os = "android") {
+            // Android expects assets to be in the same folder as the apk
+            path.to_string()
+        } else {
+            format!("{}{}", ASSET_FOLDER, path)
+        };
+        miniquad::fs::load_file(&actual_path, move |result| {
+            *data.borrow_mut() = Some(result.map_err(|err| format!("{:?}", err)));
+        });
+        state
+    }
+
+    /// The files given up on are read again
+    pub fn retry_failed(&mut self) {
+        for state in self.states.values_mut() {
+            if let AssetState::Failed { .. } = state {
+                *state = AssetState::Idle;
+            }
+        }
+    }
+
+    /// Paths of the files given up on, with the last error
+    pub fn failed(&self) -> Vec<(&String, &String)> {
+        let mut failed: Vec<(&String, &String)> = self
+            .states
+            .iter()
+            .filter_map(|(path, state)| match state {
+                AssetState::Failed {
+                    error, attempts, ..
+                } if *attempts >= LOAD_ATTEMPTS => Some((path, error)),
+                _ => None,
+            })
+            .collect();
+        failed.sort();
+        failed
+    }
+
     /// Parses the raw asset data into a game-ready format
-    pub fn process_asset(&mut self, path: &str, data: &Vec<u8>) -> AssetId {
+    pub fn process_asset(&mut self, path: &str, data: &Vec<u8>) -> Result<AssetId, String> {
+        let id = self.new_id();
+        self.parse_asset(&id, path, data)?;
+        Ok(id)
+    }
+
+    /// Id of an asset that isn't loaded from a file, like a generated mesh
+    pub fn new_id(&mut self) -> AssetId {
         let id = AssetId(self.id_sequential);
         self.id_sequential += 1;
+        id
+    }
+
+    /// Parses the data into the asset of `id`, replacing the previous one.
+    /// Nothing is replaced when the file can't be parsed
+    fn parse_asset(&mut self, id: &AssetId, path: &str, data: &Vec<u8>) -> Result<(), String> {
+        // Gzipped assets are parsed as the file without the .gz
+        let decompressed;
+        let (path, data) = match path.strip_suffix(".gz") {
+            Some(inner) => {
+                decompressed =
+                    zlib_inflate::decompress_gzip(data).map_err(|e| format!("Gzip: {:?}", e))?;
+                (inner, &decompressed)
+            }
+            None => (path, data),
+        };
 
         if path.ends_with(".png") {
-            let image = Image::from_png(data).unwrap();
+            let image = Image::from_png_with(data, &self.png_options)?;
             self.images.insert(id.clone(), image);
         }
         if path.ends_with(".obj") {
-            let mesh = Mesh::from_obj(data).unwrap();
+            let mesh = Mesh::from_obj(data)?;
+            if let Some(library) = &mesh.material_library {
+                self.request_id(library.clone());
+            }
+            self.meshes.insert(id.clone(), mesh);
+        }
+        if path.ends_with(".glb") {
+            let mesh = Mesh::from_glb(data)?;
             self.meshes.insert(id.clone(), mesh);
         }
-        if path.ends_with(".wav") {
-            let audio_pcm = AudioPcm::from_wav(data).unwrap();
+        if path.ends_with(".ttf") {
+            let font = Font::from_ttf(data)?;
+            let chars = FontAtlas::DEFAULT_CHARS.into_iter().flatten();
+            let (atlas, image) = FontAtlas::bake(&font, FONT_BAKE_SIZE, chars)?;
+            self.fonts.insert(id.clone(), atlas);
+            self.images.insert(id.clone(), image);
+            self.font_sources.insert(id.clone(), font);
+        }
+        if path.ends_with(".lang") {
+            let table = LangTable::from_lang(data)?;
+            self.lang_tables.insert(id.clone(), table);
+        }
+        if path.ends_with(".theme") {
+            let theme = Theme::from_theme(data)?;
+            self.request_id(theme.font.clone());
+            self.themes.insert(id.clone(), theme);
+        }
+        if path.ends_with(".tileset") {
+            let tile_set = TileSet::from_tileset(data)?;
+            for kind in tile_set.tiles.iter() {
+                self.request_id(kind.mesh.clone());
+            }
+            self.tile_sets.insert(id.clone(), tile_set);
+        }
+        if path.ends_with(".tilepack") {
+            let tile_pack = TilePack::from_tilepack(data)?;
+            for path in tile_pack.paths() {
+                self.request_id(path.clone());
+            }
+            self.tile_packs.insert(id.clone(), tile_pack);
+        }
+        if path.ends_with(".atlas") {
+            let atlas = SpriteAtlas::from_atlas(data)?;
+            self.request_id(atlas.image.clone());
+            self.atlases.insert(id.clone(), atlas);
+        }
+        if path == MANIFEST_PATH {
+            let manifest = Manifest::from_manifest(data)?;
+            for path in manifest.paths() {
+                self.request_id(path.clone());
+            }
+            self.manifest = Some(manifest);
+        }
+        if path.ends_with(".mtl") {
+            let library = MaterialLibrary::from_mtl(data)?;
+            self.materials.insert(id.clone(), library);
+        }
+        // Music is streamed, the file is kept as it is
+        if path.starts_with(MUSIC_FOLDER) && path.ends_with(".wav") {
+            let music = MusicData::from_wav(data)?;
+            self.music.insert(id.clone(), music);
+        } else if path.starts_with(MUSIC_FOLDER) && path.ends_with(".ogg") {
+            let music = MusicData::from_ogg(data)?;
+            self.music.insert(id.clone(), music);
+        } else if path.ends_with(".wav") {
+            let audio_pcm = AudioPcm::from_wav(data)?;
+            self.audio_pcm.insert(id.clone(), audio_pcm);
+        } else if path.ends_with(".ogg") {
+            let audio_pcm = AudioPcm::from_ogg(data)?;
             self.audio_pcm.insert(id.clone(), audio_pcm);
         }
+        Ok(())
+    }
 
-        id
+    /// Forgets the asset, it's loaded again with a new id if it's requested.
+    /// The engine frees its gpu and audio copies at the next update.
+    pub fn unload(&mut self, id: &AssetId) {
+        let Some(path) = self.get_path(id).cloned() else {
+            return;
+        };
+        self.states.remove(&path);
+        self.images.remove(id);
+        self.meshes.remove(id);
+        self.materials.remove(id);
+        self.fonts.remove(id);
+        self.font_sources.remove(id);
+        self.audio_pcm.remove(id);
+        self.lang_tables.remove(id);
+        self.themes.remove(id);
+        self.tile_sets.remove(id);
+        self.tile_packs.remove(id);
+        self.atlases.remove(id);
+        self.music.remove(id);
+        self.unloaded.push(id.clone());
+    }
+
+    /// Unloads the tile pack and the files only it uses. A pack of the manifest stays,
+    /// and so do the files of the manifest and of the other packs loaded
+    pub fn unload_tile_pack(&mut self, path: &str) {
+        let Some(id) = self.get_id(path).cloned() else {
+            return;
+        };
+        let Some(pack) = self.tile_packs.get(&id) else {
+            return;
+        };
+        let kept: HashSet<&String> = self
+            .manifest
+            .iter()
+            .flat_map(Manifest::paths)
+            .chain(
+                self.tile_packs
+                    .iter()
+                    .filter(|(other, _)| **other != id)
+                    .flat_map(|(_, pack)| pack.paths()),
+            )
+            .collect();
+        if kept.contains(&path.to_string()) {
+            return;
+        }
+        let unused: Vec<AssetId> = pack
+            .paths()
+            .filter(|path| !kept.contains(path))
+            .filter_map(|path| self.get_id(path).cloned())
+            .collect();
+        for unused in unused {
+            self.unload(&unused);
+        }
+        self.unload(&id);
+    }
+
+    /// Of each group of the manifest, empty until it's loaded
+    pub fn manifest_progress(&self) -> Vec<GroupProgress<'_>> {
+        let Some(manifest) = &self.manifest else {
+            return vec![];
+        };
+        manifest
+            .groups
+            .iter()
+            .map(|group| GroupProgress {
+                name: &group.name,
+                loaded: group
+                    .paths
+                    .iter()
+                    .filter(|path| self.get_id(path).is_some())
+                    .count(),
+                total: group.paths.len(),
+                pending: group.paths.iter().find(|path| self.get_id(path).is_none()),
+            })
+            .collect()
+    }
+
+    /// Every file of the manifest is loaded
+    pub fn preloaded(&self) -> bool {
+        self.manifest
+            .as_ref()
+            .is_some_and(|manifest| manifest.paths().all(|path| self.get_id(path).is_some()))
+    }
+
+    /// The image is the atlas of a tile pack loaded
+    pub fn is_tile_atlas(&self, path: &str) -> bool {
+        self.tile_packs.values().any(|pack| pack.atlas == path)
     }
 
     /// AssetId from the path
@@ -140,6 +495,27 @@ impl Assets {
         }
     }
 
+    /// Bytes of the decoded images, meshes and sounds
+    pub fn memory(&self) -> usize {
+        let images: usize = self.images.values().map(|image| image.raw.len()).sum();
+        let meshes: usize = self
+            .meshes
+            .values()
+            .map(|mesh| {
+                (mesh.vertices.len() + mesh.normals.len()) * std::mem::size_of::<glam::Vec3>()
+                    + mesh.uvs.len() * std::mem::size_of::<glam::Vec2>()
+                    + mesh.indices.len() * std::mem::size_of::<u32>()
+            })
+            .sum();
+        let audio: usize = self
+            .audio_pcm
+            .values()
+            .map(|pcm| pcm.samples.len() * std::mem::size_of::<f32>())
+            .sum();
+        let music: usize = self.music.values().map(|music| music.bytes.len()).sum();
+        images + meshes + audio + music
+    }
+
     /// path from AssetId
     pub fn get_path(&self, id: &AssetId) -> Option<&String> {
         self.states.iter().find_map(|(path, state)| match state {
@@ -164,6 +540,93 @@ impl Assets {
         }
     }
 
+    /// Atlas access from the path, with the id of its image once it's loaded
+    pub fn get_atlas(&self, path: &str) -> Option<(&SpriteAtlas, &AssetId)> {
+        let atlas = self.atlases.get(self.get_id(path)?)?;
+        Some((atlas, self.get_id(&atlas.image)?))
+    }
+
+    /// Image of each submesh of a mesh, from its materials.
+    /// Requests to load the images, None until they are loaded.
+    pub fn submesh_images(&mut self, mesh_id: &AssetId) -> Vec<Option<AssetId>> {
+        let Some(mesh) = self.meshes.get(mesh_id) else {
+            return vec![];
+        };
+        let library = mesh.material_library.clone();
+        let materials: Vec<Option<String>> = mesh
+            .submeshes
+            .iter()
+            .map(|submesh| submesh.material.clone())
+            .collect();
+        let library_id = library.and_then(|path| self.request_id(path));
+        let textures: Vec<Option<String>> = materials
+            .iter()
+            .map(|material| {
+                let library = self.materials.get(library_id.as_ref()?)?;
+                library.get(material.as_ref()?)?.diffuse_texture.clone()
+            })
+            .collect();
+        textures
+            .into_iter()
+            .map(|texture| self.request_id(texture?))
+            .collect()
+    }
+
+    /// Adds the characters of `text` that the font has to its atlas, like the ones of a
+    /// localized string. The atlas is uploaded again at the next update.
+    pub fn bake_glyphs(&mut self, font_id: &AssetId, text: &str) {
+        let (Some(atlas), Some(font)) = (self.fonts.get(font_id), self.font_sources.get(font_id))
+        else {
+            return;
+        };
+        let mut new: Vec<char> = text
+            .chars()
+            .filter(|c| !atlas.glyphs.contains_key(c) && font.glyph_index(*c) != 0)
+            .collect();
+        new.sort();
+        new.dedup();
+        if new.is_empty() {
+            return;
+        }
+        let chars = atlas.glyphs.keys().copied().chain(new);
+        let (atlas, image) = match FontAtlas::bake(font, atlas.pixel_size, chars) {
+            Ok(baked) => baked,
+            Err(error) => {
+                eprintln!("Glyphs not baked: {}", error);
+                return;
+            }
+        };
+        self.fonts.insert(font_id.clone(), atlas);
+        self.images.insert(font_id.clone(), image);
+        self.rebaked_fonts.push(font_id.clone());
+    }
+
+    /// When a language or a font is among the `loaded`, the characters of every language
+    /// are added to every font, so that the translations aren't drawn as missing glyphs.
+    /// The new atlases are added to the `loaded` to be uploaded like loaded images
+    fn bake_lang_glyphs(&mut self, loaded: &mut Vec<AssetId>) {
+        let new_text = loaded
+            .iter()
+            .any(|id| self.lang_tables.contains_key(id) || self.font_sources.contains_key(id));
+        if new_text {
+            let text: String = self
+                .lang_tables
+                .values()
+                .flat_map(|table| table.strings.values())
+                .map(String::as_str)
+                .collect();
+            let fonts: Vec<AssetId> = self.font_sources.keys().cloned().collect();
+            for font_id in fonts {
+                self.bake_glyphs(&font_id, &text);
+            }
+        }
+        for id in std::mem::take(&mut self.rebaked_fonts) {
+            if !loaded.contains(&id) {
+                loaded.push(id);
+            }
+        }
+    }
+
     /// Sound access from the path
     pub fn get_sound(&self, path: &str) -> Option<(&AudioPcm, &AssetId)> {
         match self.states.get(path)? {
@@ -222,3 +685,83 @@ impl<'a> ByteDecoder<'a> {
         v
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn damaged_files_are_errors() {
+        let mut assets = Assets::new();
+        let png = include_bytes!("fixtures/palette_2bit.png");
+        assert!(assets.process_asset("fine.png", &png.to_vec()).is_ok());
+        assert!(assets
+            .process_asset("cut.png", &png[..40].to_vec())
+            .is_err());
+        assert!(assets
+            .process_asset("cut.tileset.gz", &vec![0x1f, 0x8b])
+            .is_err());
+        assert!(assets
+            .process_asset("cut.obj", &b"f 1 2 3".to_vec())
+            .is_err());
+        assert_eq!(assets.images.len(), 1);
+        assert!(assets.tile_sets.is_empty() && assets.meshes.is_empty());
+    }
+
+    #[test]
+    fn unloaded_tile_pack_keeps_the_shared_files() {
+        let mut assets = Assets::new();
+        let png = include_bytes!("fixtures/palette_2bit.png").to_vec();
+        let obj = include_bytes!("fixtures/two_materials.obj").to_vec();
+        let pack = |atlas: &str| {
+            format!("atlas {atlas}\nbase base.obj\nselector base.obj\nlock base.obj\n")
+        };
+        // as if loaded by the update
+        let mut load = |path: &str, data: &Vec<u8>| {
+            let id = assets.process_asset(path, data).unwrap();
+            assets
+                .states
+                .insert(path.to_string(), AssetState::Loaded(id.clone()));
+            id
+        };
+        let a = load("a.tilepack", &pack("a.png").into());
+        load("b.tilepack", &pack("b.png").into());
+        let a_png = load("a.png", &png);
+        let b_png = load("b.png", &png);
+        let base = load("base.obj", &obj);
+
+        assets.unload_tile_pack("a.tilepack");
+        assert_eq!(assets.unloaded, [a_png, a]);
+        assert!(assets.get_id("a.tilepack").is_none() && assets.get_id("a.png").is_none());
+        assert_eq!(assets.get_id("b.png"), Some(&b_png));
+        assert_eq!(assets.get_id("base.obj"), Some(&base));
+        assert_eq!(assets.tile_packs.len(), 1);
+    }
+
+    #[test]
+    fn languages_are_baked_into_the_fonts() {
+        let mut assets = Assets::new();
+        let ttf = include_bytes!("fixtures/squares.ttf").to_vec();
+        let font = assets.process_asset("squares.ttf", &ttf).unwrap();
+        assert!(!assets.fonts[&font].glyphs.contains_key(&'Ω'));
+        let lang = assets
+            .process_asset("el.lang", &"omega=Ω\n".into())
+            .unwrap();
+        let mut loaded = vec![lang.clone()];
+        assets.bake_lang_glyphs(&mut loaded);
+        assert!(assets.fonts[&font].glyphs.contains_key(&'Ω'));
+        // the atlas is uploaded again
+        assert_eq!(loaded, [lang, font]);
+    }
+
+    #[test]
+    fn corrupt_crc_loads_when_ignored() {
+        let mut png = include_bytes!("fixtures/palette_2bit.png").to_vec();
+        // the last byte of the crc of IEND
+        *png.last_mut().unwrap() ^= 0xff;
+        let mut assets = Assets::new();
+        assert!(assets.process_asset("corrupt.png", &png).is_err());
+        assets.png_options.ignore_crc = true;
+        assert!(assets.process_asset("corrupt.png", &png).is_ok());
+    }
+}
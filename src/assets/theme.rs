@@ -0,0 +1,103 @@
+use glam::Vec4;
+
+/// Themes with a `themes/<name>.theme` asset, the names are also locale keys
+pub const THEMES: [&str; 3] = ["dark", "light", "contrast"];
+
+pub const DEFAULT_THEME: &str = "dark";
+
+pub fn theme_path(name: &str) -> String {
+    format!("themes/{}.theme", name)
+}
+
+/// Colors, sizes and font of the ui, parsed from `key=value` lines.
+/// Lines starting with '#' are comments, colors are written as "#rrggbb" or "#rrggbbaa".
+/// Missing keys keep the values of the dark theme.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Theme {
+    /// Asset path of a bitmap font sheet or of a ttf font
+    pub font: String,
+    /// Em size of a ttf font, in pixels before `text_scale`
+    pub font_size: f32,
+    pub text_scale: f32,
+    pub text_color: Vec4,
+    pub button_padding: f32,
+    pub button_normal: Vec4,
+    pub button_hover: Vec4,
+    pub button_pressed: Vec4,
+    pub button_just_pressed: Vec4,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            font: "littlefont.png".to_string(),
+            font_size: 12.,
+            text_scale: 5.,
+            text_color: Vec4::ONE,
+            button_padding: 3.,
+            button_normal: Vec4::new(0.3, 0.2, 0.2, 1.0),
+            button_hover: Vec4::new(0.5, 0.2, 0.2, 1.0),
+            button_pressed: Vec4::new(0.8, 0.4, 0.0, 1.0),
+            button_just_pressed: Vec4::new(1.0, 0.5, 0.0, 1.0),
+        }
+    }
+}
+
+impl Theme {
+    pub fn from_theme(s: &[u8]) -> Result<Self, String> {
+        let text = std::str::from_utf8(s).map_err(|e| format!("Theme: {}", e))?;
+        let mut theme = Self::default();
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or(format!("Theme: missing '=' at line {}", line_number + 1))?;
+            let (key, value) = (key.trim(), value.trim());
+            let number = || {
+                value
+                    .parse::<f32>()
+                    .map_err(|e| format!("Theme: {} at line {}", e, line_number + 1))
+            };
+            let color = || {
+                parse_color(value)
+                    .ok_or(format!("Theme: invalid color at line {}", line_number + 1))
+            };
+            match key {
+                "font" => theme.font = value.to_string(),
+                "font_size" => theme.font_size = number()?,
+                "text_scale" => theme.text_scale = number()?,
+                "text_color" => theme.text_color = color()?,
+                "button_padding" => theme.button_padding = number()?,
+                "button_normal" => theme.button_normal = color()?,
+                "button_hover" => theme.button_hover = color()?,
+                "button_pressed" => theme.button_pressed = color()?,
+                "button_just_pressed" => theme.button_just_pressed = color()?,
+                _ => {
+                    return Err(format!(
+                        "Theme: unknown key {} at line {}",
+                        key,
+                        line_number + 1
+                    ))
+                }
+            }
+        }
+        Ok(theme)
+    }
+}
+
+/// "#rrggbb" or "#rrggbbaa"
+fn parse_color(s: &str) -> Option<Vec4> {
+    let hex = s.strip_prefix('#')?;
+    if !matches!(hex.len(), 6 | 8) || !hex.is_ascii() {
+        return None;
+    }
+    let mut channels = [1.; 4];
+    for (i, channel) in channels.iter_mut().enumerate().take(hex.len() / 2) {
+        let byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+        *channel = byte as f32 / 255.;
+    }
+    Some(Vec4::from_array(channels))
+}
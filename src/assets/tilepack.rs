@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+
+use super::TileKind;
+
+/// Packs with a `tilepacks/<name>.tilepack` asset, the names are also locale keys
+pub const TILE_PACKS: [&str; 3] = ["classic", "circuits", "runes"];
+
+/// The only pack in the manifest, the others are loaded when they're picked
+pub const DEFAULT_TILE_PACK: &str = "classic";
+
+pub fn tile_pack_path(name: &str) -> String {
+    format!("tilepacks/{}.tilepack", name)
+}
+
+/// How the tiles look, parsed from lines like
+/// "atlas <image>", "base <mesh>", "selector <mesh>" and "lock <mesh>", the files of the pack, and
+/// "tile <kind> <mesh>", the kind of the tile set named by its mesh is drawn with another mesh.
+/// The kinds not listed keep the mesh of the tile set. Lines starting with '#' are comments
+#[derive(Clone, Debug, PartialEq)]
+pub struct TilePack {
+    /// Texture of every mesh of the pack
+    pub atlas: String,
+    pub base: String,
+    pub selector: String,
+    /// Marker over the locked tiles
+    pub lock: String,
+    /// Meshes replacing the ones of the tile set, by the mesh they replace
+    pub tiles: HashMap<String, String>,
+}
+
+impl TilePack {
+    pub fn from_tilepack(s: &[u8]) -> Result<Self, String> {
+        let text = std::str::from_utf8(s).map_err(|e| format!("TilePack: {}", e))?;
+        let (mut atlas, mut base, mut selector, mut lock) = (None, None, None, None);
+        let mut tiles = HashMap::new();
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.split_whitespace().collect::<Vec<&str>>()[..] {
+                ["atlas", path] => atlas = Some(path.to_string()),
+                ["base", path] => base = Some(path.to_string()),
+                ["selector", path] => selector = Some(path.to_string()),
+                ["lock", path] => lock = Some(path.to_string()),
+                ["tile", kind, path] => {
+                    tiles.insert(kind.to_string(), path.to_string());
+                }
+                _ => return Err(format!("TilePack: invalid line {}", line_number + 1)),
+            }
+        }
+        let missing = |name: &str| format!("TilePack: no {}", name);
+        Ok(Self {
+            atlas: atlas.ok_or(missing("atlas"))?,
+            base: base.ok_or(missing("base"))?,
+            selector: selector.ok_or(missing("selector"))?,
+            lock: lock.ok_or(missing("lock"))?,
+            tiles,
+        })
+    }
+
+    /// The mesh the kind is drawn with
+    pub fn tile_mesh<'a>(&'a self, kind: &'a TileKind) -> &'a str {
+        self.tiles.get(&kind.mesh).unwrap_or(&kind.mesh)
+    }
+
+    /// Every file the pack draws with, the meshes of the kinds it doesn't replace aside
+    pub fn paths(&self) -> impl Iterator<Item = &String> {
+        [&self.atlas, &self.base, &self.selector, &self.lock]
+            .into_iter()
+            .chain(self.tiles.values())
+    }
+}
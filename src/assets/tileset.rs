@@ -0,0 +1,115 @@
+/// The tile set boards are built from, `tilesets/<name>.tileset`
+pub const DEFAULT_TILE_SET: &str = "classic";
+
+pub fn tile_set_path(name: &str) -> String {
+    format!("tilesets/{}.tileset", name)
+}
+
+/// Which side values fit together.
+/// A value fits itself, unless it has partners: then it only fits them, like a plug and its socket
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EdgeTypes {
+    pairs: Vec<(u8, u8)>,
+}
+
+impl EdgeTypes {
+    pub fn matches(&self, a: u8, b: u8) -> bool {
+        if self.pairs.iter().any(|(x, y)| *x == a || *y == a) {
+            self.pairs.contains(&(a, b)) || self.pairs.contains(&(b, a))
+        } else {
+            a == b
+        }
+    }
+}
+
+/// A kind of tile and how often the generator uses it
+#[derive(Clone, Debug, PartialEq)]
+pub struct TileKind {
+    /// Asset path of the mesh, also the name levels refer to it by
+    pub mesh: String,
+    /// Right, up, left and down
+    pub sides: [u8; 4],
+    /// Most tiles of this kind on a board, unlimited when None
+    pub copies: Option<u32>,
+    /// Chance of being picked over the other kinds that fit a cell
+    pub weight: u32,
+}
+
+/// The tiles and edges of a board, parsed from lines like
+/// "edge <a> <b>", the two side values fit, and
+/// "tile <mesh> <right> <up> <left> <down> [copies=<n>] [weight=<n>]".
+/// Lines starting with '#' are comments
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TileSet {
+    pub edges: EdgeTypes,
+    pub tiles: Vec<TileKind>,
+}
+
+impl TileSet {
+    pub fn from_tileset(s: &[u8]) -> Result<Self, String> {
+        let text = std::str::from_utf8(s).map_err(|e| format!("TileSet: {}", e))?;
+        let mut tile_set = Self::default();
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let err =
+                |e: &dyn std::fmt::Display| format!("TileSet: {} at line {}", e, line_number + 1);
+            let side = |token: &str| token.parse::<u8>().map_err(|e| err(&e));
+            match line.split_whitespace().collect::<Vec<&str>>()[..] {
+                ["edge", a, b] => tile_set.edges.pairs.push((side(a)?, side(b)?)),
+                ["tile", mesh, right, up, left, down, ref options @ ..] => {
+                    let mut kind = TileKind {
+                        mesh: mesh.to_string(),
+                        sides: [side(right)?, side(up)?, side(left)?, side(down)?],
+                        copies: None,
+                        weight: 1,
+                    };
+                    for option in options {
+                        let number = |value: &str| value.parse::<u32>().map_err(|e| err(&e));
+                        match option.split_once('=') {
+                            Some(("copies", value)) => kind.copies = Some(number(value)?),
+                            Some(("weight", value)) => kind.weight = number(value)?,
+                            _ => return Err(err(&format!("unknown option {}", option))),
+                        }
+                    }
+                    tile_set.tiles.push(kind);
+                }
+                _ => return Err(err(&"invalid line")),
+            }
+        }
+        if tile_set.tiles.is_empty() {
+            return Err("TileSet: no tiles".to_string());
+        }
+        Ok(tile_set)
+    }
+
+    /// No more than `copies` tiles of each kind, like the ones it already limits
+    pub fn with_copies(&self, copies: u32) -> Self {
+        Self {
+            edges: self.edges.clone(),
+            tiles: self
+                .tiles
+                .iter()
+                .map(|kind| TileKind {
+                    copies: Some(kind.copies.map_or(copies, |limit| limit.min(copies))),
+                    ..kind.clone()
+                })
+                .collect(),
+        }
+    }
+
+    /// Only the tiles with the meshes named, all of them if `meshes` is empty
+    pub fn filtered(&self, meshes: &[&str]) -> Self {
+        Self {
+            edges: self.edges.clone(),
+            tiles: self
+                .tiles
+                .iter()
+                .filter(|kind| meshes.is_empty() || meshes.contains(&kind.mesh.as_str()))
+                .cloned()
+                .collect(),
+        }
+    }
+}
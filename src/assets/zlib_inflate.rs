@@ -1,27 +1,92 @@
+use std::sync::OnceLock;
+
+use crate::crc32;
+
 /// Decompress a zlib compressed slice
 /// https://datatracker.ietf.org/doc/html/rfc1951
 pub fn decompress_zlib(data: &[u8]) -> Result<Vec<u8>, DecompressResult> {
+    if data.len() < 6 {
+        return Err(DecompressResult::UnexpectedEof);
+    }
     let _cmf = data[0];
     let flg = data[1];
     let fdict = flg >> 5 & 1;
     let skip_dict = (2 + (fdict * 4)) as usize;
-    decompress(&data[skip_dict..(data.len() - 4)])
+    let end = data.len() - 4;
+    decompress(
+        data.get(skip_dict..end)
+            .ok_or(DecompressResult::UnexpectedEof)?,
+    )
+}
+
+/// Decompress a gzip file, the members of a multi member file are concatenated
+/// https://datatracker.ietf.org/doc/html/rfc1952
+pub fn decompress_gzip(data: &[u8]) -> Result<Vec<u8>, DecompressResult> {
+    let mut decoder = Decoder::from_bytes(data);
+    let mut output: Vec<u8> = vec![];
+    loop {
+        let header_start = decoder.byte;
+        let id = decoder.next_bytes_as_slice(3)?;
+        if id != [0x1f, 0x8b, 8] {
+            return Err(DecompressResult::IllegalHeader);
+        }
+        let flg = decoder.next_byte()?;
+        let _mtime = decoder.next_bytes_as_number(4)?;
+        let _xfl = decoder.next_byte()?;
+        let _os = decoder.next_byte()?;
+        if flg & GZIP_FEXTRA != 0 {
+            let xlen = decoder.next_bytes_as_number(2)?;
+            decoder.next_bytes_as_slice(xlen as usize)?;
+        }
+        // file name and comment are zero terminated
+        for flag in [GZIP_FNAME, GZIP_FCOMMENT] {
+            if flg & flag != 0 {
+                while decoder.next_byte()? != 0 {}
+            }
+        }
+        if flg & GZIP_FHCRC != 0 {
+            let header_crc = crc32::crc32(&data[header_start..decoder.byte]) & 0xffff;
+            if decoder.next_bytes_as_number(2)? != header_crc {
+                return Err(DecompressResult::ChecksumMismatch);
+            }
+        }
+
+        let member = inflate(&mut decoder)?;
+        let crc = decoder.next_bytes_as_number(4)?;
+        let size = decoder.next_bytes_as_number(4)?;
+        if crc != crc32::crc32(&member) {
+            return Err(DecompressResult::ChecksumMismatch);
+        }
+        if size != member.len() as u32 {
+            return Err(DecompressResult::SizeMismatch);
+        }
+        output.extend(member);
+
+        if decoder.byte >= data.len() {
+            break;
+        }
+    }
+    Ok(output)
 }
 
 /// Decompress a zlib inflate slice
 /// https://datatracker.ietf.org/doc/html/rfc1951
 pub fn decompress(data: &[u8]) -> Result<Vec<u8>, DecompressResult> {
-    let mut decoder = Decoder::from_bytes(data);
+    inflate(&mut Decoder::from_bytes(data))
+}
+
+/// Decompress the blocks up to the final one, leaving the decoder after it
+fn inflate(decoder: &mut Decoder) -> Result<Vec<u8>, DecompressResult> {
     let mut output: Vec<u8> = vec![];
     for _ in 0..LARGE_ITERATION_COUNT {
-        let bfinal = decoder.next_bits(1);
-        let btype = decoder.next_bits(2);
+        let bfinal = decoder.next_bits(1)?;
+        let btype = decoder.next_bits(2)?;
         if btype == 0 {
-            read_uncompressed(&mut decoder, &mut output)?;
+            read_uncompressed(decoder, &mut output)?;
         } else if btype == 1 {
-            decompress_huffman_static(&mut decoder, &mut output)?;
+            decompress_huffman_static(decoder, &mut output)?;
         } else if btype == 2 {
-            decompress_huffman_dynamic(&mut decoder, &mut output)?;
+            decompress_huffman_dynamic(decoder, &mut output)?;
         } else {
             return Err(DecompressResult::IllegalBlockFormat);
         }
@@ -38,21 +103,27 @@ pub enum DecompressResult {
     UncompressedLengthMismatch,
     TreeError,
     IllegalSmallTree,
+    /// A back reference before the start of the output
+    IllegalDistance,
+    /// The data ends in the middle of a block
+    UnexpectedEof,
+    /// Not a gzip member
+    IllegalHeader,
+    /// The gzip crc doesn't match the data
+    ChecksumMismatch,
+    /// The gzip size doesn't match the data
+    SizeMismatch,
 }
 
 /// Read the block as is
 fn read_uncompressed(decoder: &mut Decoder, output: &mut Vec<u8>) -> Result<(), DecompressResult> {
-    let len: u16 = decoder.next_bytes_as_number(2) as u16;
-    let nlen: u16 = decoder.next_bytes_as_number(2) as u16;
+    let len: u16 = decoder.next_bytes_as_number(2)? as u16;
+    let nlen: u16 = decoder.next_bytes_as_number(2)? as u16;
     let nlen_one_complement = !nlen;
     if len != nlen_one_complement {
-        println!("{}, {len}, {nlen_one_complement}", decoder.byte);
         return Err(DecompressResult::UncompressedLengthMismatch);
     }
-    output.extend(decoder.next_bytes_as_slice(len.into()));
-    for _ in 0..len {
-        output.push(decoder.next_byte());
-    }
+    output.extend(decoder.next_bytes_as_slice(len.into())?);
     Ok(())
 }
 
@@ -61,9 +132,15 @@ fn decompress_huffman_static(
     decoder: &mut Decoder,
     output: &mut Vec<u8>,
 ) -> Result<(), DecompressResult> {
-    // TODO: should be precomputed
-    let literal_tree = HuffmanTree::from_bitlengths(
-        (0..288)
+    let (literal_tree, distance_tree) = static_trees();
+    decode_length_distance_pairs(decoder, &literal_tree, &distance_tree, output)
+}
+
+/// The fixed trees of the spec, built once
+fn static_trees() -> &'static (HuffmanTree, HuffmanTree) {
+    static TREES: OnceLock<(HuffmanTree, HuffmanTree)> = OnceLock::new();
+    TREES.get_or_init(|| {
+        let literal_bitlengths: Vec<u16> = (0..288)
             .map(|i| {
                 if i < 144 {
                     8
@@ -75,13 +152,12 @@ fn decompress_huffman_static(
                     8
                 }
             })
-            .collect::<Vec<u16>>()
-            .as_slice(),
-        (0..288).collect::<Vec<u16>>().as_slice(),
-    );
-    let distance_tree =
-        HuffmanTree::from_bitlengths(&[5; 30], (0..30).collect::<Vec<u16>>().as_slice());
-    decode_length_distance_pairs(decoder, &literal_tree, &distance_tree, output)
+            .collect();
+        (
+            HuffmanTree::from_bitlengths(&literal_bitlengths),
+            HuffmanTree::from_bitlengths(&[5; 30]),
+        )
+    })
 }
 
 /// Read the block using the trees at the beginning of the block
@@ -96,20 +172,17 @@ fn decompress_huffman_dynamic(
 /// Decode the huffman trees at the beginning of the block
 /// The two trees are also encoded using a small static tree of max 19 nodes
 fn read_trees(decoder: &mut Decoder) -> Result<(HuffmanTree, HuffmanTree), DecompressResult> {
-    let hlit = decoder.next_bits(5) + 257;
-    let hdist = decoder.next_bits(5) + 1;
-    let hclen = decoder.next_bits(4) + 4;
+    let hlit = decoder.next_bits(5)? + 257;
+    let hdist = decoder.next_bits(5)? + 1;
+    let hclen = decoder.next_bits(4)? + 4;
 
     // Read code lengths for the code length alphabet
     let mut code_length_tree_bitlengths: [u16; 19] = [0; 19];
     for i in 0..hclen {
         code_length_tree_bitlengths[TABLE_CODE_LENGTH_ORDER[i as usize] as usize] =
-            decoder.next_bits(3) as u16;
+            decoder.next_bits(3)? as u16;
     }
-    let code_length_tree = HuffmanTree::from_bitlengths(
-        &code_length_tree_bitlengths,
-        (0..19).collect::<Vec<u16>>().as_slice(),
-    );
+    let code_length_tree = HuffmanTree::from_bitlengths(&code_length_tree_bitlengths);
 
     let mut bitlengths: [u16; 1024] = [0; 1024];
     let mut bitlengths_count = 0;
@@ -119,31 +192,29 @@ fn read_trees(decoder: &mut Decoder) -> Result<(HuffmanTree, HuffmanTree), Decom
             bitlengths[bitlengths_count] = symbol;
             bitlengths_count += 1;
         } else if symbol == 16 {
+            if bitlengths_count == 0 {
+                return Err(DecompressResult::IllegalSmallTree);
+            }
             let prev = bitlengths[bitlengths_count - 1];
-            let repeat_length = decoder.next_bits(2) + 3;
+            let repeat_length = decoder.next_bits(2)? + 3;
             for _ in 0..repeat_length {
                 bitlengths[bitlengths_count] = prev;
                 bitlengths_count += 1;
             }
         } else if symbol == 17 {
-            let repeat_length = decoder.next_bits(3) + 3;
+            let repeat_length = decoder.next_bits(3)? + 3;
             bitlengths_count += repeat_length as usize;
         } else if symbol == 18 {
-            let repeat_length = decoder.next_bits(7) + 11;
+            let repeat_length = decoder.next_bits(7)? + 11;
             bitlengths_count += repeat_length as usize;
         } else {
             return Err(DecompressResult::IllegalSmallTree);
         }
     }
 
-    let literal_tree = HuffmanTree::from_bitlengths(
-        &bitlengths[0..(hlit as usize)],
-        (0..286).collect::<Vec<u16>>().as_slice(),
-    );
-    let distance_tree = HuffmanTree::from_bitlengths(
-        &bitlengths[(hlit as usize)..(hlit as usize + 30)],
-        (0..30).collect::<Vec<u16>>().as_slice(),
-    );
+    let literal_tree = HuffmanTree::from_bitlengths(&bitlengths[0..(hlit as usize)]);
+    let distance_tree =
+        HuffmanTree::from_bitlengths(&bitlengths[(hlit as usize)..(hlit as usize + 30)]);
 
     Ok((literal_tree, distance_tree))
 }
@@ -163,11 +234,20 @@ fn decode_length_distance_pairs(
             break;
         } else {
             let special_symbol = (symbol - 257) as usize;
-            let length = decoder.next_bits(TABLE_LENGTH_EXTRA_BITS[special_symbol])
+            if special_symbol >= TABLE_LENGTH_BASE.len() {
+                return Err(DecompressResult::IllegalBlockFormat);
+            }
+            let length = decoder.next_bits(TABLE_LENGTH_EXTRA_BITS[special_symbol])?
                 + TABLE_LENGTH_BASE[special_symbol];
             let distance_symbol = distance_tree.decode_symbol(decoder)? as usize;
-            let distance = decoder.next_bits(TABLE_DISTANCE_EXTRA_BITS[distance_symbol])
+            if distance_symbol >= TABLE_DISTANCE_BASE.len() {
+                return Err(DecompressResult::IllegalBlockFormat);
+            }
+            let distance = decoder.next_bits(TABLE_DISTANCE_EXTRA_BITS[distance_symbol])?
                 + TABLE_DISTANCE_BASE[distance_symbol];
+            if distance as usize > output.len() {
+                return Err(DecompressResult::IllegalDistance);
+            }
             for _ in 0..length {
                 let byte = output[output.len() - distance as usize];
                 output.push(byte);
@@ -177,103 +257,89 @@ fn decode_length_distance_pairs(
     Ok(())
 }
 
-/// Tree that encodes a way to parse symbols of variable lengths univocally
-#[derive(Clone, Copy)]
+/// Canonical huffman code, the symbol of a code is its index in the bitlengths.
+/// Codes up to `FAST_BITS` long are decoded with a single table lookup.
 struct HuffmanTree {
-    nodes: [HuffmanNode; 1024],
-    next_free_node: usize,
+    /// Symbol and code length for each value of the next `FAST_BITS` bits,
+    /// 0 length if the code is longer
+    fast: [(u16, u8); 1 << FAST_BITS],
+    /// Number of codes of each length
+    counts: [u16; 16],
+    /// Symbols in code order
+    symbols: Vec<u16>,
 }
 
-/// Node of the tree. `left` and `right` are offsets in the tree's `nodes` array
-#[derive(Clone, Copy, Default)]
-struct HuffmanNode {
-    symbol: Option<u16>,
-    left: Option<usize>,
-    right: Option<usize>,
-}
+const FAST_BITS: u32 = 9;
 
 impl HuffmanTree {
-    /// Create a new empty tree
-    fn new() -> Self {
-        Self {
-            nodes: [HuffmanNode::default(); 1024],
-            next_free_node: 1,
-        }
-    }
-
     /// Create a tree from a compressed representation described in the inflate spec
-    fn from_bitlengths(bitlengths: &[u16], alphabet: &[u16]) -> HuffmanTree {
-        // TODO: error handling
-        let max_bits = bitlengths.iter().max().unwrap();
-        let mut counts = [0; 16];
-        let counts_len = max_bits + 1;
-        for i in 0..counts_len as u32 {
-            counts[i as usize] = bitlengths
-                .iter()
-                .filter(|&&j| j as u32 == i && j != 0)
-                .count() as u32;
+    fn from_bitlengths(bitlengths: &[u16]) -> HuffmanTree {
+        let mut counts = [0u16; 16];
+        for &len in bitlengths {
+            counts[len as usize] += 1;
         }
-        let mut next_code = [0; 1024];
-        let mut next_code_len = 2;
-        for bits in 2..(max_bits + 1) as usize {
-            let next = (next_code[bits - 1] + counts[bits - 1]) << 1;
-            next_code[next_code_len] = next;
-            next_code_len += 1;
+        counts[0] = 0;
+
+        // First code and first index in `symbols` of each length
+        let mut next_code = [0u32; 16];
+        let mut offsets = [0usize; 16];
+        for len in 1..16 {
+            next_code[len] = (next_code[len - 1] + counts[len - 1] as u32) << 1;
+            offsets[len] = offsets[len - 1] + counts[len - 1] as usize;
         }
-        let mut tree = HuffmanTree::new();
-        for i in 0..(alphabet.len().min(bitlengths.len())) {
-            if bitlengths[i] != 0 {
-                tree.insert(
-                    next_code[bitlengths[i] as usize].into(),
-                    bitlengths[i].into(),
-                    alphabet[i],
-                );
-                next_code[bitlengths[i] as usize] += 1;
+
+        let mut symbols = vec![0; offsets[15] + counts[15] as usize];
+        let mut fast = [(0, 0); 1 << FAST_BITS];
+        for (symbol, &len) in bitlengths.iter().enumerate() {
+            if len == 0 {
+                continue;
             }
-        }
-        tree
-    }
+            let len = len as usize;
+            symbols[offsets[len]] = symbol as u16;
+            offsets[len] += 1;
+            let code = next_code[len];
+            next_code[len] += 1;
 
-    /// Add a node to the tree and link it to a previous node
-    fn insert(&mut self, code: u32, n: i32, symbol: u16) {
-        let mut current = 0;
-        for i in (0..n).rev() {
-            let bit = code & (1 << i);
-            current = if bit != 0 {
-                if self.nodes[current].right.is_none() {
-                    self.nodes[current].right = Some(self.next_free_node);
-                    self.next_free_node += 1;
-                }
-                self.nodes[current].right.unwrap()
-            } else {
-                if self.nodes[current].left.is_none() {
-                    self.nodes[current].left = Some(self.next_free_node);
-                    self.next_free_node += 1;
+            // The stream has the code from the most significant bit, the table is indexed
+            // least significant bit first, the bits after the code can be anything
+            if len as u32 <= FAST_BITS && code < 1 << len {
+                let reversed = (code.reverse_bits() >> (32 - len)) as usize;
+                for index in (reversed..1 << FAST_BITS).step_by(1 << len) {
+                    fast[index] = (symbol as u16, len as u8);
                 }
-                self.nodes[current].left.unwrap()
-            };
+            }
+        }
+
+        HuffmanTree {
+            fast,
+            counts,
+            symbols,
         }
-        self.nodes[current].symbol = Some(symbol);
     }
 
     /// Use the tree to decode a symbol form a bit stream
     fn decode_symbol(&self, decoder: &mut Decoder) -> Result<u16, DecompressResult> {
-        let mut current = 0;
-        while self.nodes[current].right.is_some() || self.nodes[current].left.is_some() {
-            let bit = decoder.next_bit();
-            current = if bit != 0 {
-                self.nodes[current]
-                    .right
-                    .ok_or(DecompressResult::TreeError)?
-            } else {
-                self.nodes[current]
-                    .left
-                    .ok_or(DecompressResult::TreeError)?
-            };
+        let (symbol, len) = self.fast[decoder.peek_bits(FAST_BITS) as usize];
+        if len > 0 {
+            decoder.skip_bits(len as u32)?;
+            return Ok(symbol);
         }
-        self.nodes[current]
-            .symbol
-            .ok_or(DecompressResult::TreeError)
+
+        // Longer codes, one bit at a time: the codes of a length are consecutive
+        let mut code = 0;
+        let mut first = 0;
+        let mut index = 0;
+        for len in 1..16 {
+            code |= decoder.next_bit()? as u32;
+            let count = self.counts[len] as u32;
+            if code < first + count {
+                return Ok(self.symbols[index + (code - first) as usize]);
+            }
+            index += count as usize;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(DecompressResult::TreeError)
     }
 }
 
@@ -297,6 +363,12 @@ const TABLE_DISTANCE_BASE: [u32; 30] = [
     2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
 ];
 
+/// Gzip header flags
+const GZIP_FHCRC: u8 = 1 << 1;
+const GZIP_FEXTRA: u8 = 1 << 2;
+const GZIP_FNAME: u8 = 1 << 3;
+const GZIP_FCOMMENT: u8 = 1 << 4;
+
 /// Avoiding infinite loops
 const LARGE_ITERATION_COUNT: u64 = 100000000;
 
@@ -322,58 +394,182 @@ impl<'a> Decoder<'a> {
         }
     }
 
-    /// Returns the next byte skipping any unread bits
-    pub fn next_bytes_as_slice(&mut self, n: usize) -> &[u8] {
+    /// Returns the next n bytes skipping any unread bits
+    pub fn next_bytes_as_slice(&mut self, n: usize) -> Result<&[u8], DecompressResult> {
         if self.bit > 0 {
             self.byte += 1;
             self.bit = 0;
         }
         let current = self.byte;
+        let slice = self
+            .data
+            .get(current..current + n)
+            .ok_or(DecompressResult::UnexpectedEof)?;
         self.byte += n;
-        &self.data[current..self.byte]
+        Ok(slice)
     }
 
     /// Returns the next byte skipping any unread bits
-    pub fn next_byte(&mut self) -> u8 {
+    pub fn next_byte(&mut self) -> Result<u8, DecompressResult> {
         if self.bit > 0 {
             self.byte += 1;
             self.bit = 0;
         }
-        let byte = self.data[self.byte];
+        let byte = *self
+            .data
+            .get(self.byte)
+            .ok_or(DecompressResult::UnexpectedEof)?;
         self.byte += 1;
-        byte
+        Ok(byte)
     }
 
     // Returns the next n bytes discarding any unread bits as a number
-    pub fn next_bytes_as_number(&mut self, n: u32) -> u32 {
+    pub fn next_bytes_as_number(&mut self, n: u32) -> Result<u32, DecompressResult> {
         let mut o: u32 = 0;
         for i in 0..n {
-            o |= (self.next_byte() as u32) << (8 * i);
+            o |= (self.next_byte()? as u32) << (8 * i);
         }
-        o
+        Ok(o)
     }
 
     /// Returns the next bit
-    pub fn next_bit(&mut self) -> u8 {
-        let byte = self.data[self.byte];
+    pub fn next_bit(&mut self) -> Result<u8, DecompressResult> {
+        let byte = *self
+            .data
+            .get(self.byte)
+            .ok_or(DecompressResult::UnexpectedEof)?;
         let bit = (byte >> self.bit) & 1;
         self.bit += 1;
         if self.bit >= 8 {
             self.bit = 0;
             self.byte += 1;
         }
-        bit
+        Ok(bit)
+    }
+
+    /// Returns the next n bits without reading them, up to 16, zeros after the end
+    fn peek_bits(&self, n: u32) -> u32 {
+        let mut window = 0;
+        for i in 0..3 {
+            let byte = self.data.get(self.byte + i).copied().unwrap_or(0);
+            window |= (byte as u32) << (8 * i);
+        }
+        (window >> self.bit) & ((1 << n) - 1)
+    }
+
+    /// Skips n bits
+    fn skip_bits(&mut self, n: u32) -> Result<(), DecompressResult> {
+        let bit = self.byte * 8 + self.bit as usize + n as usize;
+        if bit > self.data.len() * 8 {
+            return Err(DecompressResult::UnexpectedEof);
+        }
+        self.byte = bit / 8;
+        self.bit = (bit % 8) as u8;
+        Ok(())
     }
 
     /// Returns the next n bits and interprets them as an unsigned integer
     /// The bits are read from least significant to most significant
     /// Example: next_bits(3) of 11001011 -> 011, which is 3
-    pub fn next_bits(&mut self, n: u32) -> u32 {
+    pub fn next_bits(&mut self, n: u32) -> Result<u32, DecompressResult> {
         let mut o: u32 = 0;
         for i in 0..n {
-            let bit = self.next_bit() as u32;
+            let bit = self.next_bit()? as u32;
             o |= bit << i;
         }
-        o
+        Ok(o)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assets::asset_bundle::deflate, rng::RandLCG};
+
+    fn round_trip(data: &[u8]) {
+        let compressed = deflate(data);
+        assert_eq!(decompress(&compressed).unwrap(), data);
+    }
+
+    /// Bytes from `alphabet` with copies of earlier runs, up to `distance` back
+    fn random_bytes(rand: &mut RandLCG, len: usize, alphabet: u32, distance: usize) -> Vec<u8> {
+        let mut data = vec![];
+        while data.len() < len {
+            if data.len() > 3 && rand.next_u32().is_multiple_of(3) {
+                let start = data.len() - 1 - rand.next_u32() as usize % distance.min(data.len());
+                let run = 3 + rand.next_u32() as usize % 300;
+                for i in 0..run {
+                    data.push(data[start + i % (data.len() - start)]);
+                }
+            } else {
+                data.push((rand.next_u32() % alphabet) as u8);
+            }
+        }
+        data.truncate(len);
+        data
+    }
+
+    #[test]
+    fn deflate_round_trip() {
+        round_trip(&[]);
+        round_trip(&[0]);
+        round_trip(b"tile tile tile tile tile tile");
+        // the longest match, a distance of 1
+        round_trip(&[7; 1000]);
+        // incompressible and over the 65535 bytes of a stored block
+        let mut rand = RandLCG::new(17);
+        round_trip(&random_bytes(&mut rand, 70000, 256, 1));
+        // matches across the whole window
+        for len in [10, 300, 5000, 40000] {
+            for alphabet in [2, 16, 256] {
+                round_trip(&random_bytes(&mut rand, len, alphabet, 32768));
+            }
+        }
+    }
+
+    #[test]
+    fn gzip_dynamic_codes() {
+        // python's gzip at level 9, a single block with its own huffman codes
+        let gzip = include_bytes!("fixtures/classic.tileset.gz");
+        assert_eq!(gzip[10] >> 1 & 3, 2);
+        let text = decompress_gzip(gzip).unwrap();
+        assert_eq!(text, include_bytes!("fixtures/classic.tileset"));
+    }
+
+    #[test]
+    fn gzip_members_concatenated() {
+        let member = |data: &[u8], flags: u8| {
+            let mut member = vec![0x1f, 0x8b, 8, flags, 0, 0, 0, 0, 0, 255];
+            if flags & GZIP_FNAME != 0 {
+                member.extend(b"tiles.txt\0");
+            }
+            member.extend(deflate(data));
+            member.extend(crc32::crc32(data).to_le_bytes());
+            member.extend((data.len() as u32).to_le_bytes());
+            member
+        };
+        let mut gzip = member(b"first ", 0);
+        gzip.extend(member(b"second", GZIP_FNAME));
+        assert_eq!(decompress_gzip(&gzip).unwrap(), b"first second");
+
+        // the size of the last member is wrong
+        let at = gzip.len() - 4;
+        gzip[at] ^= 1;
+        assert!(matches!(
+            decompress_gzip(&gzip),
+            Err(DecompressResult::SizeMismatch)
+        ));
+    }
+
+    #[test]
+    fn truncated_never_panics() {
+        let mut rand = RandLCG::new(3);
+        for alphabet in [4, 256] {
+            let data = random_bytes(&mut rand, 2000, alphabet, 500);
+            let compressed = deflate(&data);
+            for end in 0..compressed.len() {
+                assert!(decompress(&compressed[..end]).map_or(true, |out| out != data));
+            }
+        }
     }
 }
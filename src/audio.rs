@@ -0,0 +1,193 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+};
+
+use crate::quad_snd::{AudioContext, MusicPlayer, PlayMusicParams, PlaySoundParams, Sound};
+use crate::*;
+
+/// Where a sound comes from, relative to the listener
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SoundPosition {
+    /// -1 all the way left, 1 all the way right
+    pub pan: f32,
+    /// In units of the distance sounds are heard at full volume, they fade further away
+    pub distance: f32,
+}
+
+impl Default for SoundPosition {
+    fn default() -> Self {
+        Self {
+            pan: 0.,
+            distance: 1.,
+        }
+    }
+}
+
+/// Volume lost for each unit of distance past the first
+const DISTANCE_ROLLOFF: f32 = 0.5;
+
+/// How much each play of a sound effect differs at random from the others
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SoundVariation {
+    /// Up to this part louder or quieter, 0.1 is 10%
+    pub volume: f32,
+    /// Up to this part higher or lower
+    pub pitch: f32,
+}
+
+/// Sounds of the same effect, played in turn so the same one isn't heard twice in a row
+struct SoundGroup {
+    sounds: Vec<AssetId>,
+    variation: SoundVariation,
+    /// Index in `sounds` of the next one
+    next: Cell<usize>,
+}
+
+/// Owns the audio context, the loaded sounds and the music.
+/// Sound effects are scaled by the master and sfx volumes, music by the master and music volumes.
+pub struct AudioPlayer {
+    ctx: AudioContext,
+    sounds: HashMap<AssetId, Sound>,
+    groups: HashMap<String, SoundGroup>,
+    music: HashMap<AssetId, MusicData>,
+    music_player: MusicPlayer,
+    current_music: Option<AssetId>,
+    /// Draws the variations of the plays
+    variation_rand: RefCell<RandLCG>,
+    pub master_volume: f32,
+    pub sfx_volume: f32,
+    pub music_volume: f32,
+}
+
+impl AudioPlayer {
+    pub fn new(
+        master_volume: f32,
+        sfx_volume: f32,
+        music_volume: f32,
+        variation_rand: RandLCG,
+    ) -> Self {
+        Self {
+            ctx: AudioContext::new(),
+            sounds: HashMap::new(),
+            groups: HashMap::new(),
+            music: HashMap::new(),
+            music_player: MusicPlayer::new(),
+            current_music: None,
+            variation_rand: RefCell::new(variation_rand),
+            master_volume,
+            sfx_volume,
+            music_volume,
+        }
+    }
+
+    pub fn load_music(&mut self, id: &AssetId, music: &MusicData) {
+        self.music.insert(id.clone(), music.clone());
+    }
+
+    /// Crossfades to the track, if it's already playing it keeps going
+    pub fn play_music(&mut self, id: &AssetId, looped: bool, fade: f32) {
+        if self.current_music.as_ref() == Some(id) {
+            return;
+        }
+        let Some(music) = self.music.get(id) else {
+            return;
+        };
+        match music.stream(self.ctx.sample_rate()) {
+            Ok(source) => {
+                let params = PlayMusicParams {
+                    looped,
+                    volume: self.master_volume * self.music_volume,
+                    fade,
+                };
+                self.music_player.play(&self.ctx, source, params);
+                self.current_music = Some(id.clone());
+            }
+            Err(err) => eprintln!("Can't play music {id:?}: {err}"),
+        }
+    }
+
+    pub fn stop_music(&mut self, fade: f32) {
+        self.music_player.stop(&self.ctx, fade);
+        self.current_music = None;
+    }
+
+    /// Call after changing the volumes
+    pub fn update_music_volume(&mut self) {
+        let volume = self.master_volume * self.music_volume;
+        self.music_player.set_volume(&self.ctx, volume);
+    }
+
+    pub fn load(&mut self, id: &AssetId, audio_pcm: &AudioPcm) {
+        let samples = audio_pcm.resampled(self.ctx.sample_rate());
+        let sound = Sound::load(&self.ctx, &samples);
+        self.sounds.insert(id.clone(), sound);
+    }
+
+    pub fn unload(&mut self, id: &AssetId) {
+        if let Some(sound) = self.sounds.remove(id) {
+            sound.delete(&self.ctx);
+        }
+        self.music.remove(id);
+    }
+
+    /// Plays a sound effect once, does nothing if it's not loaded
+    pub fn play(&self, id: &AssetId, volume: f32, pitch: f32) {
+        self.play_at(id, volume, pitch, SoundPosition::default());
+    }
+
+    /// Plays a sound effect once, panned and quieter with the distance
+    pub fn play_at(&self, id: &AssetId, volume: f32, pitch: f32, position: SoundPosition) {
+        self.play_varied(id, volume, pitch, position, SoundVariation::default());
+    }
+
+    /// Sets the sounds of a group, replacing the previous ones
+    pub fn set_group(&mut self, name: &str, sounds: Vec<AssetId>, variation: SoundVariation) {
+        let group = SoundGroup {
+            sounds,
+            variation,
+            next: Cell::new(0),
+        };
+        self.groups.insert(name.to_string(), group);
+    }
+
+    /// Plays the next sound of the group once, varied like the group says
+    pub fn play_group(&self, name: &str, volume: f32, pitch: f32, position: SoundPosition) {
+        let Some(group) = self.groups.get(name) else {
+            return;
+        };
+        if group.sounds.is_empty() {
+            return;
+        }
+        let index = group.next.get() % group.sounds.len();
+        group.next.set(index + 1);
+        let id = &group.sounds[index];
+        self.play_varied(id, volume, pitch, position, group.variation);
+    }
+
+    fn play_varied(
+        &self,
+        id: &AssetId,
+        volume: f32,
+        pitch: f32,
+        position: SoundPosition,
+        variation: SoundVariation,
+    ) {
+        let Some(sound) = self.sounds.get(id) else {
+            return;
+        };
+        let attenuation = 1. / (1. + (position.distance - 1.).max(0.) * DISTANCE_ROLLOFF);
+        let mut rand = self.variation_rand.borrow_mut();
+        let volume = volume * (1. + rand.next_signed() * variation.volume);
+        let pitch = pitch * (1. + rand.next_signed() * variation.pitch);
+        sound.play(
+            &self.ctx,
+            PlaySoundParams {
+                looped: false,
+                volume: volume * attenuation * self.master_volume * self.sfx_volume,
+                pitch,
+                pan: position.pan,
+            },
+        );
+    }
+}
@@ -0,0 +1,80 @@
+// Relay and matchmaker for the players that can't reach each other directly:
+// one creates a room and shares its code, the other joins it by the code,
+// then the relay passes their packets on. Headless, run it as `relay [port]`.
+// The browsers connect with a WebSocket, over tcp on the same port. A page
+// served over https can only open a wss:// one, so put a proxy that
+// terminates tls in front of the port
+
+use std::{
+    net::{Ipv4Addr, TcpListener, UdpSocket},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+#[path = "../net/relay.rs"]
+mod relay;
+#[path = "../net/relay_ws.rs"]
+mod relay_ws;
+
+use relay::*;
+use relay_ws::*;
+
+/// Largest packet passed on, the messages of the game are smaller
+const MAX_PACKET_SIZE: usize = 2048;
+
+fn main() {
+    let port = match std::env::args().nth(1) {
+        Some(port) => port.parse().expect("usage: relay [port]"),
+        None => RELAY_PORT,
+    };
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port)).expect("port not bound");
+    // waits a little for the udp packets, then looks at the WebSockets
+    socket
+        .set_read_timeout(Some(Duration::from_millis(5)))
+        .unwrap();
+    let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, port)).expect("tcp port not bound");
+    listener.set_nonblocking(true).unwrap();
+    println!("Relay on port {}", port);
+
+    let seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_nanos() as u64);
+    let mut relay = Relay::new(seed);
+    let mut browsers: Vec<WsConnection> = vec![];
+    let start = Instant::now();
+    let mut buf = [0; MAX_PACKET_SIZE];
+    loop {
+        let now = start.elapsed().as_secs_f64();
+        let mut received = vec![];
+        if let Ok((amt, from)) = socket.recv_from(&mut buf) {
+            received.push((buf[..amt].to_vec(), from));
+        }
+        while let Ok((stream, address)) = listener.accept() {
+            match WsConnection::new(stream, address, now) {
+                Ok(browser) => browsers.push(browser),
+                Err(err) => eprintln!("Not accepted {}: {}", address, err),
+            }
+        }
+        for browser in browsers.iter_mut() {
+            for packet in browser.receive() {
+                received.push((packet, browser.address));
+            }
+        }
+
+        // the players of a room can be on either side
+        for (packet, from) in received {
+            for (packet, to) in relay.handle(&packet, from, now) {
+                let sent = match browsers.iter_mut().find(|b| b.address == to) {
+                    Some(browser) => browser.send(&packet),
+                    None => socket.send_to(&packet, to).map(|_| ()),
+                };
+                if let Err(err) = sent {
+                    eprintln!("Not sent to {}: {}", to, err);
+                }
+            }
+        }
+        browsers.retain(|browser| !browser.is_closed(now));
+        for code in relay.expire(now) {
+            println!("Room {} closed, {} open", code, relay.room_count());
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use std::collections::VecDeque;
+
+use crate::protocol::*;
+
+/// Seconds between the full snapshots, sent even when the deltas arrive
+const SNAPSHOT_INTERVAL: f64 = 2.;
+
+/// States kept by each side, the sender's waiting for an ack and the receiver's to apply
+/// the deltas on. A delta from an older one is dropped and the snapshot fixes it
+const STATES_KEPT: usize = 32;
+
+/// Sends a board to the other side, as the cells that changed since the last state it
+/// acknowledged. The messages are unreliable, each one replaces the ones before
+#[derive(Default)]
+pub struct BoardSender {
+    /// Of the next state sent
+    sequence: u32,
+    /// States sent and not acknowledged yet, the oldest first
+    sent: VecDeque<(u32, Vec<CellState>)>,
+    /// The latest state the other side has
+    acked: Option<(u32, Vec<CellState>)>,
+    last_snapshot: Option<f64>,
+}
+
+impl BoardSender {
+    /// The message that brings the other side to `cells`
+    pub fn message(&mut self, cells: &[CellState], current_time: f64) -> Message {
+        let sequence = self.sequence;
+        self.sequence += 1;
+        self.sent.push_back((sequence, cells.to_vec()));
+        if self.sent.len() > STATES_KEPT {
+            self.sent.pop_front();
+        }
+        let snapshot_due = self
+            .last_snapshot
+            .is_none_or(|time| current_time - time >= SNAPSHOT_INTERVAL);
+        match &self.acked {
+            // a board of another size is a new board
+            Some((base, acked)) if !snapshot_due && acked.len() == cells.len() => {
+                Message::BoardDelta {
+                    sequence,
+                    base: *base,
+                    changes: board_delta(acked, cells),
+                }
+            }
+            _ => {
+                self.last_snapshot = Some(current_time);
+                Message::BoardSnapshot {
+                    sequence,
+                    cells: cells.to_vec(),
+                }
+            }
+        }
+    }
+
+    /// The other side has the state, older acks are ignored
+    pub fn ack(&mut self, sequence: u32) {
+        if self
+            .acked
+            .as_ref()
+            .is_some_and(|(acked, _)| *acked >= sequence)
+        {
+            return;
+        }
+        let Some(index) = self.sent.iter().position(|(sent, _)| *sent == sequence) else {
+            return;
+        };
+        self.acked = self.sent.drain(..=index).next_back();
+    }
+}
+
+/// The board of the other side, built from its snapshots and deltas
+#[derive(Default)]
+pub struct BoardReceiver {
+    /// States received, the latest last
+    states: VecDeque<(u32, Vec<CellState>)>,
+}
+
+impl BoardReceiver {
+    /// Applies a board message, returns the ack to send back.
+    /// A message older than the latest state or a delta from a state not kept is dropped
+    pub fn receive(&mut self, message: &Message) -> Option<Message> {
+        let (sequence, cells) = match message {
+            Message::BoardSnapshot { sequence, cells } => (*sequence, cells.clone()),
+            Message::BoardDelta {
+                sequence,
+                base,
+                changes,
+            } => {
+                let (_, base) = self.states.iter().find(|(kept, _)| kept == base)?;
+                (*sequence, apply_board_delta(base, changes)?)
+            }
+            _ => return None,
+        };
+        if self
+            .states
+            .back()
+            .is_some_and(|(latest, _)| *latest >= sequence)
+        {
+            return None;
+        }
+        self.states.push_back((sequence, cells));
+        if self.states.len() > STATES_KEPT {
+            self.states.pop_front();
+        }
+        Some(Message::BoardAck { sequence })
+    }
+
+    /// The latest board, None until one arrived
+    pub fn cells(&self) -> Option<&[CellState]> {
+        self.states.back().map(|(_, cells)| cells.as_slice())
+    }
+}
@@ -0,0 +1,164 @@
+use std::f32::consts::{PI, TAU};
+
+use crate::*;
+
+/// Orbits a camera around the origin: right drag turns it, middle drag or two fingers pan it,
+/// the wheel or a pinch zooms it. It follows the input smoothly.
+/// The pan keeps the origin over the board, large boards zoom in closer
+#[derive(Clone, Debug)]
+pub struct CameraController {
+    /// Radians around the view axis and tilt towards the horizon
+    pub yaw: f32,
+    pub pitch: f32,
+    /// Multiplies `fit_distance`, more is further away
+    pub zoom: f32,
+    /// Of the origin in the view plane, in world units
+    pub pan: Vec2,
+    /// Distance that frames the whole board when not zoomed
+    pub fit_distance: f32,
+    /// Half the size of the board, the pan doesn't go past its edges
+    pub bounds: Vec2,
+    target_yaw: f32,
+    target_pitch: f32,
+    target_zoom: f32,
+    target_pan: Vec2,
+    /// How fast the values reach their targets, per second
+    pub damping: f32,
+    /// Distance between the two touches of a pinch last frame, and the point between them
+    pinch: Option<(f32, Vec2)>,
+}
+
+/// Radians turned for each pixel dragged
+const ORBIT_SPEED: f32 = 0.008;
+const PITCH_LIMIT: f32 = 1.3;
+const ZOOM_RANGE: (f32, f32) = (0.4, 2.5);
+/// Closest the camera gets in world units, zoomed all the way in on a large board
+const MIN_DISTANCE: f32 = 2.5;
+/// Zoom change of a wheel step
+const ZOOM_STEP: f32 = 0.9;
+
+impl Default for CameraController {
+    fn default() -> Self {
+        Self {
+            yaw: 0.,
+            pitch: 0.,
+            zoom: 1.,
+            pan: Vec2::ZERO,
+            fit_distance: 1.,
+            bounds: Vec2::ZERO,
+            target_yaw: 0.,
+            target_pitch: 0.,
+            target_zoom: 1.,
+            target_pan: Vec2::ZERO,
+            damping: 12.,
+            pinch: None,
+        }
+    }
+}
+
+impl CameraController {
+    /// Moves the targets with the input of the frame, then eases the values towards them
+    pub fn update(&mut self, input: &Input, resolution: Vec2, delta_time: f64) {
+        let delta = input.mouse_frame_delta;
+        if input.mouse_pressed.2 {
+            self.target_yaw += delta.x * ORBIT_SPEED;
+            self.target_pitch =
+                (self.target_pitch + delta.y * ORBIT_SPEED).clamp(-PITCH_LIMIT, PITCH_LIMIT);
+        }
+        if input.mouse_pressed.1 {
+            // the point under the cursor follows it
+            self.pan_by(delta, resolution);
+        }
+        if input.mouse_wheel.1 != 0. {
+            self.zoom_by(ZOOM_STEP.powf(input.mouse_wheel.1));
+        }
+        match input.touches.as_slice() {
+            [(_, a), (_, b)] => {
+                let distance = a.distance(*b);
+                let center = (*a + *b) * 0.5;
+                if let Some((last_distance, last_center)) = self.pinch {
+                    if distance > 0. {
+                        self.zoom_by(last_distance / distance);
+                    }
+                    self.pan_by(center - last_center, resolution);
+                }
+                self.pinch = Some((distance, center));
+            }
+            _ => self.pinch = None,
+        }
+        // the limits move as the board turns and resizes
+        self.target_zoom = self.target_zoom.clamp(self.min_zoom(), ZOOM_RANGE.1);
+        let limit = self.pan_limit();
+        self.target_pan = self.target_pan.clamp(-limit, limit);
+
+        let t = 1. - (-self.damping * delta_time as f32).exp();
+        self.yaw += (self.target_yaw - self.yaw) * t;
+        self.pitch += (self.target_pitch - self.pitch) * t;
+        self.zoom += (self.target_zoom - self.zoom) * t;
+        self.pan += (self.target_pan - self.pan) * t;
+    }
+
+    pub fn zoom_by(&mut self, factor: f32) {
+        self.target_zoom = (self.target_zoom * factor).clamp(self.min_zoom(), ZOOM_RANGE.1);
+    }
+
+    /// By pixels moved on the screen
+    fn pan_by(&mut self, delta: Vec2, resolution: Vec2) {
+        let world_per_pixel = self.distance() / resolution.y.max(1.);
+        self.target_pan += Vec2::new(delta.x, -delta.y) * world_per_pixel;
+    }
+
+    /// Closer than `ZOOM_RANGE` when that still frames more than `MIN_DISTANCE`
+    fn min_zoom(&self) -> f32 {
+        (MIN_DISTANCE / self.fit_distance.max(f32::EPSILON)).min(ZOOM_RANGE.0)
+    }
+
+    /// Furthest the pan goes each way: the extent of the board turned by the yaw
+    fn pan_limit(&self) -> Vec2 {
+        let (sin, cos) = self.target_yaw.sin_cos();
+        Vec2::new(
+            cos.abs() * self.bounds.x + sin.abs() * self.bounds.y,
+            sin.abs() * self.bounds.x + cos.abs() * self.bounds.y,
+        )
+    }
+
+    /// Jumps to the orientation, without easing
+    pub fn set_orbit(&mut self, yaw: f32, pitch: f32) {
+        self.yaw = yaw;
+        self.pitch = pitch;
+        self.target_yaw = yaw;
+        self.target_pitch = pitch;
+    }
+
+    /// Eases the pan until the point of the board is in the middle of the screen
+    pub fn look_at(&mut self, point: Vec2) {
+        let rotation = Quat::from_euler(EulerRot::XZY, -self.target_pitch, self.target_yaw, 0.);
+        self.target_pan = -(rotation * point.extend(0.)).truncate();
+    }
+
+    /// Eases back to looking straight at the origin, the short way around
+    pub fn reset(&mut self) {
+        self.yaw = (self.yaw + PI).rem_euclid(TAU) - PI;
+        self.target_yaw = 0.;
+        self.target_pitch = 0.;
+        self.target_zoom = 1.;
+        self.target_pan = Vec2::ZERO;
+    }
+
+    pub fn distance(&self) -> f32 {
+        self.fit_distance * self.zoom
+    }
+
+    /// The view, before it's a matrix
+    pub fn transform(&self) -> Transform {
+        Transform {
+            translation: Vec3::new(self.pan.x, self.pan.y, -self.distance()),
+            rotation: Quat::from_euler(EulerRot::XZY, -self.pitch, self.yaw, 0.),
+            scale: Vec3::ONE,
+        }
+    }
+
+    pub fn view(&self) -> Mat4 {
+        self.transform().to_mat4()
+    }
+}
@@ -0,0 +1,312 @@
+use crate::*;
+
+/// User settings, saved as `key=value` lines
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    /// Master volume
+    pub volume: f32,
+    pub sfx_volume: f32,
+    pub music_volume: f32,
+    /// MSAA samples, lowered to the ones the gpu supports
+    pub sample_count: i32,
+    pub window: WindowSettings,
+    pub palette: Palette,
+    /// The edges of the tiles are tinted by their side value, from the `palette`
+    pub color_match: bool,
+    /// Assist marking the edges that don't match
+    pub show_mistakes: bool,
+    pub ui_scale: f32,
+    /// Strength of the shakes and flashes of the screen, 0 turns them off
+    pub screen_effects: f32,
+    /// Code of one of the `LANGUAGES`
+    pub language: String,
+    /// One of the `THEMES`
+    pub theme: String,
+    /// One of the `TILE_PACKS`
+    pub tile_pack: String,
+    /// Images with a corrupt chunk are loaded anyway, with a warning.
+    /// Set in the file, the settings don't show it
+    pub ignore_png_crc: bool,
+    pub input_map: InputMap,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            volume: 1.,
+            sfx_volume: 1.,
+            music_volume: 0.6,
+            sample_count: 16,
+            window: WindowSettings::default(),
+            palette: Palette::Default,
+            color_match: false,
+            show_mistakes: false,
+            ui_scale: 1.,
+            screen_effects: 1.,
+            language: locale::FALLBACK_LANGUAGE.to_string(),
+            theme: DEFAULT_THEME.to_string(),
+            tile_pack: DEFAULT_TILE_PACK.to_string(),
+            ignore_png_crc: false,
+            input_map: InputMap::default(),
+        }
+    }
+}
+
+/// How the window is shown, applied by the engine when it changes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WindowSettings {
+    /// Size of the window when it's not fullscreen
+    pub size: IVec2,
+    /// A borderless window covering the screen at the desktop resolution,
+    /// toggled by Alt+Enter
+    pub fullscreen: bool,
+    /// Only applied at startup, the swap interval can't change after the window is made
+    pub vsync: bool,
+}
+
+impl Default for WindowSettings {
+    fn default() -> Self {
+        Self {
+            size: IVec2::new(800, 600),
+            fullscreen: false,
+            vsync: true,
+        }
+    }
+}
+
+pub const SAMPLE_COUNTS: [i32; 5] = [1, 2, 4, 8, 16];
+pub const WINDOW_SIZES: [IVec2; 4] = [
+    IVec2::new(800, 600),
+    IVec2::new(1280, 720),
+    IVec2::new(1600, 900),
+    IVec2::new(1920, 1080),
+];
+
+impl Config {
+    const FILE: &'static str = "config.txt";
+
+    /// Unknown keys and invalid values are ignored and keep their default
+    pub fn load() -> Self {
+        let mut config = Self::default();
+        let Ok(text) = storage::load(Self::FILE) else {
+            return config;
+        };
+        for line in text.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let value = value.trim();
+            match key.trim() {
+                "volume" => {
+                    if let Ok(volume) = value.parse::<f32>() {
+                        config.volume = volume.clamp(0., 1.);
+                    }
+                }
+                "sfx_volume" => {
+                    if let Ok(sfx_volume) = value.parse::<f32>() {
+                        config.sfx_volume = sfx_volume.clamp(0., 1.);
+                    }
+                }
+                "music_volume" => {
+                    if let Ok(music_volume) = value.parse::<f32>() {
+                        config.music_volume = music_volume.clamp(0., 1.);
+                    }
+                }
+                "sample_count" => {
+                    if let Ok(sample_count) = value.parse() {
+                        if SAMPLE_COUNTS.contains(&sample_count) {
+                            config.sample_count = sample_count;
+                        }
+                    }
+                }
+                "window_size" => {
+                    if let Some((w, h)) = value.split_once('x') {
+                        if let (Ok(w), Ok(h)) = (w.parse(), h.parse()) {
+                            config.window.size = IVec2::new(w, h).max(IVec2::splat(64));
+                        }
+                    }
+                }
+                "fullscreen" => {
+                    if let Ok(fullscreen) = value.parse() {
+                        config.window.fullscreen = fullscreen;
+                    }
+                }
+                "vsync" => {
+                    if let Ok(vsync) = value.parse() {
+                        config.window.vsync = vsync;
+                    }
+                }
+                // the setting before the palettes
+                "colorblind" if value == "true" => config.palette = Palette::Deuteranopia,
+                "palette" => {
+                    if let Some(palette) = Palette::ALL.into_iter().find(|p| p.name() == value) {
+                        config.palette = palette;
+                    }
+                }
+                "color_match" => {
+                    if let Ok(color_match) = value.parse() {
+                        config.color_match = color_match;
+                    }
+                }
+                "show_mistakes" => {
+                    if let Ok(show_mistakes) = value.parse() {
+                        config.show_mistakes = show_mistakes;
+                    }
+                }
+                "ui_scale" => {
+                    if let Ok(ui_scale) = value.parse::<f32>() {
+                        config.ui_scale = ui_scale.clamp(0.5, 2.);
+                    }
+                }
+                "screen_effects" => {
+                    if let Ok(screen_effects) = value.parse::<f32>() {
+                        config.screen_effects = screen_effects.clamp(0., 1.);
+                    }
+                }
+                "language" if locale::LANGUAGES.iter().any(|(code, _)| *code == value) => {
+                    config.language = value.to_string();
+                }
+                "theme" if THEMES.contains(&value) => {
+                    config.theme = value.to_string();
+                }
+                "tile_pack" if TILE_PACKS.contains(&value) => {
+                    config.tile_pack = value.to_string();
+                }
+                "ignore_png_crc" => {
+                    if let Ok(ignore_png_crc) = value.parse() {
+                        config.ignore_png_crc = ignore_png_crc;
+                    }
+                }
+                key if key.starts_with("bind_") => {
+                    let name = &key["bind_".len()..];
+                    if let Some(action) = Action::ALL.into_iter().find(|a| a.name() == name) {
+                        config
+                            .input_map
+                            .set(action, InputMap::parse_bindings(value));
+                    }
+                }
+                _ => {}
+            }
+        }
+        config
+    }
+
+    pub fn save(&self) {
+        let mut text = format!(
+            "volume={}\nsfx_volume={}\nmusic_volume={}\nsample_count={}\nwindow_size={}x{}\nfullscreen={}\nvsync={}\npalette={}\ncolor_match={}\nshow_mistakes={}\nui_scale={}\nscreen_effects={}\nlanguage={}\ntheme={}\ntile_pack={}\nignore_png_crc={}\n",
+            self.volume,
+            self.sfx_volume,
+            self.music_volume,
+            self.sample_count,
+            self.window.size.x,
+            self.window.size.y,
+            self.window.fullscreen,
+            self.window.vsync,
+            self.palette.name(),
+            self.color_match,
+            self.show_mistakes,
+            self.ui_scale,
+            self.screen_effects,
+            self.language,
+            self.theme,
+            self.tile_pack,
+            self.ignore_png_crc
+        );
+        for action in Action::ALL {
+            text += &format!(
+                "bind_{}={}\n",
+                action.name(),
+                self.input_map.bindings_text(action)
+            );
+        }
+        if let Err(err) = storage::save(Self::FILE, &text) {
+            eprintln!("Config not saved: {err}");
+        }
+    }
+}
+
+/// Colors for color blindness, of the tile texture and of the edges in color-match mode
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Palette {
+    Default,
+    /// Red-green color blindness
+    Deuteranopia,
+    /// Blue-yellow color blindness
+    Tritanopia,
+}
+
+impl Palette {
+    pub const ALL: [Palette; 3] = [Palette::Default, Palette::Deuteranopia, Palette::Tritanopia];
+
+    /// Also the key of its name in the lang files
+    pub fn name(&self) -> &'static str {
+        match self {
+            Palette::Default => "palette_default",
+            Palette::Deuteranopia => "deuteranopia",
+            Palette::Tritanopia => "tritanopia",
+        }
+    }
+
+    /// Recolors a pixel of the tile texture, None if it stays as it is
+    pub fn recolor(&self) -> Option<fn([u8; 3]) -> [u8; 3]> {
+        match self {
+            Palette::Default => None,
+            Palette::Deuteranopia => Some(deuteranopia_palette),
+            Palette::Tritanopia => Some(tritanopia_palette),
+        }
+    }
+
+    /// Color of the edges with the side value, the values after the last color wrap around
+    pub fn edge_color(&self, value: u8) -> Vec4 {
+        let colors: &[u32] = match self {
+            Palette::Default => &[
+                0x606060, 0xe03c31, 0x3cb44b, 0x4363d8, 0xffe119, 0xf032e6, 0x42d4f4, 0xf58231,
+            ],
+            // Okabe and Ito, apart for every kind of color blindness
+            Palette::Deuteranopia => &[
+                0x606060, 0xe69f00, 0x56b4e9, 0x009e73, 0xf0e442, 0x0072b2, 0xd55e00, 0xcc79a7,
+            ],
+            // reds, teals and greys, no blue against green or yellow against violet
+            Palette::Tritanopia => &[
+                0x606060, 0xe41a1c, 0x00a6a6, 0xf781bf, 0x1b1b1b, 0xa6761d, 0xffffff, 0x8c0a4b,
+            ],
+        };
+        let rgb = colors[value as usize % colors.len()];
+        Vec4::new(
+            (rgb >> 16 & 255) as f32 / 255.,
+            (rgb >> 8 & 255) as f32 / 255.,
+            (rgb & 255) as f32 / 255.,
+            1.,
+        )
+    }
+}
+
+/// Moves greens to blue and reds to orange, so the tile sides stay apart
+/// for red-green color blindness.
+pub fn deuteranopia_palette([r, g, b]: [u8; 3]) -> [u8; 3] {
+    let (r, g, b) = (r as i32, g as i32, b as i32);
+    if g > r + 32 && g > b {
+        // green -> blue
+        [(r / 3) as u8, ((g + b) / 3) as u8, g as u8]
+    } else if r > g + 64 && r > b {
+        // red -> orange
+        [r as u8, (r * 3 / 5).max(g) as u8, (b / 2) as u8]
+    } else {
+        [r as u8, g as u8, b as u8]
+    }
+}
+
+/// Moves blues to pink and yellows to red, so the tile sides stay apart
+/// for blue-yellow color blindness.
+pub fn tritanopia_palette([r, g, b]: [u8; 3]) -> [u8; 3] {
+    let (r, g, b) = (r as i32, g as i32, b as i32);
+    if b > r + 32 && b > g {
+        // blue -> pink
+        [b as u8, (g / 2) as u8, (b * 2 / 3) as u8]
+    } else if r > b + 64 && g > b + 64 {
+        // yellow -> red
+        [r.max(g) as u8, (g / 3) as u8, b as u8]
+    } else {
+        [r as u8, g as u8, b as u8]
+    }
+}
@@ -0,0 +1,193 @@
+use crate::{ui::*, *};
+
+/// Lines of output kept, the oldest are dropped
+const CONSOLE_LINES: usize = 100;
+const CONSOLE_MAX_LEN: usize = 64;
+/// In front of the ui, behind the tooltips
+const CONSOLE_Z: f32 = 0.02;
+
+/// Runs a command with its arguments, returns the line to print
+pub type CommandHandler = fn(&mut GameState, &mut EngineContext, &[&str]) -> Result<String, String>;
+
+#[derive(Clone)]
+pub struct ConsoleCommand {
+    pub name: &'static str,
+    /// Shown by `help`, like "grid <width>x<height>"
+    pub usage: &'static str,
+    pub handler: CommandHandler,
+}
+
+/// The commands of the console, each module registers its own.
+/// `help` and `clear` are always there
+#[derive(Clone, Default)]
+pub struct CommandRegistry {
+    commands: Vec<ConsoleCommand>,
+}
+
+impl CommandRegistry {
+    /// A command with the same name is replaced
+    pub fn register(&mut self, name: &'static str, usage: &'static str, handler: CommandHandler) {
+        self.commands.retain(|command| command.name != name);
+        self.commands.push(ConsoleCommand {
+            name,
+            usage,
+            handler,
+        });
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ConsoleCommand> {
+        self.commands.iter().find(|command| command.name == name)
+    }
+
+    pub fn usages(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.commands.iter().map(|command| command.usage)
+    }
+}
+
+/// Drops down from the top of the screen with the tilde key
+#[derive(Clone, Default)]
+pub struct Console {
+    pub open: bool,
+    pub registry: CommandRegistry,
+    field: UiTextField,
+    /// Commands and their output, the oldest first
+    lines: Vec<String>,
+    /// Commands entered, up and down bring them back
+    history: Vec<String>,
+    history_index: Option<usize>,
+}
+
+impl Console {
+    pub fn new(registry: CommandRegistry) -> Self {
+        Self {
+            registry,
+            field: UiTextField::new(CONSOLE_MAX_LEN),
+            ..Default::default()
+        }
+    }
+
+    pub fn print(&mut self, line: &str) {
+        self.lines.push(line.to_string());
+        if self.lines.len() > CONSOLE_LINES {
+            self.lines.remove(0);
+        }
+    }
+
+    /// Opens or closes with the tilde key, while open it takes the keyboard.
+    /// Returns the command entered this frame
+    pub fn update(&mut self, input: &mut Input) -> Option<String> {
+        if input.key_just_pressed.contains(&KeyCode::GraveAccent) {
+            self.open = !self.open;
+            self.field.clear();
+            self.history_index = None;
+            input.chars_typed.retain(|c| !['`', '~'].contains(c));
+        }
+        if !self.open {
+            return None;
+        }
+        input.chars_typed.retain(|c| !['`', '~'].contains(c));
+        if input.key_just_pressed.contains(&KeyCode::Escape) {
+            self.open = false;
+        }
+
+        let recalled = if input.key_just_pressed.contains(&KeyCode::Up) {
+            Some(match self.history_index {
+                Some(index) => index.saturating_sub(1),
+                None => self.history.len().saturating_sub(1),
+            })
+        } else if input.key_just_pressed.contains(&KeyCode::Down) {
+            self.history_index
+                .map(|index| index + 1)
+                .filter(|index| *index < self.history.len())
+        } else {
+            self.history_index
+        };
+        if recalled != self.history_index {
+            self.history_index = recalled;
+            self.field.clear();
+            if let Some(line) = recalled.and_then(|index| self.history.get(index)) {
+                self.field.text = line.clone();
+                self.field.caret = line.chars().count();
+            }
+        }
+
+        self.field.focused = self.open;
+        let submitted = self.field.edit(input);
+        // the game doesn't see the keys typed in the console
+        input.key_just_pressed.clear();
+        input.key_typed.clear();
+        input.chars_typed.clear();
+
+        let line = self.field.text.trim().to_string();
+        if !submitted || line.is_empty() {
+            return None;
+        }
+        self.field.clear();
+        self.history_index = None;
+        self.history.push(line.clone());
+        self.print(&format!("> {}", line));
+        match line.as_str() {
+            "help" => {
+                let usages: Vec<&str> = ["help", "clear"]
+                    .into_iter()
+                    .chain(self.registry.usages())
+                    .collect();
+                self.print(&usages.join(", "));
+                None
+            }
+            "clear" => {
+                self.lines.clear();
+                None
+            }
+            _ => Some(line),
+        }
+    }
+
+    /// Output above the typed command, on the top half of the screen
+    pub fn draw(&self, tile_commands: &mut RenderTileCommands, resolution: Vec2, ui_text: &UiText) {
+        if !self.open {
+            return;
+        }
+        let ui_text = UiText {
+            layout: UiTextLayout::Left,
+            vertical_layout: UiTextVerticalLayout::Center,
+            overflow: UiTextOverflow::Shrink,
+            char_scale: ui_text.char_scale * DEBUG_TEXT_SCALE,
+            color: Vec4::ONE,
+            ..ui_text.clone()
+        };
+        let line = line_height(&ui_text);
+        let panel = Rect::new(Vec2::ZERO, Vec2::new(resolution.x, resolution.y * 0.5));
+        tile_commands.draw(RenderTile {
+            world_rect: panel,
+            z: CONSOLE_Z + 0.002,
+            color: Vec4::new(0., 0., 0., 0.8),
+            ..Default::default()
+        });
+
+        let margin = line * 0.5;
+        let width = panel.size.x - margin * 2.;
+        let prompt_y = panel.size.y - margin - line;
+        let before: String = self.field.text.chars().take(self.field.caret).collect();
+        let after: String = self.field.text.chars().skip(self.field.caret).collect();
+        let typed = format!("> {}_{}", before, after);
+        draw_text(
+            tile_commands,
+            &typed,
+            Rect::new(Vec2::new(margin, prompt_y), Vec2::new(width, line)),
+            CONSOLE_Z,
+            &ui_text,
+        );
+        let rows = ((prompt_y - margin) / line).max(0.) as usize;
+        for (row, text) in self.lines.iter().rev().take(rows).enumerate() {
+            let y = prompt_y - line * (row + 1) as f32;
+            draw_text(
+                tile_commands,
+                text,
+                Rect::new(Vec2::new(margin, y), Vec2::new(width, line)),
+                CONSOLE_Z,
+                &ui_text,
+            );
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use crate::*;
+
+/// Preset of the endless boards: how big they are and which tiles they're built from
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+    Expert,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 4] = [
+        Difficulty::Easy,
+        Difficulty::Medium,
+        Difficulty::Hard,
+        Difficulty::Expert,
+    ];
+
+    /// Every tile of the tile set, like the boards before the presets
+    pub const DEFAULT: Difficulty = Difficulty::Hard;
+
+    /// Also the key of its name in the lang files
+    pub fn name(&self) -> &'static str {
+        match self {
+            Difficulty::Easy => "easy",
+            Difficulty::Medium => "medium",
+            Difficulty::Hard => "hard",
+            Difficulty::Expert => "expert",
+        }
+    }
+
+    pub fn index(&self) -> usize {
+        Self::ALL.iter().position(|d| d == self).unwrap()
+    }
+
+    pub fn grid_size(&self) -> IVec2 {
+        match self {
+            Difficulty::Easy => IVec2::splat(3),
+            Difficulty::Medium => IVec2::splat(4),
+            Difficulty::Hard => IVec2::splat(5),
+            Difficulty::Expert => IVec2::splat(7),
+        }
+    }
+
+    /// Distinct side values the tiles can have, all of them when None
+    fn edge_types(&self) -> Option<usize> {
+        match self {
+            Difficulty::Easy | Difficulty::Medium => Some(2),
+            Difficulty::Hard | Difficulty::Expert => None,
+        }
+    }
+
+    /// Kinds of tiles used at most, all of them when None
+    fn max_kinds(&self) -> Option<usize> {
+        match self {
+            Difficulty::Easy => Some(4),
+            _ => None,
+        }
+    }
+
+    /// Meshes of the kinds of the tile set the boards are built from.
+    /// The kinds come in the tile set order and the side values from the smallest
+    pub fn kinds<'a>(&self, tile_set: &'a TileSet) -> Vec<&'a str> {
+        let mut values: Vec<u8> = tile_set.tiles.iter().flat_map(|kind| kind.sides).collect();
+        values.sort();
+        values.dedup();
+        let values = &values[..self.edge_types().unwrap_or(values.len()).min(values.len())];
+        tile_set
+            .tiles
+            .iter()
+            .filter(|kind| kind.sides.iter().all(|side| values.contains(side)))
+            .take(self.max_kinds().unwrap_or(usize::MAX))
+            .map(|kind| kind.mesh.as_str())
+            .collect()
+    }
+
+    /// The next preset, the same if it's the hardest
+    pub fn harder(&self) -> Difficulty {
+        Self::ALL[(self.index() + 1).min(Self::ALL.len() - 1)]
+    }
+
+    /// A solve of a board of `grid_size` fast enough to go harder
+    pub fn is_fast_solve(grid_size: IVec2, solve_time: f64) -> bool {
+        solve_time < FAST_SOLVE_SECONDS_PER_CELL * (grid_size.x * grid_size.y) as f64
+    }
+}
+
+/// Seconds per cell of a solve that bumps the difficulty, when it's adaptive
+const FAST_SOLVE_SECONDS_PER_CELL: f64 = 3.;
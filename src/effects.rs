@@ -0,0 +1,102 @@
+use std::f32::consts::PI;
+
+use crate::*;
+
+/// Shakes per second, two frequencies that don't line up so it doesn't look periodic
+const SHAKE_FREQUENCY: Vec2 = Vec2::new(47., 61.);
+/// Radians the camera rolls as it starts shaking
+const SHAKE_ROLL: f32 = 0.02;
+
+/// Moves of the camera and flashes of the screen on top of what the board is doing:
+/// a shake, a punch towards the board, a flash of color. Each fades out over its timer
+#[derive(Clone, Debug)]
+pub struct ScreenEffects {
+    /// Multiplies every effect, 0 turns them off
+    pub intensity: f32,
+    /// And its strength in view units
+    shake: Option<(Timer, f32)>,
+    /// And how far it goes towards the board in view units
+    punch: Option<(Timer, f32)>,
+    flash: Option<(Timer, Vec4)>,
+}
+
+impl Default for ScreenEffects {
+    fn default() -> Self {
+        Self {
+            intensity: 1.,
+            shake: None,
+            punch: None,
+            flash: None,
+        }
+    }
+}
+
+impl ScreenEffects {
+    /// A stronger shake replaces the one playing
+    pub fn shake(&mut self, strength: f32, duration: f64, current_time: f64) {
+        if self.shake_strength(current_time) < strength {
+            self.shake = Some((Timer::from_duration(current_time, duration), strength));
+        }
+    }
+
+    pub fn punch(&mut self, strength: f32, duration: f64, current_time: f64) {
+        self.punch = Some((Timer::from_duration(current_time, duration), strength));
+    }
+
+    /// The alpha of `color` fades to nothing
+    pub fn flash(&mut self, color: Vec4, duration: f64, current_time: f64) {
+        self.flash = Some((Timer::from_duration(current_time, duration), color));
+    }
+
+    /// Stops them all, for a new board
+    pub fn clear(&mut self) {
+        self.shake = None;
+        self.punch = None;
+        self.flash = None;
+    }
+
+    /// Of the shake playing, 1 as it starts and 0 once it ended
+    fn shake_fade(&self, current_time: f64) -> f32 {
+        match &self.shake {
+            Some((timer, _)) if timer.contains(current_time) => {
+                1. - timer.percent(current_time) as f32
+            }
+            _ => 0.,
+        }
+    }
+
+    fn shake_strength(&self, current_time: f64) -> f32 {
+        let strength = self.shake.as_ref().map_or(0., |(_, strength)| *strength);
+        strength * self.shake_fade(current_time)
+    }
+
+    /// Moves the camera by the effects playing, the transform is the view
+    pub fn apply(&self, transform: &mut Transform, current_time: f64) {
+        let fade = self.shake_fade(current_time) * self.intensity;
+        if let Some((_, strength)) = self.shake.as_ref().filter(|_| fade > 0.) {
+            let phase = SHAKE_FREQUENCY * current_time as f32;
+            let wave = Vec2::new(phase.x.sin(), (phase.y + 1.3).sin());
+            transform.translation += (wave * *strength * fade).extend(0.);
+            transform.rotation =
+                Quat::from_rotation_z(wave.y * SHAKE_ROLL * fade) * transform.rotation;
+        }
+        if let Some((timer, strength)) = &self.punch {
+            if timer.contains(current_time) {
+                // in and back out, quicker in
+                let t = timer.percent(current_time) as f32;
+                let punch = (PI * t.sqrt()).sin() * strength * self.intensity;
+                transform.translation.z += punch;
+            }
+        }
+    }
+
+    /// The color over the screen, None when there's no flash
+    pub fn flash_color(&self, current_time: f64) -> Option<Vec4> {
+        let (timer, color) = self.flash.as_ref()?;
+        if !timer.contains(current_time) || self.intensity <= 0. {
+            return None;
+        }
+        let fade = Easing::QuadOut.apply(1. - timer.percent(current_time) as f32);
+        Some(color.truncate().extend(color.w * fade * self.intensity))
+    }
+}
@@ -1,66 +1,447 @@
-use crate::{ui::*, *};
+use std::{collections::HashMap, task::Poll};
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::mpsc;
+
+use crate::{
+    camera::*, console::*, effects::*, levels::*, locale::*, particles::*, records::*, replay::*,
+    ui::*, *,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Mode {
+    LevelSelect,
+    Settings,
+    /// Rebinding the keys of the actions, from the settings
+    Controls,
+    /// Totals of the solved boards and the latest ones
+    Statistics,
+    Level(usize),
+    Endless,
+    /// Hosting or joining a race against another player
+    Lobby,
+    /// Racing the other player on the board the host picked
+    Versus,
+    /// The name and color shown to the other players, asked on the first launch
+    Profile,
+    /// Until the files of the manifest are loaded, at the start
+    Loading,
+}
 
 pub struct GameState {
-    rand: RandLCG,
+    mode: Mode,
+    progress: Progress,
+    /// Seeded at launch, the seeds of the next boards and the particles
+    rng: Rng,
     ui_defaults: Option<UiDefaults>,
+    /// Set once the files of the manifest are loaded
+    handles: Option<Handles>,
     board: Board,
     hand: Option<(usize, usize)>,
+    cursor: Option<(usize, usize)>,
     available_tiles: Vec<KripkeTile>,
+    /// Kinds of the `available_tiles`, in the same order
+    tile_set: TileSet,
     restart: bool,
+    /// Of the next endless boards
+    difficulty: Difficulty,
+    /// The difficulty goes up after a fast endless solve
+    adaptive: bool,
+    /// The next endless boards start empty, their tiles are placed from a bag
+    bag: bool,
+    /// Kind of the tile of the bag the empty cells are filled with, instead of a tile of the board
+    bag_hand: Option<usize>,
+    /// Kind of the tile dragged out of the bag, the entry it came from and where the pointer was
+    /// last, it's placed where it's let go
+    bag_drag: Option<(usize, Rect, Vec2)>,
+    /// The board replacing the current one on restart, the mode and code it was started for
+    generating: Option<(Mode, SeedCode, PendingBoard)>,
+    /// The race against another player, from the lobby until it's left
+    versus: Option<Versus>,
+    address_field: UiTextField,
+    room_field: UiTextField,
+    /// Why the last host or join failed
+    versus_error: Option<String>,
+    profile: Profile,
+    name_field: UiTextField,
+    /// The emotes to send are shown around the middle of the screen
+    emote_wheel: bool,
     grid_size: IVec2,
     win_timer: Option<f64>,
+    /// When the board was paused and the tick it was at, its clock is stopped until resumed
+    paused: Option<(f64, u64)>,
+    /// Where the back button of the settings goes, the pause menu opens them too
+    settings_return: Mode,
+    /// Saved at the last suspend, put back on its board once it's generated
+    suspended: Option<SuspendedBoard>,
+    /// The board was opened again after a suspend, its replay misses the moves before
+    resumed: bool,
+    start_time: f64,
+    solve_time: Option<f64>,
+    moves: u32,
+    records: Records,
+    new_record: bool,
+    stats: Stats,
+    /// Hints shown on the current board
+    hints: u32,
+    hint: Option<Hint>,
+    /// Time of the next move of the solver, after giving up
+    auto_solve: Option<f64>,
+    /// The board was given up, its solve doesn't count
+    gave_up: bool,
+    seed_code: SeedCode,
+    next_seed_code: Option<SeedCode>,
+    code_field: UiTextField,
+    code_error: Option<SeedCodeError>,
+    locale: Locale,
+    /// The screen the ui focus belongs to
+    focus_mode: Mode,
+    /// Part of the screen width the menus are moved right by, they slide in when opened
+    menu_slide: Tween,
+    camera: CameraController,
+    /// View projection of the last frame, the sounds of the tiles are panned by it
+    sound_view: Mat4,
+    particles: Particles,
+    /// Of the levels solved since the game started, by level
+    thumbnails: HashMap<usize, AssetId>,
+    /// Shakes and flashes of the screen for the moves and the solve
+    effects: ScreenEffects,
+    /// Tiles moving to their cells after a rotation or a swap
+    tile_animations: Vec<TileAnimation>,
+    /// Generated mesh around the board and the board size it was made for
+    board_frame: Option<(AssetId, IVec2)>,
+    /// Generated bar over the right edge of a tile, tinted by the side value in color-match mode
+    edge_marker: Option<AssetId>,
+    /// Generated bar drawn on the edges that don't match
+    mistake_marker: Option<AssetId>,
+    /// Toggled by the debug key: the frame profiler, the bounds of the board and of its tiles,
+    /// and where the cursor points
+    debug_draw: bool,
+    /// Moves made on the current board, saved when it's solved
+    recording: Replay,
+    /// Set while a saved replay plays, the player can only watch
+    playback: Option<ReplayPlayer>,
+    /// Engine tick the current board started at
+    board_tick: u64,
+    console: Console,
+    /// The finger down went on the ui, its gesture is ignored
+    touch_on_ui: bool,
+    /// The tiles, which cells were empty and the moves before each move that changed the board,
+    /// the last on top
+    undo_stack: Vec<(Vec<KripkeTile>, Vec<bool>, u32)>,
+    /// Waiting for the key to bind to the action, on the controls screen
+    rebinding: Option<Action>,
 }
 
 #[derive(Clone)]
 pub struct Board {
     grid_tiles: Vec<KripkeTile>,
     grid_size: IVec2,
+    /// Sides of each cell in the generated solution, guides the solver
+    solution: Vec<[u8; 4]>,
+    /// Of the tile set the board was built from
+    edges: EdgeTypes,
+    /// False on the holes of a board that isn't a rectangle, every cell is there when None.
+    /// The holes keep a tile that is never drawn, matched or moved
+    mask: Option<Vec<bool>>,
+    /// True on the cells whose tile is placed from the start and can't be moved
+    locked: Vec<bool>,
+    /// Kept up to date by the methods that change the tiles
+    mismatches: EdgeMismatches,
+    /// True on the cells with no tile yet, on a board played from a bag.
+    /// They keep the tile dealt there, which isn't drawn or matched
+    empty: Vec<bool>,
+    /// Tiles of each kind of the tile set dealt to the player, empty unless the board
+    /// is played from a bag
+    bag: Vec<u32>,
 }
 
-impl Board {
-    fn randomized(
-        rand: &mut RandLCG,
+/// A board being generated, a few attempts at a time
+struct BoardGeneration {
+    board: Board,
+    /// Seeded by the seed code, the boards are the same on every machine
+    rng: Rng,
+    /// Cells whose tile is placed and locked
+    locked: Vec<usize>,
+    /// More tiles are locked until the board has a single solution
+    unique: bool,
+    available_tiles: Vec<KripkeTile>,
+    /// The kinds of the `available_tiles`, in the same order
+    tile_set: TileSet,
+    attempts: u32,
+}
+
+impl BoardGeneration {
+    fn new(
+        rng: Rng,
         size: IVec2,
-        available_tiles: &Vec<KripkeTile>,
-    ) -> Option<Self> {
-        let mut board = Self {
-            grid_tiles: vec![available_tiles[0].clone(); (size.x * size.y) as usize],
+        mask: Option<Vec<bool>>,
+        locked: Vec<usize>,
+        unique: bool,
+        available_tiles: Vec<KripkeTile>,
+        tile_set: TileSet,
+    ) -> Self {
+        let cells = (size.x * size.y) as usize;
+        let board = Board {
+            grid_tiles: vec![available_tiles[0].clone(); cells],
             grid_size: size,
+            solution: vec![],
+            edges: tile_set.edges.clone(),
+            mask,
+            locked: vec![false; cells],
+            mismatches: EdgeMismatches::default(),
+            empty: vec![false; cells],
+            bag: vec![],
+        };
+        Self {
+            board,
+            rng,
+            locked,
+            unique,
+            available_tiles,
+            tile_set,
+            attempts: 0,
+        }
+    }
+
+    /// Attempts to construct the board until one succeeds or the time passes `until`,
+    /// at least once. Ready with None when all the `GENERATION_ATTEMPTS` failed
+    fn step(&mut self, until: f64) -> Poll<Option<Board>> {
+        loop {
+            if self.attempts >= GENERATION_ATTEMPTS {
+                return Poll::Ready(None);
+            }
+            self.attempts += 1;
+            let board = &mut self.board;
+            if board.construct(&mut self.rng, &self.available_tiles, &self.tile_set) {
+                for i in self.locked.iter() {
+                    if board.has_cell(*i) {
+                        board.lock_cell(*i);
+                    }
+                }
+                if self.unique {
+                    board.lock_until_unique();
+                }
+                return Poll::Ready(Some(board.clone()));
+            }
+            if miniquad::date::now() >= until {
+                return Poll::Pending;
+            }
+        }
+    }
+
+    /// Attempts until it's done
+    fn finish(mut self) -> Option<Board> {
+        loop {
+            if let Poll::Ready(board) = self.step(f64::INFINITY) {
+                return board;
+            }
+        }
+    }
+}
+
+/// A board being generated away from the frame
+enum PendingBoard {
+    /// On a thread, sent when done
+    #[cfg(not(target_arch = "wasm32"))]
+    Worker(mpsc::Receiver<Option<Board>>),
+    /// On the main thread, where there are no threads, for a bit of each frame
+    Steps(Box<BoardGeneration>),
+}
+
+impl PendingBoard {
+    fn start(generation: BoardGeneration) -> Self {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let (sender, receiver) = mpsc::channel();
+            std::thread::spawn(move || {
+                // the receiver is gone if the board isn't wanted anymore
+                let _ = sender.send(generation.finish());
+            });
+            PendingBoard::Worker(receiver)
+        }
+        #[cfg(target_arch = "wasm32")]
+        PendingBoard::Steps(Box::new(generation))
+    }
+
+    /// Called each frame, Ready with None if the generation failed
+    fn poll(&mut self) -> Poll<Option<Board>> {
+        match self {
+            #[cfg(not(target_arch = "wasm32"))]
+            PendingBoard::Worker(receiver) => match receiver.try_recv() {
+                Ok(board) => Poll::Ready(board),
+                Err(mpsc::TryRecvError::Empty) => Poll::Pending,
+                Err(mpsc::TryRecvError::Disconnected) => Poll::Ready(None),
+            },
+            PendingBoard::Steps(generation) => {
+                generation.step(miniquad::date::now() + GENERATION_FRAME_BUDGET)
+            }
+        }
+    }
+}
+
+impl Board {
+    /// Moves the tile of the generated solution to the cell and locks it there
+    fn lock_cell(&mut self, i: usize) {
+        if self.is_locked(i) || self.solution.len() != self.grid_tiles.len() {
+            return;
+        }
+        let candidates = std::iter::once(i).chain(0..self.grid_tiles.len());
+        let found = candidates
+            .filter(|j| self.has_cell(*j) && !self.is_locked(*j))
+            .find_map(|j| {
+                (0..4)
+                    .find(|rot| self.grid_tiles[j].rotated_left_by(*rot).sides == self.solution[i])
+                    .map(|rot| (j, rot))
+            });
+        let Some((j, rot)) = found else {
+            return;
+        };
+        self.swap_tiles(i, j);
+        self.set_tile(i, self.grid_tiles[i].rotated_left_by(rot));
+        self.locked[i] = true;
+    }
+
+    /// Locks the tiles that tell the solutions apart, until only the generated one is left
+    /// or the search for another runs out of budget
+    fn lock_until_unique(&mut self) {
+        while let Some(other) = self.other_solution() {
+            // the search changes the last cells first, locking one of them rules out the most
+            let differs = (0..self.grid_tiles.len()).rev().find(|i| {
+                other.tiles[*i].is_some_and(|(index, rot)| {
+                    self.grid_tiles[index].rotated_left_by(rot).sides != self.solution[*i]
+                })
+            });
+            let Some(i) = differs else {
+                return;
+            };
+            self.lock_cell(i);
+            if !self.is_locked(i) {
+                return;
+            }
+        }
+    }
+
+    /// An arrangement of the tiles that solves the board with sides other than
+    /// the generated solution
+    fn other_solution(&self) -> Option<SparseBoard> {
+        let mut sparse_board = SparseBoard {
+            tiles: vec![None; self.grid_tiles.len()],
+            grid_size: self.grid_size,
         };
+        let mut used: Vec<bool> = (0..self.grid_tiles.len())
+            .map(|i| !self.has_cell(i) || self.is_locked(i))
+            .collect();
+        for (i, tile) in sparse_board.tiles.iter_mut().enumerate() {
+            if self.is_locked(i) {
+                *tile = Some((i, 0));
+            }
+        }
+        let mut budget = SOLVER_BUDGET;
+        self.search_other(0, false, &mut sparse_board, &mut used, &mut budget)
+            .then_some(sparse_board)
+    }
 
-        for _ in 0..1000 {
-            if board.construct(rand, available_tiles) {
-                return Some(board);
+    /// Depth first search like `solve_cell`, a full board counts if it `differs`
+    fn search_other(
+        &self,
+        cell: usize,
+        differs: bool,
+        sparse_board: &mut SparseBoard,
+        used: &mut Vec<bool>,
+        budget: &mut u32,
+    ) -> bool {
+        if cell == sparse_board.tiles.len() {
+            return differs;
+        }
+        if !self.has_cell(cell) || self.is_locked(cell) {
+            return self.search_other(cell + 1, differs, sparse_board, used, budget);
+        }
+        let mut tried: Vec<[u8; 4]> = vec![];
+        for index in 0..self.grid_tiles.len() {
+            if used[index] {
+                continue;
+            }
+            for rot in 0..4 {
+                let sides = self.grid_tiles[index].rotated_left_by(rot).sides;
+                if tried.contains(&sides) {
+                    continue;
+                }
+                tried.push(sides);
+                if *budget == 0 {
+                    sparse_board.tiles[cell] = None;
+                    return false;
+                }
+                *budget -= 1;
+                sparse_board.tiles[cell] = Some((index, rot));
+                if sparse_board.is_consistent_at(cell, &self.grid_tiles, &self.edges) {
+                    used[index] = true;
+                    let differs = differs || sides != self.solution[cell];
+                    if self.search_other(cell + 1, differs, sparse_board, used, budget) {
+                        return true;
+                    }
+                    used[index] = false;
+                }
             }
         }
-        None
+        sparse_board.tiles[cell] = None;
+        false
     }
 
-    fn construct(&mut self, rand: &mut RandLCG, available_tiles: &Vec<KripkeTile>) -> bool {
+    /// Places tiles that fit from `RNG_BOARD_GEN`, then shuffles and turns them
+    /// from `RNG_SHUFFLES`
+    fn construct(
+        &mut self,
+        rng: &mut Rng,
+        available_tiles: &Vec<KripkeTile>,
+        tile_set: &TileSet,
+    ) -> bool {
         let mut sparse_board = SparseBoard {
             tiles: vec![None; self.grid_tiles.len()],
             grid_size: self.grid_size,
         };
+        let mut placed = vec![0; available_tiles.len()];
         let mut matchings: Vec<(usize, usize)> = vec![];
         for y in 0..self.grid_size.y as usize {
             for x in 0..self.grid_size.x as usize {
                 // find all possible matching
                 // pick one
                 let tile_i = self.xy_i(x, y);
+                if !self.has_cell(tile_i) {
+                    continue;
+                }
                 matchings.clear();
-                for index in 0..available_tiles.len() {
+                for (index, kind) in tile_set.tiles.iter().enumerate() {
+                    if kind.copies.is_some_and(|copies| placed[index] >= copies) {
+                        continue;
+                    }
                     for rot in 0..4 {
                         sparse_board.tiles[tile_i] = Some((index, rot));
-                        if sparse_board.is_consistent(available_tiles) {
+                        if sparse_board.is_consistent_at(tile_i, available_tiles, &self.edges) {
                             matchings.push((index, rot))
                         }
                         sparse_board.tiles[tile_i] = None;
                     }
                 }
-                if matchings.is_empty() {
+                // each rotation that fits counts the weight of its kind
+                let total: u32 = matchings
+                    .iter()
+                    .map(|(index, _)| tile_set.tiles[*index].weight)
+                    .sum();
+                if total == 0 {
                     return false;
                 }
-                let choice = rand.next() as usize % matchings.len();
+                let mut pick = rng.stream(RNG_BOARD_GEN).next_u32() % total;
+                let choice = matchings
+                    .iter()
+                    .position(|(index, _)| {
+                        let weight = tile_set.tiles[*index].weight;
+                        let found = pick < weight;
+                        pick = pick.saturating_sub(weight);
+                        found
+                    })
+                    .expect("the pick is less than the total");
+                placed[matchings[choice].0] += 1;
                 sparse_board.tiles[tile_i] = Some(matchings[choice]);
             }
         }
@@ -70,91 +451,368 @@ impl Board {
         self.grid_tiles = sparse_board
             .tiles
             .iter()
-            .map(|opt| {
-                let (index, rotation) = opt.expect("sparse grid is filled");
-                available_tiles[index].rotated_left_by(rotation)
+            .enumerate()
+            .map(|(i, opt)| match opt {
+                Some((index, rotation)) => available_tiles[*index].rotated_left_by(*rotation),
+                None if !self.has_cell(i) => available_tiles[0].clone(),
+                None => unreachable!("sparse grid is filled"),
             })
             .collect();
+        self.mismatches = EdgeMismatches::new(self);
         assert!(self.is_solved());
-        for i in (1..self.grid_tiles.len()).rev() {
-            let j = rand.next() as usize % (i + 1);
-            let t = self.grid_tiles[i].clone();
-            self.grid_tiles[i] = self.grid_tiles[j].clone();
-            self.grid_tiles[j] = t;
+        self.solution = self.grid_tiles.iter().map(|tile| tile.sides).collect();
+        // the holes and the locked tiles stay where they are
+        let cells: Vec<usize> = (0..self.grid_tiles.len())
+            .filter(|i| self.has_cell(*i) && !self.is_locked(*i))
+            .collect();
+        let rand = rng.stream(RNG_SHUFFLES);
+        for i in (1..cells.len()).rev() {
+            let j = rand.next_u32() as usize % (i + 1);
+            self.grid_tiles.swap(cells[i], cells[j]);
         }
-        for i in 0..self.grid_tiles.len() {
-            let rotation = rand.next() as usize % 4;
+        for i in cells {
+            let rotation = rand.next_u32() as usize % 4;
             self.grid_tiles[i] = self.grid_tiles[i].rotated_left_by(rotation);
         }
+        self.mismatches = EdgeMismatches::new(self);
 
         true
     }
 
-    fn is_solvable(&self) -> bool {
-        // PERF: this sucks
+    /// Whether the tiles can be arranged into a solution, locked ones in place
+    pub fn is_solvable(&self) -> bool {
+        self.puzzle().solve(SOLVER_BUDGET).is_some()
+    }
+
+    fn puzzle(&self) -> Puzzle<'_> {
+        Puzzle {
+            sides: self.grid_tiles.iter().map(|tile| tile.sides).collect(),
+            grid_size: self.grid_size,
+            cells: (0..self.grid_tiles.len())
+                .map(|i| self.has_cell(i))
+                .collect(),
+            locked: (0..self.grid_tiles.len())
+                .map(|i| self.is_locked(i))
+                .collect(),
+            edges: &self.edges,
+        }
+    }
 
-        // indices are referring to these tiles
-        let reference_tiles = &self.grid_tiles;
-        let mut branches: Vec<SparseBoard> = vec![];
-        branches.push(SparseBoard {
+    /// Finds an arrangement of the current tiles that solves the board.
+    /// Each cell tries its own tile first, so the solution stays close to the current board.
+    /// The leading cells that already match the generated solution are kept as they are,
+    /// if the search runs out of budget the constraint solver is tried, then the generated solution.
+    fn solve(&self) -> Option<SparseBoard> {
+        let mut sparse_board = SparseBoard {
             tiles: vec![None; self.grid_tiles.len()],
             grid_size: self.grid_size,
-        });
-        for _ in 0..10000 {
-            if branches.is_empty() {
-                return false;
+        };
+        // the tiles of the holes are never used, the locked ones stay where they are
+        let mut used: Vec<bool> = (0..self.grid_tiles.len())
+            .map(|i| !self.has_cell(i) || self.is_locked(i))
+            .collect();
+        for (i, tile) in sparse_board.tiles.iter_mut().enumerate() {
+            if self.is_locked(i) {
+                *tile = Some((i, 0));
             }
-            let mut current = branches.pop().unwrap();
-            // if current is filled -> true
-            // if not, branch
-            let missing_indices = current.missing_indices(&reference_tiles);
-            if missing_indices.is_empty() {
-                return true;
+        }
+        let kept = (0..self.grid_tiles.len())
+            .take_while(|i| self.solution.get(*i) == Some(&self.grid_tiles[*i].sides))
+            .count();
+        for (i, tile) in sparse_board.tiles.iter_mut().enumerate().take(kept) {
+            if self.has_cell(i) {
+                *tile = Some((i, 0));
             }
-            for index in missing_indices {
-                // place the tile index so that it satisfies the sides requirements.
-                // if it can't, drop this branch
-                for y in 0..self.grid_size.y as usize {
-                    for x in 0..self.grid_size.x as usize {
-                        let i = self.xy_i(x, y);
-                        if current.tiles[i].is_none() {
-                            for rot in 0..4 {
-                                // sides requirements
-                                current.tiles[i] = Some((index, rot));
-                                if current.is_consistent(reference_tiles) {
-                                    let branch = current.clone();
-                                    branches.push(branch);
-                                }
-                                current.tiles[i] = None;
-                            }
-                        }
+        }
+        used[..kept].fill(true);
+        let mut budget = SOLVER_BUDGET;
+        if self.solve_cell(kept, true, &mut sparse_board, &mut used, &mut budget) {
+            return Some(sparse_board);
+        }
+        match self.puzzle().solve(SOLVER_BUDGET) {
+            Some(tiles) => Some(SparseBoard {
+                tiles,
+                grid_size: self.grid_size,
+            }),
+            None => self.solution_as_sparse(),
+        }
+    }
+
+    /// Assigns every cell a tile matching the generated solution, own tiles first
+    fn solution_as_sparse(&self) -> Option<SparseBoard> {
+        if self.solution.len() != self.grid_tiles.len() {
+            return None;
+        }
+        let mut tiles = vec![None; self.grid_tiles.len()];
+        let mut used: Vec<bool> = (0..self.grid_tiles.len())
+            .map(|i| !self.has_cell(i) || self.is_locked(i))
+            .collect();
+        for (cell, tile) in tiles.iter_mut().enumerate() {
+            if self.is_locked(cell) {
+                *tile = Some((cell, 0));
+            }
+            if !self.has_cell(cell) || self.is_locked(cell) {
+                continue;
+            }
+            let candidates =
+                std::iter::once(cell).chain((0..self.grid_tiles.len()).filter(|i| *i != cell));
+            let (index, rot) = candidates.filter(|index| !used[*index]).find_map(|index| {
+                (0..4)
+                    .find(|rot| {
+                        self.grid_tiles[index].rotated_left_by(*rot).sides == self.solution[cell]
+                    })
+                    .map(|rot| (index, rot))
+            })?;
+            used[index] = true;
+            *tile = Some((index, rot));
+        }
+        Some(SparseBoard {
+            tiles,
+            grid_size: self.grid_size,
+        })
+    }
+
+    /// Depth first search filling the cells in order.
+    /// `own_first` is true while all the previous cells kept their own tile.
+    fn solve_cell(
+        &self,
+        cell: usize,
+        own_first: bool,
+        sparse_board: &mut SparseBoard,
+        used: &mut Vec<bool>,
+        budget: &mut u32,
+    ) -> bool {
+        if cell == sparse_board.tiles.len() {
+            return true;
+        }
+        if !self.has_cell(cell) || self.is_locked(cell) {
+            return self.solve_cell(cell + 1, own_first, sparse_board, used, budget);
+        }
+        // own tile first, then the ones that fit the generated solution
+        let fits_solution = |index: &usize| {
+            let tile = &self.grid_tiles[*index];
+            self.solution
+                .get(cell)
+                .is_some_and(|sides| (0..4).any(|rot| tile.rotated_left_by(rot).sides == *sides))
+        };
+        let others = (0..self.grid_tiles.len()).filter(|i| *i != cell || !own_first);
+        let candidates = std::iter::once(cell)
+            .filter(|_| own_first)
+            .chain(others.clone().filter(fits_solution))
+            .chain(others.filter(|i| !fits_solution(i)));
+        // identical tiles and symmetric rotations would explore the same branch
+        let mut tried: Vec<[u8; 4]> = vec![];
+        for index in candidates {
+            if used[index] {
+                continue;
+            }
+            for rot in 0..4 {
+                let sides = self.grid_tiles[index].rotated_left_by(rot).sides;
+                if tried.contains(&sides) {
+                    continue;
+                }
+                tried.push(sides);
+                if *budget == 0 {
+                    sparse_board.tiles[cell] = None;
+                    return false;
+                }
+                *budget -= 1;
+                sparse_board.tiles[cell] = Some((index, rot));
+                if sparse_board.is_consistent_at(cell, &self.grid_tiles, &self.edges) {
+                    used[index] = true;
+                    let own = own_first && index == cell && rot == 0;
+                    if self.solve_cell(cell + 1, own, sparse_board, used, budget) {
+                        return true;
                     }
+                    used[index] = false;
                 }
             }
         }
+        sparse_board.tiles[cell] = None;
         false
     }
 
-    fn is_solved(&self) -> bool {
-        for y in 0..self.grid_size.y as usize {
-            for x in 0..self.grid_size.x as usize {
-                for (dir, check) in SIDE_ADJACENT.iter().zip(SIDE_CHECK.iter()) {
-                    if !self.contains(IVec2::new(x as i32, y as i32) + *dir) {
-                        continue;
-                    }
-                    let a = self.grid_tiles[self.xy_i(x, y)].sides[check.0];
-                    let b = self.grid_tiles
-                        [self.xy_i((x as i32 + dir.x) as usize, (y as i32 + dir.y) as usize)]
-                    .sides[check.1];
-                    if a != b {
-                        return false;
-                    }
+    /// Suggests a move that leads to a solution.
+    /// The first misplaced cell is suggested, so it's consistent with its placed neighbours
+    /// and following the hints always makes progress.
+    pub fn find_hint(&self) -> Option<Hint> {
+        let solution = self.solve()?;
+        let is_placed = |i: usize| solution.tiles[i] == Some((i, 0));
+        let to = (0..self.grid_tiles.len()).find(|i| self.has_cell(*i) && !is_placed(*i))?;
+        let (from, rotation) = solution.tiles[to]?;
+        Some(Hint { from, to, rotation })
+    }
+
+    /// The cells next to the cell that aren't holes
+    fn neighbours(&self, i: usize) -> impl Iterator<Item = usize> + '_ {
+        let width = self.grid_size.x as usize;
+        let pos = IVec2::new((i % width) as i32, (i / width) as i32);
+        SIDE_ADJACENT
+            .iter()
+            .map(move |dir| pos + *dir)
+            .filter(|pos| self.has_cell_at(*pos))
+            .map(|pos| self.xy_i(pos.x as usize, pos.y as usize))
+    }
+
+    /// The sides of the cell that don't match their neighbour, right, up, left and down
+    fn mismatches(&self, i: usize) -> [bool; 4] {
+        self.mismatches.sides(i)
+    }
+
+    /// Edges between two cells that don't match, the board is solved at 0
+    pub fn mismatch_count(&self) -> usize {
+        self.mismatches.count
+    }
+
+    /// `mismatches` checked on the tiles, for the cache. Nothing mismatches an empty cell
+    fn find_mismatches(&self, i: usize) -> [bool; 4] {
+        let width = self.grid_size.x as usize;
+        let pos = IVec2::new((i % width) as i32, (i / width) as i32);
+        if !self.has_cell(i) || self.is_empty(i) {
+            return [false; 4];
+        }
+        std::array::from_fn(|side| {
+            let (check, adj) = (SIDE_CHECK[side], pos + SIDE_ADJACENT[side]);
+            if !self.has_cell_at(adj) {
+                return false;
+            }
+            let j = self.xy_i(adj.x as usize, adj.y as usize);
+            if self.is_empty(j) {
+                return false;
+            }
+            let other = &self.grid_tiles[j];
+            !self
+                .edges
+                .matches(self.grid_tiles[i].sides[check.0], other.sides[check.1])
+        })
+    }
+
+    /// Fraction of the edges between cells that match, 1 when solved
+    fn progress(&self) -> f32 {
+        let width = self.grid_size.x as usize;
+        let (mut edges, mut matched) = (0, 0);
+        for i in (0..self.grid_tiles.len()).filter(|i| self.has_cell(*i)) {
+            let pos = IVec2::new((i % width) as i32, (i / width) as i32);
+            let mismatches = self.mismatches(i);
+            // right and up, each edge is counted once
+            for side in 0..2 {
+                if self.has_cell_at(pos + SIDE_ADJACENT[side]) {
+                    edges += 1;
+                    matched += !mismatches[side] as u32;
                 }
             }
         }
+        if edges == 0 {
+            return 1.;
+        }
+        matched as f32 / edges as f32
+    }
+
+    /// Which tile of the tile set is on each cell and how it's turned, to send the board
+    fn cell_states(&self) -> Vec<CellState> {
+        self.grid_tiles
+            .iter()
+            .enumerate()
+            .map(|(i, tile)| CellState {
+                tile: match self.is_empty(i) {
+                    true => EMPTY_CELL,
+                    false => tile.kind as u8,
+                },
+                rotation: tile.rotation as u8,
+            })
+            .collect()
+    }
+
+    fn is_solved(&self) -> bool {
+        self.mismatch_count() == 0 && !self.empty.contains(&true)
+    }
+
+    fn set_tile(&mut self, i: usize, tile: KripkeTile) {
+        self.grid_tiles[i] = tile;
+        self.update_mismatches(&[i]);
+    }
+
+    /// All of them and which cells are empty, like after an undo
+    fn set_tiles(&mut self, tiles: Vec<KripkeTile>, empty: Vec<bool>) {
+        self.grid_tiles = tiles;
+        self.empty = empty;
+        self.mismatches = EdgeMismatches::new(self);
+    }
+
+    /// An empty cell moves like a tile
+    fn swap_tiles(&mut self, a: usize, b: usize) {
+        self.grid_tiles.swap(a, b);
+        self.empty.swap(a, b);
+        self.update_mismatches(&[a, b]);
+    }
+
+    fn is_empty(&self, i: usize) -> bool {
+        self.empty.get(i).is_some_and(|empty| *empty)
+    }
+
+    fn is_bag(&self) -> bool {
+        !self.bag.is_empty()
+    }
+
+    /// Deals the tiles that aren't locked into the bag, the board is played by placing them
+    fn empty_into_bag(&mut self) {
+        let kinds = self.grid_tiles.iter().map(|tile| tile.kind + 1).max();
+        self.bag = vec![0; kinds.unwrap_or(0)];
+        for i in 0..self.grid_tiles.len() {
+            if self.has_cell(i) && !self.is_locked(i) {
+                self.bag[self.grid_tiles[i].kind] += 1;
+                self.empty[i] = true;
+            }
+        }
+        self.mismatches = EdgeMismatches::new(self);
+    }
+
+    /// Tiles of each kind still in the bag
+    fn bag_remaining(&self) -> Vec<u32> {
+        let mut remaining = self.bag.clone();
+        for i in 0..self.grid_tiles.len() {
+            if self.has_cell(i) && !self.is_locked(i) && !self.is_empty(i) {
+                let kind = self.grid_tiles[i].kind;
+                remaining[kind] = remaining[kind].saturating_sub(1);
+            }
+        }
+        remaining
+    }
+
+    /// There's a tile of the kind left in the bag
+    fn can_place_kind(&self, kind: usize) -> bool {
+        self.bag_remaining().get(kind).is_some_and(|left| *left > 0)
+    }
+
+    fn can_place(&self, i: usize, kind: usize) -> bool {
+        self.is_empty(i) && self.can_place_kind(kind)
+    }
+
+    /// Puts the tile on the empty cell if there's one of its kind left in the bag
+    fn place(&mut self, i: usize, tile: KripkeTile) -> bool {
+        if !self.can_place(i, tile.kind) {
+            return false;
+        }
+        self.empty[i] = false;
+        self.set_tile(i, tile);
         true
     }
 
+    /// A quarter turn, `back` the other way
+    fn rotate_tile(&mut self, i: usize, back: bool) {
+        match back {
+            true => self.grid_tiles[i].rotate_right(),
+            false => self.grid_tiles[i].rotate_left(),
+        }
+        self.update_mismatches(&[i]);
+    }
+
+    fn update_mismatches(&mut self, cells: &[usize]) {
+        let mut mismatches = std::mem::take(&mut self.mismatches);
+        mismatches.update(self, cells);
+        self.mismatches = mismatches;
+    }
+
     fn xy_i(&self, x: usize, y: usize) -> usize {
         x + y * self.grid_size.x as usize
     }
@@ -162,10 +820,206 @@ impl Board {
     fn contains(&self, IVec2 { x, y }: IVec2) -> bool {
         x >= 0 && x < self.grid_size.x && y >= 0 && y < self.grid_size.y
     }
+
+    /// False on the holes of the mask
+    fn has_cell(&self, i: usize) -> bool {
+        self.mask.as_ref().is_none_or(|mask| mask[i])
+    }
+
+    /// Inside the board and not on a hole
+    fn has_cell_at(&self, pos: IVec2) -> bool {
+        self.contains(pos) && self.has_cell(self.xy_i(pos.x as usize, pos.y as usize))
+    }
+
+    fn is_locked(&self, i: usize) -> bool {
+        self.locked.get(i) == Some(&true)
+    }
+
+    /// A cell that isn't a hole and whose tile can be moved
+    fn is_playable(&self, x: usize, y: usize) -> bool {
+        let i = self.xy_i(x, y);
+        self.has_cell_at(IVec2::new(x as i32, y as i32)) && !self.is_locked(i)
+    }
+
+    /// The bottom left cell that isn't a hole
+    fn first_cell(&self) -> (usize, usize) {
+        let width = self.grid_size.x.max(1) as usize;
+        let i = (0..self.grid_tiles.len())
+            .find(|i| self.has_cell(*i))
+            .unwrap_or(0);
+        (i % width, i / width)
+    }
+}
+
+/// The edges of a board that don't match, checked again only around the cells that change
+#[derive(Clone, Debug, Default)]
+struct EdgeMismatches {
+    /// Sides of each cell that don't match their neighbour
+    cells: Vec<[bool; 4]>,
+    /// Edges between two cells that don't match, each counted once
+    count: usize,
+}
+
+impl EdgeMismatches {
+    fn new(board: &Board) -> Self {
+        let cells: Vec<[bool; 4]> = (0..board.grid_tiles.len())
+            .map(|i| board.find_mismatches(i))
+            .collect();
+        let count = cells.iter().map(Self::counted).sum();
+        Self { cells, count }
+    }
+
+    /// Checks again the edges of the cells, from both sides
+    fn update(&mut self, board: &Board, cells: &[usize]) {
+        if self.cells.len() != board.grid_tiles.len() {
+            *self = Self::new(board);
+            return;
+        }
+        for i in cells {
+            for j in std::iter::once(*i).chain(board.neighbours(*i)) {
+                self.count -= Self::counted(&self.cells[j]);
+                self.cells[j] = board.find_mismatches(j);
+                self.count += Self::counted(&self.cells[j]);
+            }
+        }
+    }
+
+    /// Right and up, so that an edge is only counted from the cell on its left or below it
+    fn counted(sides: &[bool; 4]) -> usize {
+        sides[0] as usize + sides[1] as usize
+    }
+
+    fn sides(&self, i: usize) -> [bool; 4] {
+        self.cells.get(i).copied().unwrap_or([false; 4])
+    }
+}
+
+pub(crate) const SIDE_ADJACENT: [IVec2; 4] = [IVec2::X, IVec2::Y, IVec2::NEG_X, IVec2::NEG_Y];
+pub(crate) const SIDE_CHECK: [(usize, usize); 4] = [(0, 2), (1, 3), (2, 0), (3, 1)];
+
+/// Max placements tried by the solver before giving up
+const SOLVER_BUDGET: u32 = 20000;
+
+/// Boards constructed before giving up on a seed
+const GENERATION_ATTEMPTS: u32 = 1000;
+/// Seconds of each frame spent generating a board, where it can't be done on a thread
+const GENERATION_FRAME_BUDGET: f64 = 0.008;
+
+/// Characters of the address of the host, enough for ipv6 and a port
+const ADDRESS_MAX_LEN: usize = 48;
+
+/// Latest solves listed on the statistics screen
+const HISTORY_ROWS: usize = 6;
+
+/// Widest a menu gets for its height, on wider screens it's centered
+const MENU_MAX_ASPECT: f32 = 2.;
+
+/// Seconds a menu takes to slide in
+const MENU_SLIDE_DURATION: f64 = 0.35;
+
+/// Seconds the win banner takes to pop in
+const BANNER_DURATION: f64 = 0.5;
+/// Seconds the bubble of an emote takes to pop in
+const EMOTE_POP_DURATION: f64 = 0.3;
+
+/// Over the board while it's paused
+const PAUSE_DIM: Vec4 = Vec4::new(0., 0., 0., 0.6);
+
+/// Part of the camera distance the screen shakes by when a move mismatches more sides
+const MISMATCH_SHAKE: f32 = 0.012;
+const MISMATCH_SHAKE_DURATION: f64 = 0.25;
+/// Part of the camera distance it moves in when the moved tiles fit their neighbours
+const SNAP_PUNCH: f32 = 0.03;
+const SNAP_PUNCH_DURATION: f64 = 0.2;
+/// Over the screen as the board is solved, fading out
+const SOLVE_FLASH: Vec4 = Vec4::new(1., 1., 0.9, 0.5);
+const SOLVE_FLASH_DURATION: f64 = 0.6;
+
+/// Colors of the loading screen, drawn before a theme is loaded
+const LOADING_BAR_BACK: Vec4 = Vec4::new(0.2, 0.2, 0.2, 1.);
+const LOADING_BAR_FILL: Vec4 = Vec4::new(0.8, 0.8, 0.8, 1.);
+const LOADING_FAILED: Vec4 = Vec4::new(0.9, 0.3, 0.3, 1.);
+
+/// Seconds between the moves of the solver after giving up
+const AUTO_SOLVE_STEP: f64 = 0.5;
+
+/// Seconds a tile takes to reach its cell after a rotation or a swap
+const TILE_ANIMATION_DURATION: f64 = 0.15;
+/// Height the swapped tiles jump to, in cells
+const SWAP_LIFT: f32 = 0.4;
+/// Scale of the tiles while playing, leaving a gap between them
+const TILE_PADDING: f32 = 0.47;
+/// Scale of the tiles of a solved board, they touch
+const SOLVED_TILE_PADDING: f32 = 0.502;
+/// Boards with a side this long have a mini-map in the corner
+const MINIMAP_MIN_SIDE: i32 = 7;
+/// Of the screen height
+const MINIMAP_SIZE: f32 = 0.2;
+const MINIMAP_BACK: Vec4 = Vec4::new(0., 0., 0., 0.5);
+const MINIMAP_MATCHED: Vec4 = Vec4::new(0.85, 0.85, 0.85, 1.);
+/// Of the markers on the edges that don't match, and of their cells on the mini-map
+const MISTAKE_COLOR: Vec4 = Vec4::new(1., 0.15, 0.1, 1.);
+/// Of the screen height, the most each kind of the bag takes
+const BAG_ENTRY_SIZE: f32 = 0.08;
+/// Of the side of the tile icons of the bag
+const BAG_ICON_BAR: f32 = 0.24;
+/// Height a tile placed from the bag drops from, in cells
+const BAG_DROP_HEIGHT: f32 = 1.5;
+
+/// Copies of each kind in a bag over the fewest that cover the cells, so that the generator
+/// still has a choice
+const BAG_SPARE_COPIES: u32 = 1;
+
+/// Pixels of the thumbnails of the solved levels
+const THUMBNAIL_SIZE: UVec2 = UVec2::splat(128);
+
+// groups of the sounds of the moves in the `AudioPlayer`
+const SFX_CLICK: &str = "click";
+const SFX_ROTATE: &str = "rotate";
+const SFX_SWAP: &str = "swap";
+
+/// Seconds of crossfade between music tracks
+const MUSIC_FADE: f32 = 1.5;
+
+// streams of the `Rng`, drawing from one doesn't change the others
+/// The seeds of the next boards, and the tiles placed on a board from its seed
+const RNG_BOARD_GEN: &str = "board-gen";
+/// Where the tiles of a board go and how they're turned before the player moves them
+const RNG_SHUFFLES: &str = "shuffles";
+const RNG_PARTICLES: &str = "particles";
+/// Of the pitch and volume of the sounds, drawn by the `AudioPlayer`
+pub const RNG_AUDIO_VARIATION: &str = "audio-variation";
+
+/// Pan of a sound at the edge of the screen, less than all the way to one speaker
+const SOUND_PAN: f32 = 0.6;
+
+/// A tile moving from where it was to its cell
+#[derive(Clone, Debug)]
+struct TileAnimation {
+    cell: usize,
+    from: Transform,
+    timer: Timer,
 }
 
-const SIDE_ADJACENT: [IVec2; 4] = [IVec2::X, IVec2::Y, IVec2::NEG_X, IVec2::NEG_Y];
-const SIDE_CHECK: [(usize, usize); 4] = [(0, 2), (1, 3), (2, 0), (3, 1)];
+const CONFETTI_COLORS: [Vec4; 5] = [
+    Vec4::new(1., 0.3, 0.3, 1.),
+    Vec4::new(1., 0.8, 0.2, 1.),
+    Vec4::new(0.3, 0.9, 0.4, 1.),
+    Vec4::new(0.3, 0.6, 1., 1.),
+    Vec4::new(0.9, 0.4, 1., 1.),
+];
+
+/// Distance of the frame from the cells and its width, in cells
+const FRAME_GAP: f32 = 0.05;
+const FRAME_WIDTH: f32 = 0.15;
+
+/// The tile at index `from` belongs at index `to`, rotated left `rotation` times
+#[derive(Clone, Debug, PartialEq)]
+pub struct Hint {
+    pub from: usize,
+    pub to: usize,
+    pub rotation: usize,
+}
 
 #[derive(Clone)]
 struct SparseBoard {
@@ -174,22 +1028,7 @@ struct SparseBoard {
 }
 
 impl SparseBoard {
-    fn missing_indices(&self, reference_tiles: &Vec<KripkeTile>) -> Vec<usize> {
-        (0..reference_tiles.len())
-            .filter_map(|index| {
-                if !self.tiles.iter().any(|o| match o {
-                    Some((used_index, _)) if *used_index == index => true,
-                    _ => false,
-                }) {
-                    Some(index)
-                } else {
-                    None
-                }
-            })
-            .collect()
-    }
-
-    fn is_consistent(&self, reference_tiles: &Vec<KripkeTile>) -> bool {
+    fn is_consistent(&self, reference_tiles: &Vec<KripkeTile>, edges: &EdgeTypes) -> bool {
         for y in 0..self.grid_size.y as usize {
             for x in 0..self.grid_size.x as usize {
                 let Some(tile_i) = self.tiles[self.xy_i(x, y)] else {
@@ -204,7 +1043,7 @@ impl SparseBoard {
                     {
                         let a = reference_tiles[tile_i.0].rotated_left_by(tile_i.1).sides[check.0];
                         let b = reference_tiles[oth_i.0].rotated_left_by(oth_i.1).sides[check.1];
-                        if a != b {
+                        if !edges.matches(a, b) {
                             return false;
                         }
                     }
@@ -214,6 +1053,36 @@ impl SparseBoard {
         true
     }
 
+    /// Like `is_consistent` but only checks the sides of a single tile
+    fn is_consistent_at(
+        &self,
+        i: usize,
+        reference_tiles: &Vec<KripkeTile>,
+        edges: &EdgeTypes,
+    ) -> bool {
+        let Some(tile_i) = self.tiles[i] else {
+            return true;
+        };
+        let pos = IVec2::new(
+            (i % self.grid_size.x as usize) as i32,
+            (i / self.grid_size.x as usize) as i32,
+        );
+        for (dir, check) in SIDE_ADJACENT.iter().zip(SIDE_CHECK.iter()) {
+            let adj = pos + *dir;
+            if !self.contains(adj) {
+                continue;
+            }
+            if let Some(oth_i) = self.tiles[self.xy_i(adj.x as usize, adj.y as usize)] {
+                let a = reference_tiles[tile_i.0].rotated_left_by(tile_i.1).sides[check.0];
+                let b = reference_tiles[oth_i.0].rotated_left_by(oth_i.1).sides[check.1];
+                if !edges.matches(a, b) {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
     fn xy_i(&self, x: usize, y: usize) -> usize {
         x + y * self.grid_size.x as usize
     }
@@ -223,124 +1092,2037 @@ impl SparseBoard {
     }
 }
 
-const TILES: [(&'static str, [u8; 4]); 17] = [
-    ("tile_0000.obj", [0, 0, 0, 0]),
-    ("tile_0020.obj", [0, 0, 0, 2]),
-    ("tile_1000.obj", [0, 1, 0, 0]),
-    ("tile_1100.obj", [1, 1, 0, 0]),
-    ("tile_0202.obj", [2, 0, 2, 0]),
-    ("tile_1111.obj", [1, 1, 1, 1]),
-    ("tile_1110.obj", [1, 1, 0, 1]),
-    ("tile_1112.obj", [1, 1, 2, 1]),
-    ("tile_2211.obj", [1, 1, 2, 2]),
-    ("tile_1010.obj", [1, 0, 1, 0]),
-    ("tile_1022.obj", [0, 1, 2, 2]),
-    ("tile_1202.obj", [2, 1, 2, 0]),
-    ("tile_1220.obj", [2, 1, 0, 2]),
-    ("tile_1222.obj", [2, 1, 2, 2]),
-    ("tile_0022.obj", [0, 0, 2, 2]),
-    ("tile_0222.obj", [2, 0, 2, 2]),
-    ("tile_2222.obj", [2, 2, 2, 2]),
-];
-
-pub struct Handles {
-    pub font: AssetId,
-    pub tiles: Vec<AssetId>,
-    pub tiles_atlas: AssetId,
-    pub base: AssetId,
+/// The gameplay commands of the console
+fn register_commands(registry: &mut CommandRegistry) {
+    registry.register("seed", "seed <n>", |gamestate, _, args| {
+        let [seed] = args else {
+            return Err("expected a seed".to_string());
+        };
+        let seed: u32 = seed.parse().map_err(|e| format!("{}", e))?;
+        gamestate.mode = Mode::Endless;
+        gamestate.next_seed_code = Some(SeedCode {
+            seed,
+            grid_size: gamestate.grid_size,
+            difficulty: gamestate.difficulty,
+            bag: gamestate.bag,
+        });
+        gamestate.restart = true;
+        Ok(format!("endless board with seed {}", seed))
+    });
+    registry.register("grid", "grid <width>x<height>", |gamestate, _, args| {
+        let size = args
+            .first()
+            .and_then(|size| size.split_once('x'))
+            .and_then(|(x, y)| Some(IVec2::new(x.parse().ok()?, y.parse().ok()?)))
+            .ok_or("expected a size like 6x4".to_string())?;
+        if size.min_element() < 1 || size.max_element() > 10 {
+            return Err("the sides go from 1 to 10".to_string());
+        }
+        gamestate.mode = Mode::Endless;
+        gamestate.grid_size = size;
+        gamestate.restart = true;
+        Ok(format!("endless board of {}x{}", size.x, size.y))
+    });
+    registry.register("solve", "solve", |gamestate, engine, _| {
+        if !matches!(gamestate.mode, Mode::Level(_) | Mode::Endless) {
+            return Err("no board".to_string());
+        }
+        // the solution moves the tiles on the board, the ones in the bag aren't there yet
+        if gamestate.board.empty.contains(&true) {
+            return Err("the bag isn't empty".to_string());
+        }
+        // made as moves following the hints, so they can be undone, the solve isn't counted
+        gamestate.gave_up = true;
+        let tick = engine.tick - gamestate.board_tick;
+        let width = gamestate.board.grid_size.x as usize;
+        let xy = |i: usize| (i % width, i / width);
+        // a tile can be moved more than once when the solution changes, but not forever
+        for _ in 0..gamestate.board.grid_tiles.len() * 4 {
+            let Some(Hint { from, to, rotation }) = gamestate.board.find_hint() else {
+                break;
+            };
+            let (time, audio) = (engine.current_time, &*engine.audio);
+            if from != to {
+                gamestate.apply_action(ReplayAction::Swap(xy(from), xy(to)), tick, time, audio);
+            }
+            let (x, y) = xy(to);
+            for _ in 0..rotation {
+                gamestate.apply_action(ReplayAction::Rotate(x, y), tick, time, audio);
+            }
+        }
+        gamestate.hint = None;
+        match gamestate.board.is_solved() {
+            true => Ok("solved".to_string()),
+            false => Err("no solution".to_string()),
+        }
+    });
+    registry.register("solvable", "solvable", |gamestate, _, _| {
+        if !matches!(gamestate.mode, Mode::Level(_) | Mode::Endless) {
+            return Err("no board".to_string());
+        }
+        let start = miniquad::date::now();
+        let solvable = gamestate.board.is_solvable();
+        let ms = (miniquad::date::now() - start) * 1000.;
+        Ok(format!("solvable: {} in {:.1}ms", solvable, ms))
+    });
+    registry.register("give_tile", "give_tile <id>", |gamestate, _, args| {
+        let [id] = args else {
+            return Err("expected a tile id".to_string());
+        };
+        let id: usize = id.parse().map_err(|e| format!("{}", e))?;
+        let tile = gamestate
+            .available_tiles
+            .get(id)
+            .ok_or(format!(
+                "the ids go from 0 to {}",
+                gamestate.available_tiles.len().saturating_sub(1)
+            ))?
+            .clone();
+        if !matches!(gamestate.mode, Mode::Level(_) | Mode::Endless) {
+            return Err("no board".to_string());
+        }
+        // on the tile under the keyboard cursor, or the held one
+        let (x, y) = gamestate
+            .cursor
+            .or(gamestate.hand)
+            .ok_or("no tile under the cursor".to_string())?;
+        let i = gamestate.board.xy_i(x, y);
+        if !gamestate.board.is_playable(x, y) || gamestate.board.is_empty(i) {
+            return Err(format!("no tile to replace at {},{}", x, y));
+        }
+        // it can be undone, but it isn't a move of the replay and the solve isn't counted
+        gamestate.gave_up = true;
+        gamestate.undo_stack.push((
+            gamestate.board.grid_tiles.clone(),
+            gamestate.board.empty.clone(),
+            gamestate.moves,
+        ));
+        gamestate.moves += 1;
+        gamestate.board.set_tile(i, tile);
+        gamestate.hint = None;
+        Ok(format!(
+            "{} at {},{}",
+            gamestate.tile_set.tiles[id].mesh, x, y
+        ))
+    });
+}
+
+/// Flat ring around the cells of a board of `size`, centered on the origin
+fn board_frame_mesh(size: Vec2) -> Mesh {
+    let inner = size * 0.5 + FRAME_GAP;
+    let outer = inner + FRAME_WIDTH;
+    let corners = [
+        Vec2::new(-1., -1.),
+        Vec2::new(1., -1.),
+        Vec2::ONE,
+        Vec2::new(-1., 1.),
+    ];
+    let vertices: Vec<Vec3> = corners
+        .iter()
+        .map(|corner| (*corner * inner).extend(0.))
+        .chain(corners.iter().map(|corner| (*corner * outer).extend(0.)))
+        .collect();
+    // a quad between each side of the inner and outer rectangles, counter clockwise
+    let indices: Vec<u32> = (0..4)
+        .flat_map(|i| {
+            let next = (i + 1) % 4;
+            [i, i + 4, next + 4, i, next + 4, next]
+        })
+        .collect();
+    Mesh::from_data(vertices, vec![], indices).unwrap()
+}
+
+/// The id of a mesh made by `make`, uploaded the first time
+fn generated_mesh(
+    slot: &mut Option<AssetId>,
+    engine: &mut EngineContext,
+    make: fn() -> Mesh,
+) -> AssetId {
+    if let Some(id) = slot {
+        return id.clone();
+    }
+    let id = engine.assets.new_id();
+    engine
+        .renderer
+        .upload_dynamic_mesh(engine.ctx, &id, &make());
+    *slot = Some(id.clone());
+    id
+}
+
+/// A tile of each kind of the tile set, in order
+fn kripke_tiles(tile_set: &TileSet) -> Vec<KripkeTile> {
+    tile_set
+        .tiles
+        .iter()
+        .enumerate()
+        .map(|(index, kind)| KripkeTile {
+            sides: kind.sides,
+            rotation: 0,
+            kind: index,
+        })
+        .collect()
+}
+
+/// The tiles an endless board of the code is built from, and their kinds
+fn endless_tiles(
+    tile_set: &TileSet,
+    available_tiles: &[KripkeTile],
+    seed_code: &SeedCode,
+) -> (Vec<KripkeTile>, TileSet) {
+    let kinds = seed_code.difficulty.kinds(tile_set);
+    let tiles = tile_set
+        .tiles
+        .iter()
+        .zip(available_tiles.iter())
+        .filter(|(kind, _)| kinds.contains(&kind.mesh.as_str()))
+        .map(|(_, tile)| tile.clone())
+        .collect();
+    let tile_set = tile_set.filtered(&kinds);
+    if !seed_code.bag {
+        return (tiles, tile_set);
+    }
+    // the bag holds a few copies of each kind, barely more than the cells need
+    let cells = (seed_code.grid_size.x * seed_code.grid_size.y) as u32;
+    let copies = cells.div_ceil(kinds.len().max(1) as u32) + BAG_SPARE_COPIES;
+    (tiles, tile_set.with_copies(copies))
+}
+
+/// Generates the endless board of the code all at once, away from the game.
+/// None until the tile set is loaded, or if every attempt failed
+pub fn generate_board(seed_code: &SeedCode, assets: &Assets) -> Option<Board> {
+    let tile_set = assets
+        .tile_sets
+        .get(assets.get_id(&tile_set_path(DEFAULT_TILE_SET))?)?;
+    let available_tiles = kripke_tiles(tile_set);
+    let (tiles, tile_set) = endless_tiles(tile_set, &available_tiles, seed_code);
+    let mut board = BoardGeneration::new(
+        seed_code.rng(),
+        seed_code.grid_size,
+        None,
+        vec![],
+        false,
+        tiles,
+        tile_set,
+    )
+    .finish()?;
+    if seed_code.bag {
+        board.empty_into_bag();
+    }
+    Some(board)
+}
+
+/// Bar across the edge between two cells, just above the tiles, centered on the origin
+fn mistake_marker_mesh() -> Mesh {
+    let (x0, x1) = (-0.04, 0.04);
+    let (y0, y1) = (-0.4, 0.4);
+    let z = 0.16;
+    let vertices = vec![
+        Vec3::new(x0, y0, z),
+        Vec3::new(x1, y0, z),
+        Vec3::new(x1, y1, z),
+        Vec3::new(x0, y1, z),
+    ];
+    // counter clockwise seen from the camera
+    let indices = vec![0, 1, 2, 0, 2, 3];
+    Mesh::from_data(vertices, vec![], indices).unwrap()
+}
+
+/// Bar along the right side of a tile, just above it, in the space of the tile meshes
+fn edge_marker_mesh() -> Mesh {
+    let (x0, x1) = (0.74, 0.94);
+    let (z0, z1) = (-0.5, 0.5);
+    let y = 0.31;
+    let vertices = vec![
+        Vec3::new(x0, y, z0),
+        Vec3::new(x0, y, z1),
+        Vec3::new(x1, y, z1),
+        Vec3::new(x1, y, z0),
+    ];
+    // counter clockwise seen from above
+    let indices = vec![0, 1, 2, 0, 2, 3];
+    Mesh::from_data(vertices, vec![], indices).unwrap()
+}
+
+/// A tile seen from above for the bag: a bar from the middle to each side in the color of
+/// its value, right, up, left and down
+fn draw_tile_icon(
+    tile_commands: &mut RenderTileCommands,
+    sides: [u8; 4],
+    palette: &Palette,
+    rect: Rect,
+    z: f32,
+) {
+    tile_commands.draw(RenderTile {
+        world_rect: rect,
+        color: MINIMAP_BACK,
+        z: z + 0.001,
+        ..Default::default()
+    });
+    let (center, half, bar) = (
+        rect.pos + rect.size * 0.5,
+        rect.size * 0.5,
+        rect.size * BAG_ICON_BAR,
+    );
+    // the rows of the screen go down
+    let arms = [
+        Rect::new(center - Vec2::Y * bar.y * 0.5, Vec2::new(half.x, bar.y)),
+        Rect::new(
+            center - Vec2::new(bar.x * 0.5, half.y),
+            Vec2::new(bar.x, half.y),
+        ),
+        Rect::new(
+            center - Vec2::new(half.x, bar.y * 0.5),
+            Vec2::new(half.x, bar.y),
+        ),
+        Rect::new(center - Vec2::X * bar.x * 0.5, Vec2::new(bar.x, half.y)),
+    ];
+    for (arm, value) in arms.into_iter().zip(sides) {
+        tile_commands.draw(RenderTile {
+            world_rect: arm,
+            color: palette.edge_color(value),
+            z,
+            ..Default::default()
+        });
+    }
+    let middle = sides.into_iter().max().unwrap_or(0);
+    tile_commands.draw(RenderTile {
+        world_rect: Rect::new(center - bar * 0.5, bar),
+        color: palette.edge_color(middle),
+        z: z - 0.0005,
+        ..Default::default()
+    });
+}
+
+#[derive(Clone)]
+pub struct Handles {
+    pub tile_set: AssetId,
+    /// Name of the tile pack the meshes and the atlas are from
+    pub tile_pack: String,
+    /// Meshes of the kinds of the tile set, in order
+    pub tiles: Vec<AssetId>,
+    pub tiles_atlas: AssetId,
+    pub base: AssetId,
     pub selector: AssetId,
+    /// Marker over the locked tiles
+    pub lock: AssetId,
+    pub sfx_click: AssetId,
+    pub sfx_rotate: AssetId,
+    pub sfx_swap: AssetId,
+    pub sfx_win: AssetId,
+    pub music_menu: AssetId,
+    pub music_win: AssetId,
 }
 
 impl Handles {
-    fn request_load(engine: &mut EngineContext) -> Option<Handles> {
-        let tiles: Vec<AssetId> = TILES
-            .iter()
-            .filter_map(|(asset_name, _)| engine.assets.request_id(asset_name.to_string()))
-            .collect();
-        if tiles.len() != TILES.len() {
+    /// The ids of the files of the manifest and of the tile pack,
+    /// None until they are all loaded. The pack has to be requested
+    fn from_assets(assets: &Assets, tile_pack: &str) -> Option<Handles> {
+        if !assets.preloaded() {
             return None;
         }
+        let id = |path: &str| assets.get_id(path).cloned();
+        let tile_set = id(&tile_set_path(DEFAULT_TILE_SET))?;
+        let pack = assets.tile_packs.get(&id(&tile_pack_path(tile_pack))?)?;
+        let tiles = assets
+            .tile_sets
+            .get(&tile_set)?
+            .tiles
+            .iter()
+            .map(|kind| id(pack.tile_mesh(kind)))
+            .collect::<Option<Vec<AssetId>>>()?;
         Some(Handles {
-            font: engine.assets.request_id("littlefont.png".to_string())?,
+            tile_set,
+            tile_pack: tile_pack.to_string(),
             tiles,
-            tiles_atlas: engine.assets.request_id("tiles_atlas.png".to_string())?,
-            base: engine.assets.request_id("base.obj".to_string())?,
-            selector: engine.assets.request_id("selector.obj".to_string())?,
+            tiles_atlas: id(&pack.atlas)?,
+            base: id(&pack.base)?,
+            selector: id(&pack.selector)?,
+            lock: id(&pack.lock)?,
+            sfx_click: id("click.wav")?,
+            sfx_rotate: id("rotate.wav")?,
+            sfx_swap: id("swap.wav")?,
+            sfx_win: id("win.wav")?,
+            music_menu: id("music/menu.wav")?,
+            music_win: id("music/win.wav")?,
         })
     }
+
+    /// The sounds of the moves are played from groups, a bit different each time
+    fn set_sound_groups(&self, audio: &mut AudioPlayer) {
+        for (name, id, pitch) in [
+            (SFX_CLICK, &self.sfx_click, 0.05),
+            // its pitch also goes up with the turns
+            (SFX_ROTATE, &self.sfx_rotate, 0.02),
+            (SFX_SWAP, &self.sfx_swap, 0.05),
+        ] {
+            let variation = SoundVariation { volume: 0.1, pitch };
+            audio.set_group(name, vec![id.clone()], variation);
+        }
+    }
 }
 
 impl GameState {
-    pub fn new() -> Self {
-        let seed = (miniquad::date::now() * 1000000.) as u128;
-        let rand = RandLCG { seed };
-
+    pub fn new(rng: Rng) -> Self {
         Self {
-            rand,
+            mode: Mode::Loading,
+            progress: Progress::load(),
+            rng,
             ui_defaults: None,
             board: Board {
                 grid_tiles: Vec::new(),
                 grid_size: IVec2::ZERO,
+                solution: Vec::new(),
+                edges: EdgeTypes::default(),
+                mask: None,
+                locked: vec![],
+                mismatches: EdgeMismatches::default(),
+                empty: vec![],
+                bag: vec![],
             },
             hand: None,
+            cursor: None,
             available_tiles: Vec::new(),
+            tile_set: TileSet::default(),
             restart: true,
+            difficulty: Difficulty::DEFAULT,
+            adaptive: false,
+            bag: false,
+            bag_hand: None,
+            bag_drag: None,
+            generating: None,
+            versus: None,
+            address_field: UiTextField::new(ADDRESS_MAX_LEN),
+            room_field: UiTextField::new(ROOM_CODE_LEN),
+            profile: Profile::load().unwrap_or_default(),
+            name_field: UiTextField::new(NAME_MAX_LEN),
+            emote_wheel: false,
+            handles: None,
+            versus_error: None,
             grid_size: IVec2::splat(3),
             win_timer: None,
+            paused: None,
+            settings_return: Mode::LevelSelect,
+            suspended: None,
+            resumed: false,
+            start_time: 0.,
+            solve_time: None,
+            moves: 0,
+            records: Records::load(),
+            new_record: false,
+            stats: Stats::load(),
+            hints: 0,
+            hint: None,
+            auto_solve: None,
+            gave_up: false,
+            seed_code: SeedCode {
+                seed: 0,
+                grid_size: IVec2::splat(3),
+                difficulty: Difficulty::DEFAULT,
+                bag: false,
+            },
+            next_seed_code: None,
+            code_field: UiTextField::new(SeedCode::MAX_LEN),
+            code_error: None,
+            locale: Locale::default(),
+            focus_mode: Mode::LevelSelect,
+            menu_slide: Tween::new(0., 0., 0., MENU_SLIDE_DURATION, Easing::CubicOut),
+            camera: CameraController::default(),
+            sound_view: Mat4::IDENTITY,
+            particles: Particles::default(),
+            thumbnails: HashMap::new(),
+            effects: ScreenEffects::default(),
+            tile_animations: vec![],
+            board_frame: None,
+            edge_marker: None,
+            mistake_marker: None,
+            debug_draw: false,
+            recording: Replay::default(),
+            playback: None,
+            board_tick: 0,
+            touch_on_ui: false,
+            undo_stack: vec![],
+            rebinding: None,
+            console: Console::new({
+                let mut registry = CommandRegistry::default();
+                register_commands(&mut registry);
+                versus::register_commands(&mut registry);
+                registry
+            }),
         }
     }
 
-    pub fn update<'a>(&'a mut self, engine: &'a mut EngineContext<'a>) {
-        let Some(handles) = Handles::request_load(engine) else {
+    /// Runs a line typed in the console and prints what it returns
+    fn run_command(&mut self, line: &str, engine: &mut EngineContext) {
+        let args: Vec<&str> = line.split_whitespace().collect();
+        let Some(command) = args
+            .first()
+            .and_then(|name| self.console.registry.get(name))
+        else {
+            self.console
+                .print(&format!("unknown command '{}', try help", line));
             return;
         };
+        let handler = command.handler;
+        match handler(self, engine, &args[1..]) {
+            Ok(output) if output.is_empty() => {}
+            Ok(output) => self.console.print(&output),
+            Err(err) => self.console.print(&format!("error: {}", err)),
+        }
+    }
 
-        let Some(mut ui_defaults) = UiDefaults::new(&handles, engine) else {
+    /// Shows the current seed code and a field to play another one
+    fn code_ui(&mut self, ui: &mut Ui, rect: Rect) -> bool {
+        let mut input_used = false;
+        let [label_rect, field_rect, go_rect] = ui.horizontal(rect, &[3., 3., 1.]);
+        let label = match &self.code_error {
+            Some(SeedCodeError::Length) => self
+                .locale
+                .format("code_length", &[&SeedCode::LEN, &SeedCode::MAX_LEN]),
+            Some(SeedCodeError::Character(c)) => self.locale.format("code_character", &[c]),
+            Some(SeedCodeError::Size(size)) => self.locale.format("code_size", &[size]),
+            None => self.locale.format("code", &[&self.seed_code.encode()]),
+        };
+        ui.label(&label, label_rect.pad(label_rect.size.y * 0.2));
+
+        let typed = self.code_field.text.clone();
+        let was_focused = self.code_field.focused;
+        let submitted = ui.text_input(
+            &mut self.code_field,
+            field_rect.pad(field_rect.size.y * 0.1),
+        );
+        if self.code_field.focused || was_focused {
+            input_used = true;
+        }
+        if typed != self.code_field.text {
+            self.code_error = None;
+        }
+
+        if ui.button(self.locale.get("go"), go_rect.pad(go_rect.size.y * 0.2)) || submitted {
+            input_used = true;
+            match SeedCode::decode(&self.code_field.text) {
+                Ok(code) => {
+                    self.mode = Mode::Endless;
+                    self.grid_size = code.grid_size;
+                    self.next_seed_code = Some(code);
+                    self.restart = true;
+                    self.code_field.clear();
+                    self.code_error = None;
+                }
+                Err(error) => self.code_error = Some(error),
+            }
+        }
+        input_used
+    }
+
+    /// The win banner pops in from nothing when the board is solved
+    fn banner_scale(&self, current_time: f64) -> f32 {
+        let Some(win_timer) = self.win_timer else {
+            return 1.;
+        };
+        Tween::new(0., 1., win_timer, BANNER_DURATION, Easing::BackOut).value(current_time)
+    }
+
+    /// Playing a board that isn't solved yet
+    fn on_board(&self) -> bool {
+        matches!(self.mode, Mode::Level(_) | Mode::Endless | Mode::Versus)
+            && !self.restart
+            && self.solve_time.is_none()
+    }
+
+    /// Stops the clock and opens the pause menu, true if a board was paused
+    fn pause(&mut self, current_time: f64, tick: u64) -> bool {
+        if !self.on_board() || self.paused.is_some() {
+            return false;
+        }
+        self.paused = Some((current_time, tick));
+        self.hand = None;
+        self.emote_wheel = false;
+        true
+    }
+
+    /// The clock goes on from where it stopped, the one of a race never stops
+    fn resume(&mut self, current_time: f64, tick: u64) {
+        let Some((time, paused_tick)) = self.paused.take() else {
             return;
         };
+        self.board_tick += tick - paused_tick;
+        if self.mode != Mode::Versus {
+            self.start_time += current_time - time;
+        }
+    }
 
-        if self.available_tiles.is_empty() {
-            // load tiles
-            assert_eq!(TILES.len(), handles.tiles.len());
-            for (i, tile) in TILES.iter().enumerate() {
-                self.available_tiles.push(KripkeTile {
-                    sides: tile.1,
-                    rotation: 0,
-                    asset_id: handles.tiles[i].clone(),
+    /// The game is going to the background or closing: pauses the board and saves it,
+    /// to be opened again at the next launch. True if a board was paused
+    pub fn suspend(&mut self, current_time: f64, tick: u64) -> bool {
+        let paused = self.pause(current_time, tick);
+        // the settings can be open from the pause menu
+        let mode = match self.mode {
+            Mode::Settings | Mode::Controls if self.paused.is_some() => self.settings_return,
+            mode => mode,
+        };
+        let level = match mode {
+            Mode::Level(level) => Some(level),
+            Mode::Endless => None,
+            _ => {
+                SuspendedBoard::clear();
+                return paused;
+            }
+        };
+        // only the boards played and not given up yet
+        let Some((time, _)) = self.paused else {
+            SuspendedBoard::clear();
+            return paused;
+        };
+        if self.playback.is_some() || self.gave_up {
+            SuspendedBoard::clear();
+            return paused;
+        }
+        SuspendedBoard {
+            level,
+            code: self.seed_code.encode(),
+            elapsed: time - self.start_time,
+            moves: self.moves,
+            hints: self.hints,
+            cells: self.board.cell_states(),
+        }
+        .save();
+        paused
+    }
+
+    /// Generates the board saved at the last suspend, its tiles are put back after
+    fn open_suspended(&mut self, board: SuspendedBoard) {
+        let Ok(seed_code) = SeedCode::decode(&board.code) else {
+            return;
+        };
+        self.mode = match board.level {
+            Some(level) if level < LEVELS.len() => Mode::Level(level),
+            Some(_) => return,
+            None => {
+                self.next_seed_code = Some(seed_code);
+                Mode::Endless
+            }
+        };
+        self.restart = true;
+        self.suspended = Some(board);
+    }
+
+    /// Puts the tiles, moves and clock of a suspended board back, paused
+    fn restore_suspended(&mut self, board: SuspendedBoard, current_time: f64, tick: u64) {
+        if board.code != self.seed_code.encode() {
+            return;
+        }
+        let Some((tiles, empty)) = self.tiles_from_cells(&board.cells) else {
+            return;
+        };
+        self.board.set_tiles(tiles, empty);
+        self.moves = board.moves;
+        self.hints = board.hints;
+        self.start_time = current_time - board.elapsed;
+        self.resumed = true;
+        self.paused = Some((current_time, tick));
+    }
+
+    /// The tiles and the empty cells of `cells`, None if they don't fit the board played
+    fn tiles_from_cells(&self, cells: &[CellState]) -> Option<(Vec<KripkeTile>, Vec<bool>)> {
+        let fits = cells.len() == self.board.grid_tiles.len()
+            && cells.iter().all(|cell| {
+                (cell.tile as usize) < self.available_tiles.len()
+                    || (cell.tile == EMPTY_CELL && self.board.is_bag())
+            });
+        if !fits {
+            return None;
+        }
+        let tiles = cells
+            .iter()
+            .zip(self.board.grid_tiles.iter())
+            .map(|(cell, tile)| match cell.tile {
+                EMPTY_CELL => tile.clone(),
+                kind => self.available_tiles[kind as usize].rotated_left_by(cell.rotation as usize),
+            })
+            .collect();
+        let empty = cells.iter().map(|cell| cell.tile == EMPTY_CELL).collect();
+        Some((tiles, empty))
+    }
+
+    /// The replay of a board opened again after a suspend would start from the wrong tiles
+    fn save_recording(&self) {
+        if !self.resumed {
+            self.recording.save();
+        }
+    }
+
+    /// Over the dimmed board. Restarting a race isn't fair to the other player
+    fn pause_ui(&mut self, ui: &mut Ui, screen_rect: Rect, current_time: f64, tick: u64) {
+        let [_, title, body, _] = ui.vertical(screen_rect, &[2., 1., 3., 2.]);
+        ui.label(self.locale.get("paused"), title);
+        let [_, column, _] = ui.horizontal(body, &[1., 1., 1.]);
+        let [resume, restart, settings, quit] = ui.vertical(column, &[1., 1., 1., 1.]);
+        let pad = |rect: Rect| rect.pad(rect.size.y * 0.15);
+        if ui.button(self.locale.get("resume"), pad(resume)) {
+            self.resume(current_time, tick);
+        }
+        if self.mode != Mode::Versus && ui.button(self.locale.get("restart"), pad(restart)) {
+            // the same board from the start
+            if self.mode == Mode::Endless {
+                self.next_seed_code = Some(self.seed_code.clone());
+            }
+            self.restart = true;
+        }
+        // leaving the board for the settings would leave the race
+        if self.mode != Mode::Versus && ui.button(self.locale.get("settings"), pad(settings)) {
+            self.settings_return = self.mode;
+            self.mode = Mode::Settings;
+        }
+        if ui.button(self.locale.get("quit"), pad(quit)) {
+            self.paused = None;
+            self.mode = Mode::LevelSelect;
+        }
+    }
+
+    /// Elapsed time, frozen when solved, and moves
+    fn score_text(&self, current_time: f64) -> String {
+        let time = self.solve_time.unwrap_or(current_time - self.start_time);
+        let score = self
+            .locale
+            .format("score", &[&format_time(time), &self.moves]);
+        match self.playback {
+            Some(_) => self.locale.format("replay", &[&score]),
+            None => score,
+        }
+    }
+
+    fn level_select_ui(&mut self, ui: &mut Ui, screen_rect: Rect) {
+        let [header, body, footer] = ui.vertical(screen_rect, &[1., 4., 1.]);
+        let [_, title, progress] = ui.vertical(header, &[1., 1., 1.]);
+        ui.label(self.locale.get("levels"), title);
+        let completed = self.progress.completed_count();
+        ui.label(
+            &self
+                .locale
+                .format("completed", &[&completed, &LEVELS.len()]),
+            progress.pad(progress.size.y * 0.2),
+        );
+
+        let columns = 4;
+        let rows = LEVELS.len().div_ceil(columns);
+        let body = body.pad(body.size.y * 0.05);
+        for (y, row) in body.slice_vertical(rows).into_iter().enumerate() {
+            for (x, cell) in row.slice_horizontal(columns).into_iter().enumerate() {
+                let level = x + y * columns;
+                if level >= LEVELS.len() {
+                    break;
+                }
+                let text = if self.progress.is_completed(level) {
+                    format!("{}*", level + 1)
+                } else if self.progress.is_unlocked(level) {
+                    format!("{}", level + 1)
+                } else {
+                    "-".to_string()
+                };
+                let rect = cell.pad(cell.size.y * 0.15);
+                if let Some(thumbnail) = self.thumbnails.get(&level) {
+                    let side = rect.size.min_element();
+                    let square = Rect::new(rect.pos + (rect.size - side) * 0.5, Vec2::splat(side));
+                    ui.image(thumbnail, RENDER_TARGET_CLIP, square);
+                }
+                if ui.button(&text, rect) && self.progress.is_unlocked(level) {
+                    self.mode = Mode::Level(level);
+                    self.restart = true;
+                }
+            }
+        }
+
+        let [_, footer, _] = ui.horizontal(footer, &[0.5, 10., 0.5]);
+        let [_, buttons, _] = ui.vertical(footer, &[1., 1., 1.]);
+        let [endless, versus, statistics, settings, profile] =
+            ui.row(buttons, footer, &[1., 1., 1., 1., 1.]);
+        if ui.button(self.locale.get("endless"), endless) {
+            self.mode = Mode::Endless;
+            self.restart = true;
+        }
+        if ui.button(self.locale.get("versus"), versus) {
+            self.mode = Mode::Lobby;
+        }
+        if ui.button(self.locale.get("statistics"), statistics) {
+            self.mode = Mode::Statistics;
+        }
+        if ui.button(self.locale.get("settings"), settings) {
+            self.settings_return = Mode::LevelSelect;
+            self.mode = Mode::Settings;
+        }
+        if ui.button(self.locale.get("profile"), profile) {
+            self.name_field.text = self.profile.name.clone();
+            self.name_field.caret = self.name_field.text.chars().count();
+            self.mode = Mode::Profile;
+        }
+    }
+
+    /// The name and color of the player and their versus races. Left once there's a name
+    fn profile_ui(&mut self, ui: &mut Ui, screen_rect: Rect) {
+        let [header, body, footer] = ui.vertical(screen_rect, &[1., 4., 1.]);
+        let [_, title, _] = ui.vertical(header, &[1., 1., 1.]);
+        ui.label(self.locale.get("profile"), title);
+
+        let [intro_rect, name_rect, color_rect, record_rect, _] =
+            ui.vertical(body.pad(body.size.y * 0.05), &[1., 1., 1., 1., 1.]);
+        ui.label(
+            self.locale.get("profile_intro"),
+            intro_rect.pad(intro_rect.size.y * 0.15),
+        );
+        let [label_rect, field_rect] = ui.horizontal(name_rect, &[1., 2.]);
+        ui.label(
+            self.locale.get("name"),
+            label_rect.pad(label_rect.size.y * 0.15),
+        );
+        let submitted = ui.text_input(
+            &mut self.name_field,
+            field_rect.pad(field_rect.size.y * 0.15),
+        );
+        let [label_rect, colors_rect] = ui.horizontal(color_rect, &[1., 2.]);
+        ui.colored_label(
+            self.locale.get("color"),
+            profile_color(self.profile.color),
+            label_rect.pad(label_rect.size.y * 0.15),
+        );
+        let colors: Vec<(usize, &str)> = PROFILE_COLORS
+            .iter()
+            .enumerate()
+            .map(|(i, (key, _))| (i, self.locale.get(key)))
+            .collect();
+        let mut color = self.profile.color;
+        ui.radio(
+            &mut color,
+            &colors,
+            colors_rect.pad(colors_rect.size.y * 0.15),
+        );
+        let [label_rect, value_rect] = ui.horizontal(record_rect, &[1., 2.]);
+        ui.label(
+            self.locale.get("versus_record"),
+            label_rect.pad(label_rect.size.y * 0.15),
+        );
+        ui.label(
+            &self.locale.format(
+                "versus_record_value",
+                &[&self.profile.wins, &self.profile.losses],
+            ),
+            value_rect.pad(value_rect.size.y * 0.15),
+        );
+
+        let name = self.name_field.text.trim().to_string();
+        let [_, buttons, _] = ui.vertical(footer, &[1., 1., 1.]);
+        let [_, done, _] = ui.horizontal(buttons, &[2., 2., 2.]);
+        let done = !name.is_empty() && (ui.button(self.locale.get("done"), done) || submitted);
+        if color != self.profile.color || done {
+            self.profile.color = color;
+            if !name.is_empty() {
+                self.profile.name = name;
+                self.profile.save();
+            }
+        }
+        if done {
+            self.mode = Mode::LevelSelect;
+        }
+    }
+
+    /// Hosts a race on a board like the endless ones, or joins one by the address of the host.
+    /// Through a relay at the address, a room is created and joined by its code instead
+    fn lobby_ui(
+        &mut self,
+        ui: &mut Ui,
+        screen_rect: Rect,
+        server: &mut NetServer,
+        client: &mut NetClient,
+    ) {
+        let [header, body, footer] = ui.vertical(screen_rect, &[1., 4., 1.]);
+        let [_, title, _] = ui.vertical(header, &[1., 1., 1.]);
+        ui.label(self.locale.get("versus"), title);
+
+        let [status_rect, address_rect, room_rect, direct_rect, relay_rect] =
+            ui.vertical(body.pad(body.size.y * 0.05), &[1., 1., 1., 1., 1.]);
+        let status = match (&self.versus, &self.versus_error) {
+            (Some(versus), _) if client.room.is_some() => match &client.room {
+                Some(RelayRoom::Joined(code)) if versus.role == VersusRole::Host => {
+                    self.locale.format("room_waiting", &[code])
+                }
+                Some(RelayRoom::Joining(code) | RelayRoom::Joined(code)) => {
+                    self.locale.format("joining_room", &[code])
+                }
+                Some(RelayRoom::NotFound(code)) => self.locale.format("room_not_found", &[code]),
+                _ => self.locale.get("creating_room").to_string(),
+            },
+            (Some(versus), _) if versus.role == VersusRole::Host => {
+                self.locale.format("waiting_guest", &[&server.port])
+            }
+            (Some(_), _) => match client.server_address {
+                Some(address) => self.locale.format("joining", &[&address]),
+                None => String::new(),
+            },
+            (None, Some(error)) => error.clone(),
+            (None, None) => self.locale.get("versus_intro").to_string(),
+        };
+        ui.label(&status, status_rect.pad(status_rect.size.y * 0.15));
+
+        let [label_rect, field_rect] = ui.horizontal(address_rect, &[1., 2.]);
+        ui.label(
+            self.locale.get("address"),
+            label_rect.pad(label_rect.size.y * 0.15),
+        );
+        let join = ui.text_input(
+            &mut self.address_field,
+            field_rect.pad(field_rect.size.y * 0.15),
+        );
+        let [label_rect, field_rect] = ui.horizontal(room_rect, &[1., 2.]);
+        ui.label(
+            self.locale.get("room"),
+            label_rect.pad(label_rect.size.y * 0.15),
+        );
+        let join_room = ui.text_input(
+            &mut self.room_field,
+            field_rect.pad(field_rect.size.y * 0.15),
+        );
+
+        let [_, direct_rect, _] = ui.horizontal(direct_rect, &[1., 4., 1.]);
+        let [host_rect, join_rect] = ui.row(direct_rect, direct_rect, &[1., 1.]);
+        let host = ui.button(
+            self.locale.get("host"),
+            host_rect.pad(host_rect.size.y * 0.15),
+        );
+        let join = ui.button(
+            self.locale.get("join"),
+            join_rect.pad(join_rect.size.y * 0.15),
+        ) || join;
+        ui.tooltip(self.locale.get("tip_join"));
+        let [_, relay_rect, _] = ui.horizontal(relay_rect, &[1., 4., 1.]);
+        let [create_rect, join_room_rect] = ui.row(relay_rect, relay_rect, &[1., 1.]);
+        let create_room = ui.button(
+            self.locale.get("create_room"),
+            create_rect.pad(create_rect.size.y * 0.15),
+        );
+        ui.tooltip(self.locale.get("tip_create_room"));
+        let join_room = ui.button(
+            self.locale.get("join_room"),
+            join_room_rect.pad(join_room_rect.size.y * 0.15),
+        ) || join_room;
+        if host || join || create_room || join_room {
+            if let Some(versus) = self.versus.take() {
+                versus.close(server, client);
+            }
+            let seed_code = SeedCode {
+                seed: self.rng.stream(RNG_BOARD_GEN).next_u32(),
+                grid_size: self.grid_size,
+                difficulty: self.difficulty,
+                bag: false,
+            };
+            let address = self.address_field.text.trim();
+            let profile = &self.profile;
+            let versus = if host {
+                Versus::host(server, DEFAULT_PORT, seed_code, profile)
+            } else if join {
+                parse_address(address, DEFAULT_PORT)
+                    .and_then(|address| Versus::join(client, address, profile))
+            } else if create_room {
+                parse_address(address, RELAY_PORT)
+                    .and_then(|relay| Versus::host_room(client, relay, seed_code, profile))
+            } else {
+                parse_address(address, RELAY_PORT).and_then(|relay| {
+                    Versus::join_room(client, relay, &self.room_field.text, profile)
+                })
+            };
+            match versus {
+                Ok(versus) => {
+                    self.versus = Some(versus);
+                    self.versus_error = None;
+                }
+                Err(err) => self.versus_error = Some(err),
+            }
+        }
+
+        let [_, buttons, _] = ui.vertical(footer, &[1., 1., 1.]);
+        let [_, back, _] = ui.horizontal(buttons, &[2., 2., 2.]);
+        if ui.button(self.locale.get("back"), back) {
+            self.mode = Mode::LevelSelect;
+        }
+    }
+
+    /// Answers the other player and starts the race, leaves it with the versus screens
+    fn update_versus(&mut self, engine: &mut EngineContext) {
+        let Some(versus) = &mut self.versus else {
+            return;
+        };
+        if !matches!(self.mode, Mode::Lobby | Mode::Versus) {
+            versus.close(engine.server, engine.client);
+            self.versus = None;
+            return;
+        }
+        if let Some(seed_code) = versus.receive(engine.server, engine.client, engine.current_time) {
+            self.mode = Mode::Versus;
+            self.emote_wheel = false;
+            self.grid_size = seed_code.grid_size;
+            self.next_seed_code = Some(seed_code);
+            self.restart = true;
+        }
+        // the board is the one of the race once it's generated
+        let (status, cells) = match self.mode {
+            Mode::Versus if !self.restart => (
+                VersusStatus {
+                    progress: self.board.progress(),
+                    time: self.solve_time,
+                },
+                self.board.cell_states(),
+            ),
+            _ => (VersusStatus::default(), vec![]),
+        };
+        if let Some(versus) = &mut self.versus {
+            versus.send_status(
+                engine.server,
+                engine.client,
+                status,
+                &cells,
+                engine.current_time,
+            );
+            let outcome = versus.outcome(self.solve_time, engine.current_time);
+            if let (Some(won), false) = (outcome, versus.counted) {
+                versus.counted = true;
+                self.profile.add_result(won);
+            }
+        }
+    }
+
+    /// How far the other player is, shown over the board in their color
+    fn opponent_label(&self, ui: &mut Ui, rect: Rect, current_time: f64) {
+        let Some(versus) = &self.versus else {
+            return;
+        };
+        let (name, color) = match &versus.opponent_profile {
+            Some((name, color)) => (name.as_str(), profile_color(*color)),
+            None => (self.locale.get("opponent"), ui.ui_defaults.text.color),
+        };
+        let text = match versus.opponent.time {
+            _ if versus.is_opponent_gone(current_time) => {
+                self.locale.format("opponent_gone", &[&name])
+            }
+            Some(time) => self
+                .locale
+                .format("opponent_solved", &[&name, &format_time(time)]),
+            None => {
+                let percent = (versus.opponent.progress * 100.).round() as u32;
+                self.locale.format("opponent_progress", &[&name, &percent])
+            }
+        };
+        ui.colored_label(&text, color, rect);
+    }
+
+    /// The last emote of the other player under their label, for a while after it arrived
+    fn opponent_emote_bubble(&self, ui: &mut Ui, label_rect: Rect, current_time: f64) {
+        let Some((emote, time)) = self.versus.as_ref().and_then(|v| v.opponent_emote) else {
+            return;
+        };
+        if current_time - time > EMOTE_DURATION {
+            return;
+        }
+        let scale =
+            Tween::new(0., 1., time, EMOTE_POP_DURATION, Easing::BackOut).value(current_time);
+        let size = label_rect.size * Vec2::new(0.6, 0.8);
+        let pos = label_rect.pos + Vec2::new((label_rect.size.x - size.x) * 0.5, label_rect.size.y);
+        ui.bubble(
+            self.locale.get(EMOTES[emote]),
+            Rect::new(pos, size).scaled(scale),
+        );
+    }
+
+    /// A button for each emote on a circle around the middle of the screen,
+    /// choosing one sends it and closes the wheel. Returns true if one was chosen
+    fn emote_wheel_ui(
+        &mut self,
+        ui: &mut Ui,
+        screen_rect: Rect,
+        server: &mut NetServer,
+        client: &mut NetClient,
+        current_time: f64,
+    ) -> bool {
+        let Some(versus) = &mut self.versus else {
+            return false;
+        };
+        let center = screen_rect.pos + screen_rect.size * 0.5;
+        let radius = screen_rect.size.min_element() * 0.25;
+        let size = screen_rect.size * Vec2::new(0.18, 0.08);
+        for (i, key) in EMOTES.iter().enumerate() {
+            // the first on top, then clockwise
+            let angle = i as f32 / EMOTES.len() as f32 * std::f32::consts::TAU;
+            let offset = Vec2::new(angle.sin(), -angle.cos()) * radius;
+            let rect = Rect::new(center + offset - size * 0.5, size);
+            if ui.button(self.locale.get(key), rect) {
+                versus.send_emote(server, client, i, current_time);
+                self.emote_wheel = false;
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Totals of every solve on the left, the latest solves on the right
+    fn statistics_ui(&mut self, ui: &mut Ui, screen_rect: Rect, now: f64) {
+        let [header, body, footer] = ui.vertical(screen_rect, &[1., 4., 1.]);
+        let [_, title, _] = ui.vertical(header, &[1., 1., 1.]);
+        ui.label(self.locale.get("statistics"), title);
+
+        let body = body.pad(body.size.y * 0.05);
+        let [totals, history] = ui.row(body, body, &[1., 1.]);
+        let summary = self.stats.summary(now);
+        let days = |days: u32| self.locale.format("days", &[&days]);
+        let rows = [
+            ("solved", summary.solved.to_string()),
+            ("total_time", format_time(summary.total_time)),
+            ("average_time", format_time(summary.average_time)),
+            ("average_moves", format!("{:.1}", summary.average_moves)),
+            ("hints_used", summary.hints.to_string()),
+            ("streak", days(summary.streak)),
+            ("best_streak", days(summary.best_streak)),
+        ];
+        for ((key, value), row) in rows.iter().zip(totals.slice_vertical(HISTORY_ROWS + 1)) {
+            let [label, value_rect] = ui.horizontal(row, &[3., 2.]);
+            ui.label(self.locale.get(key), label.pad(label.size.y * 0.15));
+            ui.label(value, value_rect.pad(value_rect.size.y * 0.15));
+        }
+
+        let columns = ["column_size", "column_time", "column_moves", "column_hints"];
+        let rows = history.slice_vertical(HISTORY_ROWS + 1);
+        let headings = rows[0].slice_horizontal(columns.len());
+        for (key, rect) in columns.iter().zip(headings) {
+            ui.label(self.locale.get(key), rect.pad(rect.size.y * 0.15));
+        }
+        for (solve, row) in self.stats.history(HISTORY_ROWS).zip(&rows[1..]) {
+            let texts = [
+                format!("{}x{}", solve.grid_size.x, solve.grid_size.y),
+                format_time(solve.time),
+                solve.moves.to_string(),
+                solve.hints.to_string(),
+            ];
+            for (text, rect) in texts.iter().zip(row.slice_horizontal(columns.len())) {
+                ui.label(text, rect.pad(rect.size.y * 0.15));
+            }
+        }
+
+        let [_, buttons, _] = ui.vertical(footer, &[1., 1., 1.]);
+        let [_, back, _] = ui.horizontal(buttons, &[2., 2., 2.]);
+        if ui.button(self.locale.get("back"), back) {
+            self.mode = Mode::LevelSelect;
+        }
+    }
+
+    /// The cells of the board in a corner of the screen, red where their edges don't match.
+    /// Clicking a cell moves the camera over it. Returns true if it was clicked
+    fn minimap_ui(&mut self, ui: &mut Ui, rect: Rect) -> bool {
+        let size = self.board.grid_size.as_vec2();
+        let cell = (rect.size / size).min_element();
+        // in the bottom right of the rect
+        let map = Rect::new(rect.pos + rect.size - size * cell, size * cell);
+        ui.tile_commands.draw(RenderTile {
+            world_rect: map.pad(-cell * 0.25),
+            color: MINIMAP_BACK,
+            z: 0.101,
+            ..Default::default()
+        });
+        let width = self.board.grid_size.x as usize;
+        for i in (0..self.board.grid_tiles.len()).filter(|i| self.board.has_cell(*i)) {
+            let (x, y) = (i % width, i / width);
+            // the rows of the board go up, the ones of the screen go down
+            let pos = map.pos + Vec2::new(x as f32, size.y - 1. - y as f32) * cell;
+            let mismatched = self.board.mismatches(i).contains(&true);
+            ui.tile_commands.draw(RenderTile {
+                world_rect: Rect::new(pos, Vec2::splat(cell)).pad(cell * 0.05),
+                color: match mismatched {
+                    true => MISTAKE_COLOR,
+                    false => MINIMAP_MATCHED,
+                },
+                z: 0.1,
+                ..Default::default()
+            });
+        }
+
+        let clicked = (ui.input.mouse_just_pressed.0)
+            .then_some(ui.input.mouse_position)
+            .into_iter()
+            .chain(ui.input.just_touched.iter().copied())
+            .find(|point| map.contains_point(point));
+        let Some(point) = clicked else {
+            return false;
+        };
+        let cells = (point - map.pos) / cell;
+        self.camera
+            .look_at(Vec2::new(cells.x - size.x * 0.5, size.y * 0.5 - cells.y));
+        true
+    }
+
+    /// The board of the other player in the top right of the rect, from the last one it sent.
+    /// Its cells are in their color where the edges match, red where they don't
+    fn opponent_board_ui(&self, ui: &mut Ui, rect: Rect) {
+        let Some(versus) = &self.versus else {
+            return;
+        };
+        let Some((tiles, empty)) =
+            (versus.opponent_board.cells()).and_then(|cells| self.tiles_from_cells(cells))
+        else {
+            return;
+        };
+        let mut board = self.board.clone();
+        board.set_tiles(tiles, empty);
+        let color = match &versus.opponent_profile {
+            Some((_, color)) => profile_color(*color),
+            None => MINIMAP_MATCHED,
+        };
+
+        let size = board.grid_size.as_vec2();
+        let cell = (rect.size / size).min_element();
+        let map = Rect::new(
+            rect.pos + Vec2::new(rect.size.x - size.x * cell, 0.),
+            size * cell,
+        );
+        ui.tile_commands.draw(RenderTile {
+            world_rect: map.pad(-cell * 0.25),
+            color: MINIMAP_BACK,
+            z: 0.101,
+            ..Default::default()
+        });
+        let width = board.grid_size.x as usize;
+        for i in (0..board.grid_tiles.len()).filter(|i| board.has_cell(*i) && !board.is_empty(*i)) {
+            let (x, y) = (i % width, i / width);
+            let pos = map.pos + Vec2::new(x as f32, size.y - 1. - y as f32) * cell;
+            ui.tile_commands.draw(RenderTile {
+                world_rect: Rect::new(pos, Vec2::splat(cell)).pad(cell * 0.05),
+                color: match board.mismatches(i).contains(&true) {
+                    true => MISTAKE_COLOR,
+                    false => color,
+                },
+                z: 0.1,
+                ..Default::default()
+            });
+        }
+    }
+
+    /// The kinds of tile in the bag down the left of the rect, each with how many are left.
+    /// Pressing one holds it for the empty cells, dragging it places it on the cell it's let go
+    /// over. `entry` is the most height each kind takes. Returns true if one was pressed
+    fn bag_ui(&mut self, ui: &mut Ui, rect: Rect, entry: f32, palette: &Palette) -> bool {
+        let remaining = self.board.bag_remaining();
+        let kinds: Vec<usize> = (0..remaining.len())
+            .filter(|kind| self.board.bag[*kind] > 0)
+            .collect();
+        let entry = entry.min(rect.size.y / kinds.len().max(1) as f32);
+        let mut pressed = false;
+        for (row, kind) in kinds.into_iter().enumerate() {
+            let entry_rect = Rect::new(
+                rect.pos + Vec2::Y * row as f32 * entry,
+                Vec2::new(entry * 2.5, entry),
+            );
+            if self.bag_hand == Some(kind) {
+                ui.tile_commands.draw(RenderTile {
+                    world_rect: entry_rect,
+                    color: ui.ui_defaults.button.color_pressed,
+                    z: 0.102,
+                    ..Default::default()
                 });
             }
+            let [icon_rect, count_rect] = ui.horizontal(entry_rect, &[1., 1.5]);
+            let icon_rect = icon_rect.pad(entry * 0.1);
+            let sides = self.available_tiles[kind].sides;
+            draw_tile_icon(ui.tile_commands, sides, palette, icon_rect, 0.1);
+            let icon_pressed = (ui.input.mouse_just_pressed.0)
+                .then_some(ui.input.mouse_position)
+                .into_iter()
+                .chain(ui.input.just_touched.iter().copied())
+                .any(|point| icon_rect.contains_point(&point));
+            let count_pressed =
+                ui.button(&remaining[kind].to_string(), count_rect.pad(entry * 0.15));
+            if !icon_pressed && !count_pressed {
+                continue;
+            }
+            pressed = true;
+            if remaining[kind] > 0 {
+                self.bag_hand = Some(kind);
+                self.hand = None;
+                // not dragged when it was picked with the keys
+                self.bag_drag = ui
+                    .input
+                    .pointer()
+                    .map(|pointer| (kind, entry_rect, pointer));
+            }
+        }
+        // the dragged tile follows the pointer
+        if let Some((kind, _, pointer)) = self.bag_drag {
+            let size = Vec2::splat(entry * 0.8);
+            let sides = self.available_tiles[kind].sides;
+            let icon_rect = Rect::new(pointer - size * 0.5, size);
+            draw_tile_icon(ui.tile_commands, sides, palette, icon_rect, 0.09);
         }
+        pressed
+    }
 
-        let camera_mode = CameraMode::Perspective {
-            fov: f32::to_radians(60.),
-            near: 0.01,
-            far: 100.,
+    /// Shows a move towards the solution, counted in the stats of the board
+    fn show_hint(&mut self) {
+        // the solver plays the tiles on the board, not the ones in the bag
+        if self.board.is_bag() {
+            return;
+        }
+        self.hint = self.board.find_hint();
+        if self.hint.is_some() {
+            self.hints += 1;
+        }
+    }
+
+    /// The solver takes over the board, its solve isn't counted
+    fn give_up(&mut self, current_time: f64) {
+        if self.board.is_bag() {
+            return;
+        }
+        self.gave_up = true;
+        self.hand = None;
+        self.hint = None;
+        self.auto_solve = Some(current_time + AUTO_SOLVE_STEP);
+    }
+
+    /// Moves a tile to where the solution has it, turned to fit.
+    /// Stops when there's no solution to follow
+    fn auto_solve_step(&mut self, current_time: f64, audio: &AudioPlayer) {
+        let Some(Hint { from, to, rotation }) = self.board.find_hint() else {
+            self.auto_solve = None;
+            return;
         };
-        let camera_normal = Vec3::new(0., 0., 1.).normalize();
-        let camera_distance = {
-            let resolution_ratio = engine.resolution.x / engine.resolution.y;
-            let longest_grid = self.grid_size.x.max(self.grid_size.y) as f32;
-            (1. / resolution_ratio.min(1.)) * longest_grid
+        if from != to {
+            self.swap_cells(from, to, current_time, audio);
+        }
+        if rotation != 0 {
+            let before = self.tile_transform(to, TILE_PADDING, current_time);
+            let tile = self.board.grid_tiles[to].rotated_left_by(rotation);
+            self.board.set_tile(to, tile);
+            self.animate_tile(to, before, current_time);
+            let position = self.cell_sound_position(to);
+            audio.play_group(SFX_ROTATE, 1., 1., position);
+        }
+        self.auto_solve = Some(current_time + AUTO_SOLVE_STEP);
+    }
+
+    /// Every change is saved immediately, the engine applies it at the end of the frame
+    fn settings_ui(
+        &mut self,
+        ui: &mut Ui,
+        screen_rect: Rect,
+        config: &mut Config,
+        max_sample_count: i32,
+    ) {
+        let [header, body, footer] = ui.vertical(screen_rect, &[1., 4., 1.]);
+        let [_, title, _] = ui.vertical(header, &[1., 1., 1.]);
+        ui.label(self.locale.get("settings"), title);
+
+        let before = config.clone();
+        let rows = body.pad(body.size.y * 0.05).slice_vertical(15);
+        let row_rects: Vec<[Rect; 4]> = rows
+            .iter()
+            .map(|row| {
+                let rects: [Rect; 4] = ui.horizontal(*row, &[3., 1., 2., 1.]);
+                rects.map(|rect| rect.pad(rect.size.y * 0.15))
+            })
+            .collect();
+        let row = |i: usize| row_rects[i];
+
+        // volumes slide over the -/+ columns
+        let slider_row = |i: usize| {
+            let [label, minus, value, plus] = row(i);
+            let slider = Rect::new(minus.pos, value.pos + value.size - minus.pos);
+            (label, slider, plus)
         };
+        let (label, slider, value) = slider_row(0);
+        ui.label(self.locale.get("volume"), label);
+        ui.slider(&mut config.volume, 0.0..=1.0, slider);
+        ui.label(&format!("{:.0}%", config.volume * 100.), value);
+
+        let (label, slider, value) = slider_row(1);
+        ui.label(self.locale.get("effects"), label);
+        ui.slider(&mut config.sfx_volume, 0.0..=1.0, slider);
+        ui.label(&format!("{:.0}%", config.sfx_volume * 100.), value);
+
+        let (label, slider, value) = slider_row(2);
+        ui.label(self.locale.get("music"), label);
+        ui.slider(&mut config.music_volume, 0.0..=1.0, slider);
+        ui.label(&format!("{:.0}%", config.music_volume * 100.), value);
+
+        let [label, minus, value, plus] = row(3);
+        ui.label(self.locale.get("msaa"), label);
+        // only the ones the gpu supports
+        let sample_counts: Vec<i32> = SAMPLE_COUNTS
+            .into_iter()
+            .filter(|count| *count <= max_sample_count)
+            .collect();
+        let sample_index = sample_counts
+            .iter()
+            .rposition(|count| *count <= config.sample_count)
+            .unwrap_or(0);
+        if ui.button("-", minus) {
+            config.sample_count = sample_counts[sample_index.saturating_sub(1)];
+        }
+        ui.label(
+            &self
+                .locale
+                .format("msaa_value", &[&sample_counts[sample_index]]),
+            value,
+        );
+        if ui.button("+", plus) {
+            config.sample_count = sample_counts[(sample_index + 1).min(sample_counts.len() - 1)];
+        }
+
+        let [label, minus, value, plus] = row(4);
+        ui.label(self.locale.get("window"), label);
+        let size_index = WINDOW_SIZES
+            .iter()
+            .position(|size| *size == config.window.size)
+            .unwrap_or(0);
+        if ui.button("-", minus) {
+            config.window.size = WINDOW_SIZES[size_index.saturating_sub(1)];
+        }
+        ui.label(
+            &format!("{}x{}", config.window.size.x, config.window.size.y),
+            value,
+        );
+        if ui.button("+", plus) {
+            config.window.size = WINDOW_SIZES[(size_index + 1).min(WINDOW_SIZES.len() - 1)];
+        }
+
+        let [label, _, value, _] = row(5);
+        ui.label(self.locale.get("fullscreen"), label);
+        ui.checkbox(&mut config.window.fullscreen, value);
+
+        let [label, _, value, _] = row(6);
+        ui.label(self.locale.get("vsync"), label);
+        ui.checkbox(&mut config.window.vsync, value);
+        ui.tooltip(self.locale.get("tip_vsync"));
+
+        let [label, minus, _, plus] = row(7);
+        ui.label(self.locale.get("colorblind"), label);
+        let palettes: Vec<(Palette, &str)> = Palette::ALL
+            .iter()
+            .map(|palette| (*palette, self.locale.get(palette.name())))
+            .collect();
+        ui.radio(
+            &mut config.palette,
+            &palettes,
+            Rect::new(minus.pos, plus.pos + plus.size - minus.pos),
+        );
+
+        let [label, _, value, _] = row(8);
+        ui.label(self.locale.get("color_match"), label);
+        ui.checkbox(&mut config.color_match, value);
+        ui.tooltip(self.locale.get("tip_color_match"));
+
+        let [label, _, value, _] = row(9);
+        ui.label(self.locale.get("show_mistakes"), label);
+        ui.checkbox(&mut config.show_mistakes, value);
+        ui.tooltip(self.locale.get("tip_show_mistakes"));
+
+        let [label, minus, value, plus] = row(10);
+        ui.label(self.locale.get("ui_scale"), label);
+        if ui.button("-", minus) {
+            config.ui_scale = (config.ui_scale - 0.25).max(0.5);
+        }
+        ui.label(&format!("{:.2}", config.ui_scale), value);
+        if ui.button("+", plus) {
+            config.ui_scale = (config.ui_scale + 0.25).min(2.);
+        }
+
+        let [label, minus, _, plus] = row(11);
+        ui.label(self.locale.get("language"), label);
+        let languages: Vec<(String, &str)> = LANGUAGES
+            .iter()
+            .map(|(code, name)| (code.to_string(), *name))
+            .collect();
+        ui.radio(
+            &mut config.language,
+            &languages,
+            Rect::new(minus.pos, plus.pos + plus.size - minus.pos),
+        );
+
+        let [label, minus, _, plus] = row(12);
+        ui.label(self.locale.get("theme"), label);
+        let themes: Vec<(String, &str)> = THEMES
+            .iter()
+            .map(|name| (name.to_string(), self.locale.get(name)))
+            .collect();
+        ui.radio(
+            &mut config.theme,
+            &themes,
+            Rect::new(minus.pos, plus.pos + plus.size - minus.pos),
+        );
 
-        let mut camera_transform = Transform {
-            translation: -camera_normal * camera_distance,
-            rotation: Quat::IDENTITY,
-            scale: Vec3::ONE,
+        let [label, minus, _, plus] = row(13);
+        ui.label(self.locale.get("tile_pack"), label);
+        let tile_packs: Vec<(String, &str)> = TILE_PACKS
+            .iter()
+            .map(|name| (name.to_string(), self.locale.get(name)))
+            .collect();
+        ui.radio(
+            &mut config.tile_pack,
+            &tile_packs,
+            Rect::new(minus.pos, plus.pos + plus.size - minus.pos),
+        );
+
+        let (label, slider, value) = slider_row(14);
+        ui.label(self.locale.get("screen_effects"), label);
+        ui.slider(&mut config.screen_effects, 0.0..=1.0, slider);
+        ui.tooltip(self.locale.get("tip_screen_effects"));
+        ui.label(&format!("{:.0}%", config.screen_effects * 100.), value);
+
+        if *config != before {
+            config.save();
+        }
+
+        let [_, buttons, _] = ui.vertical(footer, &[1., 1., 1.]);
+        let [_, back, controls, _] = ui.horizontal(buttons, &[1., 2., 2., 1.]);
+        if ui.button(self.locale.get("back"), back) {
+            self.mode = self.settings_return;
+        }
+        if ui.button(self.locale.get("controls"), controls) {
+            self.mode = Mode::Controls;
+        }
+    }
+
+    /// An action on each row and the keys bound to it, clicking them waits for the new key
+    fn controls_ui(&mut self, ui: &mut Ui, screen_rect: Rect, input_map: &mut InputMap) {
+        let [header, body, footer] = ui.vertical(screen_rect, &[1., 4., 1.]);
+        let [_, title, subtitle] = ui.vertical(header, &[1., 1., 1.]);
+        ui.label(self.locale.get("controls"), title);
+        if self.rebinding.is_some() {
+            ui.label(
+                self.locale.get("press_key"),
+                subtitle.pad(subtitle.size.y * 0.2),
+            );
+        }
+
+        let rows = body
+            .pad(body.size.y * 0.05)
+            .slice_vertical(Action::ALL.len());
+        for (action, row) in Action::ALL.into_iter().zip(rows) {
+            let [label, binding] = ui.horizontal(row, &[1., 1.]);
+            ui.label(
+                self.locale.get(action.name()),
+                label.pad(label.size.y * 0.15),
+            );
+            let text = match self.rebinding {
+                Some(rebinding) if rebinding == action => "...".to_string(),
+                _ => input_map.bindings_text(action),
+            };
+            if ui.button(&text, binding.pad(binding.size.y * 0.15)) {
+                self.rebinding = Some(action);
+            }
+        }
+
+        let [_, buttons, _] = ui.vertical(footer, &[1., 1., 1.]);
+        let [_, back, reset, _] = ui.horizontal(buttons, &[1., 2., 2., 1.]);
+        if ui.button(self.locale.get("back"), back) {
+            self.rebinding = None;
+            self.mode = Mode::Settings;
+        }
+        if ui.button(self.locale.get("reset_controls"), reset) {
+            self.rebinding = None;
+            *input_map = InputMap::default();
+        }
+    }
+
+    /// Binds the first key pressed to the action waiting for it, escape cancels.
+    /// The key doesn't reach the ui
+    fn rebind(&mut self, input: &mut Input, config: &mut Config) {
+        let Some(action) = self.rebinding else {
+            return;
         };
+        if input.key_just_pressed.contains(&KeyCode::Escape) {
+            self.rebinding = None;
+        } else if let Some(binding) = Binding::just_pressed(input) {
+            self.rebinding = None;
+            config.input_map.bind(action, binding);
+            config.save();
+        } else {
+            return;
+        }
+        input.key_just_pressed.clear();
+        input.key_typed.clear();
+        input.mouse_just_pressed = (false, false, false);
+    }
+
+    /// A step of the simulation, `TICK_DELTA` long
+    pub fn tick(&mut self, engine: &mut EngineContext) {
+        self.particles
+            .update(TICK_DELTA as f32, self.rng.stream(RNG_PARTICLES));
+
+        // the moves of the replay are made on the ticks they were recorded at
+        if self.restart || self.paused.is_some() {
+            return;
+        }
+        let Some(player) = &mut self.playback else {
+            return;
+        };
+        let tick = engine.tick - self.board_tick;
+        let actions = player.due(tick);
+        for action in actions {
+            self.apply_action(action, tick, engine.current_time, engine.audio);
+        }
+    }
+
+    /// Starts generating the board of the current mode
+    fn start_generation(&mut self) {
+        let (seed_code, level, tiles, tile_set) = match self.mode {
+            Mode::Level(level) => {
+                let level = &LEVELS[level];
+                let tiles: Vec<KripkeTile> = self
+                    .tile_set
+                    .tiles
+                    .iter()
+                    .zip(self.available_tiles.iter())
+                    .filter(|(kind, _)| {
+                        level.tiles.is_empty() || level.tiles.contains(&kind.mesh.as_str())
+                    })
+                    .map(|(_, tile)| tile.clone())
+                    .collect();
+                let seed_code = SeedCode {
+                    seed: level.seed,
+                    grid_size: level.grid_size,
+                    difficulty: Difficulty::DEFAULT,
+                    bag: false,
+                };
+                let tile_set = self.tile_set.filtered(level.tiles);
+                (seed_code, Some(level), tiles, tile_set)
+            }
+            // Every board gets its own seed, so the same code always gives the same board
+            _ => {
+                let seed_code = self.next_seed_code.take().unwrap_or(SeedCode {
+                    seed: self.rng.stream(RNG_BOARD_GEN).next_u32(),
+                    grid_size: self.grid_size,
+                    difficulty: self.difficulty,
+                    bag: self.bag,
+                });
+                let (tiles, tile_set) =
+                    endless_tiles(&self.tile_set, &self.available_tiles, &seed_code);
+                (seed_code, None, tiles, tile_set)
+            }
+        };
+        let generation = BoardGeneration::new(
+            seed_code.rng(),
+            seed_code.grid_size,
+            level.and_then(|level| level.mask()),
+            level.map_or(vec![], |level| level.locked_cells()),
+            level.is_some_and(|level| level.unique),
+            tiles,
+            tile_set,
+        );
+        self.generating = Some((self.mode, seed_code, PendingBoard::start(generation)));
+    }
+
+    /// Plays a saved replay from the start of its board
+    fn play_replay(&mut self, replay: Replay) {
+        match replay.level {
+            Some(level) => self.mode = Mode::Level(level),
+            None => match SeedCode::decode(&replay.code) {
+                Ok(seed_code) => {
+                    self.mode = Mode::Endless;
+                    self.next_seed_code = Some(seed_code);
+                }
+                Err(err) => {
+                    eprintln!("Replay not played: {:?}", err);
+                    return;
+                }
+            },
+        }
+        self.playback = Some(ReplayPlayer::new(replay));
+        self.restart = true;
+    }
+
+    /// Makes a move and records it
+    fn apply_action(
+        &mut self,
+        action: ReplayAction,
+        tick: u64,
+        current_time: f64,
+        audio: &AudioPlayer,
+    ) {
+        // the holes of the board and the locked tiles can't be played on
+        let cells = match action {
+            ReplayAction::Activate(x, y)
+            | ReplayAction::Rotate(x, y)
+            | ReplayAction::RotateBack(x, y) => vec![(x, y)],
+            ReplayAction::Swap(from, to) => vec![from, to],
+            ReplayAction::Place(x, y, _) => vec![(x, y)],
+            ReplayAction::Undo => vec![],
+        };
+        if cells.iter().any(|(x, y)| !self.board.is_playable(*x, *y)) {
+            return;
+        }
+        // an empty cell has no tile to turn or to pick up, the held tile of the bag goes on it
+        let empty = |(x, y): (usize, usize)| self.board.is_empty(self.board.xy_i(x, y));
+        let action = match action {
+            ReplayAction::Activate(x, y) if self.hand.is_none() && empty((x, y)) => {
+                match self.bag_hand {
+                    Some(kind) => ReplayAction::Place(x, y, kind),
+                    None => return,
+                }
+            }
+            ReplayAction::Rotate(x, y) | ReplayAction::RotateBack(x, y) if empty((x, y)) => return,
+            ReplayAction::Swap(from, _) if empty(from) => return,
+            ReplayAction::Place(x, y, kind)
+                if !self.board.can_place(self.board.xy_i(x, y), kind) =>
+            {
+                return;
+            }
+            action => action,
+        };
+        self.recording.events.push((tick, action));
+        let before = (
+            self.board.grid_tiles.clone(),
+            self.board.empty.clone(),
+            self.moves,
+        );
+        match action {
+            ReplayAction::Activate(x, y) => {
+                self.activate_cell(x, y, current_time, audio);
+            }
+            ReplayAction::Rotate(x, y) => {
+                self.rotate_cell(self.board.xy_i(x, y), false, current_time, audio);
+            }
+            ReplayAction::RotateBack(x, y) => {
+                self.rotate_cell(self.board.xy_i(x, y), true, current_time, audio);
+            }
+            ReplayAction::Swap((x, y), (to_x, to_y)) => {
+                self.hand = None;
+                let (from, to) = (self.board.xy_i(x, y), self.board.xy_i(to_x, to_y));
+                self.swap_cells(from, to, current_time, audio);
+            }
+            ReplayAction::Place(x, y, kind) => {
+                self.place_from_bag(self.board.xy_i(x, y), kind, current_time, audio);
+            }
+            ReplayAction::Undo => {
+                if let Some((tiles, empty, moves)) = self.undo_stack.pop() {
+                    self.board.set_tiles(tiles, empty);
+                    self.moves = moves;
+                    self.hand = None;
+                    self.hint = None;
+                    self.tile_animations.clear();
+                    audio.play_group(SFX_SWAP, 1., 1., SoundPosition::default());
+                }
+                return;
+            }
+        }
+        // picking up a tile doesn't change the board
+        if self.moves != before.2 {
+            self.undo_stack.push(before);
+        }
+    }
+
+    pub fn update<'a>(&'a mut self, engine: &'a mut EngineContext<'a>) {
+        // The strings of the chosen language, built again when it changes
+        if self.locale.language != engine.config.language {
+            let table = |code: &str| {
+                let id = engine.assets.get_id(&lang_path(code))?;
+                engine.assets.lang_tables.get(id)
+            };
+            if let (Some(table), Some(fallback)) =
+                (table(&engine.config.language), table(FALLBACK_LANGUAGE))
+            {
+                self.locale = Locale::new(&engine.config.language, table, fallback);
+            }
+        }
+
+        let tile_pack = &engine.config.tile_pack;
+        if self.mode == Mode::Loading {
+            engine.assets.request_id(tile_pack_path(tile_pack));
+            let Some(handles) = Handles::from_assets(engine.assets, tile_pack) else {
+                self.loading_ui(engine);
+                return;
+            };
+            handles.set_sound_groups(engine.audio);
+            self.handles = Some(handles);
+            // the first launch asks for a name
+            self.mode = match self.profile.name.is_empty() {
+                true => Mode::Profile,
+                false => Mode::LevelSelect,
+            };
+            if let Some(board) = SuspendedBoard::take() {
+                self.open_suspended(board);
+            }
+        }
+        // the pack picked in the settings is drawn once its files are loaded,
+        // the one before until then
+        if self
+            .handles
+            .as_ref()
+            .is_some_and(|h| h.tile_pack != *tile_pack)
+        {
+            engine.assets.request_id(tile_pack_path(tile_pack));
+            if let Some(handles) = Handles::from_assets(engine.assets, tile_pack) {
+                if let Some(previous) = self.handles.replace(handles) {
+                    engine
+                        .assets
+                        .unload_tile_pack(&tile_pack_path(&previous.tile_pack));
+                }
+            }
+        }
+        let Some(handles) = self.handles.clone() else {
+            return;
+        };
+
+        let Some(mut ui_defaults) = UiDefaults::new(
+            engine.assets,
+            &engine.config.theme,
+            engine.config.ui_scale * engine.ui_scale,
+        ) else {
+            return;
+        };
+
+        // the key waited for is bound, not pressed
+        self.rebind(engine.input, engine.config);
+
+        let input_map = &engine.config.input_map;
+        if input_map.just_pressed(engine.input, Action::DebugView) {
+            self.debug_draw = !self.debug_draw;
+        }
+        if self.debug_draw {
+            let profiler = engine.profiler;
+            profiler.draw_overlay(engine, &ui_defaults.text);
+        }
+        if let Some(line) = self.console.update(engine.input) {
+            self.run_command(&line, engine);
+        }
+        self.update_versus(engine);
+        self.console
+            .draw(engine.tile_commands, *engine.resolution, &ui_defaults.text);
+
+        if self.available_tiles.is_empty() {
+            // load tiles
+            self.tile_set = engine.assets.tile_sets[&handles.tile_set].clone();
+            self.available_tiles = kripke_tiles(&self.tile_set);
+        }
+
+        // The ids of the focused widget belong to the screen they were drawn on,
+        // menus move the focus with the arrows too
+        if self.focus_mode != self.mode {
+            engine.ui_memory.clear();
+            self.focus_mode = self.mode;
+            self.menu_slide = Tween::new(
+                1.,
+                0.,
+                engine.current_time,
+                MENU_SLIDE_DURATION,
+                Easing::CubicOut,
+            );
+        }
+        let safe_rect = engine.safe_area.rect(*engine.resolution);
+        let menu_rect = Rect::new(
+            safe_rect.pos
+                + Vec2::X * engine.resolution.x * self.menu_slide.value(engine.current_time),
+            safe_rect.size,
+        )
+        .max_aspect(MENU_MAX_ASPECT);
+        let menu = matches!(
+            self.mode,
+            Mode::LevelSelect
+                | Mode::Settings
+                | Mode::Controls
+                | Mode::Statistics
+                | Mode::Lobby
+                | Mode::Profile
+        );
+        engine.ui_memory.arrows = menu;
+
+        if menu {
+            engine
+                .audio
+                .play_music(&handles.music_menu, true, MUSIC_FADE);
+        }
+
+        if self.mode == Mode::LevelSelect {
+            ui_defaults.text.layout = UiTextLayout::Center;
+            let mut ui = Ui::new(
+                engine.tile_commands,
+                engine.input,
+                &ui_defaults,
+                engine.ui_memory,
+            );
+            self.level_select_ui(&mut ui, menu_rect);
+            return;
+        }
+
+        if self.mode == Mode::Settings {
+            ui_defaults.text.layout = UiTextLayout::Center;
+            let mut ui = Ui::new(
+                engine.tile_commands,
+                engine.input,
+                &ui_defaults,
+                engine.ui_memory,
+            );
+            self.settings_ui(
+                &mut ui,
+                menu_rect,
+                engine.config,
+                engine.renderer.max_sample_count(),
+            );
+            return;
+        }
+
+        if self.mode == Mode::Statistics {
+            ui_defaults.text.layout = UiTextLayout::Center;
+            let mut ui = Ui::new(
+                engine.tile_commands,
+                engine.input,
+                &ui_defaults,
+                engine.ui_memory,
+            );
+            self.statistics_ui(&mut ui, menu_rect, miniquad::date::now());
+            return;
+        }
+
+        if self.mode == Mode::Profile {
+            ui_defaults.text.layout = UiTextLayout::Center;
+            let mut ui = Ui::new(
+                engine.tile_commands,
+                engine.input,
+                &ui_defaults,
+                engine.ui_memory,
+            );
+            self.profile_ui(&mut ui, menu_rect);
+            return;
+        }
+
+        if self.mode == Mode::Lobby {
+            ui_defaults.text.layout = UiTextLayout::Center;
+            let mut ui = Ui::new(
+                engine.tile_commands,
+                engine.input,
+                &ui_defaults,
+                engine.ui_memory,
+            );
+            self.lobby_ui(&mut ui, menu_rect, engine.server, engine.client);
+            return;
+        }
+
+        if self.mode == Mode::Controls {
+            ui_defaults.text.layout = UiTextLayout::Center;
+            let mut ui = Ui::new(
+                engine.tile_commands,
+                engine.input,
+                &ui_defaults,
+                engine.ui_memory,
+            );
+            let before = engine.config.input_map.clone();
+            self.controls_ui(&mut ui, menu_rect, &mut engine.config.input_map);
+            if engine.config.input_map != before {
+                engine.config.save();
+            }
+            return;
+        }
 
         if self.restart {
-            let Some(board) =
-                Board::randomized(&mut self.rand, self.grid_size, &self.available_tiles)
-            else {
+            // the board asked for changed while it was being generated
+            if let Some((mode, _, _)) = &self.generating {
+                if *mode != self.mode || self.next_seed_code.is_some() {
+                    self.generating = None;
+                }
+            }
+            if self.generating.is_none() {
+                self.start_generation();
+            }
+            let Some((_, _, pending)) = &mut self.generating else {
+                return;
+            };
+            let board = match pending.poll() {
+                Poll::Pending => {
+                    ui_defaults.text.layout = UiTextLayout::Center;
+                    let mut ui = Ui::new(
+                        engine.tile_commands,
+                        engine.input,
+                        &ui_defaults,
+                        engine.ui_memory,
+                    );
+                    let [_, spinner_rect, label_rect, _] =
+                        ui.vertical(safe_rect, &[3., 1., 0.5, 3.]);
+                    ui.spinner(spinner_rect, engine.current_time);
+                    ui.label(self.locale.get("generating"), label_rect);
+                    return;
+                }
+                Poll::Ready(board) => board,
+            };
+            let Some((_, seed_code, _)) = self.generating.take() else {
                 return;
             };
+            let Some(mut board) = board else {
+                eprintln!("Board not generated: {}", seed_code.encode());
+                // an endless board tries another seed, a level can't
+                if let Mode::Level(_) = self.mode {
+                    self.mode = Mode::LevelSelect;
+                    self.restart = false;
+                }
+                return;
+            };
+            if seed_code.bag {
+                board.empty_into_bag();
+            }
+            self.seed_code = seed_code;
             self.restart = false;
             self.board = board;
             self.hand = None;
+            self.bag_hand = None;
+            self.bag_drag = None;
+            self.cursor = None;
+            self.particles.clear();
+            self.effects.clear();
+            self.tile_animations.clear();
+            self.hint = None;
+            self.start_time = engine.current_time;
+            self.solve_time = None;
+            self.moves = 0;
+            self.hints = 0;
+            self.auto_solve = None;
+            self.gave_up = false;
+            self.new_record = false;
+            self.camera.reset();
+            self.undo_stack.clear();
+            self.board_tick = engine.tick;
+            self.paused = None;
+            self.resumed = false;
+            if let Some(board) = self.suspended.take() {
+                self.restore_suspended(board, engine.current_time, engine.tick);
+            }
+            self.recording = Replay {
+                level: match self.mode {
+                    Mode::Level(level) => Some(level),
+                    _ => None,
+                },
+                code: self.seed_code.encode(),
+                events: vec![],
+            };
+            // a replay only plays on the board it was started for
+            match &mut self.playback {
+                Some(player) if !player.started => player.started = true,
+                _ => self.playback = None,
+            }
         }
 
+        let camera_mode = CameraMode::Perspective {
+            fov: f32::to_radians(60.),
+            near: 0.01,
+            far: 100.,
+        };
+        self.camera.fit_distance = {
+            let resolution_ratio = engine.resolution.x / engine.resolution.y;
+            let longest_grid = self.board.grid_size.x.max(self.board.grid_size.y) as f32;
+            (1. / resolution_ratio.min(1.)) * longest_grid
+        };
+        self.camera.bounds = self.board.grid_size.as_vec2() * 0.5;
+
         let solved = self.board.is_solved();
+        if solved {
+            engine
+                .audio
+                .play_music(&handles.music_win, false, MUSIC_FADE);
+        } else {
+            engine
+                .audio
+                .play_music(&handles.music_menu, true, MUSIC_FADE);
+        }
         if solved {
             if let Some(ref win_timer) = self.win_timer {
                 let duration = engine.current_time - win_timer;
@@ -364,90 +3146,471 @@ impl GameState {
                     } else {
                         let delta = keyframe_spin_fullspeed - keyframe_spin;
                         let perc = (duration - keyframe_spin) / delta;
-                        let easing = perc * perc * perc;
-                        angle_spin = easing as f32 * runup;
+                        angle_spin = Easing::CubicIn.apply(perc as f32) * runup;
                     }
                 }
-                camera_transform.translation = -(camera_normal) * camera_distance;
-                camera_transform.rotation = Quat::from_euler(
-                    EulerRot::XZY,
-                    -angle_updown.to_radians(),
-                    angle_spin.to_radians(),
-                    0.,
-                );
+                self.camera
+                    .set_orbit(angle_spin.to_radians(), angle_updown.to_radians());
             } else {
                 self.win_timer = Some(engine.current_time);
+                engine.audio.play(&handles.sfx_win, 1., 1.);
+                self.emit_confetti();
+                self.effects
+                    .flash(SOLVE_FLASH, SOLVE_FLASH_DURATION, engine.current_time);
+                let solve_time = engine.current_time - self.start_time;
+                self.solve_time = Some(solve_time);
+                if self.playback.is_none() && !self.gave_up {
+                    self.stats.add(Solve {
+                        grid_size: self.board.grid_size,
+                        seed: self.seed_code.seed,
+                        time: solve_time,
+                        moves: self.moves,
+                        hints: self.hints,
+                        date: miniquad::date::now(),
+                    });
+                }
+                match (self.mode, &self.playback) {
+                    // watching a solve doesn't count as one
+                    (_, Some(_)) => {}
+                    _ if self.gave_up => {}
+                    (Mode::Versus, None) => self.save_recording(),
+                    (Mode::Level(level), None) => {
+                        self.save_recording();
+                        self.progress.complete(level);
+                        self.render_thumbnail(level, engine, &handles);
+                    }
+                    // its moves are the placements, they can't be compared with the other boards
+                    _ if self.board.is_bag() => self.save_recording(),
+                    _ => {
+                        self.save_recording();
+                        self.new_record =
+                            self.records
+                                .submit(self.board.grid_size, solve_time, self.moves);
+                        if self.adaptive
+                            && Difficulty::is_fast_solve(self.board.grid_size, solve_time)
+                        {
+                            self.difficulty = self.seed_code.difficulty.harder();
+                            self.grid_size = self.difficulty.grid_size();
+                        }
+                    }
+                }
             }
         } else {
             self.win_timer = None;
         }
 
+        self.camera
+            .update(engine.input, *engine.resolution, engine.delta_time);
+        self.effects.intensity = engine.config.screen_effects;
+        let mut view = self.camera.transform();
+        self.effects.apply(&mut view, engine.current_time);
         engine.renderer.camera = Camera {
             mode: camera_mode,
-            view: camera_transform.to_mat4(),
+            view: view.to_mat4(),
         };
+        self.sound_view = engine.renderer.camera.view_projection(*engine.resolution);
 
         let mut input_used = false;
 
-        let screen_rect = Rect::new(Vec2::ZERO, *engine.resolution);
-        if solved {
+        let screen_rect = safe_rect;
+        if let Some(color) = self.effects.flash_color(engine.current_time) {
+            engine.tile_commands.draw(RenderTile {
+                world_rect: Rect::new(Vec2::ZERO, *engine.resolution),
+                color,
+                z: 0.3,
+                ..Default::default()
+            });
+        }
+        if self.paused.is_some() {
+            engine.tile_commands.draw(RenderTile {
+                world_rect: Rect::new(Vec2::ZERO, *engine.resolution),
+                color: PAUSE_DIM,
+                z: 0.2,
+                ..Default::default()
+            });
+            ui_defaults.text.layout = UiTextLayout::Center;
+            let mut ui = Ui::new(
+                engine.tile_commands,
+                engine.input,
+                &ui_defaults,
+                engine.ui_memory,
+            );
+            self.pause_ui(&mut ui, screen_rect, engine.current_time, engine.tick);
+            if engine
+                .config
+                .input_map
+                .just_pressed(engine.input, Action::OpenMenu)
+            {
+                self.resume(engine.current_time, engine.tick);
+            }
+            input_used = true;
+        } else if let (true, Mode::Level(level)) = (solved, self.mode) {
             ui_defaults.text.layout = UiTextLayout::Center;
-            let mut ui = Ui::new(engine.tile_commands, engine.input, &ui_defaults);
+            let mut ui = Ui::new(
+                engine.tile_commands,
+                engine.input,
+                &ui_defaults,
+                engine.ui_memory,
+            );
             let [header, _, footer] = ui.vertical(screen_rect, &[1., 4., 1.]);
-            let [_, h1, h2] = ui.vertical(header, &[1., 1., 1.]);
-            ui.label("All matched!", h1);
-            if ui.button("Restart", h2) {
+            let [score, h1, h2] = ui.vertical(header, &[1., 1., 1.]);
+            ui.label(
+                &self.score_text(engine.current_time),
+                score.pad(score.size.y * 0.2),
+            );
+            ui.label(
+                &self.locale.format("level_complete", &[&LEVELS[level].name]),
+                h1.scaled(self.banner_scale(engine.current_time)),
+            );
+            let [_, levels, next, _] = ui.horizontal(h2, &[1., 2., 2., 1.]);
+            if ui.button(self.locale.get("levels"), levels) {
+                self.mode = Mode::LevelSelect;
+                input_used = true;
+            }
+            if level + 1 < LEVELS.len() && ui.button(self.locale.get("next"), next) {
+                self.mode = Mode::Level(level + 1);
                 self.restart = true;
                 input_used = true;
             }
-
-            let [f1, f2, _] = ui.vertical(footer, &[1., 1., 1.]);
-            ui.label(format!("Size: {}", self.grid_size).as_str(), f1);
-            let [_, x, y, nx, ny, _] = ui.horizontal(f2, &[2., 1., 1., 1., 1., 2.]);
-            if ui.button("x++", x) {
-                self.grid_size.x = 10.min(self.grid_size.x + 1);
+            let [_, f1] = ui.vertical(footer, &[3., 1.]);
+            ui.label(&crate::version::version_short(), f1.pad(f1.size.y * 0.25));
+        } else if let (true, Mode::Versus) = (solved, self.mode) {
+            ui_defaults.text.layout = UiTextLayout::Center;
+            let mut ui = Ui::new(
+                engine.tile_commands,
+                engine.input,
+                &ui_defaults,
+                engine.ui_memory,
+            );
+            let [header, _, footer] = ui.vertical(screen_rect, &[1., 4., 1.]);
+            let [score, h1, h2] = ui.vertical(header, &[1., 1., 1.]);
+            ui.label(
+                &self.score_text(engine.current_time),
+                score.pad(score.size.y * 0.2),
+            );
+            let result = match &self.versus {
+                Some(versus) => match versus.outcome(self.solve_time, engine.current_time) {
+                    Some(true) => "versus_won",
+                    Some(false) => "versus_lost",
+                    None => "versus_waiting",
+                },
+                None => "all_matched",
+            };
+            ui.label(
+                self.locale.get(result),
+                h1.scaled(self.banner_scale(engine.current_time)),
+            );
+            let [_, levels, lobby, _] = ui.horizontal(h2, &[1., 2., 2., 1.]);
+            if ui.button(self.locale.get("levels"), levels) {
+                self.mode = Mode::LevelSelect;
+                input_used = true;
+            }
+            if ui.button(self.locale.get("versus"), lobby) {
+                if let Some(versus) = self.versus.take() {
+                    versus.close(engine.server, engine.client);
+                }
+                self.mode = Mode::Lobby;
+                input_used = true;
+            }
+            let [f1, f2] = ui.vertical(footer, &[3., 1.]);
+            self.opponent_label(&mut ui, f1.pad(f1.size.y * 0.25), engine.current_time);
+            ui.label(&crate::version::version_short(), f2.pad(f2.size.y * 0.25));
+        } else if solved {
+            ui_defaults.text.layout = UiTextLayout::Center;
+            let mut ui = Ui::new(
+                engine.tile_commands,
+                engine.input,
+                &ui_defaults,
+                engine.ui_memory,
+            );
+            let [header, _, footer] = ui.vertical(screen_rect, &[1., 4., 1.]);
+            let [score, h1, h2] = ui.vertical(header, &[1., 1., 1.]);
+            ui.label(
+                &self.score_text(engine.current_time),
+                score.pad(score.size.y * 0.2),
+            );
+            let banner = h1.scaled(self.banner_scale(engine.current_time));
+            if self.new_record {
+                ui.label(self.locale.get("new_record"), banner);
+            } else {
+                ui.label(self.locale.get("all_matched"), banner);
+            }
+            let [_, levels, restart, _] = ui.horizontal(h2, &[1., 2., 2., 1.]);
+            if ui.button(self.locale.get("levels"), levels) {
+                self.mode = Mode::LevelSelect;
+                input_used = true;
+            }
+            if ui.button(self.locale.get("restart"), restart) {
+                self.restart = true;
+                input_used = true;
+            }
+
+            let [f1, f2, f3, f4, f5] = ui.vertical(footer, &[1., 1., 1., 1., 1.]);
+            let [difficulty_rect, adaptive_rect, bag_rect] = ui.horizontal(f3, &[4., 1., 1.]);
+            let difficulties: Vec<(Difficulty, &str)> = Difficulty::ALL
+                .iter()
+                .map(|difficulty| (*difficulty, self.locale.get(difficulty.name())))
+                .collect();
+            if ui.radio(
+                &mut self.difficulty,
+                &difficulties,
+                difficulty_rect.pad(difficulty_rect.size.y * 0.1),
+            ) {
+                self.grid_size = self.difficulty.grid_size();
+                input_used = true;
+            }
+            if ui.checkbox(
+                &mut self.adaptive,
+                adaptive_rect.pad(adaptive_rect.size.y * 0.1),
+            ) {
+                input_used = true;
+            }
+            ui.tooltip(self.locale.get("tip_adaptive"));
+            if ui.checkbox(&mut self.bag, bag_rect.pad(bag_rect.size.y * 0.1)) {
+                input_used = true;
+            }
+            ui.tooltip(self.locale.get("tip_bag"));
+            let best = match self.records.get(self.grid_size) {
+                Some(record) => self
+                    .locale
+                    .format("best", &[&format_time(record.time), &record.moves]),
+                None => self.locale.get("best_none").to_string(),
+            };
+            ui.label(&self.locale.format("size", &[&self.grid_size, &best]), f1);
+            let [_, x, y, nx, ny, _] = ui.horizontal(f2, &[2., 1., 1., 1., 1., 2.]);
+            if ui.button("x++", x) {
+                self.grid_size.x = 10.min(self.grid_size.x + 1);
+                input_used = true;
+            }
+            ui.tooltip(self.locale.get("tip_grow_x"));
+            if ui.button("y++", y) {
+                self.grid_size.y = 10.min(self.grid_size.y + 1);
+                input_used = true;
+            }
+            ui.tooltip(self.locale.get("tip_grow_y"));
+            if ui.button("x--", nx) {
+                self.grid_size.x = 1.max(self.grid_size.x - 1);
+                input_used = true;
+            }
+            ui.tooltip(self.locale.get("tip_shrink_x"));
+            if ui.button("y--", ny) {
+                self.grid_size.y = 1.max(self.grid_size.y - 1);
+                input_used = true;
+            }
+            ui.tooltip(self.locale.get("tip_shrink_y"));
+            if self.code_ui(&mut ui, f4) {
+                input_used = true;
+            }
+            ui.label(&crate::version::version_short(), f5.pad(f5.size.y * 0.25));
+        } else {
+            let mut ui = Ui::new(
+                engine.tile_commands,
+                engine.input,
+                &ui_defaults,
+                engine.ui_memory,
+            );
+            let [header, body, footer] = ui.vertical(screen_rect, &[1., 8., 1.]);
+            let [hud, mismatched_rect] = ui.horizontal(footer, &[1., 2.]);
+            let bag_open = self.board.is_bag() && !solved && self.paused.is_none();
+            if !solved {
+                let mismatched = self
+                    .locale
+                    .format("mismatched", &[&self.board.mismatch_count()]);
+                ui.label(
+                    &mismatched,
+                    mismatched_rect.pad(mismatched_rect.size.y * 0.2),
+                );
+            }
+            if self.board.grid_size.max_element() >= MINIMAP_MIN_SIDE && !solved {
+                let side = screen_rect.size.y * MINIMAP_SIZE;
+                let corner = body.pos + body.size - Vec2::splat(side);
+                let minimap_rect = Rect::new(corner, Vec2::splat(side));
+                if self.minimap_ui(&mut ui, minimap_rect.pad(side * 0.05)) {
+                    input_used = true;
+                }
+            }
+            if self.mode == Mode::Versus && !solved {
+                let side = screen_rect.size.y * MINIMAP_SIZE;
+                let corner = body.pos + Vec2::new(body.size.x - side, 0.);
+                let rect = Rect::new(corner, Vec2::splat(side));
+                self.opponent_board_ui(&mut ui, rect.pad(side * 0.05));
+            }
+            if bag_open {
+                let entry = screen_rect.size.y * BAG_ENTRY_SIZE;
+                if self.bag_ui(&mut ui, body, entry, &engine.config.palette) {
+                    input_used = true;
+                }
+            }
+            ui.label(
+                &self.score_text(engine.current_time),
+                hud.pad(hud.size.y * 0.2),
+            );
+            let [back_rect, title_rect, hint_rect, solution_rect] =
+                ui.horizontal(header, &[2., 5., 2., 2.]);
+            if ui.button(
+                self.locale.get("back"),
+                back_rect.pad(back_rect.size.y * 0.2),
+            ) {
+                self.mode = Mode::LevelSelect;
                 input_used = true;
             }
-            if ui.button("y++", y) {
-                self.grid_size.y = 10.min(self.grid_size.y + 1);
-                input_used = true;
+            match self.mode {
+                Mode::Level(level) => {
+                    let title = format!("{}. {}", level + 1, LEVELS[level].name);
+                    ui.label(&title, title_rect.pad(title_rect.size.y * 0.2));
+                }
+                Mode::Versus => {
+                    self.opponent_label(
+                        &mut ui,
+                        title_rect.pad(title_rect.size.y * 0.2),
+                        engine.current_time,
+                    );
+                    self.opponent_emote_bubble(&mut ui, title_rect, engine.current_time);
+                }
+                _ => {
+                    if self.code_ui(&mut ui, title_rect) {
+                        input_used = true;
+                    }
+                }
             }
-            if ui.button("x--", nx) {
-                self.grid_size.x = 1.max(self.grid_size.x - 1);
-                input_used = true;
+            let bag = self.board.is_bag();
+            if !bag {
+                if ui.button(
+                    self.locale.get("hint"),
+                    hint_rect.pad(hint_rect.size.y * 0.2),
+                ) {
+                    self.show_hint();
+                    input_used = true;
+                }
+                ui.tooltip(self.locale.get("tip_hint"));
             }
-            if ui.button("y--", ny) {
-                self.grid_size.y = 1.max(self.grid_size.y - 1);
-                input_used = true;
+            let racing = self.mode == Mode::Versus;
+            if racing {
+                if ui.button(
+                    self.locale.get("emote"),
+                    solution_rect.pad(solution_rect.size.y * 0.2),
+                ) {
+                    self.emote_wheel = !self.emote_wheel;
+                    input_used = true;
+                }
+                ui.tooltip(self.locale.get("tip_emote"));
+                if self.emote_wheel
+                    && self.emote_wheel_ui(
+                        &mut ui,
+                        screen_rect,
+                        engine.server,
+                        engine.client,
+                        engine.current_time,
+                    )
+                {
+                    input_used = true;
+                }
+            }
+            if !solved && !self.gave_up && self.playback.is_none() && !racing && !bag {
+                if ui.button(
+                    self.locale.get("solution"),
+                    solution_rect.pad(solution_rect.size.y * 0.2),
+                ) {
+                    self.give_up(engine.current_time);
+                    input_used = true;
+                }
+                ui.tooltip(self.locale.get("tip_solution"));
             }
         }
 
         let mut rays = vec![];
-        if !input_used {
-            if engine.input.mouse_just_pressed.0 {
-                rays.push(
-                    engine
-                        .renderer
-                        .camera
-                        .ray_from_cursor(&engine.input.mouse_position, engine.resolution),
-                );
+        if !input_used && engine.input.mouse_just_pressed.0 {
+            rays.push(
+                engine
+                    .renderer
+                    .camera
+                    .ray_from_cursor(&engine.input.mouse_position, engine.resolution),
+            );
+        }
+
+        // a finger that went down on the ui doesn't move tiles when it lifts
+        if input_used && !engine.input.just_touched.is_empty() {
+            self.touch_on_ui = true;
+        }
+        let mut gesture_actions = vec![];
+        for gesture in engine.input.gestures.gestures.iter() {
+            if std::mem::take(&mut self.touch_on_ui) || solved || self.paused.is_some() {
+                continue;
             }
-            for touch in engine.input.just_touched.iter() {
-                rays.push(
-                    engine
-                        .renderer
-                        .camera
-                        .ray_from_cursor(touch, engine.resolution),
-                );
+            let camera = &engine.renderer.camera;
+            let cell = |pos: &Vec2| self.cell_under(camera, pos, engine.resolution);
+            let action = match gesture {
+                Gesture::Tap(pos) => cell(pos).map(|(x, y)| ReplayAction::Activate(x, y)),
+                Gesture::LongPress(pos) => cell(pos).map(|(x, y)| ReplayAction::RotateBack(x, y)),
+                Gesture::Drag { from, to } => match (cell(from), cell(to)) {
+                    (Some(from), Some(to)) if from != to => Some(ReplayAction::Swap(from, to)),
+                    _ => None,
+                },
+            };
+            gesture_actions.extend(action);
+        }
+
+        let mut activated = gesture_actions;
+        // a tile dragged out of the bag goes on the cell it's let go over
+        if let Some((kind, entry, last)) = self.bag_drag.take() {
+            match engine.input.pointer() {
+                Some(pointer) => self.bag_drag = Some((kind, entry, pointer)),
+                None if !entry.contains_point(&last) && !solved && self.paused.is_none() => {
+                    let camera = &engine.renderer.camera;
+                    let cell = self.cell_under(camera, &last, engine.resolution);
+                    activated.extend(cell.map(|(x, y)| ReplayAction::Place(x, y, kind)));
+                }
+                None => {}
+            }
+        }
+        if !solved
+            && self.paused.is_none()
+            && !self.code_field.focused
+            && engine.ui_memory.focused.is_none()
+        {
+            let pointed = self.cell_under(
+                &engine.renderer.camera,
+                &engine.input.mouse_position,
+                engine.resolution,
+            );
+            let pressed = engine.config.input_map.just_pressed_actions(engine.input);
+            for (action, binding) in pressed {
+                match action {
+                    Action::Hint => self.show_hint(),
+                    Action::OpenMenu => {
+                        self.pause(engine.current_time, engine.tick);
+                    }
+                    _ => activated.extend(self.board_action(action, binding, pointed)),
+                }
             }
         }
 
+        self.draw_board_frame(engine);
+
+        let shift = engine.input.key_pressed.contains(&KeyCode::LeftShift)
+            || engine.input.key_pressed.contains(&KeyCode::RightShift);
+
+        self.tile_animations
+            .retain(|animation| !animation.timer.is_finished(engine.current_time));
+
+        self.particles.draw(
+            engine.tile_commands,
+            &engine.renderer.camera,
+            *engine.resolution,
+            engine.alpha,
+        );
+
         for y in 0..self.board.grid_size.y as usize {
             for x in 0..self.board.grid_size.x as usize {
+                let i = self.board.xy_i(x, y);
+                if !self.board.has_cell(i) {
+                    continue;
+                }
                 let selected = match &self.hand {
                     Some(hand) if *hand == (x, y) => true,
                     _ => false,
                 };
+                let under_cursor = self.cursor == Some((x, y)) && !solved;
 
                 let pos = Vec2::new(x as f32, y as f32);
                 let size = self.board.grid_size.as_vec2();
@@ -455,43 +3618,99 @@ impl GameState {
                 let origin =
                     Vec3::new(pos.x - (size.x - 1.) * 0.5, pos.y - (size.y - 1.) * 0.5, 0.);
 
-                let kripke_tile = &self.board.grid_tiles[self.board.xy_i(x, y)];
+                let kripke_tile = &self.board.grid_tiles[i];
                 let rot = kripke_tile.rotation as f32 * 90.;
 
-                let padding = if solved { 0.502 } else { 0.47 };
+                let padding = if solved {
+                    SOLVED_TILE_PADDING
+                } else {
+                    TILE_PADDING
+                };
+                let tile_transform = self.tile_transform(i, padding, engine.current_time);
 
-                engine.mesh_commands.draw(RenderMesh {
-                    mesh_id: kripke_tile.asset_id.clone(),
-                    transform: Transform {
-                        scale: Vec3::ONE * padding,
-                        translation: origin,
-                        rotation: Quat::from_euler(
-                            EulerRot::XYZ,
-                            f32::to_radians(90.),
-                            f32::to_radians(rot),
-                            0.,
-                        ),
-                    },
-                    color: Vec4::new(1., 1., 1., 1.),
-                    image_id: Some(handles.tiles_atlas.clone()),
-                });
+                // an empty cell is only its base
+                let empty = self.board.is_empty(i);
+                if !empty {
+                    engine.mesh_commands.draw(RenderMesh {
+                        mesh_id: handles.tiles[kripke_tile.kind].clone(),
+                        transform: tile_transform.clone(),
+                        color: Vec4::new(1., 1., 1., 1.),
+                        image_id: Some(handles.tiles_atlas.clone()),
+                    });
+                }
 
                 engine.mesh_commands.draw(RenderMesh {
                     mesh_id: handles.base.clone(),
-                    transform: Transform {
-                        scale: Vec3::ONE * padding,
-                        translation: origin,
-                        rotation: Quat::from_euler(
-                            EulerRot::XYZ,
-                            f32::to_radians(90.),
-                            f32::to_radians(rot),
-                            0.,
-                        ),
-                    },
+                    transform: tile_transform.clone(),
                     color: Vec4::new(0.2, 0.2, 0.2, 1.),
                     image_id: Some(handles.tiles_atlas.clone()),
                 });
 
+                if engine.config.color_match && !empty {
+                    let id = generated_mesh(&mut self.edge_marker, engine, edge_marker_mesh);
+                    // the marker turns with the mesh, so it goes on the sides of the unrotated tile
+                    for side in 0..4 {
+                        let value = kripke_tile.sides[(side + kripke_tile.rotation) % 4];
+                        let turn = Quat::from_rotation_y(side as f32 * 90f32.to_radians());
+                        engine.mesh_commands.draw(RenderMesh {
+                            mesh_id: id.clone(),
+                            transform: Transform {
+                                rotation: tile_transform.rotation * turn,
+                                ..tile_transform.clone()
+                            },
+                            color: engine.config.palette.edge_color(value),
+                            image_id: None,
+                        });
+                    }
+                }
+
+                if engine.config.show_mistakes && !solved {
+                    let id = generated_mesh(&mut self.mistake_marker, engine, mistake_marker_mesh);
+                    // each edge once, from the cell on its left or below it
+                    for side in [0, 1] {
+                        if !self.board.mismatches(i)[side] {
+                            continue;
+                        }
+                        engine.mesh_commands.draw(RenderMesh {
+                            mesh_id: id.clone(),
+                            transform: Transform {
+                                translation: origin
+                                    + SIDE_ADJACENT[side].as_vec2().extend(0.) * 0.5,
+                                rotation: Quat::from_rotation_z(side as f32 * 90f32.to_radians()),
+                                scale: Vec3::ONE,
+                            },
+                            color: MISTAKE_COLOR,
+                            image_id: None,
+                        });
+                    }
+                }
+
+                if self.board.is_locked(i) && !solved {
+                    engine.mesh_commands.draw(RenderMesh {
+                        mesh_id: handles.lock.clone(),
+                        transform: Transform {
+                            scale: Vec3::ONE * padding,
+                            ..tile_transform.clone()
+                        },
+                        color: Vec4::new(0.9, 0.8, 0.4, 1.),
+                        image_id: None,
+                    });
+                }
+
+                let hinted = match &self.hint {
+                    Some(hint) => [hint.from, hint.to].contains(&i),
+                    None => false,
+                };
+                if hinted {
+                    let pulse = (f64::sin(engine.current_time * 6.) * 0.5 + 0.5) as f32;
+                    engine.mesh_commands.highlight(RenderMesh {
+                        mesh_id: handles.tiles[kripke_tile.kind].clone(),
+                        transform: tile_transform,
+                        color: Vec4::new(1., 0.9, 0.5, pulse * 0.6),
+                        image_id: None,
+                    });
+                }
+
                 if selected {
                     engine.mesh_commands.draw(RenderMesh {
                         mesh_id: handles.selector.clone(),
@@ -508,6 +3727,22 @@ impl GameState {
                         color: Vec4::new(1., 1., 1., 1.),
                         image_id: None,
                     });
+                } else if under_cursor {
+                    engine.mesh_commands.draw(RenderMesh {
+                        mesh_id: handles.selector.clone(),
+                        transform: Transform {
+                            scale: Vec3::ONE * padding,
+                            translation: origin,
+                            rotation: Quat::from_euler(
+                                EulerRot::XYZ,
+                                f32::to_radians(90.),
+                                f32::to_radians(rot),
+                                0.,
+                            ),
+                        },
+                        color: Vec4::new(0.4, 0.6, 1., 1.),
+                        image_id: None,
+                    });
                 }
 
                 let (quad_origin, quad_axis_x, quad_axis_y) = (origin, Vec3::X, Vec3::Y);
@@ -515,25 +3750,575 @@ impl GameState {
                     ray_rect_intersect(*ray_pos, *ray_dir, quad_origin, quad_axis_x, quad_axis_y)
                 });
 
+                // shift+click turns the tile the other way
                 if raycast_intersection && !solved {
-                    if let Some((hand_x, hand_y)) = self.hand.take() {
-                        if hand_x == x && hand_y == y {
-                            // rotate
-                            let i = self.board.xy_i(x, y);
-                            self.board.grid_tiles[i].rotate_left();
-                        } else {
-                            // swap
-                            let temp_i = self.board.xy_i(x, y);
-                            let hand_i = self.board.xy_i(hand_x, hand_y);
-                            let temp = self.board.grid_tiles[temp_i].clone();
-                            self.board.grid_tiles[temp_i] = self.board.grid_tiles[hand_i].clone();
-                            self.board.grid_tiles[hand_i] = temp;
-                        }
-                    } else {
-                        self.hand = Some((x, y));
+                    activated.push(match shift {
+                        true => ReplayAction::RotateBack(x, y),
+                        false => ReplayAction::Activate(x, y),
+                    });
+                }
+            }
+        }
+
+        // watches the last solve again
+        if engine
+            .config
+            .input_map
+            .just_pressed(engine.input, Action::PlayReplay)
+        {
+            match Replay::load() {
+                Ok(replay) => self.play_replay(replay),
+                Err(err) => eprintln!("Replay not played: {err}"),
+            }
+        }
+        if self.debug_draw {
+            self.draw_debug(engine, &ui_defaults.text);
+        }
+
+        // the board only follows the replay while it plays, and the solver once given up
+        if let Some(next_step) = self.auto_solve {
+            if solved {
+                self.auto_solve = None;
+            } else if engine.current_time >= next_step && self.paused.is_none() {
+                self.auto_solve_step(engine.current_time, engine.audio);
+            }
+        } else if self.playback.is_none() {
+            let tick = engine.tick - self.board_tick;
+            for action in activated {
+                self.apply_action(action, tick, engine.current_time, engine.audio);
+            }
+        }
+    }
+
+    /// The cell under a point of the screen
+    fn cell_under(&self, camera: &Camera, pos: &Vec2, resolution: &Vec2) -> Option<(usize, usize)> {
+        let (ray_pos, ray_dir) = camera.ray_from_cursor(pos, resolution);
+        let size = self.board.grid_size.as_vec2();
+        let width = self.board.grid_size.x as usize;
+        (0..self.board.grid_tiles.len())
+            .filter(|i| self.board.has_cell(*i))
+            .map(|i| (i % width, i / width))
+            .find(|(x, y)| {
+                let origin = Vec3::new(
+                    *x as f32 - (size.x - 1.) * 0.5,
+                    *y as f32 - (size.y - 1.) * 0.5,
+                    0.,
+                );
+                ray_rect_intersect(ray_pos, ray_dir, origin, Vec3::X, Vec3::Y)
+            })
+    }
+
+    /// A bar for each group of the manifest, filled as its files load, with the file
+    /// loading and the ones given up on. Only the bars until the theme and its font are loaded
+    fn loading_ui(&mut self, engine: &mut EngineContext) {
+        let ui_defaults = UiDefaults::new(
+            engine.assets,
+            &engine.config.theme,
+            engine.config.ui_scale * engine.ui_scale,
+        );
+        let progress = engine.assets.manifest_progress();
+        let failed = engine.assets.failed();
+        let screen_rect = engine.safe_area.rect(*engine.resolution);
+        let column = Rect::new(
+            screen_rect.pos + screen_rect.size * Vec2::new(0.1, 0.2),
+            screen_rect.size * Vec2::new(0.8, 0.6),
+        );
+        let mut rows = vec![Rect::default(); progress.len() + 3];
+        let mut weights = vec![1.; rows.len()];
+        weights[progress.len() + 1] = 2.;
+        column.slice_vertical_weight_array(&mut rows, &weights);
+        let [title_rect, group_rows @ .., failed_rect, retry_rect] = &rows[..] else {
+            return;
+        };
+
+        let mut row_rects = vec![];
+        for (group, row) in progress.iter().zip(group_rows) {
+            let mut rects = [Rect::default(); 3];
+            row.pad(row.size.y * 0.1)
+                .slice_horizontal_weight_array(&mut rects, &[1., 2., 2.]);
+            let [_, bar_rect, _] = rects;
+            let bar_rect = bar_rect.pad(bar_rect.size.y * 0.3);
+            let fraction = match group.total {
+                0 => 1.,
+                _ => group.loaded as f32 / group.total as f32,
+            };
+            let failed_group = group
+                .pending
+                .is_some_and(|pending| failed.iter().any(|(path, _)| *path == pending));
+            engine.tile_commands.draw(RenderTile {
+                world_rect: bar_rect,
+                color: LOADING_BAR_BACK,
+                z: 0.1,
+                ..Default::default()
+            });
+            engine.tile_commands.draw(RenderTile {
+                world_rect: Rect::new(bar_rect.pos, bar_rect.size * Vec2::new(fraction, 1.)),
+                color: match failed_group {
+                    true => LOADING_FAILED,
+                    false => LOADING_BAR_FILL,
+                },
+                z: 0.09,
+                ..Default::default()
+            });
+            row_rects.push(rects);
+        }
+
+        let Some(mut ui_defaults) = ui_defaults else {
+            return;
+        };
+        ui_defaults.text.layout = UiTextLayout::Center;
+        let mut ui = Ui::new(
+            engine.tile_commands,
+            engine.input,
+            &ui_defaults,
+            engine.ui_memory,
+        );
+        ui.label(self.locale.get("loading"), *title_rect);
+        for (group, [name_rect, _, pending_rect]) in progress.iter().zip(row_rects) {
+            let name = format!("{} {}/{}", group.name, group.loaded, group.total);
+            ui.label(&name, name_rect);
+            if let Some(pending) = group.pending {
+                ui.label(pending, pending_rect);
+            }
+        }
+        if failed.is_empty() {
+            return;
+        }
+        let lines: Vec<String> = failed
+            .iter()
+            .map(|(path, error)| self.locale.format("loading_failed", &[path, error]))
+            .collect();
+        ui.colored_label(&lines.join("\n"), LOADING_FAILED, *failed_rect);
+        let retry = ui.button(self.locale.get("retry"), retry_rect.scaled(0.5));
+        if retry {
+            engine.assets.retry_failed();
+        }
+    }
+
+    /// Draws the board at rest, seen from straight above, on the thumbnail of the level
+    /// shown in the level select. Kept until the game is closed
+    fn render_thumbnail(&mut self, level: usize, engine: &mut EngineContext, handles: &Handles) {
+        let mut meshes = RenderMeshCommands::default();
+        for i in (0..self.board.grid_tiles.len()).filter(|i| self.board.has_cell(*i)) {
+            let transform = self.cell_transform(i, SOLVED_TILE_PADDING);
+            meshes.draw(RenderMesh {
+                mesh_id: handles.tiles[self.board.grid_tiles[i].kind].clone(),
+                transform: transform.clone(),
+                color: Vec4::ONE,
+                image_id: Some(handles.tiles_atlas.clone()),
+            });
+            meshes.draw(RenderMesh {
+                mesh_id: handles.base.clone(),
+                transform,
+                color: Vec4::new(0.2, 0.2, 0.2, 1.),
+                image_id: Some(handles.tiles_atlas.clone()),
+            });
+        }
+        if let Some((frame, _)) = &self.board_frame {
+            meshes.draw(RenderMesh {
+                mesh_id: frame.clone(),
+                transform: Transform::default(),
+                color: Vec4::new(0.3, 0.3, 0.3, 1.),
+                image_id: None,
+            });
+        }
+        // far enough for the frame to fit too
+        let distance = self.board.grid_size.max_element() as f32 + 1.;
+        let camera = Camera {
+            mode: CameraMode::Perspective {
+                fov: f32::to_radians(60.),
+                near: 0.01,
+                far: 100.,
+            },
+            view: Mat4::from_translation(Vec3::NEG_Z * distance),
+        };
+        let id = self
+            .thumbnails
+            .entry(level)
+            .or_insert_with(|| engine.assets.new_id())
+            .clone();
+        engine
+            .renderer
+            .render_to_texture(engine.ctx, &id, &meshes, camera, THUMBNAIL_SIZE);
+    }
+
+    /// The frame is made again when the board changes size
+    fn draw_board_frame(&mut self, engine: &mut EngineContext) {
+        let size = self.board.grid_size;
+        let id = match &self.board_frame {
+            Some((id, frame_size)) if *frame_size == size => id.clone(),
+            _ => {
+                let id = match self.board_frame.take() {
+                    Some((id, _)) => id,
+                    None => engine.assets.new_id(),
+                };
+                let mesh = board_frame_mesh(size.as_vec2());
+                engine.renderer.upload_dynamic_mesh(engine.ctx, &id, &mesh);
+                self.board_frame = Some((id.clone(), size));
+                id
+            }
+        };
+        engine.mesh_commands.draw(RenderMesh {
+            mesh_id: id,
+            transform: Transform::default(),
+            color: Vec4::new(0.3, 0.3, 0.3, 1.),
+            image_id: None,
+        });
+    }
+
+    fn draw_debug(&self, engine: &mut EngineContext, ui_text: &UiText) {
+        let size = self.board.grid_size.as_vec2();
+        let cell_lines = Vec4::new(0.5, 0.5, 0.5, 1.);
+        for x in 0..=self.board.grid_size.x {
+            let x = x as f32 - size.x * 0.5;
+            engine.debug_commands.line(
+                Vec3::new(x, -size.y * 0.5, 0.),
+                Vec3::new(x, size.y * 0.5, 0.),
+                cell_lines,
+            );
+        }
+        for y in 0..=self.board.grid_size.y {
+            let y = y as f32 - size.y * 0.5;
+            engine.debug_commands.line(
+                Vec3::new(-size.x * 0.5, y, 0.),
+                Vec3::new(size.x * 0.5, y, 0.),
+                cell_lines,
+            );
+        }
+
+        for y in 0..self.board.grid_size.y as usize {
+            for x in 0..self.board.grid_size.x as usize {
+                let i = self.board.xy_i(x, y);
+                if !self.board.has_cell(i) {
+                    continue;
+                }
+                let tile = &self.board.grid_tiles[i];
+                let origin = Vec3::new(
+                    x as f32 - (size.x - 1.) * 0.5,
+                    y as f32 - (size.y - 1.) * 0.5,
+                    0.,
+                );
+                let mesh = self
+                    .handles
+                    .as_ref()
+                    .map(|handles| &handles.tiles[tile.kind]);
+                if let Some(bounds) = mesh.and_then(|mesh| engine.renderer.mesh_bounds(mesh)) {
+                    let model = Transform {
+                        scale: Vec3::ONE * 0.47,
+                        translation: origin,
+                        rotation: Quat::from_euler(
+                            EulerRot::XYZ,
+                            f32::to_radians(90.),
+                            f32::to_radians(tile.rotation as f32 * 90.),
+                            0.,
+                        ),
+                    };
+                    engine.debug_commands.wire_box(
+                        &bounds,
+                        model.to_mat4(),
+                        Vec4::new(0.2, 1., 0.4, 1.),
+                    );
+                }
+                engine
+                    .debug_commands
+                    .text(origin, &format!("{},{}", x, y), Vec4::ONE);
+            }
+        }
+
+        // where the cursor ray meets the board plane
+        let (origin, direction) = engine
+            .renderer
+            .camera
+            .ray_from_cursor(&engine.input.mouse_position, engine.resolution);
+        if direction.z != 0. {
+            let t = -origin.z / direction.z;
+            if t > 0. {
+                engine.debug_commands.circle(
+                    origin + direction * t,
+                    Vec3::Z,
+                    0.1,
+                    Vec4::new(1., 0.9, 0.2, 1.),
+                );
+            }
+        }
+
+        draw_debug_texts(
+            engine.tile_commands,
+            engine.debug_commands,
+            &engine.renderer.camera,
+            *engine.resolution,
+            ui_text,
+        );
+    }
+
+    fn cell_position(&self, i: usize) -> Vec3 {
+        self.cell_transform(i, TILE_PADDING).translation
+    }
+
+    fn cell_sound_position(&self, i: usize) -> SoundPosition {
+        self.sound_position(self.cell_position(i))
+    }
+
+    /// Panned by where the point is on the screen, further with the camera zoomed out
+    fn sound_position(&self, pos: Vec3) -> SoundPosition {
+        let clip = self.sound_view * pos.extend(1.);
+        if clip.w <= 0. {
+            return SoundPosition::default();
+        }
+        SoundPosition {
+            pan: (clip.x / clip.w).clamp(-1., 1.) * SOUND_PAN,
+            distance: clip.w / self.camera.fit_distance.max(f32::EPSILON),
+        }
+    }
+
+    /// Picks up the tile, or drops the held one: rotates it in place or swaps it
+    fn activate_cell(&mut self, x: usize, y: usize, current_time: f64, audio: &AudioPlayer) {
+        if let Some((hand_x, hand_y)) = self.hand.take() {
+            self.hint = None;
+            if hand_x == x && hand_y == y {
+                self.rotate_cell(self.board.xy_i(x, y), false, current_time, audio);
+            } else {
+                let (i, hand_i) = (self.board.xy_i(x, y), self.board.xy_i(hand_x, hand_y));
+                self.swap_cells(hand_i, i, current_time, audio);
+            }
+        } else {
+            self.hand = Some((x, y));
+            self.bag_hand = None;
+            let position = self.cell_sound_position(self.board.xy_i(x, y));
+            audio.play_group(SFX_CLICK, 1., 1., position);
+        }
+    }
+
+    fn swap_cells(&mut self, a: usize, b: usize, current_time: f64, audio: &AudioPlayer) {
+        if a == b {
+            return;
+        }
+        self.hint = None;
+        let a_from = self.tile_transform(a, TILE_PADDING, current_time);
+        let b_from = self.tile_transform(b, TILE_PADDING, current_time);
+        let mismatched = self.mismatched_sides(&[a, b]);
+        self.board.swap_tiles(a, b);
+        self.animate_tile(a, b_from, current_time);
+        self.animate_tile(b, a_from, current_time);
+        self.moves += 1;
+        self.move_effects(&[a, b], mismatched, current_time);
+        // from between the two tiles
+        let (a_pos, b_pos) = (self.cell_position(a), self.cell_position(b));
+        let position = self.sound_position((a_pos + b_pos) * 0.5);
+        audio.play_group(SFX_SWAP, 1., 1., position);
+    }
+
+    /// The tile drops from above onto its cell, the kind stays held while there are more of it
+    fn place_from_bag(&mut self, i: usize, kind: usize, current_time: f64, audio: &AudioPlayer) {
+        let tile = self.available_tiles[kind].clone();
+        if !self.board.place(i, tile) {
+            return;
+        }
+        let mut from = self.cell_transform(i, TILE_PADDING);
+        from.translation.z += BAG_DROP_HEIGHT;
+        self.animate_tile(i, from, current_time);
+        self.moves += 1;
+        self.move_effects(&[i], 0, current_time);
+        self.hint = None;
+        if !self.board.can_place_kind(kind) {
+            self.bag_hand = None;
+        }
+        let position = self.cell_sound_position(i);
+        audio.play_group(SFX_SWAP, 1., 1., position);
+    }
+
+    /// The rotate sound goes up in pitch with each quarter turn, sparks fly off the tile.
+    /// `back` turns it the other way
+    fn rotate_cell(&mut self, i: usize, back: bool, current_time: f64, audio: &AudioPlayer) {
+        let from = self.tile_transform(i, TILE_PADDING, current_time);
+        let mismatched = self.mismatched_sides(&[i]);
+        self.board.rotate_tile(i, back);
+        self.animate_tile(i, from, current_time);
+        self.moves += 1;
+        self.move_effects(&[i], mismatched, current_time);
+        self.hint = None;
+        let pitch = 1. + self.board.grid_tiles[i].rotation as f32 * 0.06;
+        let position = self.cell_sound_position(i);
+        audio.play_group(SFX_ROTATE, 1., pitch, position);
+
+        let sparks = Emitter {
+            kind: EmitterKind::Burst { count: 12 },
+            pos: self.cell_transform(i, TILE_PADDING).translation + Vec3::Z * 0.1,
+            extent: Vec3::new(0.4, 0.4, 0.),
+            spread: 1.2,
+            speed: 0.5..1.5,
+            lifetime: 0.2..0.4,
+            size: 0.03..0.06,
+            colors: vec![Vec4::new(1., 0.9, 0.5, 1.), Vec4::new(1., 0.6, 0.2, 1.)],
+            drag: 3.,
+            ..Default::default()
+        };
+        self.particles.emit(sparks, self.rng.stream(RNG_PARTICLES));
+    }
+
+    /// Sides of the cells that don't match their neighbour
+    fn mismatched_sides(&self, cells: &[usize]) -> usize {
+        cells
+            .iter()
+            .map(|i| {
+                self.board
+                    .mismatches(*i)
+                    .iter()
+                    .filter(|side| **side)
+                    .count()
+            })
+            .sum()
+    }
+
+    /// Punches the screen when the moved tiles fit all their neighbours, shakes it when
+    /// the move left more sides mismatched than there were
+    fn move_effects(&mut self, cells: &[usize], mismatched_before: usize, current_time: f64) {
+        let mismatched = self.mismatched_sides(cells);
+        let distance = self.camera.distance();
+        if mismatched == 0 {
+            self.effects
+                .punch(SNAP_PUNCH * distance, SNAP_PUNCH_DURATION, current_time);
+        } else if mismatched > mismatched_before {
+            self.effects.shake(
+                MISMATCH_SHAKE * distance,
+                MISMATCH_SHAKE_DURATION,
+                current_time,
+            );
+        }
+    }
+
+    /// Where the tile of the cell rests, scaled by `padding`
+    fn cell_transform(&self, i: usize, padding: f32) -> Transform {
+        let size = self.board.grid_size.as_vec2();
+        let (x, y) = (
+            i % self.board.grid_size.x as usize,
+            i / self.board.grid_size.x as usize,
+        );
+        let rot = self.board.grid_tiles[i].rotation as f32 * 90.;
+        Transform {
+            scale: Vec3::ONE * padding,
+            translation: Vec3::new(
+                x as f32 - (size.x - 1.) * 0.5,
+                y as f32 - (size.y - 1.) * 0.5,
+                0.,
+            ),
+            rotation: Quat::from_euler(
+                EulerRot::XYZ,
+                f32::to_radians(90.),
+                f32::to_radians(rot),
+                0.,
+            ),
+        }
+    }
+
+    /// Where the tile of the cell is drawn, on its way to rest if it's animated.
+    /// Tiles that move between cells jump over the others
+    fn tile_transform(&self, i: usize, padding: f32, current_time: f64) -> Transform {
+        let rest = self.cell_transform(i, padding);
+        let Some(animation) = self.tile_animations.iter().find(|a| a.cell == i) else {
+            return rest;
+        };
+        let t = animation.timer.percent(current_time).clamp(0., 1.) as f32;
+        let mut transform = animation.from.lerp(&rest, Easing::QuadOut.apply(t));
+        let travel = animation
+            .from
+            .translation
+            .distance(rest.translation)
+            .min(1.);
+        transform.translation.z += travel * SWAP_LIFT * (t * std::f32::consts::PI).sin();
+        transform
+    }
+
+    /// The tile of the cell moves from `from` to where it rests
+    fn animate_tile(&mut self, i: usize, from: Transform, current_time: f64) {
+        self.tile_animations.retain(|animation| animation.cell != i);
+        self.tile_animations.push(TileAnimation {
+            cell: i,
+            from,
+            timer: Timer::from_duration(current_time, TILE_ANIMATION_DURATION),
+        });
+    }
+
+    /// A burst from the board, then a shower for a bit
+    fn emit_confetti(&mut self) {
+        let size = self.board.grid_size.as_vec2();
+        let confetti = Emitter {
+            pos: Vec3::new(0., 0., 0.5),
+            extent: (size * 0.5).extend(0.),
+            spread: 0.8,
+            speed: 3.0..6.0,
+            lifetime: 1.5..2.5,
+            size: 0.06..0.12,
+            colors: CONFETTI_COLORS.to_vec(),
+            fade: Vec4::new(1., 1., 1., 0.),
+            gravity: Vec3::new(0., -4., -2.),
+            drag: 0.8,
+            ..Default::default()
+        };
+        self.particles.emit(
+            Emitter {
+                kind: EmitterKind::Burst { count: 120 },
+                ..confetti.clone()
+            },
+            self.rng.stream(RNG_PARTICLES),
+        );
+        self.particles.emit(
+            Emitter {
+                kind: EmitterKind::Continuous {
+                    rate: 40.,
+                    duration: 1.5,
+                },
+                ..confetti
+            },
+            self.rng.stream(RNG_PARTICLES),
+        );
+    }
+
+    /// The move of a bound action: the cursor moves, the others are made on the cell
+    /// under the cursor, or under the mouse when they're bound to a mouse button
+    fn board_action(
+        &mut self,
+        action: Action,
+        binding: Binding,
+        pointed: Option<(usize, usize)>,
+    ) -> Option<ReplayAction> {
+        let dir = match action {
+            Action::CursorUp => IVec2::Y,
+            Action::CursorDown => IVec2::NEG_Y,
+            Action::CursorLeft => IVec2::NEG_X,
+            Action::CursorRight => IVec2::X,
+            Action::Activate | Action::RotateTile | Action::RotateTileBack => IVec2::ZERO,
+            Action::Undo => return Some(ReplayAction::Undo),
+            _ => return None,
+        };
+        let (x, y) = match binding {
+            Binding::Mouse(_) => pointed?,
+            Binding::Key(_) => {
+                // the cursor starts on the held tile or in the corner,
+                // it jumps over the holes and stops at the edge
+                let (x, y) = self
+                    .cursor
+                    .or(self.hand)
+                    .unwrap_or_else(|| self.board.first_cell());
+                let mut moved = IVec2::new(x as i32, y as i32);
+                let mut next = moved + dir;
+                while dir != IVec2::ZERO && self.board.contains(next) {
+                    if self.board.has_cell_at(next) {
+                        moved = next;
+                        break;
                     }
+                    next += dir;
                 }
+                let cell = (moved.x as usize, moved.y as usize);
+                self.cursor = Some(cell);
+                cell
             }
+        };
+        match action {
+            Action::Activate => Some(ReplayAction::Activate(x, y)),
+            Action::RotateTile => Some(ReplayAction::Rotate(x, y)),
+            Action::RotateTileBack => Some(ReplayAction::RotateBack(x, y)),
+            _ => None,
         }
     }
 }
@@ -542,7 +4327,8 @@ impl GameState {
 struct KripkeTile {
     sides: [u8; 4],
     rotation: usize,
-    asset_id: AssetId,
+    /// Index in the tile set, the tile pack has the mesh it's drawn with
+    kind: usize,
 }
 
 impl KripkeTile {
@@ -551,6 +4337,12 @@ impl KripkeTile {
         self.rotation += 1;
         self.rotation %= 4;
     }
+    /// The other way, undoes a `rotate_left`
+    fn rotate_right(&mut self) {
+        self.sides.rotate_left(1);
+        self.rotation += 3;
+        self.rotation %= 4;
+    }
     fn rotated_left_by(&self, rotate: usize) -> Self {
         let mut rotated = self.clone();
         for _ in 0..rotate {
@@ -560,49 +4352,100 @@ impl KripkeTile {
     }
 }
 
-/// Simple random generator
-struct RandLCG {
-    seed: u128,
+/// Why a typed code can't be played, shown in the language of the player
+#[derive(Clone, Debug, PartialEq)]
+pub enum SeedCodeError {
+    /// Not `SeedCode::LEN` or `SeedCode::MAX_LEN` characters
+    Length,
+    Character(char),
+    Size(IVec2),
 }
 
-impl RandLCG {
-    // https://en.wikipedia.org/wiki/Linear_congruential_generator#Parameters_in_common_use
-    const INCREMENT_C: u128 = 1;
-    const MULTIPLIER_A: u128 = 6364136223846793005;
-    const MODULUS_M: u128 = 18446744073709551616;
-
-    fn next(&mut self) -> u32 {
-        self.seed = (Self::MULTIPLIER_A * self.seed + Self::INCREMENT_C) % Self::MODULUS_M;
-        (self.seed >> 32) as u32
-    }
+/// Shareable code of a board: the grid size, the seed of its generator, the difficulty
+/// and whether it's played from a bag
+#[derive(Clone, Debug, PartialEq)]
+pub struct SeedCode {
+    seed: u32,
+    grid_size: IVec2,
+    difficulty: Difficulty,
+    /// The board starts empty, its tiles are placed from a bag
+    bag: bool,
 }
 
-struct Timer {
-    start_time: f64,
-    end_time: f64,
-}
+impl SeedCode {
+    // Crockford's base32, it skips I, L, O and U
+    const ALPHABET: &'static [u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+    // 32 bits of seed and 4 bits for each side, 5 bits per char
+    const LEN: usize = 8;
+    // a char before them is the difficulty, when it isn't the default or the board has a bag
+    const MAX_LEN: usize = Self::LEN + 1;
+    // of the char of the difficulty
+    const BAG_BIT: u64 = 16;
 
-impl Timer {
-    fn from_range(start_time: f64, end_time: f64) -> Self {
-        Self {
-            start_time,
-            end_time,
+    pub fn encode(&self) -> String {
+        let size = ((self.grid_size.x - 1) << 4 | (self.grid_size.y - 1)) as u64;
+        let packed = (self.seed as u64) << 8 | size;
+        let prefix = self.difficulty.index() as u64 | (self.bag as u64 * Self::BAG_BIT);
+        let difficulty = (self.difficulty != Difficulty::DEFAULT || self.bag)
+            .then(|| Self::ALPHABET[prefix as usize] as char);
+        difficulty
+            .into_iter()
+            .chain(
+                (0..Self::LEN)
+                    .rev()
+                    .map(|i| Self::ALPHABET[(packed >> (i * 5) & 31) as usize] as char),
+            )
+            .collect()
+    }
+
+    pub fn decode(code: &str) -> Result<Self, SeedCodeError> {
+        let code = code.trim();
+        if code.len() != Self::LEN && code.len() != Self::MAX_LEN {
+            return Err(SeedCodeError::Length);
+        }
+        let mut packed: u64 = 0;
+        for c in code.chars() {
+            // Lenient on the characters that are easy to mistype
+            let c = match c.to_ascii_uppercase() {
+                'O' => '0',
+                'I' | 'L' => '1',
+                c => c,
+            };
+            let Some(value) = Self::ALPHABET.iter().position(|a| *a as char == c) else {
+                return Err(SeedCodeError::Character(c));
+            };
+            packed = packed << 5 | value as u64;
+        }
+        let grid_size = IVec2::new((packed >> 4 & 15) as i32 + 1, (packed & 15) as i32 + 1);
+        if grid_size.x > 10 || grid_size.y > 10 {
+            return Err(SeedCodeError::Size(grid_size));
         }
+        let prefix = packed >> 40;
+        let difficulty = match prefix & !Self::BAG_BIT {
+            0 if code.len() == Self::LEN => Difficulty::DEFAULT,
+            index => *Difficulty::ALL
+                .get(index as usize)
+                .ok_or(SeedCodeError::Character(code.chars().next().unwrap_or(' ')))?,
+        };
+        Ok(Self {
+            seed: (packed >> 8 & 0xffff_ffff) as u32,
+            grid_size,
+            difficulty,
+            bag: prefix & Self::BAG_BIT != 0,
+        })
     }
-    fn from_duration(current_time: f64, duration: f64) -> Self {
+
+    pub fn new(seed: u32, grid_size: IVec2, difficulty: Difficulty, bag: bool) -> Self {
         Self {
-            start_time: current_time,
-            end_time: current_time + duration,
+            seed,
+            grid_size,
+            difficulty,
+            bag,
         }
     }
-    fn percent(&self, current_time: f64) -> f64 {
-        (current_time - self.start_time) / (self.end_time - self.start_time)
-    }
-    fn contains(&self, current_time: f64) -> bool {
-        current_time <= self.end_time && current_time >= self.start_time
-    }
-    fn is_finished(&self, current_time: f64) -> bool {
-        current_time >= self.end_time
+
+    fn rng(&self) -> Rng {
+        Rng::new(self.seed as u64)
     }
 }
 
@@ -641,3 +4484,75 @@ fn ray_rect_intersect(
     let size = Vec2::new(quad_axis_x.length(), quad_axis_y.length());
     Rect::new(-size * 0.5, size).contains_point(&planar)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mismatch_count_follows_the_moves() {
+        let tile_set =
+            TileSet::from_tileset(include_bytes!("../assets/tilesets/classic.tileset")).unwrap();
+        let tiles = kripke_tiles(&tile_set);
+        let mut rand = RandLCG::new(7);
+        for seed in 0..20 {
+            let code = SeedCode::new(seed, IVec2::new(7, 5), Difficulty::DEFAULT, false);
+            let mut board = BoardGeneration::new(
+                code.rng(),
+                code.grid_size,
+                None,
+                vec![],
+                false,
+                tiles.clone(),
+                tile_set.clone(),
+            )
+            .finish()
+            .unwrap();
+            for _ in 0..300 {
+                let cells = board.grid_tiles.len();
+                let a = rand.next_u32() as usize % cells;
+                let b = rand.next_u32() as usize % cells;
+                match rand.next_u32() % 3 {
+                    0 => board.swap_tiles(a, b),
+                    1 => board.rotate_tile(a, b.is_multiple_of(2)),
+                    _ => board.set_tile(a, tiles[b % tiles.len()].clone()),
+                }
+                assert_eq!(board.mismatch_count(), EdgeMismatches::new(&board).count);
+                assert_eq!(board.is_solved(), board.progress() == 1.);
+            }
+        }
+    }
+
+    #[test]
+    fn bag_board_is_solved_by_placing_its_tiles() {
+        let tile_set =
+            TileSet::from_tileset(include_bytes!("../assets/tilesets/classic.tileset")).unwrap();
+        let code = SeedCode::new(3, IVec2::new(4, 4), Difficulty::DEFAULT, true);
+        assert_eq!(SeedCode::decode(&code.encode()), Ok(code.clone()));
+        let (tiles, kinds) = endless_tiles(&tile_set, &kripke_tiles(&tile_set), &code);
+        let mut board = BoardGeneration::new(
+            code.rng(),
+            code.grid_size,
+            None,
+            vec![],
+            false,
+            tiles,
+            kinds,
+        )
+        .finish()
+        .unwrap();
+        let solution = board.solve().unwrap();
+        let solved: Vec<KripkeTile> = (solution.tiles.iter().flatten())
+            .map(|(index, rotation)| board.grid_tiles[*index].rotated_left_by(*rotation))
+            .collect();
+        board.empty_into_bag();
+        assert!(!board.is_solved());
+        assert_eq!(board.mismatch_count(), 0);
+        for (i, tile) in solved.into_iter().enumerate() {
+            assert!(board.place(i, tile.clone()));
+            assert!(!board.place(i, tile));
+        }
+        assert!(board.is_solved());
+        assert!(board.bag_remaining().iter().all(|left| *left == 0));
+    }
+}
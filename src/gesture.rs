@@ -0,0 +1,92 @@
+use crate::*;
+
+/// Seconds a touch is held still before it's a long press
+const LONG_PRESS_TIME: f64 = 0.5;
+/// Pixels a touch moves before it's a drag, multiplied by the dpi
+const DRAG_THRESHOLD: f32 = 12.;
+
+/// What a single finger did, recognized when it ends or, for long presses, while it's held
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Gesture {
+    Tap(Vec2),
+    Drag { from: Vec2, to: Vec2 },
+    LongPress(Vec2),
+}
+
+/// The touch that can still become a gesture
+#[derive(Clone, Debug)]
+struct TrackedTouch {
+    id: u64,
+    start: Vec2,
+    position: Vec2,
+    start_time: f64,
+    /// It moved past the threshold, it ends as a drag
+    dragged: bool,
+}
+
+/// Recognizes the gestures of one finger, a second finger cancels them since it's a pinch
+#[derive(Clone, Debug, Default)]
+pub struct GestureTracker {
+    touch: Option<TrackedTouch>,
+    /// Recognized this frame
+    pub gestures: Vec<Gesture>,
+}
+
+impl GestureTracker {
+    pub fn touch_event(&mut self, phase: TouchPhase, id: u64, position: Vec2, touches: usize) {
+        let now = miniquad::date::now();
+        match phase {
+            TouchPhase::Started => {
+                self.touch = match touches {
+                    1 => Some(TrackedTouch {
+                        id,
+                        start: position,
+                        position,
+                        start_time: now,
+                        dragged: false,
+                    }),
+                    _ => None,
+                };
+            }
+            TouchPhase::Moved => {
+                let threshold = DRAG_THRESHOLD * window::dpi_scale();
+                if let Some(touch) = self.touch.as_mut().filter(|touch| touch.id == id) {
+                    touch.position = position;
+                    touch.dragged |= touch.start.distance(position) > threshold;
+                }
+            }
+            TouchPhase::Ended => {
+                let Some(touch) = self.touch.take_if(|touch| touch.id == id) else {
+                    return;
+                };
+                let gesture = if touch.dragged {
+                    Gesture::Drag {
+                        from: touch.start,
+                        to: position,
+                    }
+                } else {
+                    Gesture::Tap(touch.start)
+                };
+                self.gestures.push(gesture);
+            }
+            TouchPhase::Cancelled => {
+                self.touch.take_if(|touch| touch.id == id);
+            }
+        }
+    }
+
+    /// Long presses are recognized while the finger is still down
+    pub fn update(&mut self) {
+        let Some(touch) = &self.touch else {
+            return;
+        };
+        if !touch.dragged && miniquad::date::now() - touch.start_time >= LONG_PRESS_TIME {
+            self.gestures.push(Gesture::LongPress(touch.start));
+            self.touch = None;
+        }
+    }
+
+    pub fn frame_end_reset(&mut self) {
+        self.gestures.clear();
+    }
+}
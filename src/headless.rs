@@ -0,0 +1,173 @@
+// Runs without a window, to time the board generator and the asset parsing where
+// there's no gpu: `tiling --headless` parses the files of the manifest,
+// `tiling --bench-gen 8x8 --count 100` also generates and solves endless boards
+
+use std::time::Duration;
+
+use crate::*;
+
+const USAGE: &str = "usage: tiling [--headless] [--bench-gen <width>x<height>] \
+                     [--count <n>] [--seed <n>] [--difficulty <name>]";
+
+/// Boards generated by `--bench-gen` without a `--count`
+const DEFAULT_COUNT: u32 = 100;
+
+/// Waits this long for the files being read before looking again
+const LOAD_POLL: Duration = Duration::from_millis(10);
+
+/// What to run instead of the game
+#[derive(Clone, Debug, PartialEq)]
+pub struct HeadlessOptions {
+    /// Of the endless boards to benchmark, none are generated when None
+    pub bench_grid: Option<IVec2>,
+    pub count: u32,
+    /// Of the first board, the next ones count up from it so that runs can be compared
+    pub seed: u32,
+    pub difficulty: Difficulty,
+}
+
+impl HeadlessOptions {
+    /// None if the arguments start the game, an error with the usage if they're wrong
+    pub fn from_args(args: &[String]) -> Result<Option<Self>, String> {
+        let mut headless = false;
+        let mut options = Self {
+            bench_grid: None,
+            count: DEFAULT_COUNT,
+            seed: 0,
+            difficulty: Difficulty::DEFAULT,
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or(format!("{} needs a value\n{}", arg, USAGE))
+            };
+            let number = |value: &String| {
+                value
+                    .parse::<u32>()
+                    .map_err(|e| format!("{}: {}\n{}", arg, e, USAGE))
+            };
+            match arg.as_str() {
+                "--headless" => {}
+                "--bench-gen" => options.bench_grid = Some(parse_grid(value()?)?),
+                "--count" => options.count = number(value()?)?,
+                "--seed" => options.seed = number(value()?)?,
+                "--difficulty" => {
+                    let name = value()?;
+                    options.difficulty = *Difficulty::ALL
+                        .iter()
+                        .find(|difficulty| difficulty.name() == name)
+                        .ok_or(format!("unknown difficulty {}\n{}", name, USAGE))?;
+                }
+                _ => return Err(format!("unknown argument {}\n{}", arg, USAGE)),
+            }
+            headless = true;
+        }
+        Ok(headless.then_some(options))
+    }
+}
+
+fn parse_grid(size: &str) -> Result<IVec2, String> {
+    let size = size
+        .split_once('x')
+        .and_then(|(x, y)| Some(IVec2::new(x.parse().ok()?, y.parse().ok()?)))
+        .ok_or(format!("expected a size like 8x8\n{}", USAGE))?;
+    // like the seed codes
+    if size.min_element() < 1 || size.max_element() > 10 {
+        return Err("the sides go from 1 to 10".to_string());
+    }
+    Ok(size)
+}
+
+/// The exit code, not 0 if a file or a board failed
+pub fn run(options: &HeadlessOptions) -> i32 {
+    let start = miniquad::date::now();
+    let mut assets = Assets::new();
+    assets.bundle = AssetBundle::find();
+    assets.load(&[MANIFEST_PATH]);
+    let mut parsed = 0;
+    while !assets.preloaded() {
+        let loaded = assets.update(miniquad::date::now());
+        if loaded.is_empty() {
+            let failed = assets.failed();
+            if !failed.is_empty() {
+                for (path, error) in failed {
+                    eprintln!("Failed to load {}: {}", path, error);
+                }
+                return 1;
+            }
+            std::thread::sleep(LOAD_POLL);
+        }
+        parsed += loaded.len();
+    }
+    println!(
+        "Parsed {} files in {:.1}ms",
+        parsed,
+        (miniquad::date::now() - start) * 1000.
+    );
+
+    match options.bench_grid {
+        Some(grid_size) => bench_generation(options, grid_size, &assets),
+        None => 0,
+    }
+}
+
+/// Generates the boards one after the other, then solves each of them from its shuffle
+fn bench_generation(options: &HeadlessOptions, grid_size: IVec2, assets: &Assets) -> i32 {
+    let mut generation_times = vec![];
+    let mut solve_times = vec![];
+    let mut failed = vec![];
+    let mut unsolved = vec![];
+    for seed in (0..options.count).map(|i| options.seed.wrapping_add(i)) {
+        let seed_code = SeedCode::new(seed, grid_size, options.difficulty, false);
+        let start = miniquad::date::now();
+        let board = generate_board(&seed_code, assets);
+        generation_times.push(miniquad::date::now() - start);
+        let Some(board) = board else {
+            failed.push(seed_code.encode());
+            continue;
+        };
+        let start = miniquad::date::now();
+        let solvable = board.is_solvable();
+        solve_times.push(miniquad::date::now() - start);
+        if !solvable {
+            unsolved.push(seed_code.encode());
+        }
+    }
+
+    println!(
+        "{} boards of {}x{} ({}), seeds {} and up",
+        options.count,
+        grid_size.x,
+        grid_size.y,
+        options.difficulty.name(),
+        options.seed
+    );
+    print_times("generated", &mut generation_times);
+    print_times("solved", &mut solve_times);
+    if !failed.is_empty() {
+        println!("{} not generated: {}", failed.len(), failed.join(" "));
+    }
+    // the solver ran out of budget, the game falls back to the generated solution
+    if !unsolved.is_empty() {
+        println!("{} not solved: {}", unsolved.len(), unsolved.join(" "));
+    }
+    (!failed.is_empty()) as i32
+}
+
+/// Total, mean, median and slowest, in milliseconds
+fn print_times(label: &str, times: &mut [f64]) {
+    if times.is_empty() {
+        return;
+    }
+    times.sort_by(f64::total_cmp);
+    let total: f64 = times.iter().sum();
+    println!(
+        "{:>9}: {:.1}ms total, {:.2}ms mean, {:.2}ms median, {:.2}ms max",
+        label,
+        total * 1000.,
+        total / times.len() as f64 * 1000.,
+        times[times.len() / 2] * 1000.,
+        times[times.len() - 1] * 1000.
+    );
+}
@@ -0,0 +1,85 @@
+use crate::*;
+
+/// The state of the mouse, keyboard and touches, filled by the window events.
+/// The just pressed ones last a frame
+pub struct Input {
+    pub mouse_position: Vec2,
+    pub mouse_frame_delta: Vec2,
+    pub mouse_frame_last: Vec2,
+    pub mouse_pressed: (bool, bool, bool),
+    pub mouse_just_pressed: (bool, bool, bool),
+    /// Released this frame close to where they were pressed
+    pub mouse_just_clicked: (bool, bool, bool),
+    /// Where each button was last pressed
+    pub mouse_press_position: [Vec2; 3],
+    pub mouse_wheel: (f32, f32),
+    pub key_pressed: Vec<KeyCode>,
+    pub key_just_pressed: Vec<KeyCode>,
+    /// Keys pressed this frame, with the repeats of held keys, for editing text
+    pub key_typed: Vec<KeyCode>,
+    pub chars_typed: Vec<char>,
+    pub just_touched: Vec<Vec2>,
+    /// Ongoing touches by id
+    pub touches: Vec<(u64, Vec2)>,
+    /// Where the ongoing left click or touch started, for dragging
+    pub drag_origin: Option<Vec2>,
+    /// Taps, drags and long presses of a single finger
+    pub gestures: GestureTracker,
+}
+
+impl Default for Input {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Input {
+    pub fn new() -> Self {
+        Self {
+            mouse_position: Vec2::ZERO,
+            mouse_frame_delta: Vec2::ZERO,
+            mouse_frame_last: Vec2::ZERO,
+            mouse_pressed: (false, false, false),
+            mouse_just_pressed: (false, false, false),
+            mouse_just_clicked: (false, false, false),
+            mouse_press_position: [Vec2::ZERO; 3],
+            mouse_wheel: (0., 0.),
+            key_pressed: vec![],
+            key_just_pressed: vec![],
+            key_typed: vec![],
+            chars_typed: vec![],
+            just_touched: vec![],
+            touches: vec![],
+            drag_origin: None,
+            gestures: GestureTracker::default(),
+        }
+    }
+
+    /// Position of the ongoing left click or first touch
+    pub fn pointer(&self) -> Option<Vec2> {
+        if self.mouse_pressed.0 {
+            Some(self.mouse_position)
+        } else {
+            self.touches.first().map(|(_, position)| *position)
+        }
+    }
+
+    pub fn frame_start(&mut self) {
+        self.mouse_frame_delta = self.mouse_position - self.mouse_frame_last;
+        self.mouse_frame_last = self.mouse_position;
+        self.gestures.update();
+    }
+
+    pub fn frame_end_reset(&mut self) {
+        self.mouse_just_pressed.0 = false;
+        self.mouse_just_pressed.1 = false;
+        self.mouse_just_pressed.2 = false;
+        self.mouse_just_clicked = (false, false, false);
+        self.mouse_wheel = (0., 0.);
+        self.key_just_pressed.clear();
+        self.key_typed.clear();
+        self.chars_typed.clear();
+        self.just_touched.clear();
+        self.gestures.frame_end_reset();
+    }
+}
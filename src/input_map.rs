@@ -0,0 +1,311 @@
+use crate::*;
+
+/// What the player does with a key or a mouse button
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    CursorUp,
+    CursorDown,
+    CursorLeft,
+    CursorRight,
+    /// Like a click on the cell under the keyboard cursor
+    Activate,
+    RotateTile,
+    /// The other way, for when a rotation goes one too far
+    RotateTileBack,
+    Undo,
+    Hint,
+    OpenMenu,
+    DebugView,
+    PlayReplay,
+}
+
+impl Action {
+    pub const ALL: [Action; 12] = [
+        Action::CursorUp,
+        Action::CursorDown,
+        Action::CursorLeft,
+        Action::CursorRight,
+        Action::Activate,
+        Action::RotateTile,
+        Action::RotateTileBack,
+        Action::Undo,
+        Action::Hint,
+        Action::OpenMenu,
+        Action::DebugView,
+        Action::PlayReplay,
+    ];
+
+    /// Key of the action in the config, "bind_" is prepended, and in the lang files
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::CursorUp => "cursor_up",
+            Action::CursorDown => "cursor_down",
+            Action::CursorLeft => "cursor_left",
+            Action::CursorRight => "cursor_right",
+            Action::Activate => "activate",
+            Action::RotateTile => "rotate_tile",
+            Action::RotateTileBack => "rotate_tile_back",
+            Action::Undo => "undo",
+            Action::Hint => "hint",
+            Action::OpenMenu => "open_menu",
+            Action::DebugView => "debug_view",
+            Action::PlayReplay => "play_replay",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+}
+
+/// The keys that can be bound, the tilde is left to the console
+const BINDABLE_KEYS: &[KeyCode] = {
+    use KeyCode::*;
+    &[
+        Space,
+        Apostrophe,
+        Comma,
+        Minus,
+        Period,
+        Slash,
+        Key0,
+        Key1,
+        Key2,
+        Key3,
+        Key4,
+        Key5,
+        Key6,
+        Key7,
+        Key8,
+        Key9,
+        Semicolon,
+        Equal,
+        A,
+        B,
+        C,
+        D,
+        E,
+        F,
+        G,
+        H,
+        I,
+        J,
+        K,
+        L,
+        M,
+        N,
+        O,
+        P,
+        Q,
+        R,
+        S,
+        T,
+        U,
+        V,
+        W,
+        X,
+        Y,
+        Z,
+        LeftBracket,
+        Backslash,
+        RightBracket,
+        Escape,
+        Enter,
+        Tab,
+        Backspace,
+        Insert,
+        Delete,
+        Right,
+        Left,
+        Down,
+        Up,
+        PageUp,
+        PageDown,
+        Home,
+        End,
+        F1,
+        F2,
+        F3,
+        F4,
+        F5,
+        F6,
+        F7,
+        F8,
+        F9,
+        F10,
+        F11,
+        F12,
+        Kp0,
+        Kp1,
+        Kp2,
+        Kp3,
+        Kp4,
+        Kp5,
+        Kp6,
+        Kp7,
+        Kp8,
+        Kp9,
+        KpDecimal,
+        KpDivide,
+        KpMultiply,
+        KpSubtract,
+        KpAdd,
+        KpEnter,
+        LeftShift,
+        LeftControl,
+        RightShift,
+        RightControl,
+    ]
+};
+
+/// The left button is left to the ui and the board
+const MOUSE_BUTTONS: [(MouseButton, &str); 2] = [
+    (MouseButton::Middle, "MouseMiddle"),
+    (MouseButton::Right, "MouseRight"),
+];
+
+impl Binding {
+    /// Like the variant of the key, "Space" or "F3", or "MouseRight"
+    pub fn name(&self) -> String {
+        match self {
+            Binding::Key(key) => format!("{:?}", key),
+            Binding::Mouse(button) => MOUSE_BUTTONS
+                .iter()
+                .find(|(b, _)| b == button)
+                .map_or("Mouse", |(_, name)| name)
+                .to_string(),
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        if let Some((button, _)) = MOUSE_BUTTONS.iter().find(|(_, n)| *n == name) {
+            return Some(Binding::Mouse(*button));
+        }
+        BINDABLE_KEYS
+            .iter()
+            .find(|key| format!("{:?}", key) == name)
+            .map(|key| Binding::Key(*key))
+    }
+
+    /// The first bindable key or button pressed this frame, for rebinding
+    pub fn just_pressed(input: &Input) -> Option<Self> {
+        if let Some(key) = input
+            .key_just_pressed
+            .iter()
+            .find(|key| BINDABLE_KEYS.contains(key))
+        {
+            return Some(Binding::Key(*key));
+        }
+        MOUSE_BUTTONS
+            .iter()
+            .map(|(button, _)| Binding::Mouse(*button))
+            .find(|binding| binding.is_just_pressed(input))
+    }
+
+    /// Mouse buttons when they're clicked, their drags move the camera
+    fn is_just_pressed(&self, input: &Input) -> bool {
+        match self {
+            Binding::Key(key) => input.key_just_pressed.contains(key),
+            Binding::Mouse(MouseButton::Left) => input.mouse_just_clicked.0,
+            Binding::Mouse(MouseButton::Middle) => input.mouse_just_clicked.1,
+            Binding::Mouse(MouseButton::Right) => input.mouse_just_clicked.2,
+            Binding::Mouse(MouseButton::Unknown) => false,
+        }
+    }
+}
+
+/// The keys and buttons of each action, saved in the config
+#[derive(Clone, Debug, PartialEq)]
+pub struct InputMap {
+    bindings: Vec<(Action, Vec<Binding>)>,
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        use KeyCode::*;
+        let keys = |keys: &[KeyCode]| keys.iter().map(|key| Binding::Key(*key)).collect();
+        Self {
+            bindings: vec![
+                (Action::CursorUp, keys(&[Up, W])),
+                (Action::CursorDown, keys(&[Down, S])),
+                (Action::CursorLeft, keys(&[Left, A])),
+                (Action::CursorRight, keys(&[Right, D])),
+                (Action::Activate, keys(&[Space, Enter, KpEnter])),
+                (Action::RotateTile, keys(&[R])),
+                (
+                    Action::RotateTileBack,
+                    vec![Binding::Key(Q), Binding::Mouse(MouseButton::Right)],
+                ),
+                (Action::Undo, keys(&[Z, Backspace])),
+                (Action::Hint, keys(&[H])),
+                (Action::OpenMenu, keys(&[Escape])),
+                (Action::DebugView, keys(&[F3])),
+                (Action::PlayReplay, keys(&[F5])),
+            ],
+        }
+    }
+}
+
+impl InputMap {
+    pub fn bindings(&self, action: Action) -> &[Binding] {
+        self.bindings
+            .iter()
+            .find(|(a, _)| *a == action)
+            .map_or(&[], |(_, bindings)| bindings.as_slice())
+    }
+
+    pub fn set(&mut self, action: Action, bindings: Vec<Binding>) {
+        match self.bindings.iter_mut().find(|(a, _)| *a == action) {
+            Some((_, current)) => *current = bindings,
+            None => self.bindings.push((action, bindings)),
+        }
+    }
+
+    /// Makes the binding the only one of the action, it's taken from the other actions
+    pub fn bind(&mut self, action: Action, binding: Binding) {
+        for (_, bindings) in self.bindings.iter_mut() {
+            bindings.retain(|b| *b != binding);
+        }
+        self.set(action, vec![binding]);
+    }
+
+    pub fn just_pressed(&self, input: &Input, action: Action) -> bool {
+        self.bindings(action)
+            .iter()
+            .any(|binding| binding.is_just_pressed(input))
+    }
+
+    /// The actions pressed this frame and what pressed them,
+    /// the keys in the order they were pressed and then the mouse buttons
+    pub fn just_pressed_actions(&self, input: &Input) -> Vec<(Action, Binding)> {
+        let keys = input.key_just_pressed.iter().map(|key| Binding::Key(*key));
+        let buttons = MOUSE_BUTTONS
+            .iter()
+            .map(|(button, _)| Binding::Mouse(*button))
+            .filter(|binding| binding.is_just_pressed(input));
+        keys.chain(buttons)
+            .filter_map(|binding| {
+                Action::ALL
+                    .into_iter()
+                    .find(|action| self.bindings(*action).contains(&binding))
+                    .map(|action| (action, binding))
+            })
+            .collect()
+    }
+
+    /// Names of the bindings separated by spaces
+    pub fn bindings_text(&self, action: Action) -> String {
+        self.bindings(action)
+            .iter()
+            .map(|binding| binding.name())
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+
+    /// Unknown names are skipped
+    pub fn parse_bindings(text: &str) -> Vec<Binding> {
+        text.split_whitespace().filter_map(Binding::parse).collect()
+    }
+}
@@ -0,0 +1,253 @@
+use crate::*;
+
+/// A curated puzzle of the campaign
+pub struct Level {
+    pub name: &'static str,
+    pub grid_size: IVec2,
+    /// Tiles the board is built from, all of them if empty
+    pub tiles: &'static [&'static str],
+    pub seed: u32,
+    /// Rows of the board from the top, '#' is a cell, 'L' a cell with its tile locked in place
+    /// and '.' a hole. Every cell is there if empty
+    pub mask: &'static [&'static str],
+    /// More tiles are locked in place until the board has a single solution
+    pub unique: bool,
+}
+
+impl Level {
+    /// Cells of the board, bottom row first, None if every cell is there
+    pub fn mask(&self) -> Option<Vec<bool>> {
+        if self.mask.is_empty() {
+            return None;
+        }
+        let (width, height) = (self.grid_size.x as usize, self.grid_size.y as usize);
+        let mut mask = vec![false; width * height];
+        for (row, line) in self.mask.iter().take(height).enumerate() {
+            let y = height - 1 - row;
+            for (x, c) in line.chars().take(width).enumerate() {
+                mask[x + y * width] = c != '.';
+            }
+        }
+        Some(mask)
+    }
+
+    /// Indices of the cells marked 'L' in the mask
+    pub fn locked_cells(&self) -> Vec<usize> {
+        let (width, height) = (self.grid_size.x as usize, self.grid_size.y as usize);
+        let mut locked = vec![];
+        for (row, line) in self.mask.iter().take(height).enumerate() {
+            let y = height - 1 - row;
+            for (x, c) in line.chars().take(width).enumerate() {
+                if c == 'L' {
+                    locked.push(x + y * width);
+                }
+            }
+        }
+        locked
+    }
+}
+
+const LINES: &[&str] = &[
+    "tile_0000.obj",
+    "tile_1000.obj",
+    "tile_1100.obj",
+    "tile_1010.obj",
+];
+const LINES_CROSSING: &[&str] = &[
+    "tile_0000.obj",
+    "tile_1000.obj",
+    "tile_1100.obj",
+    "tile_1010.obj",
+    "tile_1110.obj",
+    "tile_1111.obj",
+];
+const ROADS: &[&str] = &[
+    "tile_0000.obj",
+    "tile_0020.obj",
+    "tile_0202.obj",
+    "tile_0022.obj",
+    "tile_0222.obj",
+    "tile_2222.obj",
+];
+const BRIDGES: &[&str] = &[
+    "tile_0000.obj",
+    "tile_1000.obj",
+    "tile_1010.obj",
+    "tile_0202.obj",
+    "tile_1202.obj",
+    "tile_1022.obj",
+];
+
+pub const LEVELS: [Level; 16] = [
+    Level {
+        name: "First steps",
+        grid_size: IVec2::new(2, 2),
+        tiles: LINES,
+        seed: 7,
+        mask: &[],
+        unique: false,
+    },
+    Level {
+        name: "Corners",
+        grid_size: IVec2::new(3, 2),
+        tiles: LINES,
+        seed: 12,
+        mask: &[],
+        unique: false,
+    },
+    Level {
+        name: "Crossings",
+        grid_size: IVec2::new(3, 3),
+        tiles: LINES_CROSSING,
+        seed: 3,
+        mask: &[],
+        unique: false,
+    },
+    Level {
+        name: "Roads",
+        grid_size: IVec2::new(3, 3),
+        tiles: ROADS,
+        seed: 21,
+        mask: &[],
+        unique: false,
+    },
+    Level {
+        name: "Bridges",
+        grid_size: IVec2::new(4, 3),
+        tiles: BRIDGES,
+        seed: 5,
+        mask: &[],
+        unique: false,
+    },
+    Level {
+        name: "Everything",
+        grid_size: IVec2::new(3, 3),
+        tiles: &[],
+        seed: 42,
+        mask: &[],
+        unique: false,
+    },
+    Level {
+        name: "Long road",
+        grid_size: IVec2::new(6, 2),
+        tiles: ROADS,
+        seed: 8,
+        mask: &[],
+        unique: false,
+    },
+    Level {
+        name: "Square",
+        grid_size: IVec2::new(4, 4),
+        tiles: &[],
+        seed: 19,
+        mask: &[],
+        unique: false,
+    },
+    Level {
+        name: "Tower",
+        grid_size: IVec2::new(3, 6),
+        tiles: LINES_CROSSING,
+        seed: 33,
+        mask: &[],
+        unique: false,
+    },
+    Level {
+        name: "Wide",
+        grid_size: IVec2::new(6, 4),
+        tiles: &[],
+        seed: 27,
+        mask: &[],
+        unique: false,
+    },
+    Level {
+        name: "Big square",
+        grid_size: IVec2::new(6, 6),
+        tiles: &[],
+        seed: 64,
+        mask: &[],
+        unique: false,
+    },
+    Level {
+        name: "Finale",
+        grid_size: IVec2::new(8, 8),
+        tiles: &[],
+        seed: 100,
+        mask: &[],
+        unique: false,
+    },
+    Level {
+        name: "Corner",
+        grid_size: IVec2::new(4, 4),
+        tiles: LINES_CROSSING,
+        seed: 14,
+        mask: &["L...", "#...", "#...", "###L"],
+        unique: false,
+    },
+    Level {
+        name: "Diamond",
+        grid_size: IVec2::new(5, 5),
+        tiles: &[],
+        seed: 51,
+        mask: &["..#..", ".###.", "#####", ".###.", "..#.."],
+        unique: false,
+    },
+    Level {
+        name: "Courtyard",
+        grid_size: IVec2::new(5, 5),
+        tiles: ROADS,
+        seed: 77,
+        mask: &["#####", "#...#", "#.#.#", "#...#", "#####"],
+        unique: false,
+    },
+    Level {
+        name: "One way",
+        grid_size: IVec2::new(4, 4),
+        tiles: &[],
+        seed: 90,
+        mask: &[],
+        unique: true,
+    },
+];
+
+/// Names of the completed levels, saved to disk on every change
+#[derive(Default)]
+pub struct Progress {
+    completed: Vec<String>,
+}
+
+impl Progress {
+    const FILE: &'static str = "progress.txt";
+
+    pub fn load() -> Self {
+        match storage::load(Self::FILE) {
+            Ok(text) => Self {
+                completed: text.lines().map(|line| line.to_string()).collect(),
+            },
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn complete(&mut self, level: usize) {
+        let name = LEVELS[level].name;
+        if self.is_completed(level) {
+            return;
+        }
+        self.completed.push(name.to_string());
+        if let Err(err) = storage::save(Self::FILE, &self.completed.join("\n")) {
+            eprintln!("Progress not saved: {err}");
+        }
+    }
+
+    pub fn is_completed(&self, level: usize) -> bool {
+        self.completed.iter().any(|name| name == LEVELS[level].name)
+    }
+
+    /// The first level and the ones after a completed level can be played
+    pub fn is_unlocked(&self, level: usize) -> bool {
+        level == 0 || self.is_completed(level - 1)
+    }
+
+    pub fn completed_count(&self) -> usize {
+        (0..LEVELS.len()).filter(|i| self.is_completed(*i)).count()
+    }
+}
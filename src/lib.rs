@@ -0,0 +1,34 @@
+//! The mini-engine under the game, for small games on miniquad: assets loaded from a
+//! folder or a bundle, a 2d and 3d renderer, an immediate mode ui, sounds and music,
+//! and udp messages between two players.
+//!
+//! A game owns the window and its event loop. It keeps the pieces it uses, feeds the
+//! window events to `Input`, fills the `RenderTileCommands` and `RenderMeshCommands` of
+//! each frame and draws them with the `Renderer`. Its messages implement `NetMessage`.
+//! Games depend on the same `glam` and `miniquad` as the engine.
+
+use glam::*;
+use miniquad::*;
+mod quad_snd;
+
+pub mod assets;
+pub mod audio;
+pub mod camera;
+pub mod gesture;
+pub mod input;
+pub mod locale;
+pub mod net;
+pub mod render;
+pub mod rng;
+pub mod storage;
+pub mod tween;
+pub mod ui;
+
+pub use assets::*;
+pub use audio::*;
+pub use gesture::*;
+pub use input::*;
+pub use net::*;
+pub use render::*;
+pub use rng::*;
+pub use tween::*;
@@ -0,0 +1,74 @@
+use std::{collections::HashMap, fmt::Display};
+
+/// Languages with a `lang/<code>.lang` asset, code and name in the language itself
+pub const LANGUAGES: [(&str, &str); 2] = [("en", "English"), ("it", "Italiano")];
+
+/// Strings are looked up in it when the chosen language misses them
+pub const FALLBACK_LANGUAGE: &str = "en";
+
+pub fn lang_path(code: &str) -> String {
+    format!("lang/{}.lang", code)
+}
+
+/// Strings of a language, parsed from `key=value` lines.
+/// Lines starting with '#' are comments, "\n" in a value is a line break.
+#[derive(Clone, Debug, Default)]
+pub struct LangTable {
+    pub strings: HashMap<String, String>,
+}
+
+impl LangTable {
+    pub fn from_lang(s: &[u8]) -> Result<Self, String> {
+        let text = std::str::from_utf8(s).map_err(|e| format!("Lang: {}", e))?;
+        let mut strings = HashMap::new();
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or(format!("Lang: missing '=' at line {}", line_number + 1))?;
+            strings.insert(key.trim().to_string(), value.trim().replace("\\n", "\n"));
+        }
+        Ok(Self { strings })
+    }
+}
+
+/// The strings shown to the player, in the chosen language
+#[derive(Clone, Debug, Default)]
+pub struct Locale {
+    pub language: String,
+    strings: HashMap<String, String>,
+}
+
+impl Locale {
+    /// The table of the language over the fallback one
+    pub fn new(language: &str, table: &LangTable, fallback: &LangTable) -> Self {
+        let mut strings = fallback.strings.clone();
+        strings.extend(table.strings.clone());
+        Self {
+            language: language.to_string(),
+            strings,
+        }
+    }
+
+    /// The string of `key`, or the key itself so that a missing one is easy to spot
+    pub fn get<'a>(&'a self, key: &'a str) -> &'a str {
+        self.strings.get(key).map_or(key, |s| s.as_str())
+    }
+
+    /// The string of `key` with each "{}" replaced by the next argument
+    pub fn format(&self, key: &str, args: &[&dyn Display]) -> String {
+        let mut parts = self.get(key).split("{}");
+        let mut text = parts.next().unwrap_or_default().to_string();
+        let mut args = args.iter();
+        for part in parts {
+            if let Some(arg) = args.next() {
+                text.push_str(&arg.to_string());
+            }
+            text.push_str(part);
+        }
+        text
+    }
+}
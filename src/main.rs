@@ -1,44 +1,98 @@
 #![allow(dead_code)]
 // TODO: remove this when done
 
-use std::collections::HashMap;
+use std::collections::HashSet;
 
 use glam::*;
 use miniquad::*;
-mod quad_snd;
+use tiling::*;
 
-mod assets;
+mod board_sync;
+mod config;
+mod console;
+mod difficulty;
+mod effects;
 mod gamestate;
-mod net;
-mod render;
-mod ui;
-
-use assets::*;
+mod headless;
+mod input_map;
+mod levels;
+mod particles;
+mod profile;
+mod profiler;
+mod protocol;
+mod records;
+mod replay;
+mod solver;
+mod stats;
+mod suspended;
+mod version;
+mod versus;
+
+use board_sync::*;
+use config::*;
+use difficulty::*;
 use gamestate::*;
-use net::*;
-use render::*;
-
-use crate::quad_snd::{AudioContext, Sound};
+use headless::HeadlessOptions;
+use input_map::*;
+use profile::*;
+use profiler::Profiler;
+use protocol::*;
+use solver::*;
+use stats::*;
+use suspended::*;
+use ui::{SafeArea, UiMemory};
+use versus::*;
 
 fn main() {
+    // Crashes print the build version
+    version::install_crash_report_hook();
+
+    // Benchmarks without a window
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match HeadlessOptions::from_args(&args) {
+        Ok(Some(options)) => std::process::exit(headless::run(&options)),
+        Ok(None) => {}
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(2);
+        }
+    }
+
     // Load icons
     let icon = load_icon().ok();
 
+    let config = Config::load();
+
     // Miniquad window config
     let conf = conf::Conf {
         window_title: env!("CARGO_PKG_NAME").to_string(),
-        sample_count: 16,
+        window_width: config.window.size.x,
+        window_height: config.window.size.y,
+        fullscreen: config.window.fullscreen,
+        // the renderer multisamples its own target, that can fall back to fewer samples.
+        // Webgl can't, so the browser multisamples the canvas
+        sample_count: if cfg!(target_arch = "wasm32") {
+            config.sample_count
+        } else {
+            1
+        },
         high_dpi: true,
         window_resizable: true,
         icon,
-        ..conf::Conf::default()
+        platform: conf::Platform {
+            swap_interval: Some(config.window.vsync as i32),
+            ..Default::default()
+        },
     };
 
-    // Load asset requests
-    let assets = Assets::new();
+    // Load asset requests, from the bundle if the game ships with one
+    let mut assets = Assets::new();
+    assets.bundle = AssetBundle::find();
+    assets.png_options.ignore_crc = config.ignore_png_crc;
+    assets.load(&[MANIFEST_PATH]);
 
     // Start the event loop
-    miniquad::start(conf, move || Box::new(Engine::new(assets)));
+    miniquad::start(conf, move || Box::new(Engine::new(assets, config)));
 }
 
 fn load_icon() -> Result<conf::Icon, String> {
@@ -72,106 +126,226 @@ fn load_icon() -> Result<conf::Icon, String> {
     }
 }
 
-struct Input {
-    mouse_position: Vec2,
-    mouse_frame_delta: Vec2,
-    mouse_frame_last: Vec2,
-    mouse_pressed: (bool, bool, bool),
-    mouse_just_pressed: (bool, bool, bool),
-    mouse_wheel: (f32, f32),
-    key_pressed: Vec<KeyCode>,
-    key_just_pressed: Vec<KeyCode>,
-    just_touched: Vec<Vec2>,
+/// How each image is sampled: the pixel font stays sharp,
+/// the atlases of the tile packs don't shimmer when the board is seen at an angle
+fn texture_options(path: &str, tile_atlas: bool) -> TextureOptions {
+    match path {
+        "littlefont.png" => TextureOptions {
+            filter: FilterMode::Nearest,
+            ..Default::default()
+        },
+        _ if tile_atlas => TextureOptions {
+            mipmaps: true,
+            anisotropy: 8.,
+            ..Default::default()
+        },
+        _ => TextureOptions::default(),
+    }
 }
 
-impl Input {
-    fn new() -> Self {
-        Self {
-            mouse_position: Vec2::ZERO,
-            mouse_frame_delta: Vec2::ZERO,
-            mouse_frame_last: Vec2::ZERO,
-            mouse_pressed: (false, false, false),
-            mouse_just_pressed: (false, false, false),
-            mouse_wheel: (0., 0.),
-            key_pressed: vec![],
-            key_just_pressed: vec![],
-            just_touched: vec![],
-        }
-    }
+/// Pixels the mouse moves between press and release before it's a drag, multiplied by the dpi
+const CLICK_DISTANCE: f32 = 6.;
 
-    fn frame_start(&mut self) {
-        self.mouse_frame_delta = self.mouse_position - self.mouse_frame_last;
-        self.mouse_frame_last = self.mouse_position;
-    }
+/// Smallest window the ui is laid out for in pixels, a smaller one is grown back to it
+const MIN_WINDOW_SIZE: Vec2 = Vec2::new(320., 240.);
 
-    fn frame_end_reset(&mut self) {
-        self.mouse_just_pressed.0 = false;
-        self.mouse_just_pressed.1 = false;
-        self.mouse_just_pressed.2 = false;
-        self.mouse_wheel = (0., 0.);
-        self.key_just_pressed.clear();
-        self.just_touched.clear();
-    }
-}
+/// Seconds of a step of the simulation
+pub const TICK_DELTA: f64 = 1. / 60.;
+const MAX_TICKS_PER_FRAME: u64 = 15;
 
 // Platform abstraction (using miniquad for now)
 struct Engine {
     ctx: Box<dyn RenderingBackend>,
     renderer: Renderer,
     resolution: Vec2,
+    /// Insets of the screen, asked again when the window is resized
+    safe_area: SafeArea,
     tile_commands: RenderTileCommands,
     mesh_commands: RenderMeshCommands,
+    debug_commands: RenderDebugCommands,
     frame_time: Option<f64>,
+    /// Time not simulated yet, less than a tick after the update
+    tick_accumulator: f64,
+    tick: u64,
     assets: Assets,
     input: Input,
+    ui_memory: UiMemory,
     gamestate: GameState,
-    audio_ctx: AudioContext,
-    sounds: HashMap<AssetId, Sound>,
+    audio: AudioPlayer,
     server: NetServer,
     client: NetClient,
+    config: Config,
+    applied_config: Config,
+    /// The window as it is now, it follows the one in the config
+    window: WindowSettings,
+    profiler: Profiler,
 }
 
 // Passed to the GameState
 struct EngineContext<'a> {
-    ctx: &'a Box<dyn RenderingBackend>,
+    ctx: &'a mut Box<dyn RenderingBackend>,
     resolution: &'a Vec2,
+    safe_area: &'a SafeArea,
+    /// From the dpi and the size of the screen, multiplies the scale in the config
+    ui_scale: f32,
     renderer: &'a mut Renderer,
     tile_commands: &'a mut RenderTileCommands,
     mesh_commands: &'a mut RenderMeshCommands,
+    debug_commands: &'a mut RenderDebugCommands,
     assets: &'a mut Assets,
     input: &'a mut Input,
-    sounds: &'a mut HashMap<AssetId, Sound>,
+    ui_memory: &'a mut UiMemory,
+    audio: &'a mut AudioPlayer,
     current_time: f64,
     delta_time: f64,
-    audio_ctx: &'a AudioContext,
+    /// Fixed ticks simulated since the start, each `TICK_DELTA` long
+    tick: u64,
+    /// Part of the next tick already elapsed, to draw between the last two ticks
+    alpha: f32,
     server: &'a mut NetServer,
     client: &'a mut NetClient,
+    config: &'a mut Config,
+    /// Times of the frames before this one
+    profiler: &'a Profiler,
 }
 
 impl Engine {
-    pub fn new(assets: Assets) -> Engine {
+    pub fn new(assets: Assets, config: Config) -> Engine {
         let mut ctx: Box<dyn RenderingBackend> = window::new_rendering_backend();
 
         let res = miniquad::window::screen_size();
         let renderer = Renderer::new(&mut ctx, Camera::ui());
 
-        let audio_ctx = AudioContext::new();
+        // One seed for the launch, each kind of randomness draws from its own stream
+        let rng = Rng::from_time();
+        let audio = AudioPlayer::new(
+            config.volume,
+            config.sfx_volume,
+            config.music_volume,
+            rng.split(RNG_AUDIO_VARIATION),
+        );
 
         Engine {
             renderer,
             ctx,
             resolution: Vec2::new(res.0, res.1),
+            safe_area: SafeArea::query(),
             frame_time: None,
+            tick_accumulator: 0.,
+            tick: 0,
             tile_commands: RenderTileCommands::default(),
             mesh_commands: RenderMeshCommands::default(),
+            debug_commands: RenderDebugCommands::default(),
             assets,
             input: Input::new(),
-            gamestate: GameState::new(),
-            audio_ctx,
-            sounds: HashMap::new(),
+            ui_memory: UiMemory::default(),
+            gamestate: GameState::new(rng),
+            audio,
             server: NetServer::new(),
             client: NetClient::new(),
+            applied_config: config.clone(),
+            window: config.window,
+            config,
+            profiler: Profiler::default(),
+        }
+    }
+
+    /// Uploads an image to the gpu, recolored if the config asks for it
+    fn load_texture(&mut self, id: &AssetId) {
+        let (Some(image), Some(path)) = (self.assets.images.get(id), self.assets.get_path(id))
+        else {
+            return;
+        };
+        let tile_atlas = self.assets.is_tile_atlas(path);
+        let options = texture_options(path, tile_atlas);
+        if let (Some(recolor), true) = (self.config.palette.recolor(), tile_atlas) {
+            let image = image.recolored(recolor);
+            self.renderer
+                .reload_texture(&mut self.ctx, &image, id, &options);
+        } else {
+            self.renderer
+                .reload_texture(&mut self.ctx, image, id, &options);
+        }
+    }
+
+    /// Uploads again what is drawn but was evicted, evicts what is over the texture budget
+    /// and frees the copies of the unloaded assets
+    fn update_gpu_assets(&mut self) {
+        let used: HashSet<AssetId> = self
+            .tile_commands
+            .assets()
+            .chain(self.mesh_commands.assets())
+            .cloned()
+            .collect();
+        for id in self.renderer.missing(&used) {
+            if self.assets.images.contains_key(&id) {
+                self.load_texture(&id);
+            }
+            if let Some(mesh) = self.assets.meshes.get(&id) {
+                self.renderer.reload_mesh(&mut self.ctx, mesh, &id);
+                let images = self.assets.submesh_images(&id);
+                self.renderer.set_submesh_images(&id, images);
+            }
+        }
+        self.renderer.evict(&mut self.ctx, &used);
+
+        for id in std::mem::take(&mut self.assets.unloaded) {
+            self.renderer.unload(&mut self.ctx, &id);
+            self.audio.unload(&id);
+        }
+    }
+
+    /// Applies the settings changed while playing
+    fn apply_config(&mut self) {
+        self.audio.master_volume = self.config.volume;
+        self.audio.sfx_volume = self.config.sfx_volume;
+        self.audio.music_volume = self.config.music_volume;
+        self.audio.update_music_volume();
+        if self.config.palette != self.applied_config.palette {
+            let ids: Vec<AssetId> = self.assets.images.keys().cloned().collect();
+            for id in ids {
+                self.load_texture(&id);
+            }
+        }
+        if self.config.window != self.window {
+            self.apply_window();
+        }
+        self.applied_config = self.config.clone();
+    }
+
+    /// Switches to the window mode and size of the config
+    fn apply_window(&mut self) {
+        let target = self.config.window;
+        if target.fullscreen != self.window.fullscreen {
+            window::set_fullscreen(target.fullscreen);
+        }
+        // the size is kept for when the window leaves fullscreen
+        if !target.fullscreen
+            && (target.size != self.window.size || target.fullscreen != self.window.fullscreen)
+        {
+            let size = target.size.as_uvec2();
+            window::set_window_size(size.x, size.y);
         }
+        self.window = target;
+    }
+
+    /// Alt+Enter toggles fullscreen, the enter doesn't reach the game
+    fn toggle_fullscreen_shortcut(&mut self) {
+        let alt = self.input.key_pressed.contains(&KeyCode::LeftAlt)
+            || self.input.key_pressed.contains(&KeyCode::RightAlt);
+        let enter = self.input.key_just_pressed.contains(&KeyCode::Enter)
+            || self.input.key_just_pressed.contains(&KeyCode::KpEnter);
+        if !(alt && enter) {
+            return;
+        }
+        self.input
+            .key_just_pressed
+            .retain(|key| *key != KeyCode::Enter && *key != KeyCode::KpEnter);
+        self.input
+            .key_typed
+            .retain(|key| *key != KeyCode::Enter && *key != KeyCode::KpEnter);
+        self.config.window.fullscreen = !self.config.window.fullscreen;
+        self.config.save();
     }
 }
 
@@ -184,79 +358,171 @@ impl EventHandler for Engine {
             0.
         };
         self.frame_time = Some(current_time);
+        self.profiler.frame(delta_time);
+
+        // a long stall is skipped instead of simulated all at once
+        self.tick_accumulator += delta_time.min(MAX_TICKS_PER_FRAME as f64 * TICK_DELTA);
+        let ticks = (self.tick_accumulator / TICK_DELTA) as u64;
+        self.tick_accumulator -= ticks as f64 * TICK_DELTA;
+        let first_tick = self.tick;
+        self.tick += ticks;
+
+        // the reliable messages that weren't acknowledged in time go again
+        for resent in [
+            self.server.is_open().then(|| self.server.resend()),
+            self.client.is_open().then(|| self.client.resend()),
+        ] {
+            if let Some(Err(err)) = resent {
+                eprintln!("Net: {:?}", err);
+            }
+        }
 
         self.input.frame_start();
+        self.toggle_fullscreen_shortcut();
         self.tile_commands.clear();
         self.mesh_commands.clear();
+        self.debug_commands.clear();
 
-        let loaded_assets = self.assets.update();
+        let loaded_assets = self.assets.update(current_time);
+        // Changed files are parsed again and uploaded over the previous ones
+        #[cfg(all(
+            feature = "hot-reload",
+            not(any(target_arch = "wasm32", target_os = "android"))
+        ))]
+        let loaded_assets = [loaded_assets, self.assets.reload_changed()].concat();
+        let any_loaded = !loaded_assets.is_empty();
 
         // Everything that is loaded from disk is immediately loaded to gpu or audio thread
         for id in loaded_assets {
-            if let Some(image) = self.assets.images.get(&id) {
-                if let Some(path) = self.assets.get_path(&id) {
-                    let filter = if path.as_str() == "littlefont.png" {
-                        FilterMode::Nearest
-                    } else {
-                        FilterMode::Linear
-                    };
-                    self.renderer
-                        .check_load_texture(&mut self.ctx, image, &id, filter);
-                }
+            if self.assets.images.contains_key(&id) {
+                self.load_texture(&id);
             }
             if let Some(mesh) = self.assets.meshes.get(&id) {
-                self.renderer.check_load_mesh(&mut self.ctx, mesh, &id);
+                self.renderer.reload_mesh(&mut self.ctx, mesh, &id);
             }
             if let Some(audio_pcm) = self.assets.audio_pcm.get(&id) {
-                let sound = Sound::load(&self.audio_ctx, &audio_pcm.samples);
-                self.sounds.insert(id.clone(), sound);
+                self.audio.load(&id, audio_pcm);
+            }
+            if let Some(music) = self.assets.music.get(&id) {
+                self.audio.load_music(&id, music);
+            }
+        }
+        // A material library or image can complete the meshes loaded before
+        if any_loaded {
+            let mesh_ids: Vec<AssetId> = self.assets.meshes.keys().cloned().collect();
+            for mesh_id in mesh_ids {
+                let images = self.assets.submesh_images(&mesh_id);
+                self.renderer.set_submesh_images(&mesh_id, images);
             }
         }
 
         let mut engine_context = EngineContext {
             ctx: &mut self.ctx,
-            resolution: &mut self.resolution,
+            resolution: &self.resolution,
+            safe_area: &self.safe_area,
+            ui_scale: ui::screen_ui_scale(self.resolution),
             renderer: &mut self.renderer,
             tile_commands: &mut self.tile_commands,
             mesh_commands: &mut self.mesh_commands,
+            debug_commands: &mut self.debug_commands,
             assets: &mut self.assets,
             input: &mut self.input,
-            sounds: &mut self.sounds,
+            ui_memory: &mut self.ui_memory,
+            audio: &mut self.audio,
             current_time,
             delta_time,
-            audio_ctx: &self.audio_ctx,
+            tick: first_tick,
+            alpha: (self.tick_accumulator / TICK_DELTA) as f32,
             client: &mut self.client,
             server: &mut self.server,
+            config: &mut self.config,
+            profiler: &self.profiler,
         };
 
+        // the simulation advances by whole ticks, then the frame is drawn
+        for _ in 0..ticks {
+            engine_context.tick += 1;
+            self.gamestate.tick(&mut engine_context);
+        }
         self.gamestate.update(&mut engine_context);
 
+        self.update_gpu_assets();
+
+        if self.config != self.applied_config {
+            self.apply_config();
+        }
+
         self.input.frame_end_reset();
+        self.profiler.update_time = miniquad::date::now() - current_time;
+    }
+
+    fn window_minimized_event(&mut self) {
+        self.gamestate.suspend(miniquad::date::now(), self.tick);
+    }
+
+    fn quit_requested_event(&mut self) {
+        // the back button of android pauses the board instead of closing the game
+        let paused = self.gamestate.suspend(miniquad::date::now(), self.tick);
+        if paused && cfg!(target_os = "android") {
+            window::cancel_quit();
+        }
     }
 
     fn resize_event(&mut self, width: f32, height: f32) {
+        // the other platforms give the whole screen
+        #[cfg(not(any(target_arch = "wasm32", target_os = "android", target_os = "ios")))]
+        if !self.window.fullscreen && (width < MIN_WINDOW_SIZE.x || height < MIN_WINDOW_SIZE.y) {
+            let size = Vec2::new(width, height).max(MIN_WINDOW_SIZE).as_uvec2();
+            window::set_window_size(size.x, size.y);
+        }
         self.resolution = Vec2::new(width, height);
+        self.safe_area = SafeArea::query();
     }
 
     fn mouse_motion_event(&mut self, x: f32, y: f32) {
         self.input.mouse_position = Vec2::new(x, y);
     }
 
-    fn mouse_button_down_event(&mut self, button: MouseButton, _x: f32, _y: f32) {
+    fn mouse_button_down_event(&mut self, button: MouseButton, x: f32, y: f32) {
+        let position = Vec2::new(x, y);
         match button {
-            MouseButton::Left => self.input.mouse_pressed.0 = true,
-            MouseButton::Middle => self.input.mouse_pressed.1 = true,
-            MouseButton::Right => self.input.mouse_pressed.2 = true,
+            MouseButton::Left => {
+                self.input.mouse_pressed.0 = true;
+                self.input.drag_origin = Some(position);
+                self.input.mouse_press_position[0] = position;
+            }
+            MouseButton::Middle => {
+                self.input.mouse_pressed.1 = true;
+                self.input.mouse_press_position[1] = position;
+            }
+            MouseButton::Right => {
+                self.input.mouse_pressed.2 = true;
+                self.input.mouse_press_position[2] = position;
+            }
             MouseButton::Unknown => {}
         }
         self.input.mouse_just_pressed = self.input.mouse_pressed;
     }
 
-    fn mouse_button_up_event(&mut self, button: MouseButton, _x: f32, _y: f32) {
+    fn mouse_button_up_event(&mut self, button: MouseButton, x: f32, y: f32) {
+        let threshold = CLICK_DISTANCE * window::dpi_scale();
+        let clicked = |index: usize| {
+            self.input.mouse_press_position[index].distance(Vec2::new(x, y)) <= threshold
+        };
         match button {
-            MouseButton::Left => self.input.mouse_pressed.0 = false,
-            MouseButton::Middle => self.input.mouse_pressed.1 = false,
-            MouseButton::Right => self.input.mouse_pressed.2 = false,
+            MouseButton::Left => {
+                self.input.mouse_pressed.0 = false;
+                self.input.mouse_just_clicked.0 = clicked(0);
+                self.input.drag_origin = None;
+            }
+            MouseButton::Middle => {
+                self.input.mouse_pressed.1 = false;
+                self.input.mouse_just_clicked.1 = clicked(1);
+            }
+            MouseButton::Right => {
+                self.input.mouse_pressed.2 = false;
+                self.input.mouse_just_clicked.2 = clicked(2);
+            }
             MouseButton::Unknown => {}
         }
     }
@@ -273,33 +539,59 @@ impl EventHandler for Engine {
         if !self.input.key_just_pressed.contains(&keycode) {
             self.input.key_just_pressed.push(keycode);
         }
+        self.input.key_typed.push(keycode);
     }
 
     fn key_up_event(&mut self, keycode: KeyCode, _keymods: KeyMods) {
         self.input.key_pressed.retain(|k| k != &keycode);
     }
 
-    fn touch_event(&mut self, phase: TouchPhase, _id: u64, x: f32, y: f32) {
-        if phase == TouchPhase::Started {
-            self.input.just_touched.push(Vec2::new(x, y));
+    fn char_event(&mut self, character: char, _keymods: KeyMods, _repeat: bool) {
+        self.input.chars_typed.push(character);
+    }
+
+    fn touch_event(&mut self, phase: TouchPhase, id: u64, x: f32, y: f32) {
+        let position = Vec2::new(x, y);
+        let touches = &mut self.input.touches;
+        match phase {
+            TouchPhase::Started => {
+                self.input.just_touched.push(position);
+                touches.push((id, position));
+                if touches.len() == 1 {
+                    self.input.drag_origin = Some(position);
+                }
+            }
+            TouchPhase::Moved => {
+                if let Some(touch) = touches.iter_mut().find(|(touch_id, _)| *touch_id == id) {
+                    touch.1 = position;
+                }
+            }
+            TouchPhase::Ended | TouchPhase::Cancelled => {
+                touches.retain(|(touch_id, _)| *touch_id != id);
+                if touches.is_empty() && !self.input.mouse_pressed.0 {
+                    self.input.drag_origin = None;
+                }
+            }
         }
+        let touches = self.input.touches.len();
+        self.input
+            .gestures
+            .touch_event(phase, id, position, touches);
     }
 
     fn draw(&mut self) {
-        self.ctx.begin_default_pass(PassAction::Clear {
-            color: Some((0.0, 0.0, 0.0, 1.)),
-            depth: Some(1.),
-            stencil: None,
-        });
-
+        let start_time = miniquad::date::now();
+        self.renderer
+            .resize_targets(&mut self.ctx, self.config.sample_count, self.resolution);
         self.renderer.draw(
             &mut self.ctx,
             &self.tile_commands,
             &self.mesh_commands,
+            &self.debug_commands,
             self.resolution,
         );
 
-        self.ctx.end_render_pass();
         self.ctx.commit_frame();
+        self.profiler.draw_time = miniquad::date::now() - start_time;
     }
 }
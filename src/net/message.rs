@@ -0,0 +1,23 @@
+/// Largest packet sent, under the usual mtu so that it's never split
+pub const MAX_PACKET_SIZE: usize = 1200;
+
+/// What a game sends to the other side, the sockets carry it encoded. The encoded message
+/// is at most `MAX_PACKET_SIZE` bytes, a larger one is `ProtocolError::TooLarge`
+pub trait NetMessage: Sized {
+    fn encode(&self) -> Result<Vec<u8>, ProtocolError>;
+    fn decode(bytes: &[u8]) -> Result<Self, ProtocolError>;
+}
+
+/// Why a packet can't be read, or a message can't be sent
+#[derive(Clone, Debug, PartialEq)]
+pub enum ProtocolError {
+    /// The packet ended before the message
+    Truncated,
+    /// Bytes left after the message
+    Trailing,
+    Version(u8),
+    Tag(u8),
+    Utf8,
+    /// The encoded message is over `MAX_PACKET_SIZE`
+    TooLarge(usize),
+}
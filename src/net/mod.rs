@@ -0,0 +1,469 @@
+// udp on native. The browser can't host and its client connects over a WebSocket,
+// to a relay that passes the packets on
+
+use core::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::{io, net::UdpSocket};
+
+pub mod message;
+pub mod relay;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod relay_ws;
+pub mod reliable;
+pub mod simulation;
+#[cfg(target_arch = "wasm32")]
+pub mod web_socket;
+
+pub use message::*;
+pub use relay::*;
+pub use reliable::*;
+pub use simulation::*;
+
+#[cfg(not(target_arch = "wasm32"))]
+type ClientSocket = UdpSocket;
+#[cfg(target_arch = "wasm32")]
+type ClientSocket = web_socket::WebSocket;
+
+/// Used when an address is given without one
+pub const DEFAULT_PORT: u16 = 7777;
+
+/// Largest packet read from a socket: a message, its frame and the byte of the relay
+const MAX_DATAGRAM_SIZE: usize = MAX_PACKET_SIZE + FRAME_HEADER_SIZE + 1;
+
+/// Seconds between the requests of a room to the relay, until it answers
+const ROOM_REQUEST_INTERVAL: f64 = 0.5;
+
+pub struct NetServer {
+    socket: Option<UdpSocket>,
+    pub client_connections: Vec<NetRemoteConnection>,
+    pub port: u16,
+    pub stats: NetStats,
+    /// A bad network the packets go through, for testing
+    pub simulation: Option<NetSimulation>,
+}
+
+pub struct NetRemoteConnection {
+    pub address: SocketAddr,
+    pub last_message_time: f64,
+    pub channel: ReliableChannel,
+}
+
+pub struct NetClient {
+    socket: Option<ClientSocket>,
+    pub server_address: Option<SocketAddr>,
+    pub stats: NetStats,
+    pub channel: ReliableChannel,
+    /// The room of the relay the packets go through, None when they go to the server directly
+    pub room: Option<RelayRoom>,
+    room_request_time: f64,
+    /// Like `NetServer::simulation`
+    pub simulation: Option<NetSimulation>,
+}
+
+/// Where the client is with the relay
+#[derive(Clone, Debug, PartialEq)]
+pub enum RelayRoom {
+    /// Waiting for the relay to create one
+    Creating,
+    /// Waiting for the relay to let it in the room with the code
+    Joining(String),
+    /// In the room with the code, the packets go to the other player once there's one
+    Joined(String),
+    /// The relay has no room with the code, or it's full
+    NotFound(String),
+}
+
+/// Packets and bytes through a socket since it was created
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct NetStats {
+    pub packets_sent: u64,
+    pub packets_received: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+}
+
+impl NetStats {
+    fn sent(&mut self, amt: usize) {
+        self.packets_sent += 1;
+        self.bytes_sent += amt as u64;
+    }
+
+    fn received(&mut self, amt: usize) {
+        self.packets_received += 1;
+        self.bytes_received += amt as u64;
+    }
+}
+
+impl Default for NetServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NetServer {
+    pub fn new() -> Self {
+        Self {
+            socket: None,
+            client_connections: vec![],
+            port: 0,
+            stats: NetStats::default(),
+            simulation: None,
+        }
+    }
+
+    pub fn open(&mut self, port: u16) -> io::Result<()> {
+        self.socket = Some({
+            let socket = UdpSocket::bind((IpAddr::V4(Ipv4Addr::UNSPECIFIED), port))?;
+            socket.set_nonblocking(true)?;
+            socket
+        });
+        self.port = port;
+        Ok(())
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.socket.is_some()
+    }
+
+    pub fn receive(&mut self, buf: &mut [u8]) -> Result<(usize, SocketAddr), NetError> {
+        if let Some(ref socket) = self.socket {
+            let (amt, address) = match &mut self.simulation {
+                Some(simulation) => {
+                    simulation.receive(|buf| socket.recv_from(buf), buf, miniquad::date::now())
+                }
+                None => socket.recv_from(buf),
+            }
+            .map_err(|e| NetError::SocketError(e))?;
+            self.stats.received(amt);
+            self.connection(address).last_message_time = miniquad::date::now();
+
+            return Ok((amt, address));
+        }
+        Err(NetError::NoSocket)
+    }
+
+    pub fn send(&mut self, buf: &[u8], address: SocketAddr) -> Result<usize, NetError> {
+        if let (Some(_), Some(simulation)) = (&self.socket, &mut self.simulation) {
+            simulation.send(buf, address, miniquad::date::now());
+            return Ok(buf.len());
+        }
+        self.send_now(buf, address)
+    }
+
+    fn send_now(&mut self, buf: &[u8], address: SocketAddr) -> Result<usize, NetError> {
+        if let Some(ref socket) = self.socket {
+            let amt = socket
+                .send_to(buf, address)
+                .map_err(|e| NetError::SocketError(e))?;
+            self.stats.sent(amt);
+            return Ok(amt);
+        }
+        Err(NetError::NoSocket)
+    }
+
+    /// The connection to the address, new if nothing was sent to or received from it yet
+    fn connection(&mut self, address: SocketAddr) -> &mut NetRemoteConnection {
+        let index = match self
+            .client_connections
+            .iter()
+            .position(|conn| conn.address == address)
+        {
+            Some(index) => index,
+            None => {
+                self.client_connections.push(NetRemoteConnection {
+                    address,
+                    last_message_time: miniquad::date::now(),
+                    channel: ReliableChannel::default(),
+                });
+                self.client_connections.len() - 1
+            }
+        };
+        &mut self.client_connections[index]
+    }
+
+    pub fn send_message(
+        &mut self,
+        message: &impl NetMessage,
+        delivery: Delivery,
+        address: SocketAddr,
+    ) -> Result<usize, NetError> {
+        let bytes = message.encode().map_err(NetError::Protocol)?;
+        let packet = self
+            .connection(address)
+            .channel
+            .wrap(&bytes, delivery, miniquad::date::now());
+        self.send(&packet, address)
+    }
+
+    /// The next message that arrived, the reliable ones in order.
+    /// A malformed packet is an error but doesn't stop the ones after it
+    pub fn receive_message<M: NetMessage>(&mut self) -> Result<(M, SocketAddr), NetError> {
+        loop {
+            let ready = self.client_connections.iter_mut().find_map(|conn| {
+                let bytes = conn.channel.pop()?;
+                Some((bytes, conn.address))
+            });
+            if let Some((bytes, address)) = ready {
+                let message = M::decode(&bytes).map_err(NetError::Protocol)?;
+                return Ok((message, address));
+            }
+            let mut buf = [0; MAX_DATAGRAM_SIZE];
+            let (amt, address) = self.receive(&mut buf)?;
+            let ack = self
+                .connection(address)
+                .channel
+                .unwrap(&buf[..amt])
+                .map_err(NetError::Protocol)?;
+            if let Some(ack) = ack {
+                self.send(&ack, address)?;
+            }
+        }
+    }
+
+    /// Sends again the reliable messages that weren't acknowledged in time,
+    /// and the ones the simulation held long enough
+    pub fn resend(&mut self) -> Result<(), NetError> {
+        let now = miniquad::date::now();
+        let due = self
+            .simulation
+            .as_mut()
+            .map(|simulation| simulation.due(now));
+        for (packet, address) in due.unwrap_or_default() {
+            self.send_now(&packet, address)?;
+        }
+        let resends: Vec<(Vec<u8>, SocketAddr)> = self
+            .client_connections
+            .iter_mut()
+            .flat_map(|conn| {
+                let address = conn.address;
+                conn.channel
+                    .resend(now)
+                    .into_iter()
+                    .map(move |packet| (packet, address))
+            })
+            .collect();
+        for (packet, address) in resends {
+            self.send(&packet, address)?;
+        }
+        Ok(())
+    }
+
+    pub fn close(&mut self) {
+        self.client_connections.clear();
+        self.socket = None;
+    }
+}
+
+impl Default for NetClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NetClient {
+    pub fn new() -> Self {
+        Self {
+            socket: None,
+            server_address: None,
+            stats: NetStats::default(),
+            channel: ReliableChannel::default(),
+            room: None,
+            room_request_time: f64::MIN,
+            simulation: None,
+        }
+    }
+
+    pub fn connect(&mut self, server_address: SocketAddr) -> io::Result<()> {
+        #[cfg(not(target_arch = "wasm32"))]
+        let socket = {
+            let socket = UdpSocket::bind("0.0.0.0:0")?;
+            socket.set_nonblocking(true)?;
+            socket
+        };
+        #[cfg(target_arch = "wasm32")]
+        let socket = web_socket::WebSocket::connect(server_address)?;
+        self.socket = Some(socket);
+        self.server_address = Some(server_address);
+        self.channel = ReliableChannel::default();
+        self.room = None;
+        Ok(())
+    }
+
+    /// Connects to a relay and creates a room, or joins the one with the code
+    pub fn connect_relay(
+        &mut self,
+        relay_address: SocketAddr,
+        code: Option<&str>,
+    ) -> io::Result<()> {
+        self.connect(relay_address)?;
+        self.room = Some(match code {
+            Some(code) => RelayRoom::Joining(code.trim().to_uppercase()),
+            None => RelayRoom::Creating,
+        });
+        self.room_request_time = f64::MIN;
+        Ok(())
+    }
+
+    /// Asks the relay for the room again, the request or its answer may be lost
+    fn request_room(&mut self) -> Result<(), NetError> {
+        let now = miniquad::date::now();
+        if now - self.room_request_time < ROOM_REQUEST_INTERVAL {
+            return Ok(());
+        }
+        let request = match &self.room {
+            Some(RelayRoom::Creating) => vec![RELAY_CREATE],
+            Some(RelayRoom::Joining(code)) => [&[RELAY_JOIN][..], code.as_bytes()].concat(),
+            _ => return Ok(()),
+        };
+        self.room_request_time = now;
+        self.send_raw(&request)?;
+        Ok(())
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.socket.is_some()
+    }
+
+    fn receive_raw(&mut self, buf: &mut [u8]) -> Result<(usize, SocketAddr), NetError> {
+        if let Some(ref socket) = self.socket {
+            let (amt, src) = match &mut self.simulation {
+                Some(simulation) => {
+                    simulation.receive(|buf| socket.recv_from(buf), buf, miniquad::date::now())
+                }
+                None => socket.recv_from(buf),
+            }
+            .map_err(|e| NetError::SocketError(e))?;
+            self.stats.received(amt);
+            return Ok((amt, src));
+        }
+        Err(NetError::NoSocket)
+    }
+
+    /// Through a relay, the packets of the other player and the answers of the relay
+    /// come from it: the answers are read here and the packets unwrapped
+    pub fn receive(&mut self, buf: &mut [u8]) -> Result<(usize, SocketAddr), NetError> {
+        loop {
+            let (amt, src) = self.receive_raw(buf)?;
+            let Some(room) = &self.room else {
+                return Ok((amt, src));
+            };
+            if Some(src) != self.server_address || amt == 0 {
+                continue;
+            }
+            let code = String::from_utf8_lossy(&buf[1..amt]).to_string();
+            match buf[0] {
+                RELAY_DATA => {
+                    buf.copy_within(1..amt, 0);
+                    return Ok((amt - 1, src));
+                }
+                RELAY_ROOM | RELAY_JOINED => self.room = Some(RelayRoom::Joined(code)),
+                RELAY_NO_ROOM => {
+                    if let RelayRoom::Joining(code) = room {
+                        self.room = Some(RelayRoom::NotFound(code.clone()));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Wrapped for the relay when there's one
+    pub fn send(&mut self, buf: &[u8]) -> Result<usize, NetError> {
+        match self.room {
+            Some(_) => self.send_raw(&[&[RELAY_DATA][..], buf].concat()),
+            None => self.send_raw(buf),
+        }
+    }
+
+    fn send_raw(&mut self, buf: &[u8]) -> Result<usize, NetError> {
+        if let (Some(_), Some(address), Some(simulation)) =
+            (&self.socket, self.server_address, &mut self.simulation)
+        {
+            simulation.send(buf, address, miniquad::date::now());
+            return Ok(buf.len());
+        }
+        self.send_now(buf)
+    }
+
+    fn send_now(&mut self, buf: &[u8]) -> Result<usize, NetError> {
+        if let (Some(ref socket), Some(ref address)) = (&self.socket, &self.server_address) {
+            let amt = socket
+                .send_to(buf, address)
+                .map_err(|e| NetError::SocketError(e))?;
+            self.stats.sent(amt);
+            return Ok(amt);
+        }
+        Err(NetError::NoSocket)
+    }
+
+    pub fn send_message(
+        &mut self,
+        message: &impl NetMessage,
+        delivery: Delivery,
+    ) -> Result<usize, NetError> {
+        let bytes = message.encode().map_err(NetError::Protocol)?;
+        let packet = self.channel.wrap(&bytes, delivery, miniquad::date::now());
+        self.send(&packet)
+    }
+
+    /// Like `NetServer::receive_message`, from the server only
+    pub fn receive_message<M: NetMessage>(&mut self) -> Result<(M, SocketAddr), NetError> {
+        loop {
+            if let (Some(bytes), Some(address)) = (self.channel.pop(), self.server_address) {
+                let message = M::decode(&bytes).map_err(NetError::Protocol)?;
+                return Ok((message, address));
+            }
+            let mut buf = [0; MAX_DATAGRAM_SIZE];
+            let (amt, address) = self.receive(&mut buf)?;
+            if Some(address) != self.server_address {
+                continue;
+            }
+            if let Some(ack) = self
+                .channel
+                .unwrap(&buf[..amt])
+                .map_err(NetError::Protocol)?
+            {
+                self.send(&ack)?;
+            }
+        }
+    }
+
+    /// Like `NetServer::resend`, and asks the relay for the room until it answers
+    pub fn resend(&mut self) -> Result<(), NetError> {
+        let now = miniquad::date::now();
+        let due = self
+            .simulation
+            .as_mut()
+            .map(|simulation| simulation.due(now));
+        for (packet, _) in due.unwrap_or_default() {
+            self.send_now(&packet)?;
+        }
+        self.request_room()?;
+        for packet in self.channel.resend(miniquad::date::now()) {
+            self.send(&packet)?;
+        }
+        Ok(())
+    }
+
+    pub fn close(&mut self) {
+        self.socket = None;
+        self.room = None;
+    }
+}
+
+/// An address like "<ip>[:port]", on `default_port` if it has none
+pub fn parse_address(address: &str, default_port: u16) -> Result<SocketAddr, String> {
+    match address.parse::<SocketAddr>() {
+        Ok(address) => Ok(address),
+        Err(_) => Ok(SocketAddr::new(
+            address.parse::<IpAddr>().map_err(|e| format!("{}", e))?,
+            default_port,
+        )),
+    }
+}
+
+#[derive(Debug)]
+pub enum NetError {
+    NoSocket,
+    SocketError(io::Error),
+    Protocol(ProtocolError),
+}
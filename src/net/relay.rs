@@ -0,0 +1,151 @@
+// The rooms of the relay and the packets to and from it, for the players that can't reach
+// each other directly. Only std, the relay binary (src/bin/relay.rs) includes it too
+
+use std::{collections::HashMap, net::SocketAddr};
+
+/// Used when the address of the relay is given without one
+pub const RELAY_PORT: u16 = 7778;
+
+pub const ROOM_CODE_LEN: usize = 4;
+
+/// Seconds without packets before a room is closed
+pub const ROOM_TIMEOUT: f64 = 60.;
+
+// the first byte of the packets to and from the relay
+/// Asks for a new room, answered with `RELAY_ROOM`
+pub const RELAY_CREATE: u8 = 0xf0;
+/// The code of the room created, then its characters
+pub const RELAY_ROOM: u8 = 0xf1;
+/// Asks to join a room, then the characters of its code
+pub const RELAY_JOIN: u8 = 0xf2;
+/// The room was joined, then the characters of its code
+pub const RELAY_JOINED: u8 = 0xf3;
+/// There's no room with the code or someone joined it already
+pub const RELAY_NO_ROOM: u8 = 0xf4;
+/// Passed on to the other player of the room, then the packet
+pub const RELAY_DATA: u8 = 0xf5;
+
+// Crockford's base32 like the seed codes, it skips I, L, O and U
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+struct Room {
+    host: SocketAddr,
+    guest: Option<SocketAddr>,
+    last_time: f64,
+}
+
+/// Rooms by their code, each pairs the player that created it with the first that joins.
+/// Times are in seconds, from any start
+pub struct Relay {
+    rooms: HashMap<String, Room>,
+    /// Of the generator of the codes
+    state: u64,
+}
+
+impl Relay {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rooms: HashMap::new(),
+            state: seed | 1,
+        }
+    }
+
+    /// Reads a packet from `from`, returns the packets to send and where to
+    pub fn handle(
+        &mut self,
+        packet: &[u8],
+        from: SocketAddr,
+        current_time: f64,
+    ) -> Vec<(Vec<u8>, SocketAddr)> {
+        let Some((&kind, rest)) = packet.split_first() else {
+            return vec![];
+        };
+        match kind {
+            RELAY_CREATE => {
+                // the answer may have been lost, the room is the same
+                let code = match self.room_of(from) {
+                    Some(code) => code,
+                    None => {
+                        let code = self.new_code();
+                        self.rooms.insert(
+                            code.clone(),
+                            Room {
+                                host: from,
+                                guest: None,
+                                last_time: current_time,
+                            },
+                        );
+                        code
+                    }
+                };
+                vec![([&[RELAY_ROOM][..], code.as_bytes()].concat(), from)]
+            }
+            RELAY_JOIN => {
+                let code = String::from_utf8_lossy(rest).to_uppercase();
+                match self.rooms.get_mut(&code) {
+                    Some(room) if room.host != from && room.guest.unwrap_or(from) == from => {
+                        room.guest = Some(from);
+                        room.last_time = current_time;
+                        vec![([&[RELAY_JOINED][..], code.as_bytes()].concat(), from)]
+                    }
+                    _ => vec![(vec![RELAY_NO_ROOM], from)],
+                }
+            }
+            RELAY_DATA => {
+                let Some(code) = self.room_of(from) else {
+                    return vec![];
+                };
+                let room = self.rooms.get_mut(&code).unwrap();
+                room.last_time = current_time;
+                let to = if room.host == from {
+                    room.guest
+                } else {
+                    Some(room.host)
+                };
+                to.map(|to| (packet.to_vec(), to)).into_iter().collect()
+            }
+            _ => vec![],
+        }
+    }
+
+    /// Closes the rooms nothing went through for a while, returns their codes
+    pub fn expire(&mut self, current_time: f64) -> Vec<String> {
+        let expired: Vec<String> = self
+            .rooms
+            .iter()
+            .filter(|(_, room)| current_time - room.last_time > ROOM_TIMEOUT)
+            .map(|(code, _)| code.clone())
+            .collect();
+        for code in expired.iter() {
+            self.rooms.remove(code);
+        }
+        expired
+    }
+
+    pub fn room_count(&self) -> usize {
+        self.rooms.len()
+    }
+
+    /// The code of the room the address is in
+    fn room_of(&self, address: SocketAddr) -> Option<String> {
+        self.rooms
+            .iter()
+            .find(|(_, room)| room.host == address || room.guest == Some(address))
+            .map(|(code, _)| code.clone())
+    }
+
+    /// A code no room has, from a xorshift generator
+    fn new_code(&mut self) -> String {
+        loop {
+            self.state ^= self.state << 13;
+            self.state ^= self.state >> 7;
+            self.state ^= self.state << 17;
+            let code: String = (0..ROOM_CODE_LEN)
+                .map(|i| ALPHABET[(self.state >> (i * 5) & 31) as usize] as char)
+                .collect();
+            if !self.rooms.contains_key(&code) {
+                return code;
+            }
+        }
+    }
+}
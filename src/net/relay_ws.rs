@@ -0,0 +1,432 @@
+// The WebSocket side of the relay, for the web build that has no udp. Each binary message
+// is a packet, it goes into the same rooms as the udp ones. Only std, the relay binary
+// (src/bin/relay.rs) includes it too.
+// Specification: https://www.rfc-editor.org/rfc/rfc6455, the server side the browsers need
+
+use std::{
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpStream},
+};
+
+/// Seconds a connection has to ask for the upgrade
+pub const HANDSHAKE_TIMEOUT: f64 = 10.;
+
+/// Longest upgrade request and message read, the packets of the game are smaller
+const MAX_INCOMING: usize = 8192;
+
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_BINARY: u8 = 0x2;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xa;
+
+/// A browser connected to the relay, first over http then upgraded to a WebSocket
+pub struct WsConnection {
+    stream: TcpStream,
+    /// The player in the rooms of the relay
+    pub address: SocketAddr,
+    /// Of the accept, in the seconds of the relay
+    since: f64,
+    upgraded: bool,
+    closed: bool,
+    /// Read and not parsed yet
+    incoming: Vec<u8>,
+    /// Not written yet, the socket was full
+    outgoing: Vec<u8>,
+}
+
+impl WsConnection {
+    pub fn new(stream: TcpStream, address: SocketAddr, current_time: f64) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        Ok(Self {
+            stream,
+            address,
+            since: current_time,
+            upgraded: false,
+            closed: false,
+            incoming: vec![],
+            outgoing: vec![],
+        })
+    }
+
+    /// Closed by either side, or it didn't upgrade in time
+    pub fn is_closed(&self, current_time: f64) -> bool {
+        self.closed || (!self.upgraded && current_time - self.since > HANDSHAKE_TIMEOUT)
+    }
+
+    /// Reads what arrived, returns the packets. Answers the upgrade, the pings and the close
+    pub fn receive(&mut self) -> Vec<Vec<u8>> {
+        let mut buf = [0; 4096];
+        while !self.closed {
+            match self.stream.read(&mut buf) {
+                Ok(0) => self.closed = true,
+                Ok(amt) => self.incoming.extend_from_slice(&buf[..amt]),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(_) => self.closed = true,
+            }
+        }
+        if !self.upgraded {
+            self.upgrade();
+        }
+        let mut packets = vec![];
+        while self.upgraded && !self.closed {
+            match parse_frame(&self.incoming) {
+                Ok(Some((frame, len))) => {
+                    self.incoming.drain(..len);
+                    match frame.opcode {
+                        OPCODE_BINARY => packets.push(frame.payload),
+                        OPCODE_PING => self.queue(&encode_frame(OPCODE_PONG, &frame.payload)),
+                        OPCODE_CLOSE => {
+                            self.queue(&encode_frame(OPCODE_CLOSE, &frame.payload));
+                            self.flush();
+                            self.closed = true;
+                        }
+                        // text and pongs
+                        _ => {}
+                    }
+                }
+                Ok(None) => break,
+                Err(_) => self.closed = true,
+            }
+        }
+        self.flush();
+        packets
+    }
+
+    /// A binary message with the packet
+    pub fn send(&mut self, packet: &[u8]) -> io::Result<()> {
+        if self.closed || !self.upgraded {
+            return Err(io::Error::from(io::ErrorKind::NotConnected));
+        }
+        self.queue(&encode_frame(OPCODE_BINARY, packet));
+        self.flush();
+        Ok(())
+    }
+
+    /// Answers the http request once it's all there, a bad one is refused and closed
+    fn upgrade(&mut self) {
+        let Some(end) = self.incoming.windows(4).position(|w| w == b"\r\n\r\n") else {
+            self.closed |= self.incoming.len() > MAX_INCOMING;
+            return;
+        };
+        let request = String::from_utf8_lossy(&self.incoming[..end]).to_string();
+        self.incoming.drain(..end + 4);
+        match handshake_response(&request) {
+            Ok(response) => {
+                self.queue(response.as_bytes());
+                self.upgraded = true;
+            }
+            Err(response) => {
+                self.queue(response.as_bytes());
+                self.flush();
+                self.closed = true;
+            }
+        }
+    }
+
+    fn queue(&mut self, bytes: &[u8]) {
+        self.outgoing.extend_from_slice(bytes);
+    }
+
+    /// Writes what the socket takes, the rest waits for the next call
+    fn flush(&mut self) {
+        while !self.outgoing.is_empty() {
+            match self.stream.write(&self.outgoing) {
+                Ok(0) => self.closed = true,
+                Ok(amt) => {
+                    self.outgoing.drain(..amt);
+                    continue;
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {}
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) => self.closed = true,
+            }
+            break;
+        }
+    }
+}
+
+/// The 101 response to a WebSocket upgrade request, or the 400 one if it isn't
+fn handshake_response(request: &str) -> Result<String, String> {
+    let bad_request = || "HTTP/1.1 400 Bad Request\r\nConnection: close\r\n\r\n".to_string();
+    let mut lines = request.lines();
+    if !lines.next().is_some_and(|line| line.starts_with("GET ")) {
+        return Err(bad_request());
+    }
+    let key = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("Sec-WebSocket-Key"))
+        .map(|(_, value)| value.trim())
+        .ok_or_else(bad_request)?;
+    Ok(format!(
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        accept_key(key)
+    ))
+}
+
+/// Proves to the browser that the server read its key
+fn accept_key(key: &str) -> String {
+    base64(&sha1(format!("{key}{ACCEPT_GUID}").as_bytes()))
+}
+
+#[derive(Debug, PartialEq)]
+struct Frame {
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+/// The first frame of `bytes` and its length, None until it all arrived.
+/// The frames of the browsers are masked, the messages split in fragments are refused
+fn parse_frame(bytes: &[u8]) -> Result<Option<(Frame, usize)>, String> {
+    let [first, second, ..] = bytes[..] else {
+        return Ok(None);
+    };
+    let fin = first & 0x80 != 0;
+    let opcode = first & 0x0f;
+    if !fin || opcode == OPCODE_CONTINUATION {
+        return Err("WebSocket: fragmented messages are not supported".to_string());
+    }
+    if second & 0x80 == 0 {
+        return Err("WebSocket: unmasked frame from a client".to_string());
+    }
+    let (len, mut at) = match second & 0x7f {
+        126 => match bytes.get(2..4) {
+            Some(len) => (u16::from_be_bytes(len.try_into().unwrap()) as usize, 4),
+            None => return Ok(None),
+        },
+        127 => match bytes.get(2..10) {
+            Some(len) => (u64::from_be_bytes(len.try_into().unwrap()) as usize, 10),
+            None => return Ok(None),
+        },
+        len => (len as usize, 2),
+    };
+    if len > MAX_INCOMING {
+        return Err(format!("WebSocket: message of {len} bytes"));
+    }
+    let Some(mask) = bytes.get(at..at + 4) else {
+        return Ok(None);
+    };
+    at += 4;
+    let Some(payload) = bytes.get(at..at + len) else {
+        return Ok(None);
+    };
+    let payload = payload
+        .iter()
+        .enumerate()
+        .map(|(i, byte)| byte ^ mask[i % 4])
+        .collect();
+    Ok(Some((Frame { opcode, payload }, at + len)))
+}
+
+/// A whole message from the server, unmasked
+fn encode_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        len @ 0..=125 => frame.push(len as u8),
+        len @ 126..=0xffff => {
+            frame.push(126);
+            frame.extend((len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend((len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    frame
+}
+
+// Specification: https://www.rfc-editor.org/rfc/rfc3174
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend((data.len() as u64 * 8).to_be_bytes());
+    for block in message.chunks_exact(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes(word.try_into().unwrap());
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5a827999),
+                20..=39 => (b ^ c ^ d, 0x6ed9eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+                _ => (b ^ c ^ d, 0xca62c1d6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            (e, d, c, b, a) = (d, c, b.rotate_left(30), a, temp);
+        }
+        for (h, v) in h.iter_mut().zip([a, b, c, d, e]) {
+            *h = h.wrapping_add(v);
+        }
+    }
+    let mut digest = [0; 20];
+    for (bytes, h) in digest.chunks_exact_mut(4).zip(h) {
+        bytes.copy_from_slice(&h.to_be_bytes());
+    }
+    digest
+}
+
+/// Standard alphabet, padded
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut text = String::new();
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let bits = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            match i <= chunk.len() {
+                true => text.push(ALPHABET[(bits >> (18 - i * 6) & 63) as usize] as char),
+                false => text.push('='),
+            }
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, TcpListener};
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    /// A frame like the browsers send
+    fn masked(opcode: u8, payload: &[u8], mask: [u8; 4]) -> Vec<u8> {
+        let mut frame = encode_frame(opcode, payload);
+        let at = frame.len() - payload.len();
+        frame[1] |= 0x80;
+        for (i, byte) in frame[at..].iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+        frame.splice(at..at, mask);
+        frame
+    }
+
+    #[test]
+    fn sha1_and_base64() {
+        assert_eq!(
+            hex(&sha1(b"abc")),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        assert_eq!(
+            hex(&sha1(&[b'a'; 1000])),
+            "291e9a6c66994949b57ba5e650361e98fc36b1ba"
+        );
+        let encoded = ["", "Zg==", "Zm8=", "Zm9v", "Zm9vYg=="];
+        for (len, encoded) in encoded.iter().enumerate() {
+            assert_eq!(base64(&b"foob"[..len]), *encoded);
+        }
+        // the example of the specification
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[test]
+    fn frames() {
+        // "Hello" from the specification
+        let hello = [
+            0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58,
+        ];
+        let frame = Frame {
+            opcode: 1,
+            payload: b"Hello".to_vec(),
+        };
+        assert_eq!(parse_frame(&hello), Ok(Some((frame, hello.len()))));
+        assert_eq!(parse_frame(&hello[..8]), Ok(None));
+
+        let long = vec![7; 300];
+        let bytes = masked(OPCODE_BINARY, &long, [1, 2, 3, 4]);
+        let (frame, len) = parse_frame(&bytes).unwrap().unwrap();
+        assert_eq!((frame.payload, len), (long, bytes.len()));
+
+        // unmasked and fragmented
+        assert!(parse_frame(&encode_frame(OPCODE_BINARY, b"x")).is_err());
+        assert!(parse_frame(&[0x02, 0x81, 0, 0, 0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn upgrade_and_packets() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let mut browser = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, address) = listener.accept().unwrap();
+        let mut connection = WsConnection::new(stream, address, 0.).unwrap();
+
+        browser
+            .write_all(
+                b"GET / HTTP/1.1\r\nHost: relay\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+                sec-websocket-key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n",
+            )
+            .unwrap();
+        // answered before the packet arrives
+        browser
+            .write_all(&masked(OPCODE_PING, b"hi", [1, 1, 1, 1]))
+            .unwrap();
+        browser
+            .write_all(&masked(OPCODE_BINARY, &[0xf0], [9, 8, 7, 6]))
+            .unwrap();
+        let mut packets = vec![];
+        for _ in 0..100 {
+            packets.extend(connection.receive());
+            if !packets.is_empty() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert_eq!(packets, [vec![0xf0]]);
+        connection.send(&[0xf1, b'A']).unwrap();
+
+        let response = "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n\r\n";
+        let mut expected = response.as_bytes().to_vec();
+        expected.extend(encode_frame(OPCODE_PONG, b"hi"));
+        expected.extend(encode_frame(OPCODE_BINARY, &[0xf1, b'A']));
+        let mut received = vec![0; expected.len()];
+        browser.read_exact(&mut received).unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&received),
+            String::from_utf8_lossy(&expected)
+        );
+        assert!(!connection.is_closed(100.));
+
+        drop(browser);
+        for _ in 0..100 {
+            connection.receive();
+            if connection.is_closed(0.) {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert!(connection.is_closed(0.));
+    }
+
+    #[test]
+    fn not_an_upgrade_is_refused() {
+        assert!(handshake_response("GET / HTTP/1.1\r\nHost: relay").is_err());
+        assert!(handshake_response("POST / HTTP/1.1\r\nSec-WebSocket-Key: a").is_err());
+    }
+}
@@ -0,0 +1,138 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use super::message::*;
+
+/// Seconds before a reliable packet that wasn't acknowledged is sent again
+const RESEND_INTERVAL: f64 = 0.2;
+
+/// Reliable packets that arrived before the ones sent ahead of them, kept at most
+const MAX_EARLY: usize = 256;
+
+/// Bytes before the message: the kind of packet and its sequence
+pub const FRAME_HEADER_SIZE: usize = 3;
+
+// the first byte of a packet
+const UNRELIABLE: u8 = 0;
+const RELIABLE: u8 = 1;
+const ACK: u8 = 2;
+
+/// How a message gets to the other side
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Delivery {
+    /// Sent once, it can be lost or arrive twice or out of order.
+    /// For frequent state, that the next message replaces
+    Unreliable,
+    /// Sent again until it's acknowledged, delivered once and in the order it was sent.
+    /// For moves and syncing the board
+    Reliable,
+}
+
+struct Unacked {
+    sequence: u16,
+    packet: Vec<u8>,
+    sent_time: f64,
+}
+
+/// Sequence numbers, acks and resends for the messages to and from a single peer.
+/// A packet is a byte for its kind, then the sequence for the reliable ones and the acks,
+/// then the message, if it's not an ack
+#[derive(Default)]
+pub struct ReliableChannel {
+    /// Sequence of the next reliable message sent
+    next_sequence: u16,
+    unacked: Vec<Unacked>,
+    /// Sequence of the next reliable message to deliver
+    expected: u16,
+    /// Reliable messages received ahead of `expected`, encoded
+    early: BTreeMap<u16, Vec<u8>>,
+    /// Encoded messages ready to be read, in order
+    inbox: VecDeque<Vec<u8>>,
+}
+
+/// `a` was sent after `b`, the sequences wrap around
+fn is_after(a: u16, b: u16) -> bool {
+    a != b && a.wrapping_sub(b) < 0x8000
+}
+
+impl ReliableChannel {
+    /// The packet of the encoded message, a reliable one is kept to be sent again until
+    /// it's acknowledged
+    pub fn wrap(&mut self, bytes: &[u8], delivery: Delivery, current_time: f64) -> Vec<u8> {
+        match delivery {
+            Delivery::Unreliable => [&[UNRELIABLE][..], bytes].concat(),
+            Delivery::Reliable => {
+                let sequence = self.next_sequence;
+                self.next_sequence = sequence.wrapping_add(1);
+                let packet = [&[RELIABLE][..], &sequence.to_le_bytes(), bytes].concat();
+                self.unacked.push(Unacked {
+                    sequence,
+                    packet: packet.clone(),
+                    sent_time: current_time,
+                });
+                packet
+            }
+        }
+    }
+
+    /// Reads a packet, the messages it lets through are queued for `pop`.
+    /// Returns the ack to send back for a reliable one, even if it was a duplicate
+    pub fn unwrap(&mut self, packet: &[u8]) -> Result<Option<Vec<u8>>, ProtocolError> {
+        let (&kind, rest) = packet.split_first().ok_or(ProtocolError::Truncated)?;
+        if kind == UNRELIABLE {
+            self.inbox.push_back(rest.to_vec());
+            return Ok(None);
+        }
+        let sequence = rest.get(..2).ok_or(ProtocolError::Truncated)?;
+        let sequence = u16::from_le_bytes([sequence[0], sequence[1]]);
+        match kind {
+            ACK => {
+                self.unacked.retain(|unacked| unacked.sequence != sequence);
+                Ok(None)
+            }
+            RELIABLE => {
+                let message = rest[2..].to_vec();
+                let ack = [&[ACK][..], &sequence.to_le_bytes()].concat();
+                // older ones were delivered already, their ack was lost
+                if sequence == self.expected {
+                    self.inbox.push_back(message);
+                    self.expected = self.expected.wrapping_add(1);
+                    while let Some(message) = self.early.remove(&self.expected) {
+                        self.inbox.push_back(message);
+                        self.expected = self.expected.wrapping_add(1);
+                    }
+                } else if is_after(sequence, self.expected) {
+                    if self.early.len() >= MAX_EARLY && !self.early.contains_key(&sequence) {
+                        // dropped unacknowledged, it's sent again once there's room
+                        return Ok(None);
+                    }
+                    self.early.insert(sequence, message);
+                }
+                Ok(Some(ack))
+            }
+            kind => Err(ProtocolError::Tag(kind)),
+        }
+    }
+
+    /// The next message received, still encoded, in the order they were sent for the
+    /// reliable ones
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        self.inbox.pop_front()
+    }
+
+    /// The reliable packets waited on for too long, to be sent again
+    pub fn resend(&mut self, current_time: f64) -> Vec<Vec<u8>> {
+        self.unacked
+            .iter_mut()
+            .filter(|unacked| current_time - unacked.sent_time >= RESEND_INTERVAL)
+            .map(|unacked| {
+                unacked.sent_time = current_time;
+                unacked.packet.clone()
+            })
+            .collect()
+    }
+
+    /// Reliable messages not acknowledged yet
+    pub fn unacked_count(&self) -> usize {
+        self.unacked.len()
+    }
+}
@@ -0,0 +1,111 @@
+// A bad network on demand, to test the reliable messages and the races on one.
+// Set from the console with `netsim`, it's off otherwise
+
+use core::net::SocketAddr;
+use std::io;
+
+use crate::rng::RandLCG;
+
+/// How bad the network is, each way: a packet sent is held for its delay then sent,
+/// a packet received is held for its delay then read
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct NetConditions {
+    /// Seconds every packet is held
+    pub latency: f64,
+    /// Up to these seconds more at random, a packet held longer is passed by the ones after it
+    pub jitter: f64,
+    /// Fraction of the packets dropped, in 0..1
+    pub loss: f32,
+}
+
+struct Held {
+    packet: Vec<u8>,
+    address: SocketAddr,
+    /// When it goes on
+    time: f64,
+}
+
+/// Holds and drops the packets of a socket as the conditions say
+pub struct NetSimulation {
+    pub conditions: NetConditions,
+    outgoing: Vec<Held>,
+    incoming: Vec<Held>,
+    rand: RandLCG,
+}
+
+impl NetSimulation {
+    pub fn new(conditions: NetConditions) -> Self {
+        Self {
+            conditions,
+            outgoing: vec![],
+            incoming: vec![],
+            rand: RandLCG::new((miniquad::date::now() * 1000000.) as u128),
+        }
+    }
+
+    /// None if it's dropped
+    fn hold(&mut self, packet: &[u8], address: SocketAddr, current_time: f64) -> Option<Held> {
+        if self.rand.next_f32() < self.conditions.loss {
+            return None;
+        }
+        Some(Held {
+            packet: packet.to_vec(),
+            address,
+            time: current_time
+                + self.conditions.latency
+                + self.conditions.jitter * self.rand.next_f32() as f64,
+        })
+    }
+
+    /// Instead of sending it, `due` gives the packet back when it's time
+    pub fn send(&mut self, packet: &[u8], address: SocketAddr, current_time: f64) {
+        let held = self.hold(packet, address, current_time);
+        self.outgoing.extend(held);
+    }
+
+    /// The packets to send now, the ones held the least first
+    pub fn due(&mut self, current_time: f64) -> Vec<(Vec<u8>, SocketAddr)> {
+        let (mut due, held): (Vec<Held>, Vec<Held>) = std::mem::take(&mut self.outgoing)
+            .into_iter()
+            .partition(|held| held.time <= current_time);
+        self.outgoing = held;
+        due.sort_by(|a, b| a.time.total_cmp(&b.time));
+        due.into_iter()
+            .map(|held| (held.packet, held.address))
+            .collect()
+    }
+
+    /// Reads everything that arrived on the socket with `receive` and holds it,
+    /// then gives the first packet whose time came like `recv_from` would
+    pub fn receive(
+        &mut self,
+        mut receive: impl FnMut(&mut [u8]) -> io::Result<(usize, SocketAddr)>,
+        buf: &mut [u8],
+        current_time: f64,
+    ) -> io::Result<(usize, SocketAddr)> {
+        loop {
+            match receive(buf) {
+                Ok((amt, address)) => {
+                    let held = self.hold(&buf[..amt], address, current_time);
+                    self.incoming.extend(held);
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) => return Err(err),
+            }
+        }
+        let first = self
+            .incoming
+            .iter()
+            .enumerate()
+            .filter(|(_, held)| held.time <= current_time)
+            .min_by(|(_, a), (_, b)| a.time.total_cmp(&b.time))
+            .map(|(index, _)| index);
+        let Some(index) = first else {
+            return Err(io::Error::from(io::ErrorKind::WouldBlock));
+        };
+        let held = self.incoming.remove(index);
+        let amt = held.packet.len().min(buf.len());
+        buf[..amt].copy_from_slice(&held.packet[..amt]);
+        Ok((amt, held.address))
+    }
+}
@@ -0,0 +1,65 @@
+// The browser has no udp, the web build talks to a relay over a WebSocket instead.
+// The functions are the "tiling_net" plugin of resources/wasm-res/index.html
+
+use core::net::SocketAddr;
+use std::io;
+
+extern "C" {
+    fn net_ws_connect(host: *const u8, host_len: u32) -> u32;
+    /// 0 connecting, 1 open, 2 closing, 3 closed, like `WebSocket.readyState`
+    fn net_ws_state(socket: u32) -> u32;
+    /// Queued by the browser while connecting
+    fn net_ws_send(socket: u32, data: *const u8, data_len: u32);
+    /// Copies the oldest message received into `buf`, returns its length or -1 if there's none.
+    /// A message longer than `buf` is cut
+    fn net_ws_receive(socket: u32, buf: *mut u8, buf_len: u32) -> i32;
+    fn net_ws_close(socket: u32);
+}
+
+const CLOSING: u32 = 2;
+
+/// A WebSocket of the browser used like a nonblocking `UdpSocket` connected to one address,
+/// each message is a packet
+pub struct WebSocket {
+    socket: u32,
+    address: SocketAddr,
+}
+
+impl WebSocket {
+    /// Starts connecting to "ws://<address>", or "wss://<address>" when the page is
+    /// served over https. Sending before it's open is fine
+    pub fn connect(address: SocketAddr) -> io::Result<Self> {
+        let host = address.to_string();
+        let socket = unsafe { net_ws_connect(host.as_ptr(), host.len() as u32) };
+        Ok(Self { socket, address })
+    }
+
+    fn check_open(&self) -> io::Result<()> {
+        if unsafe { net_ws_state(self.socket) } >= CLOSING {
+            return Err(io::Error::from(io::ErrorKind::ConnectionReset));
+        }
+        Ok(())
+    }
+
+    pub fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        self.check_open()?;
+        let amt = unsafe { net_ws_receive(self.socket, buf.as_mut_ptr(), buf.len() as u32) };
+        if amt < 0 {
+            return Err(io::Error::from(io::ErrorKind::WouldBlock));
+        }
+        Ok((amt as usize, self.address))
+    }
+
+    /// Only to the address it's connected to
+    pub fn send_to(&self, buf: &[u8], _address: &SocketAddr) -> io::Result<usize> {
+        self.check_open()?;
+        unsafe { net_ws_send(self.socket, buf.as_ptr(), buf.len() as u32) };
+        Ok(buf.len())
+    }
+}
+
+impl Drop for WebSocket {
+    fn drop(&mut self) {
+        unsafe { net_ws_close(self.socket) }
+    }
+}
@@ -0,0 +1,200 @@
+use std::ops::Range;
+
+use crate::{ui::Rect, *};
+
+/// In front of the board, behind the ui
+const PARTICLES_Z: f32 = 0.5;
+
+/// A square that flies, falls and changes color until its lifetime ends
+#[derive(Clone, Debug)]
+pub struct Particle {
+    pub pos: Vec3,
+    /// Before the last update, it's drawn in between
+    pub last_pos: Vec3,
+    pub velocity: Vec3,
+    /// Seconds since it was emitted
+    pub age: f32,
+    pub lifetime: f32,
+    /// In world units
+    pub size: f32,
+    pub color_start: Vec4,
+    pub color_end: Vec4,
+    pub gravity: Vec3,
+    /// Part of the velocity lost each second
+    pub drag: f32,
+}
+
+impl Particle {
+    pub fn color(&self) -> Vec4 {
+        let t = (self.age / self.lifetime).clamp(0., 1.);
+        self.color_start.lerp(self.color_end, t)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum EmitterKind {
+    /// All the particles at once
+    Burst { count: usize },
+    /// Particles per second, for the duration in seconds
+    Continuous { rate: f32, duration: f32 },
+}
+
+/// Where the particles start and the ranges their values are picked from
+#[derive(Clone, Debug)]
+pub struct Emitter {
+    pub kind: EmitterKind,
+    pub pos: Vec3,
+    /// Half size of the box around `pos` the particles start in
+    pub extent: Vec3,
+    pub direction: Vec3,
+    /// Radians the velocities can turn away from `direction`
+    pub spread: f32,
+    pub speed: Range<f32>,
+    pub lifetime: Range<f32>,
+    pub size: Range<f32>,
+    /// One is picked for each particle, they fade to it multiplied by `fade`
+    pub colors: Vec<Vec4>,
+    pub fade: Vec4,
+    pub gravity: Vec3,
+    pub drag: f32,
+}
+
+impl Default for Emitter {
+    fn default() -> Self {
+        Self {
+            kind: EmitterKind::Burst { count: 10 },
+            pos: Vec3::ZERO,
+            extent: Vec3::ZERO,
+            direction: Vec3::Z,
+            spread: 0.5,
+            speed: 1.0..2.0,
+            lifetime: 0.5..1.0,
+            size: 0.05..0.1,
+            colors: vec![Vec4::ONE],
+            fade: Vec4::new(1., 1., 1., 0.),
+            gravity: Vec3::ZERO,
+            drag: 0.,
+        }
+    }
+}
+
+/// The particles alive and the emitters still emitting
+#[derive(Clone, Debug, Default)]
+pub struct Particles {
+    pub particles: Vec<Particle>,
+    /// Continuous emitters with the seconds they have left and the particles owed
+    emitters: Vec<(Emitter, f32, f32)>,
+}
+
+impl Particles {
+    /// Bursts emit right away, continuous emitters from the next update
+    pub fn emit(&mut self, emitter: Emitter, rand: &mut RandLCG) {
+        match emitter.kind {
+            EmitterKind::Burst { count } => {
+                for _ in 0..count {
+                    self.spawn(&emitter, rand);
+                }
+            }
+            EmitterKind::Continuous { duration, .. } => {
+                self.emitters.push((emitter, duration, 0.));
+            }
+        }
+    }
+
+    pub fn update(&mut self, delta_time: f32, rand: &mut RandLCG) {
+        let mut emitters = std::mem::take(&mut self.emitters);
+        for (emitter, left, owed) in emitters.iter_mut() {
+            let EmitterKind::Continuous { rate, .. } = emitter.kind else {
+                continue;
+            };
+            *owed += rate * delta_time.min(*left);
+            *left -= delta_time;
+            while *owed >= 1. {
+                self.spawn(emitter, rand);
+                *owed -= 1.;
+            }
+        }
+        emitters.retain(|(_, left, _)| *left > 0.);
+        self.emitters = emitters;
+
+        for particle in self.particles.iter_mut() {
+            particle.velocity += particle.gravity * delta_time;
+            particle.velocity *= (1. - particle.drag * delta_time).max(0.);
+            particle.last_pos = particle.pos;
+            particle.pos += particle.velocity * delta_time;
+            particle.age += delta_time;
+        }
+        self.particles
+            .retain(|particle| particle.age < particle.lifetime);
+    }
+
+    /// As squares facing the screen, behind the ui.
+    /// `alpha` is the part of the next update already elapsed
+    pub fn draw(
+        &self,
+        tile_commands: &mut RenderTileCommands,
+        camera: &Camera,
+        resolution: Vec2,
+        alpha: f32,
+    ) {
+        // world units are measured along the up of the camera
+        let up = camera.view.inverse().transform_vector3(Vec3::Y);
+        for particle in self.particles.iter() {
+            let pos = particle.last_pos.lerp(particle.pos, alpha);
+            let (Some(center), Some(top)) = (
+                camera.world_to_screen(pos, resolution),
+                camera.world_to_screen(pos + up * particle.size, resolution),
+            ) else {
+                continue;
+            };
+            let size = Vec2::splat(center.distance(top));
+            tile_commands.draw(RenderTile {
+                world_rect: Rect::new(center - size * 0.5, size),
+                z: PARTICLES_Z,
+                color: particle.color(),
+                ..Default::default()
+            });
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.particles.clear();
+        self.emitters.clear();
+    }
+
+    fn spawn(&mut self, emitter: &Emitter, rand: &mut RandLCG) {
+        let mut random = || rand.next_f32();
+        let mut between = |range: &Range<f32>| range.start + (range.end - range.start) * random();
+        let offset = Vec3::new(
+            between(&(-1.0..1.0)),
+            between(&(-1.0..1.0)),
+            between(&(-1.0..1.0)),
+        ) * emitter.extent;
+        // turned away from the direction by up to the spread, around it by any angle
+        let direction = emitter.direction.normalize_or_zero();
+        let (axis_x, axis_y) = direction.any_orthonormal_pair();
+        let tilt = between(&(0.0..emitter.spread));
+        let around = between(&(0.0..std::f32::consts::TAU));
+        let velocity_direction =
+            direction * tilt.cos() + (axis_x * around.cos() + axis_y * around.sin()) * tilt.sin();
+        let speed = between(&emitter.speed);
+        let lifetime = between(&emitter.lifetime);
+        let size = between(&emitter.size);
+        let color_start = match emitter.colors.len() {
+            0 => Vec4::ONE,
+            len => emitter.colors[(random() * len as f32) as usize % len],
+        };
+        self.particles.push(Particle {
+            pos: emitter.pos + offset,
+            last_pos: emitter.pos + offset,
+            velocity: velocity_direction * speed,
+            age: 0.,
+            lifetime,
+            size,
+            color_start,
+            color_end: color_start * emitter.fade,
+            gravity: emitter.gravity,
+            drag: emitter.drag,
+        });
+    }
+}
@@ -0,0 +1,89 @@
+use crate::*;
+
+pub const NAME_MAX_LEN: usize = 16;
+
+/// The colors a player can pick, by the key of their name in the lang files.
+/// Sent as the index, the order can't change
+pub const PROFILE_COLORS: [(&str, Vec4); 6] = [
+    ("color_red", Vec4::new(0.9, 0.3, 0.3, 1.)),
+    ("color_orange", Vec4::new(0.95, 0.6, 0.2, 1.)),
+    ("color_yellow", Vec4::new(0.95, 0.85, 0.3, 1.)),
+    ("color_green", Vec4::new(0.4, 0.8, 0.4, 1.)),
+    ("color_blue", Vec4::new(0.35, 0.6, 0.95, 1.)),
+    ("color_purple", Vec4::new(0.7, 0.45, 0.9, 1.)),
+];
+
+/// The player on this machine, shown to the opponents. Saved as `key=value` lines
+#[derive(Clone, Debug, PartialEq)]
+pub struct Profile {
+    pub name: String,
+    /// Index in `PROFILE_COLORS`
+    pub color: usize,
+    /// Versus races
+    pub wins: u32,
+    pub losses: u32,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            color: 4,
+            wins: 0,
+            losses: 0,
+        }
+    }
+}
+
+impl Profile {
+    const FILE: &'static str = "profile.txt";
+
+    /// None on the first launch, until a name is entered
+    pub fn load() -> Option<Self> {
+        let text = storage::load(Self::FILE).ok()?;
+        let mut profile = Self::default();
+        for line in text.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let value = value.trim();
+            match key.trim() {
+                "name" => profile.name = value.chars().take(NAME_MAX_LEN).collect(),
+                "color" => {
+                    if let Some(color) = PROFILE_COLORS.iter().position(|(name, _)| *name == value)
+                    {
+                        profile.color = color;
+                    }
+                }
+                "wins" => profile.wins = value.parse().unwrap_or(0),
+                "losses" => profile.losses = value.parse().unwrap_or(0),
+                _ => {}
+            }
+        }
+        (!profile.name.is_empty()).then_some(profile)
+    }
+
+    pub fn save(&self) {
+        let text = format!(
+            "name={}\ncolor={}\nwins={}\nlosses={}\n",
+            self.name, PROFILE_COLORS[self.color].0, self.wins, self.losses
+        );
+        if let Err(err) = storage::save(Self::FILE, &text) {
+            eprintln!("Profile not saved: {err}");
+        }
+    }
+
+    /// Counts a versus race
+    pub fn add_result(&mut self, won: bool) {
+        match won {
+            true => self.wins += 1,
+            false => self.losses += 1,
+        }
+        self.save();
+    }
+}
+
+/// The color of a player, the first one for an index out of the list
+pub fn profile_color(color: usize) -> Vec4 {
+    PROFILE_COLORS.get(color).unwrap_or(&PROFILE_COLORS[0]).1
+}
@@ -0,0 +1,156 @@
+use std::collections::VecDeque;
+
+use crate::{ui::*, *};
+
+/// Frames shown by the graph
+const PROFILER_FRAMES: usize = 120;
+/// Frame time at the top of the graph, in seconds
+const GRAPH_MAX_TIME: f64 = 1. / 20.;
+/// In front of the ui, the text is on top of the background
+const OVERLAY_Z: f32 = 0.04;
+
+/// Times of the last frames, measured by the engine
+#[derive(Clone, Debug, Default)]
+pub struct Profiler {
+    /// Seconds between frames, the oldest first
+    frame_times: VecDeque<f64>,
+    /// Seconds spent updating and drawing the last frame
+    pub update_time: f64,
+    pub draw_time: f64,
+}
+
+impl Profiler {
+    pub fn frame(&mut self, delta_time: f64) {
+        if delta_time <= 0. {
+            return;
+        }
+        self.frame_times.push_back(delta_time);
+        while self.frame_times.len() > PROFILER_FRAMES {
+            self.frame_times.pop_front();
+        }
+    }
+
+    pub fn average_frame_time(&self) -> f64 {
+        match self.frame_times.len() {
+            0 => 0.,
+            len => self.frame_times.iter().sum::<f64>() / len as f64,
+        }
+    }
+
+    pub fn max_frame_time(&self) -> f64 {
+        self.frame_times.iter().copied().fold(0., f64::max)
+    }
+
+    /// In the top left corner: the frame times as a graph, what the renderer drew,
+    /// the memory of the assets and the packets of the net
+    pub fn draw_overlay(&self, engine: &mut EngineContext, ui_text: &UiText) {
+        let render = engine.renderer.stats();
+        let (server, client) = (engine.server.stats, engine.client.stats);
+        let average = self.average_frame_time();
+        let lines = [
+            format!(
+                "{:.0} fps {:.1} ms, max {:.1} ms",
+                if average > 0. { 1. / average } else { 0. },
+                average * 1000.,
+                self.max_frame_time() * 1000.
+            ),
+            format!(
+                "update {:.2} ms, draw {:.2} ms",
+                self.update_time * 1000.,
+                self.draw_time * 1000.
+            ),
+            format!(
+                "{} draw calls, {} tiles, {} meshes",
+                render.draw_calls, render.tiles, render.meshes
+            ),
+            format!(
+                "assets {}, textures {}",
+                format_bytes(engine.assets.memory()),
+                format_bytes(engine.renderer.texture_memory())
+            ),
+            format!(
+                "net sent {} ({}), received {} ({})",
+                server.packets_sent + client.packets_sent,
+                format_bytes((server.bytes_sent + client.bytes_sent) as usize),
+                server.packets_received + client.packets_received,
+                format_bytes((server.bytes_received + client.bytes_received) as usize),
+            ),
+        ];
+
+        let ui_text = UiText {
+            layout: UiTextLayout::Left,
+            vertical_layout: UiTextVerticalLayout::Center,
+            overflow: UiTextOverflow::Shrink,
+            char_scale: ui_text.char_scale * DEBUG_TEXT_SCALE,
+            color: Vec4::ONE,
+            ..ui_text.clone()
+        };
+        let line = line_height(&ui_text);
+        let width = (engine.resolution.x * 0.4).min(line * 24.);
+        let graph_height = line * 3.;
+        let margin = line * 0.5;
+        let origin = Vec2::splat(margin);
+        engine.tile_commands.draw(RenderTile {
+            world_rect: Rect::new(
+                Vec2::ZERO,
+                Vec2::new(width, line * lines.len() as f32 + graph_height) + margin * 2.,
+            ),
+            z: OVERLAY_Z + 0.001,
+            color: Vec4::new(0., 0., 0., 0.7),
+            ..Default::default()
+        });
+
+        for (row, text) in lines.iter().enumerate() {
+            let rect = Rect::new(
+                origin + Vec2::new(0., line * row as f32),
+                Vec2::new(width, line),
+            );
+            draw_text(engine.tile_commands, text, rect, OVERLAY_Z, &ui_text);
+        }
+
+        // a bar for each frame, the line is the time of a tick
+        let graph = Rect::new(
+            origin + Vec2::new(0., line * lines.len() as f32),
+            Vec2::new(width, graph_height),
+        );
+        let bar_width = width / PROFILER_FRAMES as f32;
+        let offset = PROFILER_FRAMES - self.frame_times.len();
+        for (i, frame_time) in self.frame_times.iter().enumerate() {
+            let height = (frame_time / GRAPH_MAX_TIME).min(1.) as f32 * graph.size.y;
+            let color = if *frame_time <= TICK_DELTA * 1.2 {
+                Vec4::new(0.3, 0.9, 0.3, 1.)
+            } else if *frame_time <= TICK_DELTA * 2.2 {
+                Vec4::new(0.9, 0.8, 0.2, 1.)
+            } else {
+                Vec4::new(0.9, 0.3, 0.2, 1.)
+            };
+            engine.tile_commands.draw(RenderTile {
+                world_rect: Rect::new(
+                    Vec2::new(
+                        graph.pos.x + (offset + i) as f32 * bar_width,
+                        graph.pos.y + graph.size.y - height,
+                    ),
+                    Vec2::new(bar_width, height),
+                ),
+                z: OVERLAY_Z,
+                color,
+                ..Default::default()
+            });
+        }
+        let tick_y = graph.pos.y + graph.size.y * (1. - (TICK_DELTA / GRAPH_MAX_TIME) as f32);
+        engine.tile_commands.draw(RenderTile {
+            world_rect: Rect::new(Vec2::new(graph.pos.x, tick_y), Vec2::new(width, 1.)),
+            z: OVERLAY_Z - 0.001,
+            color: Vec4::new(1., 1., 1., 0.5),
+            ..Default::default()
+        });
+    }
+}
+
+fn format_bytes(bytes: usize) -> String {
+    match bytes {
+        0..=0x3ff => format!("{} B", bytes),
+        0x400..=0xfffff => format!("{:.1} KiB", bytes as f64 / 1024.),
+        _ => format!("{:.1} MiB", bytes as f64 / (1024. * 1024.)),
+    }
+}
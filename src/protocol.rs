@@ -0,0 +1,623 @@
+use crate::{replay::ReplayAction, NetMessage, ProtocolError, MAX_PACKET_SIZE};
+
+/// Bumped on every change of the format or of the board a seed code builds,
+/// packets of other versions are dropped
+pub const PROTOCOL_VERSION: u8 = 6;
+
+/// A packet between the players: the protocol version, a tag byte for the kind of message
+/// and its fields in order. Numbers are little endian, strings are a u16 length then utf-8
+/// and an option is a byte, 0 for None or 1 followed by the value. A list is a u16 length
+/// then the items
+#[derive(Clone, Debug, PartialEq)]
+pub enum Message {
+    /// A guest asks to join, the host answers with its own. Who the player is
+    Hello {
+        name: String,
+        /// Index in `PROFILE_COLORS`
+        color: u8,
+    },
+    /// Sent back with the same time, to measure the round trip
+    Ping {
+        time: f64,
+        reply: bool,
+    },
+    /// The board a player is on and how far they are
+    BoardState {
+        /// Seed code of the board
+        code: String,
+        /// Fraction of the edges that match, 1 when solved
+        progress: f32,
+        /// Seconds it took to solve, None until solved
+        time: Option<f64>,
+    },
+    /// A move on the board, at its tick since the start of the board
+    Move {
+        tick: u64,
+        action: ReplayAction,
+    },
+    ChatLine {
+        text: String,
+    },
+    /// Every cell of the board, in order
+    BoardSnapshot {
+        /// Of the state, counted up by the sender
+        sequence: u32,
+        cells: Vec<CellState>,
+    },
+    /// The cells that changed since a state the other side acknowledged
+    BoardDelta {
+        sequence: u32,
+        /// Sequence of the state the changes are from
+        base: u32,
+        changes: Vec<CellChange>,
+    },
+    /// The state with the sequence arrived, the next deltas can be from it
+    BoardAck {
+        sequence: u32,
+    },
+    /// A quick message like "nice!", by its index in `EMOTES`
+    Emote {
+        emote: u8,
+    },
+}
+
+/// The tile on a cell: its index in the tile set and how many times it's turned left
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CellState {
+    pub tile: u8,
+    pub rotation: u8,
+}
+
+/// The `tile` of a cell with no tile yet, on a board played from a bag
+pub const EMPTY_CELL: u8 = u8::MAX;
+
+/// A cell, by its index on the board, and the tile on it now
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CellChange {
+    pub index: u16,
+    pub state: CellState,
+}
+
+/// The cells of `cells` that differ from `base`, the two are the same board
+pub fn board_delta(base: &[CellState], cells: &[CellState]) -> Vec<CellChange> {
+    cells
+        .iter()
+        .zip(base)
+        .enumerate()
+        .filter(|(_, (cell, base))| cell != base)
+        .map(|(index, (state, _))| CellChange {
+            index: index as u16,
+            state: *state,
+        })
+        .collect()
+}
+
+/// `base` with the changes on it, None if one is out of the board
+pub fn apply_board_delta(base: &[CellState], changes: &[CellChange]) -> Option<Vec<CellState>> {
+    let mut cells = base.to_vec();
+    for change in changes {
+        *cells.get_mut(change.index as usize)? = change.state;
+    }
+    Some(cells)
+}
+
+impl Message {
+    const HELLO: u8 = 0;
+    const PING: u8 = 1;
+    const BOARD_STATE: u8 = 2;
+    const MOVE: u8 = 3;
+    const CHAT_LINE: u8 = 4;
+    const BOARD_SNAPSHOT: u8 = 5;
+    const BOARD_DELTA: u8 = 6;
+    const BOARD_ACK: u8 = 7;
+    const EMOTE: u8 = 8;
+}
+
+impl NetMessage for Message {
+    fn encode(&self) -> Result<Vec<u8>, ProtocolError> {
+        let mut w = Writer(vec![PROTOCOL_VERSION]);
+        match self {
+            Message::Hello { name, color } => {
+                w.u8(Self::HELLO);
+                w.str(name)?;
+                w.u8(*color);
+            }
+            Message::Ping { time, reply } => {
+                w.u8(Self::PING);
+                w.f64(*time);
+                w.bool(*reply);
+            }
+            Message::BoardState {
+                code,
+                progress,
+                time,
+            } => {
+                w.u8(Self::BOARD_STATE);
+                w.str(code)?;
+                w.f32(*progress);
+                w.option(*time, Writer::f64);
+            }
+            Message::Move { tick, action } => {
+                w.u8(Self::MOVE);
+                w.u64(*tick);
+                w.action(action);
+            }
+            Message::ChatLine { text } => {
+                w.u8(Self::CHAT_LINE);
+                w.str(text)?;
+            }
+            Message::BoardSnapshot { sequence, cells } => {
+                w.u8(Self::BOARD_SNAPSHOT);
+                w.u32(*sequence);
+                w.list(cells, Writer::cell_state)?;
+            }
+            Message::BoardDelta {
+                sequence,
+                base,
+                changes,
+            } => {
+                w.u8(Self::BOARD_DELTA);
+                w.u32(*sequence);
+                w.u32(*base);
+                w.list(changes, |w, change| {
+                    w.u16(change.index);
+                    w.cell_state(&change.state);
+                })?;
+            }
+            Message::BoardAck { sequence } => {
+                w.u8(Self::BOARD_ACK);
+                w.u32(*sequence);
+            }
+            Message::Emote { emote } => {
+                w.u8(Self::EMOTE);
+                w.u8(*emote);
+            }
+        }
+        if w.0.len() > MAX_PACKET_SIZE {
+            return Err(ProtocolError::TooLarge(w.0.len()));
+        }
+        Ok(w.0)
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, ProtocolError> {
+        let mut r = Reader { bytes, pos: 0 };
+        let version = r.u8()?;
+        if version != PROTOCOL_VERSION {
+            return Err(ProtocolError::Version(version));
+        }
+        let message = match r.u8()? {
+            Self::HELLO => Message::Hello {
+                name: r.str()?,
+                color: r.u8()?,
+            },
+            Self::PING => Message::Ping {
+                time: r.f64()?,
+                reply: r.bool()?,
+            },
+            Self::BOARD_STATE => Message::BoardState {
+                code: r.str()?,
+                progress: r.f32()?,
+                time: r.option(Reader::f64)?,
+            },
+            Self::MOVE => Message::Move {
+                tick: r.u64()?,
+                action: r.action()?,
+            },
+            Self::CHAT_LINE => Message::ChatLine { text: r.str()? },
+            Self::BOARD_SNAPSHOT => Message::BoardSnapshot {
+                sequence: r.u32()?,
+                cells: r.list(Reader::cell_state)?,
+            },
+            Self::BOARD_DELTA => Message::BoardDelta {
+                sequence: r.u32()?,
+                base: r.u32()?,
+                changes: r.list(|r| {
+                    Ok(CellChange {
+                        index: r.u16()?,
+                        state: r.cell_state()?,
+                    })
+                })?,
+            },
+            Self::BOARD_ACK => Message::BoardAck { sequence: r.u32()? },
+            Self::EMOTE => Message::Emote { emote: r.u8()? },
+            tag => return Err(ProtocolError::Tag(tag)),
+        };
+        if r.pos != bytes.len() {
+            return Err(ProtocolError::Trailing);
+        }
+        Ok(message)
+    }
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn f32(&mut self, value: f32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn f64(&mut self, value: f64) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn str(&mut self, value: &str) -> Result<(), ProtocolError> {
+        let len = u16::try_from(value.len()).map_err(|_| ProtocolError::TooLarge(value.len()))?;
+        self.u16(len);
+        self.0.extend_from_slice(value.as_bytes());
+        Ok(())
+    }
+
+    fn option<T>(&mut self, value: Option<T>, write: fn(&mut Self, T)) {
+        self.bool(value.is_some());
+        if let Some(value) = value {
+            write(self, value);
+        }
+    }
+
+    /// A u16 length, then the items
+    fn list<T>(&mut self, items: &[T], write: fn(&mut Self, &T)) -> Result<(), ProtocolError> {
+        let len = u16::try_from(items.len()).map_err(|_| ProtocolError::TooLarge(items.len()))?;
+        self.u16(len);
+        for item in items {
+            write(self, item);
+        }
+        Ok(())
+    }
+
+    fn cell_state(&mut self, state: &CellState) {
+        self.u8(state.tile);
+        self.u8(state.rotation);
+    }
+
+    /// A byte for each coordinate, boards are far smaller than 256 cells across
+    fn cell(&mut self, (x, y): (usize, usize)) {
+        self.u8(x as u8);
+        self.u8(y as u8);
+    }
+
+    /// A tag byte like the messages, then the cells
+    fn action(&mut self, action: &ReplayAction) {
+        match *action {
+            ReplayAction::Activate(x, y) => {
+                self.u8(0);
+                self.cell((x, y));
+            }
+            ReplayAction::Rotate(x, y) => {
+                self.u8(1);
+                self.cell((x, y));
+            }
+            ReplayAction::RotateBack(x, y) => {
+                self.u8(2);
+                self.cell((x, y));
+            }
+            ReplayAction::Swap(from, to) => {
+                self.u8(3);
+                self.cell(from);
+                self.cell(to);
+            }
+            ReplayAction::Undo => self.u8(4),
+            ReplayAction::Place(x, y, kind) => {
+                self.u8(5);
+                self.cell((x, y));
+                self.u8(kind as u8);
+            }
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], ProtocolError> {
+        let bytes = self
+            .bytes
+            .get(self.pos..self.pos + N)
+            .ok_or(ProtocolError::Truncated)?;
+        self.pos += N;
+        Ok(bytes.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8, ProtocolError> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn bool(&mut self) -> Result<bool, ProtocolError> {
+        Ok(self.u8()? != 0)
+    }
+
+    fn u16(&mut self) -> Result<u16, ProtocolError> {
+        Ok(u16::from_le_bytes(self.take()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, ProtocolError> {
+        Ok(u32::from_le_bytes(self.take()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, ProtocolError> {
+        Ok(u64::from_le_bytes(self.take()?))
+    }
+
+    fn f32(&mut self) -> Result<f32, ProtocolError> {
+        Ok(f32::from_le_bytes(self.take()?))
+    }
+
+    fn f64(&mut self) -> Result<f64, ProtocolError> {
+        Ok(f64::from_le_bytes(self.take()?))
+    }
+
+    fn str(&mut self) -> Result<String, ProtocolError> {
+        let len = self.u16()? as usize;
+        let bytes = self
+            .bytes
+            .get(self.pos..self.pos + len)
+            .ok_or(ProtocolError::Truncated)?;
+        self.pos += len;
+        String::from_utf8(bytes.to_vec()).map_err(|_| ProtocolError::Utf8)
+    }
+
+    fn option<T>(
+        &mut self,
+        read: fn(&mut Self) -> Result<T, ProtocolError>,
+    ) -> Result<Option<T>, ProtocolError> {
+        match self.bool()? {
+            true => Ok(Some(read(self)?)),
+            false => Ok(None),
+        }
+    }
+
+    fn list<T>(
+        &mut self,
+        read: fn(&mut Self) -> Result<T, ProtocolError>,
+    ) -> Result<Vec<T>, ProtocolError> {
+        let len = self.u16()?;
+        (0..len).map(|_| read(self)).collect()
+    }
+
+    fn cell_state(&mut self) -> Result<CellState, ProtocolError> {
+        Ok(CellState {
+            tile: self.u8()?,
+            rotation: self.u8()?,
+        })
+    }
+
+    fn cell(&mut self) -> Result<(usize, usize), ProtocolError> {
+        Ok((self.u8()? as usize, self.u8()? as usize))
+    }
+
+    fn action(&mut self) -> Result<ReplayAction, ProtocolError> {
+        Ok(match self.u8()? {
+            0 => {
+                let (x, y) = self.cell()?;
+                ReplayAction::Activate(x, y)
+            }
+            1 => {
+                let (x, y) = self.cell()?;
+                ReplayAction::Rotate(x, y)
+            }
+            2 => {
+                let (x, y) = self.cell()?;
+                ReplayAction::RotateBack(x, y)
+            }
+            3 => ReplayAction::Swap(self.cell()?, self.cell()?),
+            4 => ReplayAction::Undo,
+            5 => {
+                let (x, y) = self.cell()?;
+                ReplayAction::Place(x, y, self.u8()? as usize)
+            }
+            tag => return Err(ProtocolError::Tag(tag)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(message: Message) {
+        let bytes = message.encode().unwrap();
+        assert_eq!(Message::decode(&bytes), Ok(message));
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        round_trip(Message::Hello {
+            name: "Jacopo".to_string(),
+            color: 4,
+        });
+        round_trip(Message::Ping {
+            time: 1234.5678,
+            reply: true,
+        });
+        round_trip(Message::BoardState {
+            code: "3K7QZ0M2A".to_string(),
+            progress: 0.625,
+            time: None,
+        });
+        round_trip(Message::BoardState {
+            code: String::new(),
+            progress: 1.,
+            time: Some(83.25),
+        });
+        round_trip(Message::ChatLine {
+            text: "ciao, è la mia mossa".to_string(),
+        });
+        round_trip(Message::BoardSnapshot {
+            sequence: 0,
+            cells: vec![],
+        });
+        round_trip(Message::BoardSnapshot {
+            sequence: 41,
+            cells: (0..100)
+                .map(|i| CellState {
+                    tile: (i * 7 % 64) as u8,
+                    rotation: (i % 4) as u8,
+                })
+                .collect(),
+        });
+        round_trip(Message::BoardDelta {
+            sequence: u32::MAX,
+            base: 12,
+            changes: vec![
+                CellChange {
+                    index: 0,
+                    state: CellState {
+                        tile: 3,
+                        rotation: 1,
+                    },
+                },
+                CellChange {
+                    index: 99,
+                    state: CellState {
+                        tile: 255,
+                        rotation: 3,
+                    },
+                },
+            ],
+        });
+        round_trip(Message::BoardAck { sequence: 7 });
+        round_trip(Message::Emote { emote: 2 });
+        for action in [
+            ReplayAction::Activate(0, 9),
+            ReplayAction::Rotate(3, 4),
+            ReplayAction::RotateBack(9, 0),
+            ReplayAction::Swap((1, 2), (7, 8)),
+            ReplayAction::Undo,
+            ReplayAction::Place(5, 6, 15),
+        ] {
+            round_trip(Message::Move {
+                tick: u64::MAX - 1,
+                action,
+            });
+        }
+    }
+
+    #[test]
+    fn layout() {
+        let bytes = Message::BoardState {
+            code: "AB".to_string(),
+            progress: 0.5,
+            time: None,
+        }
+        .encode()
+        .unwrap();
+        let mut expected = vec![PROTOCOL_VERSION, 2, 2, 0, b'A', b'B'];
+        expected.extend_from_slice(&0.5f32.to_le_bytes());
+        expected.push(0);
+        assert_eq!(bytes, expected);
+    }
+
+    #[test]
+    fn board_delta_round_trip() {
+        let base: Vec<CellState> = (0..24)
+            .map(|i| CellState {
+                tile: i as u8,
+                rotation: 0,
+            })
+            .collect();
+        let mut cells = base.clone();
+        // a swap and a turn
+        cells.swap(2, 17);
+        cells[5].rotation = 3;
+        let changes = board_delta(&base, &cells);
+        assert_eq!(
+            changes
+                .iter()
+                .map(|change| change.index)
+                .collect::<Vec<_>>(),
+            [2, 5, 17]
+        );
+        let message = Message::BoardDelta {
+            sequence: 2,
+            base: 1,
+            changes,
+        };
+        let Ok(Message::BoardDelta { changes, .. }) = Message::decode(&message.encode().unwrap())
+        else {
+            panic!("not a delta");
+        };
+        assert_eq!(apply_board_delta(&base, &changes), Some(cells.clone()));
+        assert!(board_delta(&cells, &cells).is_empty());
+
+        let outside = CellChange {
+            index: 24,
+            state: CellState::default(),
+        };
+        assert_eq!(apply_board_delta(&base, &[outside]), None);
+    }
+
+    #[test]
+    fn delta_smaller_than_snapshot() {
+        let cells = vec![CellState::default(); 100];
+        let mut changed = cells.clone();
+        changed[42].tile = 1;
+        let snapshot = Message::BoardSnapshot {
+            sequence: 1,
+            cells: changed.clone(),
+        };
+        let delta = Message::BoardDelta {
+            sequence: 1,
+            base: 0,
+            changes: board_delta(&cells, &changed),
+        };
+        assert!(delta.encode().unwrap().len() * 10 < snapshot.encode().unwrap().len());
+    }
+
+    #[test]
+    fn malformed() {
+        let bytes = Message::ChatLine {
+            text: "hello".to_string(),
+        }
+        .encode()
+        .unwrap();
+        for len in 0..bytes.len() {
+            assert_eq!(
+                Message::decode(&bytes[..len]),
+                Err(ProtocolError::Truncated)
+            );
+        }
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(Message::decode(&trailing), Err(ProtocolError::Trailing));
+        let mut version = bytes.clone();
+        version[0] = PROTOCOL_VERSION + 1;
+        assert_eq!(
+            Message::decode(&version),
+            Err(ProtocolError::Version(PROTOCOL_VERSION + 1))
+        );
+        assert_eq!(
+            Message::decode(&[PROTOCOL_VERSION, 200]),
+            Err(ProtocolError::Tag(200))
+        );
+        assert_eq!(
+            Message::decode(&[PROTOCOL_VERSION, 4, 1, 0, 0xff]),
+            Err(ProtocolError::Utf8)
+        );
+        let long = Message::ChatLine {
+            text: "a".repeat(MAX_PACKET_SIZE),
+        };
+        assert!(matches!(long.encode(), Err(ProtocolError::TooLarge(_))));
+    }
+}
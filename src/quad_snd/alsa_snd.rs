@@ -17,7 +17,8 @@ mod consts {
     pub const PCM_PERIOD_SIZE: ::std::os::raw::c_ulong = PCM_BUFFER_SIZE / 4; /* Send interrupt every 1024 (stereo) frames. */
 }
 
-unsafe fn setup_pcm_device() -> *mut sys::snd_pcm_t {
+/// Returns the device and the rate it accepted
+unsafe fn setup_pcm_device() -> (*mut sys::snd_pcm_t, u32) {
     let mut pcm_handle = std::ptr::null_mut();
 
     // Open the PCM device in playback mode
@@ -32,6 +33,8 @@ unsafe fn setup_pcm_device() -> *mut sys::snd_pcm_t {
         panic!("Can't open PCM device.");
     }
 
+    let mut rate = consts::RATEMAX;
+
     // Set hardware parameters
     {
         let mut hw_params: *mut sys::snd_pcm_hw_params_t = std::ptr::null_mut();
@@ -83,7 +86,7 @@ unsafe fn setup_pcm_device() -> *mut sys::snd_pcm_t {
             panic!("Cannot set rate.");
         }
 
-        let mut rate = ratemin.max(ratemax);
+        rate = ratemin.max(ratemax);
         if sys::snd_pcm_hw_params_set_rate_near(
             pcm_handle,
             hw_params,
@@ -93,7 +96,6 @@ unsafe fn setup_pcm_device() -> *mut sys::snd_pcm_t {
         {
             panic!("Cannot set rate.");
         }
-        // TODO: pass this rate to the asset pcm resampler?
 
         // Write parameters
         if sys::snd_pcm_hw_params(pcm_handle, hw_params) < 0 {
@@ -137,13 +139,18 @@ unsafe fn setup_pcm_device() -> *mut sys::snd_pcm_t {
         panic!("Cannot prepare audio interface for use.");
     }
 
-    pcm_handle
+    (pcm_handle, rate)
 }
 
-unsafe fn audio_thread(mut mixer: crate::quad_snd::mixer::Mixer) {
+unsafe fn audio_thread(
+    mixer_builder: crate::quad_snd::mixer::MixerBuilder,
+    rate_tx: mpsc::Sender<u32>,
+) {
     let mut buffer: Vec<f32> = vec![0.0; consts::PCM_BUFFER_SIZE as usize * 2];
 
-    let pcm_handle = setup_pcm_device();
+    let (pcm_handle, rate) = setup_pcm_device();
+    let _ = rate_tx.send(rate);
+    let mut mixer = mixer_builder.build(rate);
 
     loop {
         // Wait for PCM to be ready for next write (no timeout)
@@ -191,6 +198,7 @@ unsafe fn audio_thread(mut mixer: crate::quad_snd::mixer::Mixer) {
 
 pub struct AudioContext {
     pub(crate) mixer_ctrl: crate::quad_snd::mixer::MixerControl,
+    sample_rate: u32,
 }
 
 impl AudioContext {
@@ -198,11 +206,22 @@ impl AudioContext {
         use crate::quad_snd::mixer::Mixer;
 
         let (mixer_builder, mixer_ctrl) = Mixer::new();
+        // the rate is negotiated with the device by the audio thread
+        let (rate_tx, rate_rx) = mpsc::channel();
         std::thread::spawn(move || unsafe {
-            audio_thread(mixer_builder.build());
+            audio_thread(mixer_builder, rate_tx);
         });
+        let sample_rate = rate_rx.recv().unwrap_or(consts::RATEMAX);
+
+        AudioContext {
+            mixer_ctrl,
+            sample_rate,
+        }
+    }
 
-        AudioContext { mixer_ctrl }
+    /// Frequency of the output, sounds must be resampled to it
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
     }
 }
 
@@ -46,7 +46,7 @@ impl AudioContext {
         use crate::mixer::{self, Mixer};
 
         let (mixer_builder, mixer_ctrl) = Mixer::new();
-        let mixer = Box::new(mixer_builder.build());
+        let mixer = Box::new(mixer_builder.build(consts::RATE));
 
         unsafe {
             let fmt = _saudio_AudioStreamBasicDescription {
@@ -96,6 +96,11 @@ impl AudioContext {
 
         AudioContext { mixer_ctrl }
     }
+
+    /// Frequency of the output, sounds must be resampled to it
+    pub fn sample_rate(&self) -> u32 {
+        consts::RATE
+    }
 }
 
 #[derive(Clone)]
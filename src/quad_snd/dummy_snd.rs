@@ -6,6 +6,10 @@ impl AudioContext {
     pub fn new() -> AudioContext {
         AudioContext
     }
+
+    pub fn sample_rate(&self) -> u32 {
+        44100
+    }
 }
 
 pub struct Playback;
@@ -1,3 +1,4 @@
+use crate::quad_snd::music::{MusicSource, PlayMusicParams};
 use crate::quad_snd::{AudioContext, PlaySoundParams};
 
 use std::cell::Cell;
@@ -7,38 +8,104 @@ use std::sync::Arc;
 
 enum AudioMessage {
     AddSound(u32, Vec<f32>),
-    Play(u32, u32, bool, f32),
+    Play(u32, u32, bool, f32, f32, f32),
     Stop(u32),
     StopAll(u32),
     SetVolume(u32, f32),
     SetVolumeAll(u32, f32),
     Delete(u32),
+    PlayMusic(Box<dyn MusicSource>, bool, f32, f32),
+    StopMusic(f32),
+    SetMusicVolume(f32),
+}
+
+struct MusicState {
+    source: Box<dyn MusicSource>,
+    looped: bool,
+    volume: f32,
+    // crossfade gain, moves by `fade_step` each frame
+    gain: f32,
+    fade_step: f32,
+}
+
+impl MusicState {
+    /// Mixes into the stereo `buffer`, returns false when the track is over
+    fn mix(&mut self, buffer: &mut [f32], scratch: &mut Vec<f32>) -> bool {
+        scratch.clear();
+        scratch.resize(buffer.len(), 0.);
+        let mut written = 0;
+        while written < scratch.len() / 2 {
+            let frames = self.source.read(&mut scratch[written * 2..]);
+            written += frames;
+            if written < scratch.len() / 2 {
+                if !self.looped || frames == 0 {
+                    break;
+                }
+                self.source.rewind();
+            }
+        }
+        for (out, frame) in buffer.chunks_exact_mut(2).zip(scratch.chunks_exact(2)) {
+            self.gain = (self.gain + self.fade_step).clamp(0., 1.);
+            out[0] += frame[0] * self.volume * self.gain;
+            out[1] += frame[1] * self.volume * self.gain;
+        }
+        let faded_out = self.fade_step < 0. && self.gain <= 0.;
+        written == scratch.len() / 2 && !faded_out
+    }
+}
+
+fn fade_step(seconds: f32, sample_rate: u32) -> f32 {
+    1. / (seconds * sample_rate as f32).max(1.)
 }
 
 #[derive(Debug)]
 pub struct SoundState {
     sound_id: u32,
     play_id: u32,
-    sample: usize,
+    // in frames, fractional when the pitch is not 1
+    position: f64,
     data: Arc<[f32]>,
     looped: bool,
     volume: f32,
+    pitch: f32,
+    /// Gains of the left and right channels, from the pan
+    balance: [f32; 2],
 }
 
 impl SoundState {
-    fn get_samples(&mut self, n: usize) -> &[f32] {
-        let data = &self.data[self.sample..];
-
-        self.sample += n;
-
-        match data.get(..n) {
-            Some(data) => data,
-            None => data,
+    /// Mixes into the stereo `buffer`, returns false when the sound is over
+    fn mix(&mut self, buffer: &mut [f32]) -> bool {
+        let frames = self.data.len() / 2;
+        if frames == 0 {
+            return false;
         }
-    }
+        for out in buffer.chunks_exact_mut(2) {
+            // linear interpolation between the two nearest frames
+            let index = self.position as usize;
+            let t = (self.position - index as f64) as f32;
+            let next = if index + 1 < frames {
+                index + 1
+            } else if self.looped {
+                0
+            } else {
+                index
+            };
+            for channel in 0..2 {
+                let a = self.data[index * 2 + channel];
+                let b = self.data[next * 2 + channel];
+                out[channel] += (a + (b - a) * t) * self.volume * self.balance[channel];
+            }
 
-    fn rewind(&mut self) {
-        self.sample = 0;
+            self.position += self.pitch as f64;
+            if self.position >= frames as f64 {
+                if self.looped {
+                    self.position %= frames as f64;
+                } else {
+                    return false;
+                }
+            }
+        }
+        true
     }
 }
 
@@ -46,6 +113,9 @@ pub struct Mixer {
     rx: mpsc::Receiver<AudioMessage>,
     sounds: HashMap<u32, Arc<[f32]>>,
     mixer_state: Vec<SoundState>,
+    music: Vec<MusicState>,
+    music_scratch: Vec<f32>,
+    sample_rate: u32,
 }
 
 pub struct MixerBuilder {
@@ -97,6 +167,8 @@ impl MixerControl {
             play_id,
             params.looped,
             params.volume,
+            params.pitch.max(0.01),
+            params.pan.clamp(-1., 1.),
         ));
 
         self.play_id.set(play_id + 1);
@@ -104,6 +176,23 @@ impl MixerControl {
         Playback { play_id }
     }
 
+    pub fn play_music(&self, source: Box<dyn MusicSource>, params: PlayMusicParams) {
+        self.send(AudioMessage::PlayMusic(
+            source,
+            params.looped,
+            params.volume,
+            params.fade,
+        ));
+    }
+
+    pub fn stop_music(&self, fade: f32) {
+        self.send(AudioMessage::StopMusic(fade));
+    }
+
+    pub fn set_music_volume(&self, volume: f32) {
+        self.send(AudioMessage::SetMusicVolume(volume));
+    }
+
     pub fn stop(&self, play_id: u32) {
         self.send(AudioMessage::Stop(play_id));
     }
@@ -128,11 +217,15 @@ impl MixerControl {
 }
 
 impl MixerBuilder {
-    pub fn build(self) -> Mixer {
+    /// `sample_rate` is the output rate of the device
+    pub fn build(self, sample_rate: u32) -> Mixer {
         Mixer {
             rx: self.rx,
             sounds: HashMap::new(),
             mixer_state: vec![],
+            music: vec![],
+            music_scratch: vec![],
+            sample_rate,
         }
     }
 }
@@ -157,15 +250,18 @@ impl Mixer {
                 AudioMessage::AddSound(id, data) => {
                     self.sounds.insert(id, data.into());
                 }
-                AudioMessage::Play(sound_id, play_id, looped, volume) => {
+                AudioMessage::Play(sound_id, play_id, looped, volume, pitch, pan) => {
                     if let Some(data) = self.sounds.get(&sound_id) {
                         self.mixer_state.push(SoundState {
                             sound_id,
                             play_id,
-                            sample: 0,
+                            position: 0.,
                             data: data.clone(),
                             looped,
                             volume,
+                            pitch,
+                            // the side panned to stays as loud, the other fades out
+                            balance: [(1. - pan).min(1.), (1. + pan).min(1.)],
                         });
                     }
                 }
@@ -204,6 +300,28 @@ impl Mixer {
                     }
                     self.sounds.remove(&sound_id);
                 }
+                AudioMessage::PlayMusic(source, looped, volume, fade) => {
+                    for music in self.music.iter_mut() {
+                        music.fade_step = -fade_step(fade, self.sample_rate);
+                    }
+                    self.music.push(MusicState {
+                        source,
+                        looped,
+                        volume,
+                        gain: if fade > 0. { 0. } else { 1. },
+                        fade_step: fade_step(fade, self.sample_rate),
+                    });
+                }
+                AudioMessage::StopMusic(fade) => {
+                    for music in self.music.iter_mut() {
+                        music.fade_step = -fade_step(fade, self.sample_rate);
+                    }
+                }
+                AudioMessage::SetMusicVolume(volume) => {
+                    if let Some(music) = self.music.last_mut() {
+                        music.volume = volume;
+                    }
+                }
             }
         }
 
@@ -214,30 +332,20 @@ impl Mixer {
         let mut i = 0;
 
         while let Some(sound) = self.mixer_state.get_mut(i) {
-            let volume = sound.volume;
-            let mut remainder = buffer.len();
-
-            loop {
-                let samples = sound.get_samples(remainder);
-
-                for (b, s) in buffer.iter_mut().zip(samples) {
-                    *b += s * volume;
-                }
-
-                remainder -= samples.len();
-
-                if remainder > 0 && sound.looped {
-                    sound.rewind();
-                    continue;
-                }
-
-                break;
+            if sound.mix(buffer) {
+                i += 1;
+            } else {
+                self.mixer_state.swap_remove(i);
             }
+        }
 
-            if remainder > 0 {
-                self.mixer_state.swap_remove(i);
-            } else {
+        // the order matters, the last track is the current one
+        let mut i = 0;
+        while let Some(music) = self.music.get_mut(i) {
+            if music.mix(buffer, &mut self.music_scratch) {
                 i += 1;
+            } else {
+                self.music.remove(i);
             }
         }
     }
@@ -29,11 +29,18 @@ mod snd;
 #[cfg(not(target_arch = "wasm32"))]
 mod mixer;
 
+mod music;
+
+pub use music::{MusicPlayer, MusicSource, PlayMusicParams};
 pub use snd::{AudioContext, Playback, Sound};
 
 pub struct PlaySoundParams {
     pub looped: bool,
     pub volume: f32,
+    /// Playback speed, 2 is an octave up.
+    pub pitch: f32,
+    /// Balance between the speakers, -1 only left and 1 only right.
+    pub pan: f32,
 }
 
 impl Default for PlaySoundParams {
@@ -41,6 +48,8 @@ impl Default for PlaySoundParams {
         PlaySoundParams {
             looped: false,
             volume: 1.,
+            pitch: 1.,
+            pan: 0.,
         }
     }
 }
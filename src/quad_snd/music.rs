@@ -0,0 +1,114 @@
+//! Streamed background music, decoded a buffer at a time.
+
+use crate::quad_snd::AudioContext;
+
+/// Incremental decoder of a track.
+pub trait MusicSource: Send {
+    /// Fills `out` with interleaved stereo samples at the `AudioContext` sample rate.
+    /// Returns the number of frames written, less than requested at the end of the track.
+    fn read(&mut self, out: &mut [f32]) -> usize;
+
+    /// Back to the start, used to loop.
+    fn rewind(&mut self);
+}
+
+pub struct PlayMusicParams {
+    pub looped: bool,
+    pub volume: f32,
+    /// Seconds of crossfade with the track that was playing
+    pub fade: f32,
+}
+
+impl Default for PlayMusicParams {
+    fn default() -> PlayMusicParams {
+        PlayMusicParams {
+            looped: true,
+            volume: 1.,
+            fade: 1.,
+        }
+    }
+}
+
+/// Only one track plays at a time, the previous ones fade out.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct MusicPlayer;
+
+#[cfg(not(target_arch = "wasm32"))]
+impl MusicPlayer {
+    pub fn new() -> MusicPlayer {
+        MusicPlayer
+    }
+
+    pub fn play(
+        &mut self,
+        ctx: &AudioContext,
+        source: Box<dyn MusicSource>,
+        params: PlayMusicParams,
+    ) {
+        ctx.mixer_ctrl.play_music(source, params);
+    }
+
+    pub fn stop(&mut self, ctx: &AudioContext, fade: f32) {
+        ctx.mixer_ctrl.stop_music(fade);
+    }
+
+    pub fn set_volume(&mut self, ctx: &AudioContext, volume: f32) {
+        ctx.mixer_ctrl.set_music_volume(volume);
+    }
+}
+
+/// The web backend has no mixer thread: the track is decoded whole
+/// and played as a looped sound, without crossfade.
+#[cfg(target_arch = "wasm32")]
+pub struct MusicPlayer {
+    current: Option<(crate::quad_snd::Sound, crate::quad_snd::Playback)>,
+}
+
+#[cfg(target_arch = "wasm32")]
+impl MusicPlayer {
+    pub fn new() -> MusicPlayer {
+        MusicPlayer { current: None }
+    }
+
+    pub fn play(
+        &mut self,
+        ctx: &AudioContext,
+        mut source: Box<dyn MusicSource>,
+        params: PlayMusicParams,
+    ) {
+        self.stop(ctx, 0.);
+        let mut samples = vec![];
+        let mut chunk = vec![0.; 4096 * 2];
+        loop {
+            chunk.fill(0.);
+            let frames = source.read(&mut chunk);
+            samples.extend_from_slice(&chunk[..frames * 2]);
+            if frames * 2 < chunk.len() {
+                break;
+            }
+        }
+        let sound = crate::quad_snd::Sound::load(ctx, &samples);
+        let playback = sound.play(
+            ctx,
+            crate::quad_snd::PlaySoundParams {
+                looped: params.looped,
+                volume: params.volume,
+                ..Default::default()
+            },
+        );
+        self.current = Some((sound, playback));
+    }
+
+    pub fn stop(&mut self, ctx: &AudioContext, _fade: f32) {
+        if let Some((sound, playback)) = self.current.take() {
+            playback.stop(ctx);
+            sound.delete(ctx);
+        }
+    }
+
+    pub fn set_volume(&mut self, ctx: &AudioContext, volume: f32) {
+        if let Some((_, playback)) = &self.current {
+            playback.set_volume(ctx, volume);
+        }
+    }
+}
@@ -4,6 +4,8 @@ use std::sync::mpsc;
 
 pub use crate::quad_snd::mixer::Playback;
 
+const SAMPLE_RATE: u32 = 44100;
+
 // Slightly reduced OpenSLES implementation
 // from an amazing "audir" library: https://github.com/norse-rs/audir/
 // and a little bit of glue code to make it work with macroquad
@@ -360,7 +362,7 @@ unsafe fn audio_thread(
             DeviceDesc {
                 sample_desc: SampleDesc {
                     format: Format::F32,
-                    sample_rate: 44100,
+                    sample_rate: SAMPLE_RATE as usize,
                 },
             },
             Channels {
@@ -414,12 +416,17 @@ impl AudioContext {
 
         let (mixer_builder, mixer_ctrl) = Mixer::new();
         std::thread::spawn(move || unsafe {
-            audio_thread(mixer_builder.build(), rx1);
+            audio_thread(mixer_builder.build(SAMPLE_RATE), rx1);
         });
 
         AudioContext { mixer_ctrl, tx1 }
     }
 
+    /// Frequency of the output, sounds must be resampled to it
+    pub fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
     pub fn pause(&mut self) {
         self.tx1.send(ControlMessage::Pause).unwrap()
     }
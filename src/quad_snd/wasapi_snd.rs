@@ -196,11 +196,16 @@ impl AudioContext {
 
         let (mixer_builder, mixer_ctrl) = Mixer::new();
         std::thread::spawn(move || unsafe {
-            audio_thread(mixer_builder.build());
+            audio_thread(mixer_builder.build(consts::SAMPLE_RATE));
         });
 
         AudioContext { mixer_ctrl }
     }
+
+    /// Frequency of the output, sounds must be resampled to it
+    pub fn sample_rate(&self) -> u32 {
+        consts::SAMPLE_RATE
+    }
 }
 
 #[derive(Clone)]
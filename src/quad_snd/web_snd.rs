@@ -4,7 +4,7 @@ extern "C" {
     fn audio_init();
     fn audio_add_buffer_raw(content: *const f32, content_len: u32) -> u32;
     fn audio_add_buffer(content: *const u8, content_len: u32) -> u32;
-    fn audio_play_buffer(buffer: u32, volume: f32, repeat: bool) -> u32;
+    fn audio_play_buffer(buffer: u32, volume: f32, repeat: bool, pan: f32, pitch: f32) -> u32;
     fn audio_source_is_loaded(buffer: u32) -> bool;
     fn audio_source_set_volume(buffer: u32, volume: f32);
     fn audio_source_stop(buffer: u32);
@@ -28,6 +28,12 @@ impl AudioContext {
 
         AudioContext
     }
+
+    /// Frequency of the output, sounds must be resampled to it.
+    /// The buffers are created at this rate in `audio_add_buffer_raw`.
+    pub fn sample_rate(&self) -> u32 {
+        44100
+    }
 }
 
 #[derive(Clone)]
@@ -65,7 +71,15 @@ impl Sound {
     }
 
     pub fn play(&self, _ctx: &AudioContext, params: PlaySoundParams) -> Playback {
-        let id = unsafe { audio_play_buffer(self.0, params.volume, params.looped) };
+        let id = unsafe {
+            audio_play_buffer(
+                self.0,
+                params.volume,
+                params.looped,
+                params.pan,
+                params.pitch,
+            )
+        };
 
         Playback(id)
     }
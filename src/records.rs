@@ -0,0 +1,84 @@
+use crate::*;
+
+/// Best time and fewest moves for a grid size, they can come from different games
+#[derive(Clone, Debug, PartialEq)]
+pub struct Record {
+    pub grid_size: IVec2,
+    pub time: f64,
+    pub moves: u32,
+}
+
+/// Best scores of the endless mode, saved to disk on every change
+#[derive(Default)]
+pub struct Records {
+    records: Vec<Record>,
+}
+
+impl Records {
+    const FILE: &'static str = "records.txt";
+
+    pub fn load() -> Self {
+        let Ok(text) = storage::load(Self::FILE) else {
+            return Self::default();
+        };
+        // each line is "WxH time moves"
+        let records = text
+            .lines()
+            .filter_map(|line| {
+                let mut parts = line.split_whitespace();
+                let (w, h) = parts.next()?.split_once('x')?;
+                Some(Record {
+                    grid_size: IVec2::new(w.parse().ok()?, h.parse().ok()?),
+                    time: parts.next()?.parse().ok()?,
+                    moves: parts.next()?.parse().ok()?,
+                })
+            })
+            .collect();
+        Self { records }
+    }
+
+    fn save(&self) {
+        let text: Vec<String> = self
+            .records
+            .iter()
+            .map(|r| format!("{}x{} {} {}", r.grid_size.x, r.grid_size.y, r.time, r.moves))
+            .collect();
+        if let Err(err) = storage::save(Self::FILE, &text.join("\n")) {
+            eprintln!("Records not saved: {err}");
+        }
+    }
+
+    pub fn get(&self, grid_size: IVec2) -> Option<&Record> {
+        self.records.iter().find(|r| r.grid_size == grid_size)
+    }
+
+    /// Keeps the better time and moves, returns true if any of them improved
+    pub fn submit(&mut self, grid_size: IVec2, time: f64, moves: u32) -> bool {
+        let improved = match self.records.iter_mut().find(|r| r.grid_size == grid_size) {
+            Some(record) => {
+                let improved = time < record.time || moves < record.moves;
+                record.time = record.time.min(time);
+                record.moves = record.moves.min(moves);
+                improved
+            }
+            None => {
+                self.records.push(Record {
+                    grid_size,
+                    time,
+                    moves,
+                });
+                true
+            }
+        };
+        if improved {
+            self.save();
+        }
+        improved
+    }
+}
+
+/// Minutes, seconds and tenths like 1:05.3
+pub fn format_time(seconds: f64) -> String {
+    let tenths = (seconds * 10.) as u64;
+    format!("{}:{:02}.{}", tenths / 600, tenths / 10 % 60, tenths % 10)
+}
@@ -1,12 +1,22 @@
-use std::collections::HashMap;
+use std::{
+    cell::Cell,
+    collections::{HashMap, HashSet},
+    ops::Range,
+};
 
 use glam::*;
 use miniquad::*;
 
-use crate::assets::{image::Image, mesh::Mesh, AssetId};
+use crate::assets::{image::Image, mesh::Mesh, AssetId, Assets};
 use crate::ui::Rect;
 
 const MAX_VERTICES_PER_TEXTURE: usize = 0x10000;
+/// Bytes of the textures kept on the gpu before the least recently drawn are evicted
+const TEXTURE_BUDGET: usize = 256 << 20;
+/// Even, so that the lines aren't split between draw calls
+const MAX_DEBUG_VERTICES: usize = 0x10000;
+/// Meshes drawn by a single instanced draw call
+const MAX_INSTANCES: usize = 1024;
 
 #[derive(Debug, Clone)]
 pub struct Transform {
@@ -48,18 +58,65 @@ impl Transform {
     }
 }
 
+/// How an image is sampled once it's a texture
+#[derive(Debug, Clone)]
+pub struct TextureOptions {
+    pub filter: FilterMode,
+    /// Smaller copies for when it's drawn small or at an angle, only for power of two sizes
+    pub mipmaps: bool,
+    /// Samples along the slope of a surface at an angle, 1 is off
+    pub anisotropy: f32,
+}
+
+impl Default for TextureOptions {
+    fn default() -> Self {
+        Self {
+            filter: FilterMode::Linear,
+            mipmaps: false,
+            anisotropy: 1.,
+        }
+    }
+}
+
+// from EXT_texture_filter_anisotropic
+const GL_TEXTURE_MAX_ANISOTROPY: u32 = 0x84FE;
+const GL_MAX_TEXTURE_MAX_ANISOTROPY: u32 = 0x84FF;
+
+/// Highest anisotropy of the textures, 1 if the extension is missing.
+/// Webgl needs to enable the extension first, so it goes without.
+fn max_anisotropy(ctx: &dyn RenderingBackend) -> f32 {
+    if cfg!(target_arch = "wasm32") || ctx.info().backend != Backend::OpenGl {
+        return 1.;
+    }
+    unsafe {
+        use miniquad::gl::*;
+        // null on core profiles, that list the extensions one by one
+        let extensions = glGetString(GL_EXTENSIONS);
+        if extensions.is_null() {
+            return 1.;
+        }
+        let extensions = std::ffi::CStr::from_ptr(extensions as *const _).to_string_lossy();
+        if !extensions.contains("texture_filter_anisotropic") {
+            return 1.;
+        }
+        let mut max = 1;
+        glGetIntegerv(GL_MAX_TEXTURE_MAX_ANISOTROPY, &mut max);
+        max.max(1) as f32
+    }
+}
+
 pub fn atlas_to_uv(atlas_image: &Image, tile_size: Vec2, atlas_pos: Vec2) -> Rect {
-    atlas_to_uv_pad_offset(atlas_image, tile_size, atlas_pos, Vec2::ZERO, Vec2::ZERO)
+    let image_size = Vec2::new(atlas_image.width as f32, atlas_image.height as f32);
+    atlas_to_uv_pad_offset(image_size, tile_size, atlas_pos, Vec2::ZERO, Vec2::ZERO)
 }
 
 pub fn atlas_to_uv_pad_offset(
-    atlas_image: &Image,
+    image_size: Vec2,
     tile_size: Vec2,
     atlas_pos: Vec2,
     pad: Vec2,
     offset: Vec2,
 ) -> Rect {
-    let image_size = Vec2::new(atlas_image.width as f32, atlas_image.height as f32);
     let pos = (offset + atlas_pos * (tile_size + pad)) / image_size;
     let size = tile_size / image_size;
     Rect::new(pos, size)
@@ -99,46 +156,376 @@ impl Default for RenderTile {
 
 #[derive(Debug, Clone, Default)]
 pub struct RenderTileCommands {
-    textured: HashMap<AssetId, Vec<RenderTile>>,
-    solid: Vec<RenderTile>,
+    batches: Vec<TileBatch>,
+    /// Nested scissors, the last one clips the tiles drawn next
+    scissors: Vec<Rect>,
+}
+
+/// Tiles drawn with the same scissor
+#[derive(Debug, Clone, Default)]
+struct TileBatch {
+    /// In pixels from the top left, None draws on the whole screen
+    scissor: Option<Rect>,
+    /// In the order they are drawn in, with their texture or None if solid.
+    /// They are sorted back to front before being drawn
+    tiles: Vec<(RenderTile, Option<AssetId>)>,
 }
 
 impl RenderTileCommands {
     pub fn draw(&mut self, tile: RenderTile) {
-        self.solid.push(tile);
+        self.batch().tiles.push((tile, None));
     }
     pub fn draw_textured(&mut self, tile: RenderTile, texture: AssetId) {
-        self.textured.entry(texture).or_default().push(tile);
+        self.batch().tiles.push((tile, Some(texture)));
+    }
+    /// Draws the sprite with the name in the atlas at `atlas_path` over the `world_rect`
+    /// of the tile. Nothing is drawn until the atlas and its image are loaded.
+    pub fn draw_sprite(&mut self, assets: &Assets, atlas_path: &str, name: &str, tile: RenderTile) {
+        let Some((atlas, image_id)) = assets.get_atlas(atlas_path) else {
+            return;
+        };
+        let (Some(region), Some(image)) = (atlas.get(name), assets.images.get(image_id)) else {
+            return;
+        };
+        let image_size = Vec2::new(image.width as f32, image.height as f32);
+        let clip_rect =
+            atlas_to_uv_pad_offset(image_size, region.size, Vec2::ZERO, Vec2::ZERO, region.pos);
+        self.draw_textured(RenderTile { clip_rect, ..tile }, image_id.clone());
+    }
+    /// Tiles drawn until the matching `pop_scissor` are cut to the rect,
+    /// and to the scissors pushed before it
+    pub fn push_scissor(&mut self, rect: Rect) {
+        let rect = match self.scissors.last() {
+            Some(outer) => outer.intersect(&rect),
+            None => rect,
+        };
+        self.scissors.push(rect);
+    }
+    pub fn pop_scissor(&mut self) {
+        self.scissors.pop();
+    }
+    /// Textures of the tiles drawn
+    pub fn assets(&self) -> impl Iterator<Item = &AssetId> {
+        self.batches
+            .iter()
+            .flat_map(|batch| batch.tiles.iter())
+            .filter_map(|(_, texture)| texture.as_ref())
     }
     pub fn clear(&mut self) {
-        self.textured.clear();
-        self.solid.clear();
+        self.batches.clear();
+        self.scissors.clear();
+    }
+    fn batch(&mut self) -> &mut TileBatch {
+        let scissor = self.scissors.last().copied();
+        let index = match self.batches.iter().position(|b| b.scissor == scissor) {
+            Some(index) => index,
+            None => {
+                self.batches.push(TileBatch {
+                    scissor,
+                    ..Default::default()
+                });
+                self.batches.len() - 1
+            }
+        };
+        &mut self.batches[index]
     }
 }
 
+/// Meshes with the same mesh and image, drawn together with instancing
+type MeshGroups = HashMap<(AssetId, Option<AssetId>), Vec<RenderMesh>>;
+
 #[derive(Debug, Clone, Default)]
 pub struct RenderMeshCommands {
-    meshes: HashMap<AssetId, Vec<RenderMesh>>,
+    meshes: MeshGroups,
+    highlights: MeshGroups,
 }
 
 impl RenderMeshCommands {
     pub fn draw(&mut self, mesh: RenderMesh) {
         self.meshes
-            .entry(mesh.mesh_id.clone())
+            .entry((mesh.mesh_id.clone(), mesh.image_id.clone()))
+            .or_default()
+            .push(mesh);
+    }
+    /// Draws the mesh additively on top of the other meshes, the color alpha is the intensity
+    pub fn highlight(&mut self, mesh: RenderMesh) {
+        self.highlights
+            .entry((mesh.mesh_id.clone(), mesh.image_id.clone()))
             .or_default()
             .push(mesh);
     }
+    /// Meshes and images drawn
+    pub fn assets(&self) -> impl Iterator<Item = &AssetId> {
+        self.meshes
+            .keys()
+            .chain(self.highlights.keys())
+            .flat_map(|(mesh_id, image_id)| std::iter::once(mesh_id).chain(image_id))
+    }
     pub fn clear(&mut self) {
         self.meshes.clear();
+        self.highlights.clear();
     }
 }
 
-#[repr(C)]
-#[derive(Clone, Copy)]
+/// Lines drawn over the meshes, to see rays, bounds and grids
+#[derive(Debug, Clone, Default)]
+pub struct RenderDebugCommands {
+    /// Two vertices per line, in world space
+    lines: Vec<VertexDebug>,
+    /// Drawn by the ui, that has the font
+    pub texts: Vec<DebugText>,
+}
+
+/// Text centered on a point in world space
+#[derive(Debug, Clone)]
+pub struct DebugText {
+    pub pos: Vec3,
+    pub text: String,
+    pub color: Vec4,
+}
+
+/// Segments of the circles
+const DEBUG_CIRCLE_SEGMENTS: usize = 32;
+
+impl RenderDebugCommands {
+    pub fn line(&mut self, from: Vec3, to: Vec3, color: Vec4) {
+        self.lines.extend([
+            VertexDebug { pos: from, color },
+            VertexDebug { pos: to, color },
+        ]);
+    }
+    pub fn ray(&mut self, origin: Vec3, direction: Vec3, length: f32, color: Vec4) {
+        self.line(
+            origin,
+            origin + direction.normalize_or_zero() * length,
+            color,
+        );
+    }
+    /// The edges of the box moved by `model`, like the bounds of a drawn mesh
+    pub fn wire_box(&mut self, aabb: &Aabb, model: Mat4, color: Vec4) {
+        let corners = aabb.corners().map(|corner| model.transform_point3(corner));
+        // the corners differ by one axis along an edge
+        for (a, b) in [
+            (0, 1),
+            (2, 3),
+            (4, 5),
+            (6, 7),
+            (0, 2),
+            (1, 3),
+            (4, 6),
+            (5, 7),
+            (0, 4),
+            (1, 5),
+            (2, 6),
+            (3, 7),
+        ] {
+            self.line(corners[a], corners[b], color);
+        }
+    }
+    /// Circle around the `normal` axis
+    pub fn circle(&mut self, center: Vec3, normal: Vec3, radius: f32, color: Vec4) {
+        let (axis_x, axis_y) = normal.normalize_or_zero().any_orthonormal_pair();
+        let point = |i: usize| {
+            let angle = i as f32 / DEBUG_CIRCLE_SEGMENTS as f32 * std::f32::consts::TAU;
+            center + (axis_x * angle.cos() + axis_y * angle.sin()) * radius
+        };
+        for i in 0..DEBUG_CIRCLE_SEGMENTS {
+            self.line(point(i), point(i + 1), color);
+        }
+    }
+    pub fn text(&mut self, pos: Vec3, text: &str, color: Vec4) {
+        self.texts.push(DebugText {
+            pos,
+            text: text.to_string(),
+            color,
+        });
+    }
+    pub fn clear(&mut self) {
+        self.lines.clear();
+        self.texts.clear();
+    }
+}
+
+#[derive(Clone)]
 struct MeshBuffers {
     vertex_buffer: BufferId,
     index_buffer: BufferId,
-    indices_len: usize,
+    submeshes: Vec<SubmeshBuffer>,
+    bounds: Aabb,
+    /// Vertices and indices that fit in the buffers, if they can be updated
+    dynamic_capacity: Option<(usize, usize)>,
+}
+
+impl MeshBuffers {
+    fn new(ctx: &mut Box<dyn RenderingBackend>, mesh: &Mesh, usage: BufferUsage) -> Self {
+        let vertices = vertices_of(mesh);
+        let vertex_buffer = ctx.new_buffer(
+            BufferType::VertexBuffer,
+            usage,
+            BufferSource::slice(&vertices),
+        );
+        // 32 bit indices only when needed, es2 has them as an extension
+        let index_buffer = match short_indices_of(mesh) {
+            Some(indices) => ctx.new_buffer(
+                BufferType::IndexBuffer,
+                usage,
+                BufferSource::slice(&indices),
+            ),
+            None => ctx.new_buffer(
+                BufferType::IndexBuffer,
+                usage,
+                BufferSource::slice(&mesh.indices),
+            ),
+        };
+        let dynamic_capacity = match usage {
+            BufferUsage::Immutable => None,
+            _ => Some((mesh.vertices.len(), mesh.indices.len())),
+        };
+        Self {
+            vertex_buffer,
+            index_buffer,
+            submeshes: submeshes_of(mesh),
+            bounds: Aabb::from_points(&mesh.vertices),
+            dynamic_capacity,
+        }
+    }
+
+    /// Writes the mesh over the buffers, false if they can't hold it
+    fn update(&mut self, ctx: &mut Box<dyn RenderingBackend>, mesh: &Mesh) -> bool {
+        let Some((vertex_capacity, index_capacity)) = self.dynamic_capacity else {
+            return false;
+        };
+        // the index size is chosen by the vertices, it can't change
+        let short = vertex_capacity <= u16::MAX as usize + 1;
+        if mesh.vertices.len() > vertex_capacity
+            || mesh.indices.len() > index_capacity
+            || short_indices_of(mesh).is_some() != short
+        {
+            return false;
+        }
+        let vertices = vertices_of(mesh);
+        ctx.buffer_update(self.vertex_buffer, BufferSource::slice(&vertices));
+        match short_indices_of(mesh) {
+            Some(indices) => ctx.buffer_update(self.index_buffer, BufferSource::slice(&indices)),
+            None => ctx.buffer_update(self.index_buffer, BufferSource::slice(&mesh.indices)),
+        }
+        // the images stay with the submeshes that keep their place
+        let images: Vec<Option<AssetId>> =
+            self.submeshes.iter().map(|s| s.image_id.clone()).collect();
+        self.submeshes = submeshes_of(mesh);
+        for (submesh, image_id) in self.submeshes.iter_mut().zip(images) {
+            submesh.image_id = image_id;
+        }
+        self.bounds = Aabb::from_points(&mesh.vertices);
+        true
+    }
+}
+
+/// Vertices of the 3d pipelines, with smooth normals if the mesh has none
+fn vertices_of(mesh: &Mesh) -> Vec<Vertex3d> {
+    let smooth_normals;
+    let normals = if mesh.normals.len() == mesh.vertices.len() {
+        &mesh.normals
+    } else {
+        smooth_normals = smooth_normals_of(mesh);
+        &smooth_normals
+    };
+    mesh.vertices
+        .iter()
+        .zip(normals)
+        .enumerate()
+        .map(|(i, (pos, normal))| Vertex3d {
+            pos: *pos,
+            uv: *mesh.uvs.get(i).unwrap_or(&Vec2::ZERO),
+            normal: *normal,
+        })
+        .collect()
+}
+
+/// The indices as 16 bits, if there are few enough vertices
+fn short_indices_of(mesh: &Mesh) -> Option<Vec<u16>> {
+    if mesh.vertices.len() <= u16::MAX as usize + 1 {
+        Some(mesh.indices.iter().map(|&i| i as u16).collect())
+    } else {
+        None
+    }
+}
+
+/// A single submesh for the meshes without materials
+fn submeshes_of(mesh: &Mesh) -> Vec<SubmeshBuffer> {
+    let mut submeshes: Vec<SubmeshBuffer> = mesh
+        .submeshes
+        .iter()
+        .map(|submesh| SubmeshBuffer {
+            indices: submesh.indices.clone(),
+            image_id: None,
+        })
+        .collect();
+    if submeshes.is_empty() {
+        submeshes.push(SubmeshBuffer {
+            indices: 0..mesh.indices.len(),
+            image_id: None,
+        });
+    }
+    submeshes
+}
+
+/// Axis aligned box, around the vertices of a mesh in its own space
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    /// Inverted, so that it contains nothing, if there are no points
+    pub fn from_points(points: &[Vec3]) -> Self {
+        points.iter().fold(
+            Self {
+                min: Vec3::INFINITY,
+                max: Vec3::NEG_INFINITY,
+            },
+            |aabb, point| Self {
+                min: aabb.min.min(*point),
+                max: aabb.max.max(*point),
+            },
+        )
+    }
+
+    pub fn corners(&self) -> [Vec3; 8] {
+        [0, 1, 2, 3, 4, 5, 6, 7].map(|i| {
+            Vec3::select(
+                BVec3::new(i & 1 != 0, i & 2 != 0, i & 4 != 0),
+                self.max,
+                self.min,
+            )
+        })
+    }
+
+    /// False if the box is surely out of view, when all its corners are past
+    /// the same plane of the frustum of `model_view_proj`
+    pub fn in_frustum(&self, model_view_proj: Mat4) -> bool {
+        if self.min.cmpgt(self.max).any() {
+            return false;
+        }
+        let clip = self
+            .corners()
+            .map(|corner| model_view_proj * corner.extend(1.));
+        let outside = |past: fn(&Vec4) -> bool| clip.iter().all(past);
+        !(outside(|c| c.x < -c.w)
+            || outside(|c| c.x > c.w)
+            || outside(|c| c.y < -c.w)
+            || outside(|c| c.y > c.w)
+            || outside(|c| c.z < -c.w)
+            || outside(|c| c.z > c.w))
+    }
+}
+
+/// Indices of a mesh drawn with their own image
+#[derive(Clone)]
+struct SubmeshBuffer {
+    indices: Range<usize>,
+    image_id: Option<AssetId>,
 }
 
 #[repr(C)]
@@ -154,6 +541,42 @@ struct Vertex2d {
 struct Vertex3d {
     pos: Vec3,
     uv: Vec2,
+    normal: Vec3,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+struct VertexDebug {
+    pos: Vec3,
+    color: Vec4,
+}
+
+/// Per instance data of the 3d pipelines
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct Instance3d {
+    model: Mat4,
+    color: Vec4,
+}
+
+/// Light of the 3d meshes: a directional light over an ambient term
+#[derive(Debug, Clone)]
+pub struct RenderLights {
+    /// Where the light travels to, in world space
+    pub direction: Vec3,
+    pub color: Vec3,
+    pub ambient: Vec3,
+}
+
+impl Default for RenderLights {
+    fn default() -> Self {
+        // from behind the camera, a bit above and to the left
+        Self {
+            direction: Vec3::new(0.4, 0.6, 1.),
+            color: Vec3::splat(0.65),
+            ambient: Vec3::splat(0.45),
+        }
+    }
 }
 
 pub struct Camera {
@@ -206,6 +629,16 @@ impl Camera {
         self.mode.projection(resolution) * self.view
     }
 
+    /// Pixels from the top left of a point in world space, None if it's behind the camera
+    pub fn world_to_screen(&self, pos: Vec3, resolution: Vec2) -> Option<Vec2> {
+        let clip = self.view_projection(resolution) * pos.extend(1.);
+        if clip.w <= 0. {
+            return None;
+        }
+        let ndc = clip.xy() / clip.w;
+        Some((ndc * Vec2::new(0.5, -0.5) + 0.5) * resolution)
+    }
+
     pub fn ray_from_cursor(&self, cursor: &Vec2, resolution: &Vec2) -> (Vec3, Vec3) {
         // PERF: slow calls to .inverse()
         let projection_view = self.view_projection(*resolution).inverse();
@@ -248,15 +681,85 @@ impl CameraMode {
     }
 }
 
+/// The scene is drawn in it with multisampling, then resolved and drawn on the screen
+struct MsaaTarget {
+    pass: RenderPass,
+    color: TextureId,
+    /// Attached with gl calls, miniquad can't attach a multisampled depth
+    depth_renderbuffer: u32,
+    resolve: TextureId,
+    size: UVec2,
+    sample_count: i32,
+}
+
+/// A texture the meshes are drawn on instead of the screen, with its depth
+struct TextureTarget {
+    pass: RenderPass,
+    size: UVec2,
+}
+
+/// Uv of the whole texture of a render target, its rows start from the bottom
+pub const RENDER_TARGET_CLIP: Rect = Rect {
+    pos: Vec2::Y,
+    size: Vec2::new(1., -1.),
+};
+
+// from ES3 and desktop gl, missing from miniquad
+const GL_MAX_SAMPLES: u32 = 0x8D57;
+
+/// Most samples of the offscreen targets, 1 if they can't be resolved.
+/// Webgl1 can't, its canvas is multisampled by the browser instead.
+fn max_sample_count(ctx: &dyn RenderingBackend) -> i32 {
+    if !ctx.info().features.resolve_attachments || ctx.info().backend != Backend::OpenGl {
+        return 1;
+    }
+    let mut max = 1;
+    unsafe { miniquad::gl::glGetIntegerv(GL_MAX_SAMPLES, &mut max) };
+    max.max(1)
+}
+
 // 2d and 3d OpenGLES2 immediate renderer
 pub struct Renderer {
     pipeline_2d: Pipeline,
     pipeline_3d: Pipeline,
+    pipeline_3d_highlight: Pipeline,
+    pipeline_debug: Pipeline,
     bindings: Bindings,
+    /// Vertices of the debug lines, drawn in order by the index buffer
+    debug_bindings: Bindings,
     textures: HashMap<AssetId, TextureId>,
+    /// Size of each texture, with its mipmaps
+    texture_bytes: HashMap<AssetId, usize>,
+    /// Frame each texture was last drawn in
+    texture_last_used: HashMap<AssetId, u64>,
+    frame: u64,
+    /// Past it textures not drawn in this frame are deleted, the images stay in `Assets`
+    pub texture_budget: usize,
     texture_white_pixel: TextureId,
     mesh_buffers: HashMap<AssetId, MeshBuffers>,
+    /// Transforms and colors of the meshes drawn by an instanced draw call
+    instance_buffer: BufferId,
+    /// Without it, each instance is a draw call
+    instancing: bool,
+    max_anisotropy: f32,
+    max_sample_count: i32,
+    /// None draws directly on the screen, without multisampling
+    msaa: Option<MsaaTarget>,
+    /// Of the textures drawn by `render_to_texture`, by their id
+    texture_targets: HashMap<AssetId, TextureTarget>,
     pub camera: Camera,
+    pub lights: RenderLights,
+    /// Counted while the frame is drawn
+    stats: Cell<RenderStats>,
+}
+
+/// What the last frame took to draw
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RenderStats {
+    pub draw_calls: usize,
+    pub tiles: usize,
+    /// Instances in view, the culled ones aren't counted
+    pub meshes: usize,
 }
 
 impl Renderer {
@@ -333,12 +836,34 @@ impl Renderer {
             )
             .unwrap();
 
+        let instances = [Instance3d::default(); MAX_INSTANCES];
+        let instance_buffer = ctx.new_buffer(
+            BufferType::VertexBuffer,
+            BufferUsage::Stream,
+            BufferSource::slice(&instances),
+        );
+        let instancing = ctx.info().features.instancing;
+        let max_anisotropy = max_anisotropy(ctx.as_ref());
+        let max_sample_count = max_sample_count(ctx.as_ref());
+
+        let layout_3d = [
+            BufferLayout::default(),
+            BufferLayout {
+                step_func: VertexStep::PerInstance,
+                ..Default::default()
+            },
+        ];
+        let attributes_3d = [
+            VertexAttribute::new("vertex_pos", VertexFormat::Float3),
+            VertexAttribute::new("vertex_uv", VertexFormat::Float2),
+            VertexAttribute::new("vertex_normal", VertexFormat::Float3),
+            VertexAttribute::with_buffer("instance_model", VertexFormat::Mat4, 1),
+            VertexAttribute::with_buffer("instance_color", VertexFormat::Float4, 1),
+        ];
+
         let pipeline_3d = ctx.new_pipeline(
-            &[BufferLayout::default()],
-            &[
-                VertexAttribute::new("vertex_pos", VertexFormat::Float3),
-                VertexAttribute::new("vertex_uv", VertexFormat::Float2),
-            ],
+            &layout_3d,
+            &attributes_3d,
             shader_3d,
             PipelineParams {
                 color_blend: Some(BlendState::new(
@@ -357,32 +882,216 @@ impl Renderer {
             },
         );
 
+        // additive, drawn over the meshes of the same position
+        let pipeline_3d_highlight = ctx.new_pipeline(
+            &layout_3d,
+            &attributes_3d,
+            shader_3d,
+            PipelineParams {
+                color_blend: Some(BlendState::new(
+                    Equation::Add,
+                    BlendFactor::Value(BlendValue::SourceAlpha),
+                    BlendFactor::One,
+                )),
+                alpha_blend: Some(BlendState::new(
+                    Equation::Add,
+                    BlendFactor::Zero,
+                    BlendFactor::One,
+                )),
+                depth_test: Comparison::Always,
+                depth_write: false,
+                ..Default::default()
+            },
+        );
+
+        let shader_debug = ctx
+            .new_shader(
+                ShaderSource::Glsl {
+                    vertex: shader_debug::VERTEX,
+                    fragment: shader_debug::FRAGMENT,
+                },
+                shader_debug::meta(),
+            )
+            .unwrap();
+
+        // seen through the meshes
+        let pipeline_debug = ctx.new_pipeline(
+            &[BufferLayout::default()],
+            &[
+                VertexAttribute::new("vertex_pos", VertexFormat::Float3),
+                VertexAttribute::new("vertex_color", VertexFormat::Float4),
+            ],
+            shader_debug,
+            PipelineParams {
+                color_blend: Some(BlendState::new(
+                    Equation::Add,
+                    BlendFactor::Value(BlendValue::SourceAlpha),
+                    BlendFactor::OneMinusValue(BlendValue::SourceAlpha),
+                )),
+                depth_test: Comparison::Always,
+                depth_write: false,
+                primitive_type: PrimitiveType::Lines,
+                ..Default::default()
+            },
+        );
+
+        let debug_vertices = vec![VertexDebug::default(); MAX_DEBUG_VERTICES];
+        let debug_indices: Vec<u16> = (0..MAX_DEBUG_VERTICES as u32).map(|i| i as u16).collect();
+        let debug_bindings = Bindings {
+            vertex_buffers: vec![ctx.new_buffer(
+                BufferType::VertexBuffer,
+                BufferUsage::Stream,
+                BufferSource::slice(&debug_vertices),
+            )],
+            index_buffer: ctx.new_buffer(
+                BufferType::IndexBuffer,
+                BufferUsage::Immutable,
+                BufferSource::slice(&debug_indices),
+            ),
+            images: vec![],
+        };
+
         Self {
             pipeline_2d,
             pipeline_3d,
+            pipeline_3d_highlight,
+            pipeline_debug,
             bindings,
+            debug_bindings,
             textures: HashMap::new(),
+            texture_bytes: HashMap::new(),
+            texture_last_used: HashMap::new(),
+            frame: 0,
+            texture_budget: TEXTURE_BUDGET,
             texture_white_pixel,
             mesh_buffers: HashMap::new(),
+            instance_buffer,
+            instancing,
+            max_anisotropy,
+            max_sample_count,
+            msaa: None,
+            texture_targets: HashMap::new(),
             camera,
+            lights: RenderLights::default(),
+            stats: Cell::new(RenderStats::default()),
         }
     }
 
+    pub fn stats(&self) -> RenderStats {
+        self.stats.get()
+    }
+
+    /// Bytes of the textures on the gpu
+    pub fn texture_memory(&self) -> usize {
+        self.texture_bytes.values().sum()
+    }
+
+    /// Every draw call goes through here to be counted
+    fn draw_call(
+        &self,
+        ctx: &mut Box<dyn RenderingBackend>,
+        base_element: i32,
+        num_elements: i32,
+        num_instances: i32,
+    ) {
+        ctx.draw(base_element, num_elements, num_instances);
+        self.count(|stats| stats.draw_calls += 1);
+    }
+
+    fn count(&self, f: impl FnOnce(&mut RenderStats)) {
+        let mut stats = self.stats.get();
+        f(&mut stats);
+        self.stats.set(stats);
+    }
+
     pub fn check_load_texture(
         &mut self,
         ctx: &mut Box<dyn RenderingBackend>,
         image: &Image,
         id: &AssetId,
-        filter_mode: FilterMode,
+        options: &TextureOptions,
     ) {
         if !self.textures.contains_key(id) {
-            let texture =
-                ctx.new_texture_from_rgba8(image.width as u16, image.height as u16, &image.raw);
-            ctx.texture_set_filter(texture, filter_mode, MipmapFilterMode::None);
+            // es2 can only sample the mipmaps of power of two sizes
+            let mipmaps =
+                options.mipmaps && image.width.is_power_of_two() && image.height.is_power_of_two();
+            let texture = ctx.new_texture(
+                TextureAccess::Static,
+                TextureSource::Bytes(&image.raw),
+                TextureParams {
+                    width: image.width,
+                    height: image.height,
+                    format: TextureFormat::RGBA8,
+                    min_filter: options.filter,
+                    mag_filter: options.filter,
+                    mipmap_filter: MipmapFilterMode::None,
+                    allocate_mipmaps: mipmaps,
+                    ..Default::default()
+                },
+            );
+            let mut bytes = image.raw.len();
+            if mipmaps {
+                self.upload_mipmaps(ctx, texture, image, options);
+                // each level is a quarter of the one before
+                bytes += bytes / 3;
+            }
             self.textures.insert(id.clone(), texture);
+            self.texture_bytes.insert(id.clone(), bytes);
+            self.texture_last_used.insert(id.clone(), self.frame);
         }
     }
 
+    /// Fills the levels of the texture with the image downsampled on the cpu
+    fn upload_mipmaps(
+        &self,
+        ctx: &mut Box<dyn RenderingBackend>,
+        texture: TextureId,
+        image: &Image,
+        options: &TextureOptions,
+    ) {
+        match unsafe { ctx.texture_raw_id(texture) } {
+            RawId::OpenGl(raw) if ctx.info().backend == Backend::OpenGl => unsafe {
+                use miniquad::gl::*;
+                glActiveTexture(GL_TEXTURE0);
+                glBindTexture(GL_TEXTURE_2D, raw);
+                for (level, mipmap) in image.mipmaps().iter().enumerate() {
+                    glTexImage2D(
+                        GL_TEXTURE_2D,
+                        level as i32 + 1,
+                        GL_RGBA as i32,
+                        mipmap.width as i32,
+                        mipmap.height as i32,
+                        0,
+                        GL_RGBA,
+                        GL_UNSIGNED_BYTE,
+                        mipmap.raw.as_ptr() as *const _,
+                    );
+                }
+                let anisotropy = options.anisotropy.min(self.max_anisotropy);
+                if anisotropy > 1. {
+                    glTexParameterf(GL_TEXTURE_2D, GL_TEXTURE_MAX_ANISOTROPY, anisotropy);
+                }
+            },
+            _ => ctx.texture_generate_mipmaps(texture),
+        }
+        // miniquad binds the texture again and restores the one it tracks as bound
+        ctx.texture_set_filter(texture, options.filter, MipmapFilterMode::Linear);
+    }
+
+    /// Replaces the texture of an already loaded image
+    pub fn reload_texture(
+        &mut self,
+        ctx: &mut Box<dyn RenderingBackend>,
+        image: &Image,
+        id: &AssetId,
+        options: &TextureOptions,
+    ) {
+        if let Some(texture) = self.textures.remove(id) {
+            ctx.delete_texture(texture);
+        }
+        self.check_load_texture(ctx, image, id, options);
+    }
+
     pub fn check_load_mesh(
         &mut self,
         ctx: &mut Box<dyn RenderingBackend>,
@@ -390,43 +1099,330 @@ impl Renderer {
         id: &AssetId,
     ) {
         if !self.mesh_buffers.contains_key(id) {
-            let mut vertices: Vec<Vertex3d> = vec![];
-            for i in 0..mesh.vertices.len() {
-                vertices.push(Vertex3d {
-                    pos: mesh.vertices[i],
-                    uv: *mesh.uvs.get(i).unwrap_or(&Vec2::ZERO),
-                });
+            let buffers = MeshBuffers::new(ctx, mesh, BufferUsage::Immutable);
+            self.mesh_buffers.insert(id.clone(), buffers);
+        }
+    }
+
+    /// Uploads a mesh made while playing, like generated geometry, to draw it with `id`.
+    /// Uploaded again, its buffers are updated in place if they are big enough
+    pub fn upload_dynamic_mesh(
+        &mut self,
+        ctx: &mut Box<dyn RenderingBackend>,
+        id: &AssetId,
+        mesh: &Mesh,
+    ) {
+        if let Some(buffers) = self.mesh_buffers.get_mut(id) {
+            if buffers.update(ctx, mesh) {
+                return;
             }
-            let vertex_buffer = ctx.new_buffer(
-                BufferType::VertexBuffer,
-                BufferUsage::Immutable,
-                BufferSource::slice(&vertices),
-            );
-            let index_buffer = ctx.new_buffer(
-                BufferType::IndexBuffer,
-                BufferUsage::Immutable,
-                BufferSource::slice(&mesh.indices),
+        }
+        if let Some(buffers) = self.mesh_buffers.remove(id) {
+            ctx.delete_buffer(buffers.vertex_buffer);
+            ctx.delete_buffer(buffers.index_buffer);
+        }
+        let buffers = MeshBuffers::new(ctx, mesh, BufferUsage::Dynamic);
+        self.mesh_buffers.insert(id.clone(), buffers);
+    }
+
+    /// Replaces the buffers of an already loaded mesh
+    pub fn reload_mesh(&mut self, ctx: &mut Box<dyn RenderingBackend>, mesh: &Mesh, id: &AssetId) {
+        if let Some(buffers) = self.mesh_buffers.remove(id) {
+            ctx.delete_buffer(buffers.vertex_buffer);
+            ctx.delete_buffer(buffers.index_buffer);
+        }
+        self.check_load_mesh(ctx, mesh, id);
+    }
+
+    /// Deletes the texture and the buffers of the asset
+    pub fn unload(&mut self, ctx: &mut Box<dyn RenderingBackend>, id: &AssetId) {
+        if let Some(target) = self.texture_targets.remove(id) {
+            // with its textures
+            ctx.delete_render_pass(target.pass);
+            self.textures.remove(id);
+        } else if let Some(texture) = self.textures.remove(id) {
+            ctx.delete_texture(texture);
+        }
+        self.texture_bytes.remove(id);
+        self.texture_last_used.remove(id);
+        if let Some(buffers) = self.mesh_buffers.remove(id) {
+            ctx.delete_buffer(buffers.vertex_buffer);
+            ctx.delete_buffer(buffers.index_buffer);
+        }
+    }
+
+    /// Of the assets drawn this frame, the ones that have to be uploaded again:
+    /// textures that were evicted, also the ones of the submeshes, and meshes
+    pub fn missing(&self, used: &HashSet<AssetId>) -> Vec<AssetId> {
+        let mut missing = vec![];
+        for id in used {
+            match self.mesh_buffers.get(id) {
+                Some(mesh) => missing.extend(
+                    mesh.submeshes
+                        .iter()
+                        .filter_map(|submesh| submesh.image_id.as_ref())
+                        .filter(|image_id| !self.textures.contains_key(image_id))
+                        .cloned(),
+                ),
+                None if !self.textures.contains_key(id) => missing.push(id.clone()),
+                None => {}
+            }
+        }
+        missing.sort();
+        missing.dedup();
+        missing
+    }
+
+    /// Marks the textures as drawn in this frame, with the ones of the submeshes,
+    /// then evicts the least recently drawn ones until they fit in the budget
+    pub fn evict(&mut self, ctx: &mut Box<dyn RenderingBackend>, used: &HashSet<AssetId>) {
+        self.frame += 1;
+        for id in used {
+            if let Some(mesh) = self.mesh_buffers.get(id) {
+                for image_id in mesh.submeshes.iter().filter_map(|s| s.image_id.as_ref()) {
+                    if let Some(last_used) = self.texture_last_used.get_mut(image_id) {
+                        *last_used = self.frame;
+                    }
+                }
+            }
+            if let Some(last_used) = self.texture_last_used.get_mut(id) {
+                *last_used = self.frame;
+            }
+        }
+
+        let mut total: usize = self.texture_bytes.values().sum();
+        if total <= self.texture_budget {
+            return;
+        }
+        let mut unused: Vec<(u64, AssetId)> = self
+            .texture_last_used
+            .iter()
+            .filter(|(_, last_used)| **last_used < self.frame)
+            .map(|(id, last_used)| (*last_used, id.clone()))
+            .collect();
+        unused.sort();
+        for (_, id) in unused {
+            if total <= self.texture_budget {
+                break;
+            }
+            total -= self.texture_bytes.get(&id).copied().unwrap_or(0);
+            if let Some(texture) = self.textures.remove(&id) {
+                ctx.delete_texture(texture);
+            }
+            self.texture_bytes.remove(&id);
+            self.texture_last_used.remove(&id);
+        }
+    }
+
+    /// Bounds of a loaded mesh, in its own space
+    pub fn mesh_bounds(&self, id: &AssetId) -> Option<Aabb> {
+        self.mesh_buffers.get(id).map(|mesh| mesh.bounds)
+    }
+
+    /// Sets the image of each submesh, drawn instead of the `RenderMesh` image
+    pub fn set_submesh_images(&mut self, id: &AssetId, image_ids: Vec<Option<AssetId>>) {
+        let Some(mesh) = self.mesh_buffers.get_mut(id) else {
+            return;
+        };
+        for (submesh, image_id) in mesh.submeshes.iter_mut().zip(image_ids) {
+            submesh.image_id = image_id;
+        }
+    }
+
+    pub fn max_sample_count(&self) -> i32 {
+        self.max_sample_count
+    }
+
+    /// Creates the multisampled target again when the samples or the resolution change.
+    /// The samples are lowered to the ones the gpu supports.
+    pub fn resize_targets(
+        &mut self,
+        ctx: &mut Box<dyn RenderingBackend>,
+        sample_count: i32,
+        resolution: Vec2,
+    ) {
+        let sample_count = sample_count.min(self.max_sample_count);
+        let size = resolution.max(Vec2::ONE).as_uvec2();
+        if let Some(target) = &self.msaa {
+            if target.sample_count == sample_count && target.size == size {
+                return;
+            }
+        }
+        if let Some(target) = self.msaa.take() {
+            ctx.delete_render_pass(target.pass);
+            ctx.delete_texture(target.color);
+            ctx.delete_texture(target.resolve);
+            unsafe { miniquad::gl::glDeleteRenderbuffers(1, &target.depth_renderbuffer) };
+        }
+        if sample_count <= 1 {
+            return;
+        }
+
+        let params = TextureParams {
+            width: size.x,
+            height: size.y,
+            format: TextureFormat::RGBA8,
+            min_filter: FilterMode::Nearest,
+            mag_filter: FilterMode::Nearest,
+            ..Default::default()
+        };
+        let color = ctx.new_render_texture(TextureParams {
+            sample_count,
+            ..params
+        });
+        let resolve = ctx.new_render_texture(params);
+        let pass = ctx.new_render_pass_mrt(&[color], Some(&[resolve]), None);
+
+        // the framebuffer of the pass is the one bound while it's begun
+        ctx.begin_pass(Some(pass), PassAction::Nothing);
+        let mut depth_renderbuffer = 0;
+        unsafe {
+            use miniquad::gl::*;
+            glGenRenderbuffers(1, &mut depth_renderbuffer);
+            glBindRenderbuffer(GL_RENDERBUFFER, depth_renderbuffer);
+            glRenderbufferStorageMultisample(
+                GL_RENDERBUFFER,
+                sample_count,
+                GL_DEPTH_COMPONENT16,
+                size.x as i32,
+                size.y as i32,
             );
-            self.mesh_buffers.insert(
-                id.clone(),
-                MeshBuffers {
-                    vertex_buffer,
-                    index_buffer,
-                    indices_len: mesh.indices.len(),
-                },
+            glFramebufferRenderbuffer(
+                GL_FRAMEBUFFER,
+                GL_DEPTH_ATTACHMENT,
+                GL_RENDERBUFFER,
+                depth_renderbuffer,
             );
+            glBindRenderbuffer(GL_RENDERBUFFER, 0);
         }
+        ctx.end_render_pass();
+
+        self.msaa = Some(MsaaTarget {
+            pass,
+            color,
+            depth_renderbuffer,
+            resolve,
+            size,
+            sample_count,
+        });
+    }
+
+    /// Draws the meshes seen from `camera` on a texture of `size`, kept as the texture of `id`
+    /// and drawn like an image with `RENDER_TARGET_CLIP`. There's no image to upload it
+    /// again from, so it's never evicted: it stays until it's unloaded or drawn again
+    pub fn render_to_texture(
+        &mut self,
+        ctx: &mut Box<dyn RenderingBackend>,
+        id: &AssetId,
+        meshes: &RenderMeshCommands,
+        camera: Camera,
+        size: UVec2,
+    ) {
+        let size = size.max(UVec2::ONE);
+        if self
+            .texture_targets
+            .get(id)
+            .is_some_and(|target| target.size != size)
+        {
+            self.unload(ctx, id);
+        }
+        if !self.texture_targets.contains_key(id) {
+            let params = TextureParams {
+                width: size.x,
+                height: size.y,
+                format: TextureFormat::RGBA8,
+                min_filter: FilterMode::Linear,
+                mag_filter: FilterMode::Linear,
+                ..Default::default()
+            };
+            let color = ctx.new_render_texture(params);
+            let depth = ctx.new_render_texture(TextureParams {
+                format: TextureFormat::Depth,
+                ..params
+            });
+            let pass = ctx.new_render_pass(color, Some(depth));
+            self.textures.insert(id.clone(), color);
+            self.texture_bytes
+                .insert(id.clone(), (size.x * size.y * 4) as usize);
+            self.texture_targets
+                .insert(id.clone(), TextureTarget { pass, size });
+        }
+
+        let pass = self.texture_targets[id].pass;
+        ctx.begin_pass(
+            Some(pass),
+            PassAction::Clear {
+                color: Some((0., 0., 0., 0.)),
+                depth: Some(1.),
+                stencil: None,
+            },
+        );
+        let screen_camera = std::mem::replace(&mut self.camera, camera);
+        self.pass_3d(ctx, meshes, size.as_vec2());
+        self.camera = screen_camera;
+        ctx.end_render_pass();
     }
 
+    /// Draws the frame on the screen, through the multisampled target if there is one
     pub fn draw(
         &self,
         ctx: &mut Box<dyn RenderingBackend>,
         tiles: &RenderTileCommands,
         meshes: &RenderMeshCommands,
+        debug: &RenderDebugCommands,
         resolution: Vec2,
     ) {
+        let clear = || PassAction::Clear {
+            color: Some((0.0, 0.0, 0.0, 1.)),
+            depth: Some(1.),
+            stencil: None,
+        };
+        match &self.msaa {
+            Some(target) => ctx.begin_pass(Some(target.pass), clear()),
+            None => ctx.begin_default_pass(clear()),
+        }
+        self.stats.set(RenderStats::default());
         self.pass_3d(ctx, meshes, resolution);
+        self.pass_debug(ctx, debug, resolution);
         self.pass_2d(ctx, tiles, resolution);
+        ctx.end_render_pass();
+
+        if let Some(target) = &self.msaa {
+            ctx.begin_default_pass(clear());
+            ctx.apply_pipeline(&self.pipeline_2d);
+            let mut tile_buffer = TileBuffer::new(resolution, target.resolve);
+            // the rows of a render target start from the bottom
+            tile_buffer.tiles_to_triangles([&RenderTile {
+                world_rect: Rect::new(Vec2::ZERO, resolution),
+                clip_rect: RENDER_TARGET_CLIP,
+                ..Default::default()
+            }]);
+            tile_buffer.render(ctx, self);
+            ctx.end_render_pass();
+        }
+    }
+
+    pub fn pass_debug(
+        &self,
+        ctx: &mut Box<dyn RenderingBackend>,
+        debug_commands: &RenderDebugCommands,
+        resolution: Vec2,
+    ) {
+        if debug_commands.lines.is_empty() {
+            return;
+        }
+        ctx.apply_pipeline(&self.pipeline_debug);
+        ctx.apply_uniforms(UniformsSource::table(&shader_debug::Uniforms {
+            view_projection: self.camera.view_projection(resolution),
+        }));
+        for chunk in debug_commands.lines.chunks(MAX_DEBUG_VERTICES) {
+            ctx.buffer_update(
+                self.debug_bindings.vertex_buffers[0],
+                BufferSource::slice(chunk),
+            );
+            ctx.apply_bindings(&self.debug_bindings);
+            self.draw_call(ctx, 0, chunk.len() as i32, 1);
+        }
     }
 
     pub fn pass_2d(
@@ -437,21 +1433,44 @@ impl Renderer {
     ) {
         ctx.apply_pipeline(&self.pipeline_2d);
 
-        if !tile_commands.solid.is_empty() {
-            let mut tile_buffer = TileBuffer::new(resolution, self.texture_white_pixel);
-            tile_buffer.tiles_to_triangles(&tile_commands.solid);
-            tile_buffer.render(ctx, &self);
-        }
+        for batch in tile_commands.batches.iter() {
+            let scissor = batch
+                .scissor
+                .unwrap_or(Rect::new(Vec2::ZERO, resolution))
+                .intersect(&Rect::new(Vec2::ZERO, resolution));
+            // the scissor of opengl starts from the bottom left
+            ctx.apply_scissor_rect(
+                scissor.pos.x.round() as i32,
+                (resolution.y - scissor.pos.y - scissor.size.y).round() as i32,
+                scissor.size.x.round() as i32,
+                scissor.size.y.round() as i32,
+            );
 
-        for (asset_id, tiles) in tile_commands.textured.iter() {
-            let Some(texture_id) = self.textures.get(&asset_id) else {
-                eprintln!("No texture for asset_id: {:?}", asset_id);
-                return;
-            };
-            let mut tile_buffer = TileBuffer::new(resolution, *texture_id);
-            tile_buffer.tiles_to_triangles(&tiles);
-            tile_buffer.render(ctx, &self);
+            // back to front so that translucent tiles blend over what's behind them,
+            // the tiles of a texture at the same depth are drawn together
+            let mut tiles: Vec<&(RenderTile, Option<AssetId>)> = batch.tiles.iter().collect();
+            tiles.sort_by(|(a, a_texture), (b, b_texture)| {
+                b.z.total_cmp(&a.z).then_with(|| a_texture.cmp(b_texture))
+            });
+            for run in tiles.chunk_by(|(_, a), (_, b)| a == b) {
+                let texture_id = match &run[0].1 {
+                    Some(asset_id) => match self.textures.get(asset_id) {
+                        Some(texture_id) => *texture_id,
+                        None => {
+                            eprintln!("No texture for asset_id: {:?}", asset_id);
+                            continue;
+                        }
+                    },
+                    None => self.texture_white_pixel,
+                };
+                let mut tile_buffer = TileBuffer::new(resolution, texture_id);
+                tile_buffer.tiles_to_triangles(run.iter().map(|(tile, _)| tile));
+                tile_buffer.render(ctx, &self);
+            }
+            self.count(|stats| stats.tiles += batch.tiles.len());
         }
+
+        ctx.apply_scissor_rect(0, 0, resolution.x as i32, resolution.y as i32);
     }
 
     pub fn pass_3d(
@@ -464,44 +1483,111 @@ impl Renderer {
 
         let view_proj = self.camera.view_projection(resolution);
 
-        for (mesh_id, meshes) in mesh_commands.meshes.iter() {
-            for render_mesh in meshes.iter() {
-                let Some(texture_id) = (match render_mesh.image_id {
-                    Some(ref image_id) => self.textures.get(&image_id).copied(),
-                    None => Some(self.texture_white_pixel),
-                }) else {
-                    eprintln!("No texture for mesh_id: {:?}", mesh_id);
-                    return;
-                };
+        for (key, meshes) in mesh_commands.meshes.iter() {
+            if !self.draw_meshes(ctx, key, meshes, view_proj) {
+                return;
+            }
+        }
 
-                let Some(mesh) = self.mesh_buffers.get(mesh_id) else {
-                    eprintln!("No mesh buffers for mesh_id: {:?}", mesh_id);
+        if !mesh_commands.highlights.is_empty() {
+            ctx.apply_pipeline(&self.pipeline_3d_highlight);
+            for (key, meshes) in mesh_commands.highlights.iter() {
+                if !self.draw_meshes(ctx, key, meshes, view_proj) {
                     return;
-                };
+                }
+            }
+        }
+    }
+
+    /// Draws the meshes of a group with as few draw calls as the instance buffer allows.
+    /// Returns false if the gpu resources of the mesh are missing
+    fn draw_meshes(
+        &self,
+        ctx: &mut Box<dyn RenderingBackend>,
+        (mesh_id, image_id): &(AssetId, Option<AssetId>),
+        render_meshes: &[RenderMesh],
+        view_proj: Mat4,
+    ) -> bool {
+        let Some(texture_id) = (match image_id {
+            Some(image_id) => self.textures.get(image_id).copied(),
+            None => Some(self.texture_white_pixel),
+        }) else {
+            eprintln!("No texture for mesh_id: {:?}", mesh_id);
+            return false;
+        };
+
+        let Some(mesh) = self.mesh_buffers.get(mesh_id) else {
+            eprintln!("No mesh buffers for mesh_id: {:?}", mesh_id);
+            return false;
+        };
 
+        // the meshes out of view are skipped
+        let instances: Vec<Instance3d> = render_meshes
+            .iter()
+            .map(|render_mesh| Instance3d {
+                model: render_mesh.transform.to_mat4(),
+                color: render_mesh.color,
+            })
+            .filter(|instance| mesh.bounds.in_frustum(view_proj * instance.model))
+            .collect();
+
+        ctx.apply_uniforms(UniformsSource::table(&shader_3d::Uniforms {
+            view_projection: view_proj,
+            light_direction: self.lights.direction.normalize_or_zero(),
+            light_color: self.lights.color,
+            ambient: self.lights.ambient,
+        }));
+        let chunk_len = if self.instancing { MAX_INSTANCES } else { 1 };
+        for chunk in instances.chunks(chunk_len) {
+            ctx.buffer_update(self.instance_buffer, BufferSource::slice(chunk));
+            for submesh in mesh.submeshes.iter() {
+                let texture_id = submesh
+                    .image_id
+                    .as_ref()
+                    .and_then(|image_id| self.textures.get(image_id).copied())
+                    .unwrap_or(texture_id);
                 ctx.apply_bindings(&Bindings {
-                    vertex_buffers: vec![mesh.vertex_buffer],
+                    vertex_buffers: vec![mesh.vertex_buffer, self.instance_buffer],
                     index_buffer: mesh.index_buffer,
                     images: vec![texture_id],
                 });
-
-                let transform = Mat4::from_scale_rotation_translation(
-                    render_mesh.transform.scale,
-                    render_mesh.transform.rotation,
-                    render_mesh.transform.translation,
+                self.draw_call(
+                    ctx,
+                    submesh.indices.start as i32,
+                    submesh.indices.len() as i32,
+                    chunk.len() as i32,
                 );
-                let mvp = view_proj * transform;
-
-                ctx.apply_uniforms(UniformsSource::table(&shader_3d::Uniforms {
-                    world_transform: mvp,
-                    color: render_mesh.color,
-                }));
-                ctx.draw(0, mesh.indices_len as i32, 1);
             }
         }
+        self.count(|stats| stats.meshes += instances.len());
+        true
     }
 }
 
+/// Normals of a mesh that has none, the average of the faces around each vertex
+fn smooth_normals_of(mesh: &Mesh) -> Vec<Vec3> {
+    let mut normals = vec![Vec3::ZERO; mesh.vertices.len()];
+    for face in mesh.indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| face[i] as usize);
+        let (Some(pa), Some(pb), Some(pc)) = (
+            mesh.vertices.get(a),
+            mesh.vertices.get(b),
+            mesh.vertices.get(c),
+        ) else {
+            continue;
+        };
+        // weighted by the area of the face
+        let normal = (*pb - *pa).cross(*pc - *pa);
+        for i in [a, b, c] {
+            normals[i] += normal;
+        }
+    }
+    normals
+        .into_iter()
+        .map(|normal| normal.try_normalize().unwrap_or(Vec3::Z))
+        .collect()
+}
+
 struct TileBuffer {
     vertices: Vec<Vertex2d>,
     indices: Vec<u16>,
@@ -521,7 +1607,7 @@ impl TileBuffer {
         }
     }
 
-    fn tiles_to_triangles(&mut self, tiles: &Vec<RenderTile>) {
+    fn tiles_to_triangles<'a>(&mut self, tiles: impl IntoIterator<Item = &'a RenderTile>) {
         for tile in tiles {
             let mut vs = [
                 Vertex2d {
@@ -575,7 +1661,7 @@ impl TileBuffer {
             images: vec![self.texture_id],
             ..renderer.bindings.clone()
         });
-        ctx.draw(0, self.indices.len() as i32, 1);
+        renderer.draw_call(ctx, 0, self.indices.len() as i32, 1);
     }
 }
 
@@ -617,23 +1703,33 @@ mod shader_3d {
     pub const VERTEX: &str = r#"#version 100
     attribute vec3 vertex_pos;
     attribute vec2 vertex_uv;
-    uniform mat4 world_transform;
-    uniform vec4 color;
+    attribute vec3 vertex_normal;
+    attribute mat4 instance_model;
+    attribute vec4 instance_color;
+    uniform mat4 view_projection;
+    uniform vec3 light_direction;
+    uniform vec3 light_color;
+    uniform vec3 ambient;
     varying lowp vec4 forward_color;
     varying lowp vec2 texcoord;
+    varying lowp vec3 lighting;
     void main() {
         vec4 pos = vec4(vertex_pos, 1);
-        gl_Position = world_transform * pos;
-        forward_color = color;
+        gl_Position = view_projection * instance_model * pos;
+        forward_color = instance_color;
         texcoord = vertex_uv;
+        // lambert, the model is scaled uniformly so it can rotate the normal
+        vec3 normal = normalize((instance_model * vec4(vertex_normal, 0)).xyz);
+        lighting = ambient + light_color * max(dot(normal, -light_direction), 0.0);
     }"#;
 
     pub const FRAGMENT: &str = r#"#version 100
     varying lowp vec4 forward_color;
     varying lowp vec2 texcoord;
+    varying lowp vec3 lighting;
     uniform sampler2D tex;
     void main() {
-        gl_FragColor = texture2D(tex, texcoord) * forward_color;
+        gl_FragColor = texture2D(tex, texcoord) * forward_color * vec4(lighting, 1);
         if (gl_FragColor.a <= 0.1) { discard; }
     }"#;
 
@@ -642,8 +1738,10 @@ mod shader_3d {
             images: vec!["tex".to_string()],
             uniforms: UniformBlockLayout {
                 uniforms: vec![
-                    UniformDesc::new("world_transform", UniformType::Mat4),
-                    UniformDesc::new("color", UniformType::Float4),
+                    UniformDesc::new("view_projection", UniformType::Mat4),
+                    UniformDesc::new("light_direction", UniformType::Float3),
+                    UniformDesc::new("light_color", UniformType::Float3),
+                    UniformDesc::new("ambient", UniformType::Float3),
                 ],
             },
         }
@@ -651,7 +1749,43 @@ mod shader_3d {
 
     #[repr(C)]
     pub struct Uniforms {
-        pub world_transform: glam::Mat4,
-        pub color: glam::Vec4,
+        pub view_projection: glam::Mat4,
+        pub light_direction: glam::Vec3,
+        pub light_color: glam::Vec3,
+        pub ambient: glam::Vec3,
+    }
+}
+
+mod shader_debug {
+    use miniquad::*;
+
+    pub const VERTEX: &str = r#"#version 100
+    attribute vec3 vertex_pos;
+    attribute vec4 vertex_color;
+    uniform mat4 view_projection;
+    varying lowp vec4 color;
+    void main() {
+        gl_Position = view_projection * vec4(vertex_pos, 1);
+        color = vertex_color;
+    }"#;
+
+    pub const FRAGMENT: &str = r#"#version 100
+    varying lowp vec4 color;
+    void main() {
+        gl_FragColor = color;
+    }"#;
+
+    pub fn meta() -> ShaderMeta {
+        ShaderMeta {
+            images: vec![],
+            uniforms: UniformBlockLayout {
+                uniforms: vec![UniformDesc::new("view_projection", UniformType::Mat4)],
+            },
+        }
+    }
+
+    #[repr(C)]
+    pub struct Uniforms {
+        pub view_projection: glam::Mat4,
     }
 }
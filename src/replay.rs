@@ -0,0 +1,159 @@
+use crate::{levels::LEVELS, *};
+
+/// A move on the board, by the cell it's made on
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReplayAction {
+    /// Like a click: picks up, drops or rotates in place
+    Activate(usize, usize),
+    Rotate(usize, usize),
+    /// The other way, from a long press
+    RotateBack(usize, usize),
+    /// From a drag between two cells
+    Swap((usize, usize), (usize, usize)),
+    /// Takes back the last move that changed the board
+    Undo,
+    /// A tile of the kind, the index in the tile set, from the bag onto an empty cell
+    Place(usize, usize, usize),
+}
+
+/// The moves of a game and the ticks they were made at, counted from the start of the board.
+/// Saved as a line with the board, "level <index>" or "endless <seed code>",
+/// then a line for each move: "<tick> activate <x> <y>", "<tick> rotate <x> <y>",
+/// "<tick> rotate_back <x> <y>", "<tick> swap <x> <y> <to x> <to y>", "<tick> undo"
+/// or "<tick> place <x> <y> <kind>"
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Replay {
+    /// Index in `LEVELS`, None for an endless board
+    pub level: Option<usize>,
+    /// Seed code of an endless board
+    pub code: String,
+    pub events: Vec<(u64, ReplayAction)>,
+}
+
+impl Replay {
+    const FILE: &'static str = "replay.txt";
+
+    pub fn load() -> Result<Self, String> {
+        Self::from_text(&storage::load(Self::FILE)?)
+    }
+
+    pub fn save(&self) {
+        if let Err(err) = storage::save(Self::FILE, &self.to_text()) {
+            eprintln!("Replay not saved: {err}");
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        let board = match self.level {
+            Some(level) => format!("level {}", level),
+            None => format!("endless {}", self.code),
+        };
+        let events = self.events.iter().map(|(tick, action)| match action {
+            ReplayAction::Activate(x, y) => format!("{} activate {} {}", tick, x, y),
+            ReplayAction::Rotate(x, y) => format!("{} rotate {} {}", tick, x, y),
+            ReplayAction::RotateBack(x, y) => format!("{} rotate_back {} {}", tick, x, y),
+            ReplayAction::Swap((x, y), (to_x, to_y)) => {
+                format!("{} swap {} {} {} {}", tick, x, y, to_x, to_y)
+            }
+            ReplayAction::Undo => format!("{} undo", tick),
+            ReplayAction::Place(x, y, kind) => format!("{} place {} {} {}", tick, x, y, kind),
+        });
+        std::iter::once(board)
+            .chain(events)
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    pub fn from_text(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+        let board = lines.next().ok_or("Replay: empty".to_string())?;
+        let mut replay = match board.split_whitespace().collect::<Vec<&str>>()[..] {
+            ["level", level] => {
+                let level: usize = level.parse().map_err(|e| format!("Replay: {}", e))?;
+                if level >= LEVELS.len() {
+                    return Err(format!("Replay: no level {}", level));
+                }
+                Self {
+                    level: Some(level),
+                    ..Default::default()
+                }
+            }
+            ["endless", code] => Self {
+                code: code.to_string(),
+                ..Default::default()
+            },
+            _ => return Err(format!("Replay: unknown board '{}'", board)),
+        };
+        for (line_number, line) in lines.enumerate() {
+            let parse = |token: &str| {
+                token
+                    .parse::<u64>()
+                    .map_err(|e| format!("Replay: {} at line {}", e, line_number + 2))
+            };
+            let event = match line.split_whitespace().collect::<Vec<&str>>()[..] {
+                [tick, "activate", x, y] => (
+                    parse(tick)?,
+                    ReplayAction::Activate(parse(x)? as usize, parse(y)? as usize),
+                ),
+                [tick, "rotate", x, y] => (
+                    parse(tick)?,
+                    ReplayAction::Rotate(parse(x)? as usize, parse(y)? as usize),
+                ),
+                [tick, "rotate_back", x, y] => (
+                    parse(tick)?,
+                    ReplayAction::RotateBack(parse(x)? as usize, parse(y)? as usize),
+                ),
+                [tick, "swap", x, y, to_x, to_y] => (
+                    parse(tick)?,
+                    ReplayAction::Swap(
+                        (parse(x)? as usize, parse(y)? as usize),
+                        (parse(to_x)? as usize, parse(to_y)? as usize),
+                    ),
+                ),
+                [tick, "undo"] => (parse(tick)?, ReplayAction::Undo),
+                [tick, "place", x, y, kind] => (
+                    parse(tick)?,
+                    ReplayAction::Place(
+                        parse(x)? as usize,
+                        parse(y)? as usize,
+                        parse(kind)? as usize,
+                    ),
+                ),
+                _ => return Err(format!("Replay: invalid move at line {}", line_number + 2)),
+            };
+            replay.events.push(event);
+        }
+        Ok(replay)
+    }
+}
+
+/// Gives back the moves of a replay as their ticks come
+#[derive(Clone, Debug)]
+pub struct ReplayPlayer {
+    pub replay: Replay,
+    /// Index of the next move
+    next: usize,
+    /// False until its board is set up
+    pub started: bool,
+}
+
+impl ReplayPlayer {
+    pub fn new(replay: Replay) -> Self {
+        Self {
+            replay,
+            next: 0,
+            started: false,
+        }
+    }
+
+    /// The moves up to `tick` not given yet
+    pub fn due(&mut self, tick: u64) -> Vec<ReplayAction> {
+        let due: Vec<ReplayAction> = self.replay.events[self.next..]
+            .iter()
+            .take_while(|(event_tick, _)| *event_tick <= tick)
+            .map(|(_, action)| *action)
+            .collect();
+        self.next += due.len();
+        due
+    }
+}
@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+
+/// Simple random generator
+#[derive(Clone, Debug)]
+pub struct RandLCG {
+    seed: u128,
+}
+
+impl RandLCG {
+    // https://en.wikipedia.org/wiki/Linear_congruential_generator#Parameters_in_common_use
+    const INCREMENT_C: u128 = 1;
+    const MULTIPLIER_A: u128 = 6364136223846793005;
+    const MODULUS_M: u128 = 18446744073709551616;
+
+    pub fn new(seed: u128) -> Self {
+        Self { seed }
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        self.seed = (Self::MULTIPLIER_A * self.seed + Self::INCREMENT_C) % Self::MODULUS_M;
+        (self.seed >> 32) as u32
+    }
+
+    /// In 0..1
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1 << 24) as f32
+    }
+
+    /// In -1..1
+    pub fn next_signed(&mut self) -> f32 {
+        self.next_f32() * 2. - 1.
+    }
+}
+
+/// Random generators by name, each seeded from the seed and its name.
+/// Drawing from a stream doesn't change what the others give, so the same seed gives
+/// the same numbers on a stream however much the others were used
+#[derive(Clone, Debug)]
+pub struct Rng {
+    seed: u64,
+    streams: HashMap<&'static str, RandLCG>,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            streams: HashMap::new(),
+        }
+    }
+
+    /// Seeded by the clock, different at each launch
+    pub fn from_time() -> Self {
+        Self::new((miniquad::date::now() * 1000000.) as u64)
+    }
+
+    /// Started at its seed the first time it's used
+    pub fn stream(&mut self, name: &'static str) -> &mut RandLCG {
+        let seed = self.seed;
+        self.streams
+            .entry(name)
+            .or_insert_with(|| RandLCG::new(stream_seed(seed, name) as u128))
+    }
+
+    /// A generator of its own that starts like the stream, for a part that draws
+    /// away from the others
+    pub fn split(&self, name: &str) -> RandLCG {
+        RandLCG::new(stream_seed(self.seed, name) as u128)
+    }
+}
+
+/// The name hashed with FNV-1a into the seed, mixed so that close seeds start far apart
+fn stream_seed(seed: u64, name: &str) -> u64 {
+    let hash = name.bytes().fold(0xcbf2_9ce4_8422_2325, |hash: u64, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    });
+    // the finalizer of splitmix64
+    let mut z = seed ^ hash;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn draws(rand: &mut RandLCG) -> Vec<u32> {
+        (0..8).map(|_| rand.next_u32()).collect()
+    }
+
+    #[test]
+    fn streams_are_independent() {
+        let mut quiet = Rng::new(7);
+        let mut busy = Rng::new(7);
+        for _ in 0..100 {
+            busy.stream("particles").next_u32();
+        }
+        let expected = draws(quiet.stream("board-gen"));
+        assert_eq!(draws(busy.stream("board-gen")), expected);
+        assert_eq!(draws(&mut Rng::new(7).split("board-gen")), expected);
+        assert_ne!(draws(quiet.stream("shuffles")), expected);
+        assert_ne!(draws(Rng::new(8).stream("board-gen")), expected);
+    }
+}
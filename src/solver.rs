@@ -0,0 +1,374 @@
+use crate::*;
+
+/// The tiles of a board to arrange, by their sides as they lie on it
+pub struct Puzzle<'a> {
+    pub sides: Vec<[u8; 4]>,
+    pub grid_size: IVec2,
+    /// False on the holes
+    pub cells: Vec<bool>,
+    /// True on the cells whose tile stays as it is
+    pub locked: Vec<bool>,
+    pub edges: &'a EdgeTypes,
+}
+
+/// A set of options as bits
+#[derive(Clone, Debug, PartialEq)]
+struct Options(Vec<u64>);
+
+impl Options {
+    fn empty(len: usize) -> Self {
+        Self(vec![0; len.div_ceil(64)])
+    }
+
+    fn insert(&mut self, option: usize) {
+        self.0[option / 64] |= 1 << (option % 64);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.iter().all(|word| *word == 0)
+    }
+
+    fn len(&self) -> u32 {
+        self.0.iter().map(|word| word.count_ones()).sum()
+    }
+
+    fn intersects(&self, other: &Options) -> bool {
+        self.0
+            .iter()
+            .zip(other.0.iter())
+            .any(|(word, other)| word & other != 0)
+    }
+
+    fn is_subset(&self, other: &Options) -> bool {
+        self.0
+            .iter()
+            .zip(other.0.iter())
+            .all(|(word, other)| word & !other == 0)
+    }
+
+    fn union(&mut self, other: &Options) {
+        for (word, other) in self.0.iter_mut().zip(other.0.iter()) {
+            *word |= other;
+        }
+    }
+
+    /// Keeps the options in both, true if some were removed
+    fn intersect(&mut self, other: &Options) -> bool {
+        let mut changed = false;
+        for (word, other) in self.0.iter_mut().zip(other.0.iter()) {
+            changed |= *word & other != *word;
+            *word &= other;
+        }
+        changed
+    }
+
+    /// Drops the options in `other`, true if some were removed
+    fn remove(&mut self, other: &Options) -> bool {
+        let mut changed = false;
+        for (word, other) in self.0.iter_mut().zip(other.0.iter()) {
+            changed |= *word & other != 0;
+            *word &= !other;
+        }
+        changed
+    }
+
+    fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.0.iter().enumerate().flat_map(|(i, word)| {
+            (0..64)
+                .filter(move |bit| word & (1 << bit) != 0)
+                .map(move |bit| i * 64 + bit)
+        })
+    }
+}
+
+/// Constraint propagation with backtracking, like wave function collapse.
+/// Identical tiles are grouped in kinds and each distinct rotation of a kind is an option.
+/// Every cell keeps the options that fit its neighbours, the search fixes the cell with
+/// the fewest and propagates the choice until every cell has one.
+/// The tiles left are counted too, a kind can't take more cells than it has tiles or fewer
+struct Solver {
+    /// Sides of each option, the rotations are looked up once
+    option_sides: Vec<[u8; 4]>,
+    option_kind: Vec<usize>,
+    /// Options of each kind
+    kind_options: Vec<Options>,
+    /// Options that can be next to each option, towards each of `SIDE_ADJACENT`
+    compatible: [Vec<Options>; 4],
+    /// Cells next to each cell, towards each of `SIDE_ADJACENT`
+    neighbours: Vec<[Option<usize>; 4]>,
+}
+
+impl<'a> Puzzle<'a> {
+    /// For each cell, the index of the tile placed on it and the times it's rotated left.
+    /// None if there's no solution or the search tries more than `budget` options
+    pub fn solve(&self, budget: u32) -> Option<Vec<Option<(usize, usize)>>> {
+        let rotated = |sides: [u8; 4], rotation: usize| {
+            let mut sides = sides;
+            sides.rotate_right(rotation);
+            sides
+        };
+        // tiles are the same kind if they're the same once rotated
+        let canonical = |sides: [u8; 4]| (0..4).map(|r| rotated(sides, r)).min().unwrap();
+        let mut kinds: Vec<[u8; 4]> = vec![];
+        let mut tile_kind = vec![];
+        for (i, sides) in self.sides.iter().enumerate() {
+            if !self.cells[i] {
+                tile_kind.push(None);
+                continue;
+            }
+            let sides = canonical(*sides);
+            let kind = kinds
+                .iter()
+                .position(|kind| *kind == sides)
+                .unwrap_or_else(|| {
+                    kinds.push(sides);
+                    kinds.len() - 1
+                });
+            tile_kind.push(Some(kind));
+        }
+
+        let mut option_sides = vec![];
+        let mut option_kind = vec![];
+        for (kind, sides) in kinds.iter().enumerate() {
+            // symmetric tiles have fewer distinct rotations
+            let first = option_sides.len();
+            for rotation in 0..4 {
+                let sides = rotated(*sides, rotation);
+                if !option_sides[first..].contains(&sides) {
+                    option_sides.push(sides);
+                    option_kind.push(kind);
+                }
+            }
+        }
+        let options = option_sides.len();
+        let mut kind_options = vec![Options::empty(options); kinds.len()];
+        for (option, kind) in option_kind.iter().enumerate() {
+            kind_options[*kind].insert(option);
+        }
+        let compatible = std::array::from_fn(|dir| {
+            let (side, other_side) = SIDE_CHECK[dir];
+            option_sides
+                .iter()
+                .map(|sides| {
+                    let mut fits = Options::empty(options);
+                    for (other, other_sides) in option_sides.iter().enumerate() {
+                        if self.edges.matches(sides[side], other_sides[other_side]) {
+                            fits.insert(other);
+                        }
+                    }
+                    fits
+                })
+                .collect()
+        });
+        let width = self.grid_size.x as usize;
+        let neighbours = (0..self.sides.len())
+            .map(|i| {
+                let pos = IVec2::new((i % width) as i32, (i / width) as i32);
+                SIDE_ADJACENT.map(|dir| {
+                    let next = pos + dir;
+                    let inside = next.cmpge(IVec2::ZERO).all() && next.cmplt(self.grid_size).all();
+                    // the index of a cell outside would overflow
+                    let j = inside.then(|| next.x as usize + next.y as usize * width)?;
+                    self.cells[j].then_some(j)
+                })
+            })
+            .collect();
+        let solver = Solver {
+            option_sides,
+            option_kind,
+            kind_options,
+            compatible,
+            neighbours,
+        };
+
+        // the locked tiles have a single option, the others can be any
+        let mut counts = vec![0; kinds.len()];
+        let mut domains = vec![];
+        for i in 0..self.sides.len() {
+            let mut domain = Options::empty(options);
+            if !self.cells[i] {
+                domains.push(domain);
+                continue;
+            }
+            if self.locked[i] {
+                let option = solver
+                    .option_sides
+                    .iter()
+                    .position(|s| *s == self.sides[i])?;
+                domain.insert(option);
+            } else {
+                for option in 0..options {
+                    domain.insert(option);
+                }
+                counts[tile_kind[i]?] += 1;
+            }
+            domains.push(domain);
+        }
+        let mut assigned: Vec<Option<usize>> = (0..self.sides.len())
+            .map(|i| {
+                let locked = self.cells[i] && self.locked[i];
+                locked.then(|| domains[i].iter().next()).flatten()
+            })
+            .collect();
+        let all: Vec<usize> = (0..self.sides.len()).filter(|i| self.cells[*i]).collect();
+        if !solver.propagate(&mut domains, &assigned, &counts, all) {
+            return None;
+        }
+        let mut budget = budget;
+        if !solver.search(&mut domains, &mut counts, &mut assigned, &mut budget) {
+            return None;
+        }
+
+        // each option is played by a tile of its kind, turned to match
+        let mut used: Vec<bool> = (0..self.sides.len())
+            .map(|i| !self.cells[i] || self.locked[i])
+            .collect();
+        let mut placed = vec![None; self.sides.len()];
+        for (cell, option) in assigned.iter().enumerate() {
+            let Some(option) = option else {
+                continue;
+            };
+            if self.locked[cell] {
+                placed[cell] = Some((cell, 0));
+                continue;
+            }
+            let sides = solver.option_sides[*option];
+            let kind = Some(solver.option_kind[*option]);
+            let index = (0..self.sides.len()).find(|i| !used[*i] && tile_kind[*i] == kind)?;
+            let rotation = (0..4).find(|r| rotated(self.sides[index], *r) == sides)?;
+            used[index] = true;
+            placed[cell] = Some((index, rotation));
+        }
+        Some(placed)
+    }
+}
+
+impl Solver {
+    /// Removes the options that don't fit the neighbours, starting from the `changed` cells,
+    /// and the kinds that the tiles left can't cover.
+    /// False if a cell is left with none
+    fn propagate(
+        &self,
+        domains: &mut [Options],
+        assigned: &[Option<usize>],
+        counts: &[u32],
+        mut changed: Vec<usize>,
+    ) -> bool {
+        loop {
+            while let Some(cell) = changed.pop() {
+                for (dir, neighbour) in self.neighbours[cell].iter().enumerate() {
+                    let Some(neighbour) = *neighbour else {
+                        continue;
+                    };
+                    let mut fits = Options::empty(self.option_sides.len());
+                    for option in domains[cell].iter() {
+                        fits.union(&self.compatible[dir][option]);
+                    }
+                    if domains[neighbour].intersect(&fits) {
+                        if domains[neighbour].is_empty() {
+                            return false;
+                        }
+                        changed.push(neighbour);
+                    }
+                }
+            }
+
+            // each tile left needs a cell and each cell a tile: a kind that can go on as many
+            // cells as its tiles takes them all, one that fills all its tiles leaves the others
+            let free: Vec<usize> = (0..domains.len())
+                .filter(|i| assigned[*i].is_none() && !domains[*i].is_empty())
+                .collect();
+            for (kind, options) in self.kind_options.iter().enumerate() {
+                let hosts: Vec<usize> = free
+                    .iter()
+                    .copied()
+                    .filter(|i| domains[*i].intersects(options))
+                    .collect();
+                let only: Vec<usize> = hosts
+                    .iter()
+                    .copied()
+                    .filter(|i| domains[*i].is_subset(options))
+                    .collect();
+                let count = counts[kind] as usize;
+                if hosts.len() < count || only.len() > count {
+                    return false;
+                }
+                if hosts.len() == count && only.len() < count {
+                    for i in hosts {
+                        if domains[i].intersect(options) {
+                            changed.push(i);
+                        }
+                    }
+                } else if only.len() == count && hosts.len() > count {
+                    for i in hosts {
+                        if !only.contains(&i) && domains[i].remove(options) {
+                            changed.push(i);
+                        }
+                    }
+                }
+            }
+            if changed.is_empty() {
+                return true;
+            }
+        }
+    }
+
+    fn search(
+        &self,
+        domains: &mut Vec<Options>,
+        counts: &mut Vec<u32>,
+        assigned: &mut Vec<Option<usize>>,
+        budget: &mut u32,
+    ) -> bool {
+        // the cell with the fewest options left
+        let cell = (0..domains.len())
+            .filter(|i| assigned[*i].is_none() && !domains[*i].is_empty())
+            .min_by_key(|i| domains[*i].len());
+        let Some(cell) = cell else {
+            return true;
+        };
+        // the kinds closest to running out of cells first
+        let mut options: Vec<usize> = domains[cell].iter().collect();
+        let free: Vec<usize> = (0..domains.len())
+            .filter(|i| assigned[*i].is_none())
+            .collect();
+        let slack: Vec<usize> = self
+            .kind_options
+            .iter()
+            .enumerate()
+            .map(|(kind, options)| {
+                let hosts = free
+                    .iter()
+                    .filter(|i| domains[**i].intersects(options))
+                    .count();
+                hosts.saturating_sub(counts[kind] as usize)
+            })
+            .collect();
+        options.sort_by_key(|option| slack[self.option_kind[*option]]);
+        for option in options {
+            let kind = self.option_kind[option];
+            if counts[kind] == 0 {
+                continue;
+            }
+            if *budget == 0 {
+                return false;
+            }
+            *budget -= 1;
+
+            let mut next = domains.clone();
+            next[cell] = Options::empty(self.option_sides.len());
+            next[cell].insert(option);
+            counts[kind] -= 1;
+            assigned[cell] = Some(option);
+            if self.propagate(&mut next, assigned, counts, vec![cell])
+                && self.search(&mut next, counts, assigned, budget)
+            {
+                *domains = next;
+                return true;
+            }
+            counts[kind] += 1;
+            assigned[cell] = None;
+        }
+        false
+    }
+}
@@ -0,0 +1,125 @@
+use crate::*;
+
+/// A solved board
+#[derive(Clone, Debug, PartialEq)]
+pub struct Solve {
+    pub grid_size: IVec2,
+    pub seed: u32,
+    pub time: f64,
+    pub moves: u32,
+    pub hints: u32,
+    /// Seconds since the unix epoch it was solved at
+    pub date: f64,
+}
+
+/// Every board solved, the oldest first, saved to disk on every solve
+#[derive(Default)]
+pub struct Stats {
+    solves: Vec<Solve>,
+}
+
+/// Totals and averages of the solves
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StatsSummary {
+    pub solved: usize,
+    pub total_time: f64,
+    pub average_time: f64,
+    pub average_moves: f64,
+    pub hints: u32,
+    /// Days in a row with a solve, up to today or yesterday
+    pub streak: u32,
+    pub best_streak: u32,
+}
+
+impl Stats {
+    const FILE: &'static str = "stats.txt";
+
+    pub fn load() -> Self {
+        let Ok(text) = storage::load(Self::FILE) else {
+            return Self::default();
+        };
+        // each line is "WxH seed time moves hints date"
+        let solves = text
+            .lines()
+            .filter_map(|line| {
+                let mut parts = line.split_whitespace();
+                let (w, h) = parts.next()?.split_once('x')?;
+                Some(Solve {
+                    grid_size: IVec2::new(w.parse().ok()?, h.parse().ok()?),
+                    seed: parts.next()?.parse().ok()?,
+                    time: parts.next()?.parse().ok()?,
+                    moves: parts.next()?.parse().ok()?,
+                    hints: parts.next()?.parse().ok()?,
+                    date: parts.next()?.parse().ok()?,
+                })
+            })
+            .collect();
+        Self { solves }
+    }
+
+    fn save(&self) {
+        let text: Vec<String> = self
+            .solves
+            .iter()
+            .map(|s| {
+                format!(
+                    "{}x{} {} {} {} {} {}",
+                    s.grid_size.x, s.grid_size.y, s.seed, s.time, s.moves, s.hints, s.date
+                )
+            })
+            .collect();
+        if let Err(err) = storage::save(Self::FILE, &text.join("\n")) {
+            eprintln!("Stats not saved: {err}");
+        }
+    }
+
+    pub fn add(&mut self, solve: Solve) {
+        self.solves.push(solve);
+        self.save();
+    }
+
+    /// The latest solves, the most recent first
+    pub fn history(&self, count: usize) -> impl Iterator<Item = &Solve> {
+        self.solves.iter().rev().take(count)
+    }
+
+    /// `now` is in seconds since the unix epoch, it tells if the streak is still going
+    pub fn summary(&self, now: f64) -> StatsSummary {
+        let solved = self.solves.len();
+        if solved == 0 {
+            return StatsSummary::default();
+        }
+        let total_time: f64 = self.solves.iter().map(|s| s.time).sum();
+        let total_moves: u32 = self.solves.iter().map(|s| s.moves).sum();
+
+        let mut days: Vec<i64> = self.solves.iter().map(|s| day(s.date)).collect();
+        days.sort();
+        days.dedup();
+        let mut best_streak = 0;
+        let mut run = 0;
+        for (i, today) in days.iter().enumerate() {
+            run = match i.checked_sub(1).map(|i| days[i]) {
+                Some(previous) if previous + 1 == *today => run + 1,
+                _ => 1,
+            };
+            best_streak = best_streak.max(run);
+        }
+        let last = days.last().copied().unwrap_or(i64::MIN);
+        let streak = if day(now) - last <= 1 { run } else { 0 };
+
+        StatsSummary {
+            solved,
+            total_time,
+            average_time: total_time / solved as f64,
+            average_moves: total_moves as f64 / solved as f64,
+            hints: self.solves.iter().map(|s| s.hints).sum(),
+            streak,
+            best_streak,
+        }
+    }
+}
+
+/// Days since the unix epoch, in UTC
+fn day(date: f64) -> i64 {
+    (date / 86400.).floor() as i64
+}
@@ -0,0 +1,107 @@
+// Small text files that persist between runs, kept in the user data directory.
+// The web build keeps them in the localStorage of the page instead, through the
+// "tiling_storage" plugin of resources/wasm-res/index.html
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::PathBuf;
+
+#[cfg(not(any(target_arch = "wasm32", target_os = "android")))]
+fn data_dir() -> Option<PathBuf> {
+    let home = || std::env::var_os("HOME").map(PathBuf::from);
+    let dir = if cfg!(target_os = "windows") {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        home().map(|home| home.join("Library/Application Support"))
+    } else {
+        std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| home().map(|home| home.join(".local/share")))
+    };
+    Some(dir?.join(env!("CARGO_PKG_NAME")))
+}
+
+/// The internal files directory of the activity, private to the app and kept until
+/// it's uninstalled. Asked once, the activity doesn't change it
+#[cfg(target_os = "android")]
+fn data_dir() -> Option<PathBuf> {
+    use miniquad::native::android::{attach_jni_env, ACTIVITY};
+    use miniquad::{call_object_method, get_utf_str};
+    static FILES_DIR: std::sync::OnceLock<Option<PathBuf>> = std::sync::OnceLock::new();
+    FILES_DIR
+        .get_or_init(|| unsafe {
+            let env = attach_jni_env();
+            let dir = call_object_method!(env, ACTIVITY, "getFilesDir", "()Ljava/io/File;");
+            if dir.is_null() {
+                return None;
+            }
+            let path = call_object_method!(env, dir, "getAbsolutePath", "()Ljava/lang/String;");
+            if path.is_null() {
+                return None;
+            }
+            Some(PathBuf::from(get_utf_str!(env, path)))
+        })
+        .clone()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load(name: &str) -> Result<String, String> {
+    let dir = data_dir().ok_or("No data directory".to_string())?;
+    let path = dir.join(name);
+    std::fs::read_to_string(&path).map_err(|e| format!("Reading {}: {e}", path.display()))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save(name: &str, contents: &str) -> Result<(), String> {
+    let dir = data_dir().ok_or("No data directory".to_string())?;
+    std::fs::create_dir_all(&dir).map_err(|e| format!("Creating {}: {e}", dir.display()))?;
+    let path = dir.join(name);
+    // written aside and moved over it, the app killed halfway leaves the previous contents
+    let partial = dir.join(format!("{name}.partial"));
+    std::fs::write(&partial, contents)
+        .map_err(|e| format!("Writing {}: {e}", partial.display()))?;
+    std::fs::rename(&partial, &path).map_err(|e| format!("Writing {}: {e}", path.display()))
+}
+
+#[cfg(target_arch = "wasm32")]
+extern "C" {
+    /// Bytes of the value of the key, -1 if it has none
+    fn storage_length(key: *const u8, key_len: u32) -> i32;
+    /// Copies the value of the key into `buf`, cut to `buf_len`
+    fn storage_read(key: *const u8, key_len: u32, buf: *mut u8, buf_len: u32);
+    /// 0 if the browser refused it, when the storage is full or disabled
+    fn storage_write(key: *const u8, key_len: u32, value: *const u8, value_len: u32) -> u32;
+}
+
+/// The pages of a domain share the storage, like the games on itch.io
+#[cfg(target_arch = "wasm32")]
+fn storage_key(name: &str) -> String {
+    format!("{}/{}", env!("CARGO_PKG_NAME"), name)
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn load(name: &str) -> Result<String, String> {
+    let key = storage_key(name);
+    let len = unsafe { storage_length(key.as_ptr(), key.len() as u32) };
+    if len < 0 {
+        return Err(format!("Reading {key}: not stored"));
+    }
+    let mut buf = vec![0; len as usize];
+    unsafe { storage_read(key.as_ptr(), key.len() as u32, buf.as_mut_ptr(), len as u32) };
+    String::from_utf8(buf).map_err(|e| format!("Reading {key}: {e}"))
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn save(name: &str, contents: &str) -> Result<(), String> {
+    let key = storage_key(name);
+    let stored = unsafe {
+        storage_write(
+            key.as_ptr(),
+            key.len() as u32,
+            contents.as_ptr(),
+            contents.len() as u32,
+        )
+    };
+    match stored {
+        0 => Err(format!("Writing {key}: refused by the browser")),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,144 @@
+use crate::*;
+
+/// The board being played when the game was closed or sent to the background,
+/// opened again paused at the next launch. Saved as `key=value` lines
+#[derive(Clone, Debug, PartialEq)]
+pub struct SuspendedBoard {
+    /// None for an endless board
+    pub level: Option<usize>,
+    pub code: String,
+    /// Seconds played on it
+    pub elapsed: f64,
+    pub moves: u32,
+    pub hints: u32,
+    pub cells: Vec<CellState>,
+}
+
+impl SuspendedBoard {
+    const FILE: &'static str = "suspended.txt";
+
+    /// The saved board, forgotten once read so that it's only opened again once
+    pub fn take() -> Option<Self> {
+        let text = storage::load(Self::FILE).ok()?;
+        if text.is_empty() {
+            return None;
+        }
+        Self::clear();
+        Self::from_text(&text)
+    }
+
+    fn from_text(text: &str) -> Option<Self> {
+        let mut level = None;
+        let mut code = None;
+        let mut elapsed = 0.;
+        let mut moves = 0;
+        let mut hints = 0;
+        let mut cells = vec![];
+        for line in text.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let value = value.trim();
+            match key.trim() {
+                "level" => level = value.parse().ok(),
+                "code" => code = Some(value.to_string()),
+                "elapsed" => elapsed = value.parse().unwrap_or(0.),
+                "moves" => moves = value.parse().unwrap_or(0),
+                "hints" => hints = value.parse().unwrap_or(0),
+                // each cell is "tile:rotation"
+                "cells" => {
+                    cells = value
+                        .split(',')
+                        .filter_map(|cell| {
+                            let (tile, rotation) = cell.split_once(':')?;
+                            Some(CellState {
+                                tile: tile.parse().ok()?,
+                                rotation: rotation.parse().ok()?,
+                            })
+                        })
+                        .collect()
+                }
+                _ => {}
+            }
+        }
+        Some(Self {
+            level,
+            code: code?,
+            elapsed,
+            moves,
+            hints,
+            cells,
+        })
+    }
+
+    pub fn save(&self) {
+        if let Err(err) = storage::save(Self::FILE, &self.to_text()) {
+            eprintln!("Board not suspended: {err}");
+        }
+    }
+
+    fn to_text(&self) -> String {
+        let level = match self.level {
+            Some(level) => level.to_string(),
+            None => "endless".to_string(),
+        };
+        let cells: Vec<String> = self
+            .cells
+            .iter()
+            .map(|cell| format!("{}:{}", cell.tile, cell.rotation))
+            .collect();
+        format!(
+            "level={}\ncode={}\nelapsed={}\nmoves={}\nhints={}\ncells={}\n",
+            level,
+            self.code,
+            self.elapsed,
+            self.moves,
+            self.hints,
+            cells.join(",")
+        )
+    }
+
+    /// There's no board to open at the next launch
+    pub fn clear() {
+        if let Err(err) = storage::save(Self::FILE, "") {
+            eprintln!("Suspended board not cleared: {err}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_round_trip() {
+        let board = SuspendedBoard {
+            level: None,
+            code: "4x4-abc".to_string(),
+            elapsed: 12.5,
+            moves: 7,
+            hints: 1,
+            cells: vec![
+                CellState {
+                    tile: 3,
+                    rotation: 1,
+                },
+                CellState {
+                    tile: EMPTY_CELL,
+                    rotation: 0,
+                },
+            ],
+        };
+        assert_eq!(
+            SuspendedBoard::from_text(&board.to_text()),
+            Some(board.clone())
+        );
+        let level = SuspendedBoard {
+            level: Some(4),
+            ..board
+        };
+        assert_eq!(SuspendedBoard::from_text(&level.to_text()), Some(level));
+        // without its code the board can't be generated again
+        assert_eq!(SuspendedBoard::from_text("level=4\nmoves=3\n"), None);
+    }
+}
@@ -0,0 +1,111 @@
+/// Span of time, in the seconds of `miniquad::date::now`
+#[derive(Clone, Debug)]
+pub struct Timer {
+    pub start_time: f64,
+    pub end_time: f64,
+}
+
+impl Timer {
+    pub fn from_range(start_time: f64, end_time: f64) -> Self {
+        Self {
+            start_time,
+            end_time,
+        }
+    }
+    pub fn from_duration(current_time: f64, duration: f64) -> Self {
+        Self {
+            start_time: current_time,
+            end_time: current_time + duration,
+        }
+    }
+    pub fn percent(&self, current_time: f64) -> f64 {
+        (current_time - self.start_time) / (self.end_time - self.start_time)
+    }
+    pub fn contains(&self, current_time: f64) -> bool {
+        current_time <= self.end_time && current_time >= self.start_time
+    }
+    pub fn is_finished(&self, current_time: f64) -> bool {
+        current_time >= self.end_time
+    }
+}
+
+/// Shape of an animation, maps the elapsed part of it to the travelled part
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Easing {
+    Linear,
+    QuadIn,
+    QuadOut,
+    QuadInOut,
+    CubicIn,
+    CubicOut,
+    /// Goes a bit past the end and comes back
+    BackOut,
+}
+
+impl Easing {
+    /// `t` is clamped in 0..1
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0., 1.);
+        match self {
+            Easing::Linear => t,
+            Easing::QuadIn => t * t,
+            Easing::QuadOut => 1. - (1. - t) * (1. - t),
+            Easing::QuadInOut => match t < 0.5 {
+                true => 2. * t * t,
+                false => 1. - (-2. * t + 2.).powi(2) * 0.5,
+            },
+            Easing::CubicIn => t * t * t,
+            Easing::CubicOut => 1. - (1. - t).powi(3),
+            Easing::BackOut => {
+                // https://easings.net/#easeOutBack
+                let c1 = 1.70158;
+                let c3 = c1 + 1.;
+                1. + c3 * (t - 1.).powi(3) + c1 * (t - 1.).powi(2)
+            }
+        }
+    }
+}
+
+/// A value that goes from `from` to `to` during the timer
+#[derive(Clone, Debug)]
+pub struct Tween {
+    pub from: f32,
+    pub to: f32,
+    pub timer: Timer,
+    pub easing: Easing,
+}
+
+impl Tween {
+    pub fn new(from: f32, to: f32, current_time: f64, duration: f64, easing: Easing) -> Self {
+        Self {
+            from,
+            to,
+            timer: Timer::from_duration(current_time, duration),
+            easing,
+        }
+    }
+
+    pub fn value(&self, current_time: f64) -> f32 {
+        let t = match self.timer.is_finished(current_time) {
+            true => 1.,
+            false => self.timer.percent(current_time).max(0.) as f32,
+        };
+        self.from + (self.to - self.from) * self.easing.apply(t)
+    }
+
+    /// Goes to `to` from where it is now, in the same duration.
+    /// Nothing changes if it's already going there.
+    pub fn retarget(&mut self, to: f32, current_time: f64) {
+        if self.to == to {
+            return;
+        }
+        let duration = self.timer.end_time - self.timer.start_time;
+        *self = Self::new(
+            self.value(current_time),
+            to,
+            current_time,
+            duration,
+            self.easing,
+        );
+    }
+}
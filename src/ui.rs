@@ -1,24 +1,43 @@
+use std::ops::RangeInclusive;
+
 use crate::*;
 
 pub struct Ui<'a> {
     pub tile_commands: &'a mut RenderTileCommands,
     pub input: &'a Input,
     pub ui_defaults: &'a UiDefaults,
+    pub memory: &'a mut UiMemory,
+    /// Bounds of the widget drawn last, for its tooltip
+    last_rect: Rect,
 }
 
 impl<'a> Ui<'a> {
+    /// The ui of a frame, there is only one each frame since it moves the focus
     pub fn new(
         tile_commands: &'a mut RenderTileCommands,
         input: &'a Input,
         ui_defaults: &'a UiDefaults,
+        memory: &'a mut UiMemory,
     ) -> Ui<'a> {
+        memory.begin(input);
         Ui {
             tile_commands,
             input,
             ui_defaults,
+            memory,
+            last_rect: Rect::default(),
         }
     }
 
+    /// The button style of a widget, it looks hovered while it has the focus
+    fn focus_button(&self, focused: bool) -> UiButton {
+        let mut ui_button = self.ui_defaults.button.clone();
+        if focused {
+            ui_button.color_normal = ui_button.color_hover;
+        }
+        ui_button
+    }
+
     pub fn vertical<const LEN: usize>(&mut self, rect: Rect, weights: &[f32]) -> [Rect; LEN] {
         assert_eq!(weights.len(), LEN);
         let mut rects = [Rect::default(); LEN];
@@ -33,23 +52,384 @@ impl<'a> Ui<'a> {
         rects
     }
 
+    /// Cells side by side in `rect` like `horizontal`, or one above the other in `bounds`
+    /// like `vertical` when they're too narrow there: the way the smallest cell is squeezed
+    /// the least under `MIN_CELL_SIZE`. `bounds` holds `rect`, it's the room for the stack
+    pub fn row<const LEN: usize>(
+        &mut self,
+        rect: Rect,
+        bounds: Rect,
+        weights: &[f32],
+    ) -> [Rect; LEN] {
+        let min_size = MIN_CELL_SIZE * self.ui_defaults.scale;
+        let fit = |rects: &[Rect]| {
+            rects
+                .iter()
+                .map(|rect| (rect.size / min_size).min_element())
+                .fold(f32::INFINITY, f32::min)
+        };
+        let across = self.horizontal(rect, weights);
+        let stacked = self.vertical(bounds, weights);
+        if fit(&across) >= 1. || fit(&across) >= fit(&stacked) {
+            across
+        } else {
+            stacked
+        }
+    }
+
     pub fn label(&mut self, text: &str, rect: Rect) {
+        self.last_rect = rect;
         draw_text(self.tile_commands, text, rect, 0.1, &self.ui_defaults.text);
     }
 
+    /// A label in another color than the one of the text
+    pub fn colored_label(&mut self, text: &str, color: Vec4, rect: Rect) {
+        self.last_rect = rect;
+        let mut ui_text = self.ui_defaults.text.clone();
+        ui_text.color = color;
+        draw_text(self.tile_commands, text, rect, 0.1, &ui_text);
+    }
+
+    /// A texture between the backgrounds of the widgets and their text,
+    /// `clip_rect` is the part of it shown in uv
+    pub fn image(&mut self, image_id: &AssetId, clip_rect: Rect, rect: Rect) {
+        self.last_rect = rect;
+        self.tile_commands.draw_textured(
+            RenderTile {
+                world_rect: rect,
+                clip_rect,
+                z: 0.1005,
+                ..Default::default()
+            },
+            image_id.clone(),
+        );
+    }
+
+    /// Text on a background like the one of the tooltips, where it's put
+    pub fn bubble(&mut self, text: &str, rect: Rect) {
+        self.last_rect = rect;
+        self.tile_commands.draw(RenderTile {
+            world_rect: rect,
+            color: self.ui_defaults.button.color_pressed,
+            z: 0.101,
+            ..Default::default()
+        });
+        draw_text(
+            self.tile_commands,
+            text,
+            rect.pad(self.ui_defaults.button.padding),
+            0.1,
+            &self.ui_defaults.text,
+        );
+    }
+
+    /// Dots going around in a circle, for something that takes a while
+    pub fn spinner(&mut self, rect: Rect, current_time: f64) {
+        self.last_rect = rect;
+        draw_spinner(
+            self.tile_commands,
+            rect,
+            self.ui_defaults.text.color,
+            current_time,
+            0.1,
+        );
+    }
+
+    /// Grows a bit while hovered or focused
     pub fn button(&mut self, text: &str, rect: Rect) -> bool {
+        let (focused, activated) = self.memory.next();
+        self.last_rect = rect;
+        let hovered = focused || rect.contains_point(&self.input.mouse_position);
+        let scale = self.memory.hover_scale(hovered);
+        let mut ui_button = self.focus_button(focused);
+        ui_button.padding *= scale;
+        let mut ui_text = self.ui_defaults.text.clone();
+        ui_text.char_scale *= scale;
         match draw_button_text(
-            &self.ui_defaults.button,
-            &self.ui_defaults.text,
+            &ui_button,
+            &ui_text,
             self.tile_commands,
             text,
+            rect.scaled(scale),
+            0.1,
+            self.input,
+        ) {
+            UiInteraction::Clicked { just } => just,
+            _ => activated,
+        }
+    }
+
+    /// Box on the left of the rect, checked when `value` is true.
+    /// Returns true when it's toggled.
+    pub fn checkbox(&mut self, value: &mut bool, rect: Rect) -> bool {
+        let (focused, activated) = self.memory.next();
+        self.last_rect = rect;
+        if activated {
+            *value = !*value;
+        }
+        match draw_checkbox(
+            &self.focus_button(focused),
+            self.tile_commands,
+            value,
             rect,
             0.1,
             self.input,
         ) {
             UiInteraction::Clicked { just } => just,
-            _ => false,
+            _ => activated,
+        }
+    }
+
+    /// A button for each option side by side, the one equal to `value` stays pressed.
+    /// When focused, left and right choose the option next to it.
+    /// Returns true when another option is chosen.
+    pub fn radio<T: PartialEq + Clone>(
+        &mut self,
+        value: &mut T,
+        options: &[(T, &str)],
+        rect: Rect,
+    ) -> bool {
+        let (focused, activated) = self.memory.next();
+        self.last_rect = rect;
+        let before = value.clone();
+        if focused && !options.is_empty() {
+            let current = options.iter().position(|(option, _)| option == value);
+            let mut index = current.unwrap_or(0);
+            for key in self.input.key_typed.iter() {
+                match key {
+                    KeyCode::Left => index = index.saturating_sub(1),
+                    KeyCode::Right => index = (index + 1).min(options.len() - 1),
+                    _ => {}
+                }
+            }
+            // activating it goes through the options
+            if activated {
+                index = (index + 1) % options.len();
+            }
+            if current != Some(index) {
+                *value = options[index].0.clone();
+            }
+        }
+        let labels: Vec<(&str, bool)> = options
+            .iter()
+            .map(|(option, label)| (*label, option == value))
+            .collect();
+        match draw_radio_group(
+            &self.focus_button(focused),
+            &self.ui_defaults.text,
+            self.tile_commands,
+            &labels,
+            rect,
+            0.1,
+            self.input,
+        ) {
+            Some(index) if options[index].0 != *value => {
+                *value = options[index].0.clone();
+                true
+            }
+            _ => *value != before,
+        }
+    }
+
+    /// Horizontal slider, the knob follows a drag that started on it or on the track.
+    /// When focused, left and right move it by a twentieth of the range.
+    /// Returns true when the value changes.
+    pub fn slider(&mut self, value: &mut f32, range: RangeInclusive<f32>, rect: Rect) -> bool {
+        let (focused, _) = self.memory.next();
+        self.last_rect = rect;
+        let before = *value;
+        if focused {
+            let step = (range.end() - range.start()) / 20.;
+            for key in self.input.key_typed.iter() {
+                match key {
+                    KeyCode::Left => *value = (*value - step).max(*range.start()),
+                    KeyCode::Right => *value = (*value + step).min(*range.end()),
+                    _ => {}
+                }
+            }
+        }
+        let dragged = draw_slider(
+            &self.focus_button(focused),
+            self.tile_commands,
+            value,
+            range,
+            rect,
+            0.1,
+            self.input,
+        );
+        dragged || *value != before
+    }
+
+    /// Single line text field, clicking on it or moving the focus to it lets it edit.
+    /// Returns true when enter is pressed while focused.
+    pub fn text_input(&mut self, field: &mut UiTextField, rect: Rect) -> bool {
+        let (focused, _) = self.memory.next();
+        self.last_rect = rect;
+        if self.memory.moved && focused != field.focused {
+            field.focused = focused;
+            field.caret = field.text.chars().count();
+            field.caret_time = miniquad::date::now();
+            window::show_keyboard(focused);
+        }
+        let submitted = draw_text_input(
+            &self.ui_defaults.button,
+            &self.ui_defaults.text,
+            self.tile_commands,
+            field,
+            rect,
+            0.1,
+            self.input,
+        );
+        // a click or escape moves the focus of the field
+        let id = self.memory.next_id - 1;
+        if field.focused {
+            self.memory.focused = Some(id);
+        } else if self.memory.focused == Some(id) {
+            self.memory.focused = None;
+        }
+        submitted
+    }
+
+    /// Shows `tip` in a bubble next to the cursor once it rests on the widget drawn last
+    /// for `TOOLTIP_DELAY` seconds
+    pub fn tooltip(&mut self, tip: &str) {
+        if !self.last_rect.contains_point(&self.input.mouse_position) {
+            return;
+        }
+        let now = miniquad::date::now();
+        self.memory.tooltip_shown = true;
+        match &self.memory.tooltip {
+            Some((hovered, since)) if hovered == tip => {
+                if now - since < TOOLTIP_DELAY {
+                    return;
+                }
+            }
+            _ => {
+                self.memory.tooltip = Some((tip.to_string(), now));
+                return;
+            }
         }
+        let mut ui_text = self.ui_defaults.text.clone();
+        ui_text.layout = UiTextLayout::Right;
+        ui_text.vertical_layout = UiTextVerticalLayout::Top;
+        ui_text.overflow = UiTextOverflow::Shrink;
+        ui_text.char_scale *= TOOLTIP_TEXT_SCALE;
+        draw_tooltip(
+            &self.ui_defaults.button,
+            &ui_text,
+            self.tile_commands,
+            tip,
+            self.input.mouse_position,
+            0.01,
+        );
+    }
+}
+
+/// Seconds the cursor rests on a widget before its tooltip shows
+const TOOLTIP_DELAY: f64 = 0.6;
+/// Of the text of the widgets
+pub const DEBUG_TEXT_SCALE: f32 = 0.5;
+const TOOLTIP_TEXT_SCALE: f32 = 0.6;
+
+/// State of the widgets kept across frames: the keyboard focus and the animations.
+/// Widgets get their id from the order they are drawn in, tab and shift+tab
+/// cycle through them and enter or space activate the focused one.
+#[derive(Clone, Debug, Default)]
+pub struct UiMemory {
+    pub focused: Option<usize>,
+    /// Up and down cycle the focus too, for menus that don't use the arrows otherwise
+    pub arrows: bool,
+    /// Widgets drawn last frame
+    count: usize,
+    /// Id of the next widget drawn this frame
+    next_id: usize,
+    /// The focused widget is activated this frame
+    activate: bool,
+    /// The keys moved the focus this frame
+    moved: bool,
+    /// Scale of each widget by id, that grows while it's hovered
+    hover: Vec<Tween>,
+    /// Tip of the hovered widget and since when it's hovered
+    tooltip: Option<(String, f64)>,
+    /// A widget with a tip was hovered this frame
+    tooltip_shown: bool,
+}
+
+/// Scale of a hovered button, reached in `HOVER_DURATION` seconds
+const HOVER_SCALE: f32 = 1.08;
+const HOVER_DURATION: f64 = 0.12;
+
+impl UiMemory {
+    /// Forgets the focused widget, when the widgets it counted are gone
+    pub fn clear(&mut self) {
+        self.focused = None;
+        self.next_id = 0;
+        self.hover.clear();
+    }
+
+    /// Moves the focus with the keys of this frame, before the widgets are drawn.
+    /// A click or touch anywhere drops it.
+    fn begin(&mut self, input: &Input) {
+        self.count = self.next_id;
+        self.next_id = 0;
+        self.activate = false;
+        self.moved = false;
+        if !self.tooltip_shown {
+            self.tooltip = None;
+        }
+        self.tooltip_shown = false;
+        if input.mouse_just_pressed.0 || !input.just_touched.is_empty() {
+            self.focused = None;
+        }
+        let shift = input.key_pressed.contains(&KeyCode::LeftShift)
+            || input.key_pressed.contains(&KeyCode::RightShift);
+        for key in input.key_typed.iter() {
+            let step: i32 = match key {
+                KeyCode::Tab if shift => -1,
+                KeyCode::Tab => 1,
+                KeyCode::Down if self.arrows => 1,
+                KeyCode::Up if self.arrows => -1,
+                KeyCode::Enter | KeyCode::KpEnter | KeyCode::Space => {
+                    self.activate = self.focused.is_some();
+                    continue;
+                }
+                KeyCode::Escape => {
+                    self.focused = None;
+                    continue;
+                }
+                _ => continue,
+            };
+            if self.count == 0 {
+                continue;
+            }
+            self.focused = Some(match self.focused {
+                Some(focused) => (focused as i32 + step).rem_euclid(self.count as i32) as usize,
+                None if step > 0 => 0,
+                None => self.count - 1,
+            });
+            self.moved = true;
+        }
+    }
+
+    /// Scale of the last widget, eased towards `HOVER_SCALE` while hovered
+    fn hover_scale(&mut self, hovered: bool) -> f32 {
+        let id = self.next_id - 1;
+        let now = miniquad::date::now();
+        if self.hover.len() <= id {
+            self.hover.resize_with(id + 1, || {
+                Tween::new(1., 1., now, HOVER_DURATION, Easing::QuadOut)
+            });
+        }
+        let hover = &mut self.hover[id];
+        hover.retarget(if hovered { HOVER_SCALE } else { 1. }, now);
+        hover.value(now)
+    }
+
+    /// Id for the next widget, returns whether it's focused and activated
+    fn next(&mut self) -> (bool, bool) {
+        let focused = self.focused == Some(self.next_id);
+        self.next_id += 1;
+        (focused, focused && self.activate)
     }
 }
 
@@ -66,43 +446,97 @@ const LITTLEFONT_KERNING: [u8; 128] = [
 pub struct UiDefaults {
     pub text: UiText,
     pub button: UiButton,
+    /// Of the config and the screen together
+    pub scale: f32,
 }
 
 impl UiDefaults {
-    pub fn new(handles: &Handles, engine: &EngineContext) -> Option<Self> {
-        let font_image = engine.assets.images.get(&handles.font)?;
+    /// From one of the `THEMES`, None until it and its font are loaded.
+    /// `scale` multiplies the text and the padding
+    pub fn new(assets: &Assets, theme: &str, scale: f32) -> Option<Self> {
+        let theme_id = assets.get_id(&theme_path(theme))?;
+        let theme = assets.themes.get(theme_id)?;
+        let font_id = assets.get_id(&theme.font)?;
+        // bitmap fonts are sheets laid out like littlefont
+        let font = if theme.font.ends_with(".ttf") {
+            UiFont::ttf(assets, font_id, theme.font_size)?
+        } else {
+            let font_image = assets.images.get(font_id)?;
+            UiFont::Bitmap {
+                image_size: UVec2::new(font_image.width, font_image.height).as_vec2(),
+                image_id: font_id.clone(),
+                char_size: Vec2::new(6., 12.),
+                kerning: LITTLEFONT_KERNING,
+            }
+        };
         Some(UiDefaults {
             text: UiText {
-                image_font_size: UVec2::new(font_image.width, font_image.height).as_vec2(),
-                image_font_id: handles.font.clone(),
-                image_font_char_size: Vec2::new(6., 12.),
-                image_font_kerning: LITTLEFONT_KERNING,
+                font,
                 layout: UiTextLayout::Right,
-                char_scale: Vec2::new(5., 5.),
-                color: Vec4::splat(1.),
+                vertical_layout: UiTextVerticalLayout::Top,
+                overflow: UiTextOverflow::Shrink,
+                char_scale: Vec2::splat(theme.text_scale) * scale,
+                color: theme.text_color,
             },
             button: UiButton {
-                padding: 3.,
-                color_normal: Vec4::new(0.3, 0.2, 0.2, 1.0),
-                color_hover: Vec4::new(0.5, 0.2, 0.2, 1.0),
-                color_pressed: Vec4::new(0.8, 0.4, 0.0, 1.0),
-                color_just_pressed: Vec4::new(1.0, 0.5, 0.0, 1.0),
+                padding: theme.button_padding * scale,
+                color_normal: theme.button_normal,
+                color_hover: theme.button_hover,
+                color_pressed: theme.button_pressed,
+                color_just_pressed: theme.button_just_pressed,
             },
+            scale,
         })
     }
 }
 
 #[derive(Clone, Debug)]
 pub struct UiText {
-    pub image_font_size: Vec2,
-    pub image_font_id: AssetId,
-    pub image_font_char_size: Vec2,
-    pub image_font_kerning: [u8; 128],
+    pub font: UiFont,
     pub layout: UiTextLayout,
+    pub vertical_layout: UiTextVerticalLayout,
+    pub overflow: UiTextOverflow,
     pub char_scale: Vec2,
     pub color: Vec4,
 }
 
+#[derive(Clone, Debug)]
+pub enum UiFont {
+    /// Ascii sheet of characters in cells of the same size
+    Bitmap {
+        image_size: Vec2,
+        image_id: AssetId,
+        char_size: Vec2,
+        kerning: [u8; 128],
+    },
+    /// Atlas of a ttf asset, which is also the id of its image.
+    /// `size` is the em, in the same units as the `char_size` of a bitmap font.
+    Ttf {
+        font_id: AssetId,
+        atlas: FontAtlas,
+        size: f32,
+    },
+}
+
+impl UiFont {
+    /// The ttf font at `size`, None until it's loaded
+    pub fn ttf(assets: &Assets, font_id: &AssetId, size: f32) -> Option<Self> {
+        Some(UiFont::Ttf {
+            font_id: font_id.clone(),
+            atlas: assets.fonts.get(font_id)?.clone(),
+            size,
+        })
+    }
+
+    /// The texture of the glyphs
+    pub fn image_id(&self) -> &AssetId {
+        match self {
+            UiFont::Bitmap { image_id, .. } => image_id,
+            UiFont::Ttf { font_id, .. } => font_id,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct UiButton {
     pub padding: f32,
@@ -112,6 +546,80 @@ pub struct UiButton {
     pub color_just_pressed: Vec4,
 }
 
+/// State of a text input, owned by the caller
+#[derive(Clone, Debug, Default)]
+pub struct UiTextField {
+    pub text: String,
+    pub focused: bool,
+    /// In characters
+    pub max_len: usize,
+    /// Characters before the caret
+    pub caret: usize,
+    /// When the caret last moved, it blinks from then
+    pub caret_time: f64,
+}
+
+impl UiTextField {
+    pub fn new(max_len: usize) -> Self {
+        Self {
+            max_len,
+            ..Default::default()
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.text.clear();
+        self.caret = 0;
+    }
+
+    /// Byte index of the caret in the text
+    fn caret_index(&self) -> usize {
+        self.text
+            .char_indices()
+            .nth(self.caret)
+            .map_or(self.text.len(), |(i, _)| i)
+    }
+
+    /// Applies the typed characters and editing keys, returns true on enter
+    pub fn edit(&mut self, input: &Input) -> bool {
+        let caret = self.caret;
+        self.caret = self.caret.min(self.text.chars().count());
+        for c in input.chars_typed.iter() {
+            if !c.is_control() && self.text.chars().count() < self.max_len {
+                self.text.insert(self.caret_index(), *c);
+                self.caret += 1;
+            }
+        }
+        let mut submitted = false;
+        for key in input.key_typed.iter() {
+            let len = self.text.chars().count();
+            match key {
+                KeyCode::Backspace if self.caret > 0 => {
+                    self.caret -= 1;
+                    self.text.remove(self.caret_index());
+                }
+                KeyCode::Delete if self.caret < len => {
+                    self.text.remove(self.caret_index());
+                }
+                KeyCode::Left => self.caret = self.caret.saturating_sub(1),
+                KeyCode::Right => self.caret = (self.caret + 1).min(len),
+                KeyCode::Home => self.caret = 0,
+                KeyCode::End => self.caret = len,
+                KeyCode::Enter | KeyCode::KpEnter => submitted = true,
+                KeyCode::Escape => {
+                    self.focused = false;
+                    window::show_keyboard(false);
+                }
+                _ => {}
+            }
+        }
+        if caret != self.caret {
+            self.caret_time = miniquad::date::now();
+        }
+        submitted
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum UiTextLayout {
     Left,
@@ -119,6 +627,24 @@ pub enum UiTextLayout {
     Right,
 }
 
+#[derive(Clone, Debug)]
+pub enum UiTextVerticalLayout {
+    Top,
+    Center,
+    Bottom,
+}
+
+/// What happens to text that doesn't fit its bounds
+#[derive(Clone, Debug)]
+pub enum UiTextOverflow {
+    /// Scaled down until it fits
+    Shrink,
+    /// Broken into lines between words, then scaled down if they are too many
+    Wrap,
+    /// Lines that are too long are cut and end with "..."
+    Ellipsis,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum UiInteraction {
     None,
@@ -180,6 +706,294 @@ pub fn draw_button(
     interaction
 }
 
+/// Toggles `value` when clicked
+pub fn draw_checkbox(
+    ui_button: &UiButton,
+    tile_commands: &mut RenderTileCommands,
+    value: &mut bool,
+    bounds_rect: Rect,
+    z: f32,
+    input: &Input,
+) -> UiInteraction {
+    let side = bounds_rect.size.y.min(bounds_rect.size.x);
+    let box_rect = Rect::new(bounds_rect.pos, Vec2::splat(side));
+    let interaction = draw_button(ui_button, tile_commands, input, box_rect, z + 0.001);
+    if interaction == (UiInteraction::Clicked { just: true }) {
+        *value = !*value;
+    }
+    if *value {
+        tile_commands.draw(RenderTile {
+            world_rect: box_rect.pad(side * 0.25),
+            color: ui_button.color_just_pressed,
+            z,
+            ..Default::default()
+        });
+    }
+    interaction
+}
+
+/// Buttons for the labels side by side, the selected ones look pressed.
+/// Returns the index of the clicked one.
+pub fn draw_radio_group(
+    ui_button: &UiButton,
+    ui_text: &UiText,
+    tile_commands: &mut RenderTileCommands,
+    labels: &[(&str, bool)],
+    bounds_rect: Rect,
+    z: f32,
+    input: &Input,
+) -> Option<usize> {
+    let mut clicked = None;
+    let cells = bounds_rect.slice_horizontal(labels.len());
+    for (i, ((label, selected), cell)) in labels.iter().zip(cells).enumerate() {
+        let mut option_button = ui_button.clone();
+        if *selected {
+            option_button.color_normal = ui_button.color_pressed;
+            option_button.color_hover = ui_button.color_pressed;
+        }
+        let interaction = draw_button_text(
+            &option_button,
+            ui_text,
+            tile_commands,
+            label,
+            cell.pad(cell.size.y * 0.05),
+            z,
+            input,
+        );
+        if interaction == (UiInteraction::Clicked { just: true }) {
+            clicked = Some(i);
+        }
+    }
+    clicked
+}
+
+pub fn draw_slider(
+    ui_button: &UiButton,
+    tile_commands: &mut RenderTileCommands,
+    value: &mut f32,
+    range: RangeInclusive<f32>,
+    rect: Rect,
+    z: f32,
+    input: &Input,
+) -> bool {
+    let (start, end) = (*range.start(), *range.end());
+    let knob_width = rect.size.y.min(rect.size.x * 0.5);
+    let travel = rect.size.x - knob_width;
+
+    let dragged = match (input.drag_origin, input.pointer()) {
+        (Some(origin), Some(pointer)) if rect.contains_point(&origin) => Some(pointer),
+        _ => None,
+    };
+    let before = *value;
+    if let Some(pointer) = dragged {
+        let t = ((pointer.x - rect.pos.x - knob_width * 0.5) / travel).clamp(0., 1.);
+        *value = start + t * (end - start);
+    }
+    let t = match end != start {
+        true => ((*value - start) / (end - start)).clamp(0., 1.),
+        false => 0.,
+    };
+
+    let track_height = rect.size.y * 0.25;
+    let track = Rect::xywh(
+        rect.pos.x,
+        rect.pos.y + (rect.size.y - track_height) * 0.5,
+        rect.size.x,
+        track_height,
+    );
+    let filled = Rect::new(
+        track.pos,
+        Vec2::new(knob_width * 0.5 + travel * t, track.size.y),
+    );
+    let knob = Rect::xywh(rect.pos.x + travel * t, rect.pos.y, knob_width, rect.size.y);
+    tile_commands.draw(RenderTile {
+        world_rect: track,
+        color: ui_button.color_normal,
+        z: z + 0.002,
+        ..Default::default()
+    });
+    tile_commands.draw(RenderTile {
+        world_rect: filled,
+        color: ui_button.color_pressed,
+        z: z + 0.001,
+        ..Default::default()
+    });
+    tile_commands.draw(RenderTile {
+        world_rect: knob,
+        color: if dragged.is_some() {
+            ui_button.color_just_pressed
+        } else if rect.contains_point(&input.mouse_position) {
+            ui_button.color_hover
+        } else {
+            ui_button.color_normal
+        },
+        z,
+        ..Default::default()
+    });
+    *value != before
+}
+
+pub fn draw_text_input(
+    ui_button: &UiButton,
+    ui_text: &UiText,
+    tile_commands: &mut RenderTileCommands,
+    field: &mut UiTextField,
+    bounds_rect: Rect,
+    z: f32,
+    input: &Input,
+) -> bool {
+    // The focused field looks hovered
+    let mut ui_button = ui_button.clone();
+    if field.focused {
+        ui_button.color_normal = ui_button.color_hover;
+    }
+    let interaction = draw_button(&ui_button, tile_commands, input, bounds_rect, z + 0.001);
+
+    // Any click or touch moves the focus
+    if input.mouse_just_pressed.0 || !input.just_touched.is_empty() {
+        let focus = interaction == UiInteraction::Clicked { just: true };
+        if focus != field.focused {
+            field.focused = focus;
+            field.caret = field.text.chars().count();
+            field.caret_time = miniquad::date::now();
+            window::show_keyboard(focus);
+        }
+    }
+
+    let submitted = field.focused && field.edit(input);
+
+    let text_rect = bounds_rect.pad(ui_button.padding);
+    let drawn_rect = draw_text(tile_commands, &field.text, text_rect, z, ui_text);
+
+    // Half a second on, half off
+    let blink = (miniquad::date::now() - field.caret_time) % 1. < 0.5;
+    if field.focused && blink {
+        let caret_rect = if field.text.is_empty() {
+            let height = line_height(ui_text).min(text_rect.size.y);
+            let x = match ui_text.layout {
+                UiTextLayout::Left => text_rect.size.x,
+                UiTextLayout::Center => text_rect.size.x * 0.5,
+                UiTextLayout::Right => 0.,
+            };
+            let y = match ui_text.vertical_layout {
+                UiTextVerticalLayout::Top => 0.,
+                UiTextVerticalLayout::Center => (text_rect.size.y - height) * 0.5,
+                UiTextVerticalLayout::Bottom => text_rect.size.y - height,
+            };
+            Rect::new(text_rect.pos + Vec2::new(x, y), Vec2::new(0., height))
+        } else {
+            // the caret is placed in the drawn text, that may be shrunk
+            let before: String = field.text.chars().take(field.caret).collect();
+            let ratio = line_width(&before, ui_text) / line_width(&field.text, ui_text);
+            Rect::new(
+                drawn_rect.pos + Vec2::X * drawn_rect.size.x * ratio,
+                Vec2::new(0., drawn_rect.size.y),
+            )
+        };
+        let width = caret_rect.size.y * 0.1;
+        tile_commands.draw(RenderTile {
+            world_rect: Rect::xywh(
+                caret_rect.pos.x - width * 0.5,
+                caret_rect.pos.y,
+                width,
+                caret_rect.size.y,
+            ),
+            color: ui_text.color,
+            z,
+            ..Default::default()
+        });
+    }
+    submitted
+}
+
+/// Bubble with the text below and right of `cursor`, moved left or up to stay on screen
+pub fn draw_tooltip(
+    ui_button: &UiButton,
+    ui_text: &UiText,
+    tile_commands: &mut RenderTileCommands,
+    tip: &str,
+    cursor: Vec2,
+    z: f32,
+) {
+    let lines = tip.lines().count().max(1);
+    let width = tip
+        .lines()
+        .map(|line| line_width(line, ui_text))
+        .fold(0., f32::max);
+    let text_size = Vec2::new(width, line_height(ui_text) * lines as f32);
+    let padding = ui_button.padding;
+    let size = text_size + Vec2::splat(padding * 2.);
+    let screen = Vec2::from(window::screen_size());
+    let offset = Vec2::new(12., 16.);
+    let mut pos = cursor + offset;
+    if pos.x + size.x > screen.x {
+        pos.x = (cursor.x - size.x).max(0.);
+    }
+    if pos.y + size.y > screen.y {
+        pos.y = (cursor.y - size.y).max(0.);
+    }
+    let bubble = Rect::new(pos, size);
+    tile_commands.draw(RenderTile {
+        world_rect: bubble,
+        color: ui_button.color_pressed,
+        z: z + 0.001,
+        ..Default::default()
+    });
+    draw_text(tile_commands, tip, bubble.pad(padding), z, ui_text);
+}
+
+/// The dots of a spinner fade behind the brightest one
+pub fn draw_spinner(
+    tile_commands: &mut RenderTileCommands,
+    rect: Rect,
+    color: Vec4,
+    current_time: f64,
+    z: f32,
+) {
+    const DOTS: usize = 8;
+    const TURNS_PER_SECOND: f64 = 1.;
+    let side = rect.size.x.min(rect.size.y);
+    let center = rect.pos + rect.size * 0.5;
+    let dot = Vec2::splat(side * 0.12);
+    let head = (current_time * TURNS_PER_SECOND * DOTS as f64) as usize % DOTS;
+    for i in 0..DOTS {
+        let angle = i as f32 / DOTS as f32 * std::f32::consts::TAU;
+        let pos = center + Vec2::new(angle.cos(), -angle.sin()) * side * 0.4;
+        let behind = (head + DOTS - i) % DOTS;
+        tile_commands.draw(RenderTile {
+            world_rect: Rect::new(pos - dot * 0.5, dot),
+            color: color * Vec4::new(1., 1., 1., 1. - behind as f32 / DOTS as f32),
+            z,
+            ..Default::default()
+        });
+    }
+}
+
+/// Texts of the debug commands, centered on where their points are on the screen
+pub fn draw_debug_texts(
+    tile_commands: &mut RenderTileCommands,
+    debug_commands: &RenderDebugCommands,
+    camera: &Camera,
+    resolution: Vec2,
+    ui_text: &UiText,
+) {
+    for debug_text in debug_commands.texts.iter() {
+        let Some(pos) = camera.world_to_screen(debug_text.pos, resolution) else {
+            continue;
+        };
+        let ui_text = UiText {
+            layout: UiTextLayout::Center,
+            vertical_layout: UiTextVerticalLayout::Center,
+            char_scale: ui_text.char_scale * DEBUG_TEXT_SCALE,
+            color: debug_text.color,
+            ..ui_text.clone()
+        };
+        let size = Vec2::new(resolution.x, line_height(&ui_text));
+        let rect = Rect::new(pos - size * 0.5, size);
+        draw_text(tile_commands, &debug_text.text, rect, 0.05, &ui_text);
+    }
+}
+
 pub fn draw_text(
     tile_commands: &mut RenderTileCommands,
     text: &str,
@@ -187,39 +1001,12 @@ pub fn draw_text(
     z: f32,
     ui_text: &UiText,
 ) -> Rect {
+    let lines = fit_lines(text, bounds_rect.size.x, ui_text);
     let mut tiles: Vec<RenderTile> = vec![];
-    let font_char_size = ui_text.image_font_char_size;
-    let mut row = -1;
-    for line in text.lines().take(100) {
-        row += 1;
-        let mut kerning: u32 = 0;
-        for c in line.chars() {
-            // conversion from char to ascii bitmap position
-            let (x, y) = (c as u8 % 32, c as u8 / 32);
-            let sheet_xy = Vec2::new(x as f32, y as f32);
-
-            let font_image_size = Vec2::new(
-                ui_text.image_font_size.x as f32,
-                ui_text.image_font_size.y as f32,
-            );
-
-            let world_rect = Rect {
-                pos: Vec2::new(kerning as f32, row as f32 * font_char_size.y + 1.)
-                    * ui_text.char_scale,
-                size: font_char_size * ui_text.char_scale,
-            };
-            let clip_rect = Rect {
-                pos: (font_char_size * sheet_xy + Vec2::X) / font_image_size,
-                size: (font_char_size - Vec2::new(1., 0.)) / font_image_size,
-            };
-            tiles.push(RenderTile {
-                world_rect,
-                clip_rect,
-                z,
-                color: ui_text.color,
-            });
-            kerning += 5 - ui_text.image_font_kerning[c as usize] as u32 + 2;
-        }
+    // tiles of characters missing from a bitmap font, drawn as solid boxes
+    let mut boxes: Vec<usize> = vec![];
+    for (row, line) in lines.iter().take(100).enumerate() {
+        layout_line(line, row, z, ui_text, &mut tiles, &mut boxes);
     }
 
     let mut drawn_rect = get_drawn_rect(tiles.as_slice());
@@ -256,13 +1043,187 @@ pub fn draw_text(
         _ => {}
     }
 
-    for tile in tiles {
-        tile_commands.draw_textured(tile, ui_text.image_font_id.clone());
+    let offset_y = match ui_text.vertical_layout {
+        UiTextVerticalLayout::Top => 0.,
+        UiTextVerticalLayout::Center => bounds_rect.size.y * 0.5 - drawn_rect.size.y * 0.5,
+        UiTextVerticalLayout::Bottom => bounds_rect.size.y - drawn_rect.size.y,
+    };
+    drawn_rect.pos.y += offset_y;
+    for tile in &mut tiles {
+        tile.world_rect.pos.y += offset_y;
+    }
+
+    for (i, tile) in tiles.into_iter().enumerate() {
+        if boxes.contains(&i) {
+            tile_commands.draw(tile);
+        } else {
+            tile_commands.draw_textured(tile, ui_text.font.image_id().clone());
+        }
     }
 
     return drawn_rect;
 }
 
+/// Tiles of a line of text on the `row`, before it's moved in the bounds.
+/// Returns the width the line advances.
+fn layout_line(
+    line: &str,
+    row: usize,
+    z: f32,
+    ui_text: &UiText,
+    tiles: &mut Vec<RenderTile>,
+    boxes: &mut Vec<usize>,
+) -> f32 {
+    match &ui_text.font {
+        UiFont::Bitmap {
+            image_size,
+            char_size,
+            kerning,
+            ..
+        } => {
+            let mut pen: u32 = 0;
+            for c in line.chars() {
+                let Some(c) = bitmap_char(c) else {
+                    boxes.push(tiles.len());
+                    tiles.push(RenderTile {
+                        world_rect: Rect {
+                            pos: Vec2::new(pen as f32, row as f32 * char_size.y + 2.)
+                                * ui_text.char_scale,
+                            size: Vec2::new(5., 8.) * ui_text.char_scale,
+                        },
+                        z,
+                        color: ui_text.color * Vec4::new(1., 1., 1., 0.5),
+                        ..Default::default()
+                    });
+                    pen += 7;
+                    continue;
+                };
+                // conversion from ascii to bitmap position
+                let (x, y) = (c % 32, c / 32);
+                let sheet_xy = Vec2::new(x as f32, y as f32);
+
+                let world_rect = Rect {
+                    pos: Vec2::new(pen as f32, row as f32 * char_size.y + 1.) * ui_text.char_scale,
+                    size: *char_size * ui_text.char_scale,
+                };
+                // the first column of each cell is spacing
+                let clip_rect = atlas_to_uv_pad_offset(
+                    *image_size,
+                    *char_size - Vec2::X,
+                    sheet_xy,
+                    Vec2::X,
+                    Vec2::X,
+                );
+                tiles.push(RenderTile {
+                    world_rect,
+                    clip_rect,
+                    z,
+                    color: ui_text.color,
+                });
+                pen += 5 - kerning[c as usize] as u32 + 2;
+            }
+            pen as f32 * ui_text.char_scale.x
+        }
+        UiFont::Ttf { atlas, size, .. } => {
+            let scale = ui_text.char_scale * (*size / atlas.pixel_size);
+            let baseline = row as f32 * atlas.line_height + atlas.ascent;
+            let mut pen = 0.;
+            let mut previous = None;
+            for c in line.chars() {
+                let glyph = atlas.glyph(c);
+                if let Some(kerning) = previous.and_then(|p| atlas.kerning.get(&(p, c))) {
+                    pen += kerning;
+                }
+                // blanks like the space only advance
+                if glyph.size.x > 0. {
+                    tiles.push(RenderTile {
+                        world_rect: Rect {
+                            pos: (Vec2::new(pen, baseline) + glyph.offset) * scale,
+                            size: glyph.size * scale,
+                        },
+                        clip_rect: Rect {
+                            pos: glyph.atlas_pos / atlas.image_size,
+                            size: glyph.size / atlas.image_size,
+                        },
+                        z,
+                        color: ui_text.color,
+                    });
+                }
+                pen += glyph.advance;
+                previous = Some(c);
+            }
+            pen * scale.x
+        }
+    }
+}
+
+/// Width of the line once drawn
+fn line_width(line: &str, ui_text: &UiText) -> f32 {
+    layout_line(line, 0, 0., ui_text, &mut vec![], &mut vec![])
+}
+
+/// Height of a line once drawn
+pub fn line_height(ui_text: &UiText) -> f32 {
+    match &ui_text.font {
+        UiFont::Bitmap { char_size, .. } => char_size.y * ui_text.char_scale.y,
+        UiFont::Ttf { atlas, size, .. } => {
+            atlas.line_height * ui_text.char_scale.y * (*size / atlas.pixel_size)
+        }
+    }
+}
+
+/// Lines of the text as they are drawn, wrapped or cut to `width` by the overflow mode
+fn fit_lines(text: &str, width: f32, ui_text: &UiText) -> Vec<String> {
+    let mut lines = vec![];
+    for line in text.lines() {
+        match ui_text.overflow {
+            UiTextOverflow::Shrink => lines.push(line.to_string()),
+            UiTextOverflow::Wrap => {
+                // a word longer than the width gets its own line, and shrinks the text
+                let mut current = String::new();
+                for word in line.split(' ') {
+                    let joined = match current.is_empty() {
+                        true => word.to_string(),
+                        false => format!("{} {}", current, word),
+                    };
+                    if !current.is_empty() && line_width(&joined, ui_text) > width {
+                        lines.push(std::mem::replace(&mut current, word.to_string()));
+                    } else {
+                        current = joined;
+                    }
+                }
+                lines.push(current);
+            }
+            UiTextOverflow::Ellipsis => {
+                if line_width(line, ui_text) <= width {
+                    lines.push(line.to_string());
+                    continue;
+                }
+                let chars: Vec<char> = line.chars().collect();
+                let cut = |len: usize| {
+                    let kept: String = chars[..len].iter().collect();
+                    format!("{}...", kept.trim_end())
+                };
+                let len = (0..chars.len())
+                    .rev()
+                    .find(|len| line_width(&cut(*len), ui_text) <= width)
+                    .unwrap_or(0);
+                lines.push(cut(len));
+            }
+        }
+    }
+    lines
+}
+
+/// Cell of the character in an ascii sheet, a similar character if it's not ascii
+fn bitmap_char(c: char) -> Option<u8> {
+    let c = match c.is_ascii_graphic() || c == ' ' {
+        true => c,
+        false => fallback_char(c)?,
+    };
+    Some(c as u8)
+}
+
 pub fn get_drawn_rect(tiles: &[RenderTile]) -> Rect {
     let mut min = Vec2::INFINITY;
     let mut max = Vec2::NEG_INFINITY;
@@ -273,7 +1234,80 @@ pub fn get_drawn_rect(tiles: &[RenderTile]) -> Rect {
     Rect::new(min, max - min)
 }
 
-#[derive(Debug, Clone, Copy, Default)]
+/// Smallest cell of a `Ui::row` at scale 1 that still fits a short label, in pixels
+const MIN_CELL_SIZE: Vec2 = Vec2::new(64., 24.);
+
+/// Short side of the screen in pixels the ui is sized for at scale 1
+const REFERENCE_SHORT_SIDE: f32 = 600.;
+
+/// Scale of the text and padding of the ui. It grows with the screen,
+/// and with the dpi so that it stays readable on the small, dense screens of phones
+pub fn screen_ui_scale(resolution: Vec2) -> f32 {
+    let by_size = resolution.min_element() / REFERENCE_SHORT_SIDE;
+    by_size.max(window::dpi_scale()).max(0.25)
+}
+
+/// Pixels at the edges of the screen covered by notches and system bars
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SafeArea {
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+    pub left: f32,
+}
+
+impl SafeArea {
+    /// Asks the platform, only android has insets
+    pub fn query() -> Self {
+        #[cfg(target_os = "android")]
+        if let Some(insets) = android_insets() {
+            return insets;
+        }
+        Self::default()
+    }
+
+    /// The screen without the insets
+    pub fn rect(&self, resolution: Vec2) -> Rect {
+        let pos = Vec2::new(self.left, self.top);
+        let size = resolution - pos - Vec2::new(self.right, self.bottom);
+        Rect::new(pos, size.max(Vec2::ZERO))
+    }
+}
+
+/// The system window insets of the activity, they include the display cutout
+#[cfg(target_os = "android")]
+fn android_insets() -> Option<SafeArea> {
+    use miniquad::native::android::{attach_jni_env, ACTIVITY};
+    use miniquad::{call_int_method, call_object_method};
+    unsafe {
+        let env = attach_jni_env();
+        let window = call_object_method!(env, ACTIVITY, "getWindow", "()Landroid/view/Window;");
+        if window.is_null() {
+            return None;
+        }
+        let view = call_object_method!(env, window, "getDecorView", "()Landroid/view/View;");
+        if view.is_null() {
+            return None;
+        }
+        let insets = call_object_method!(
+            env,
+            view,
+            "getRootWindowInsets",
+            "()Landroid/view/WindowInsets;"
+        );
+        if insets.is_null() {
+            return None;
+        }
+        Some(SafeArea {
+            top: call_int_method!(env, insets, "getSystemWindowInsetTop", "()I") as f32,
+            right: call_int_method!(env, insets, "getSystemWindowInsetRight", "()I") as f32,
+            bottom: call_int_method!(env, insets, "getSystemWindowInsetBottom", "()I") as f32,
+            left: call_int_method!(env, insets, "getSystemWindowInsetLeft", "()I") as f32,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
 pub struct Rect {
     pub pos: Vec2,
     pub size: Vec2,
@@ -310,6 +1344,30 @@ impl Rect {
         x && y
     }
 
+    /// The area in both rects, empty if they don't overlap
+    pub fn intersect(&self, other: &Rect) -> Self {
+        let min = self.pos.max(other.pos);
+        let max = (self.pos + self.size).min(other.pos + other.size);
+        Self::new(min, (max - min).max(Vec2::ZERO))
+    }
+
+    /// Narrowed around its center to at most `aspect` times as wide as it's tall
+    pub fn max_aspect(&self, aspect: f32) -> Self {
+        let width = self.size.x.min(self.size.y * aspect);
+        Self {
+            pos: self.pos + Vec2::X * (self.size.x - width) * 0.5,
+            size: Vec2::new(width, self.size.y),
+        }
+    }
+
+    /// Grown or shrunk around its center
+    pub fn scaled(&self, scale: f32) -> Self {
+        Self {
+            pos: self.pos + self.size * (1. - scale) * 0.5,
+            size: self.size * scale,
+        }
+    }
+
     pub fn pad(&self, pad: f32) -> Self {
         Self {
             pos: self.pos + Vec2::splat(pad),
@@ -0,0 +1,32 @@
+// Build metadata injected by build.rs
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+pub const GIT_DESCRIBE: &str = env!("TILING_GIT_DESCRIBE");
+pub const GIT_COMMIT: &str = env!("TILING_GIT_COMMIT");
+pub const GIT_DATE: &str = env!("TILING_GIT_DATE");
+
+/// Short version shown in game, ex: "v0.1.0 (a1b2c3d)"
+pub fn version_short() -> String {
+    format!("v{} ({})", VERSION, GIT_COMMIT)
+}
+
+/// Full version for crash reports and logs
+pub fn version_long() -> String {
+    format!(
+        "{} v{} {} {} {}",
+        env!("CARGO_PKG_NAME"),
+        VERSION,
+        GIT_DESCRIBE,
+        GIT_COMMIT,
+        GIT_DATE
+    )
+}
+
+/// Prepends the version to the default panic message
+pub fn install_crash_report_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        eprintln!("crash report: {}", version_long());
+        default_hook(info);
+    }));
+}
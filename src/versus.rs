@@ -0,0 +1,436 @@
+use core::net::SocketAddr;
+
+use crate::{console::CommandRegistry, *};
+
+/// Seconds between the status messages sent to the other player
+const STATUS_INTERVAL: f64 = 0.25;
+/// Seconds between the tries of the guest to join, until the board arrives
+const JOIN_INTERVAL: f64 = 0.5;
+/// Seconds without a message before the other player counts as gone
+const VERSUS_TIMEOUT: f64 = 5.;
+
+/// The quick messages to the other player, by the key of their text in the lang files.
+/// Sent as the index, the order can't change
+pub const EMOTES: [&str; 5] = [
+    "emote_nice",
+    "emote_hurry",
+    "emote_thinking",
+    "emote_oops",
+    "emote_good_game",
+];
+/// Seconds an emote of the other player is shown
+pub const EMOTE_DURATION: f64 = 2.5;
+/// Seconds before the player can send another emote
+const EMOTE_COOLDOWN: f64 = 1.;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VersusRole {
+    /// Opened the server and picks the board
+    Host,
+    /// Connected to the host by its address
+    Guest,
+}
+
+/// How far the other player is
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct VersusStatus {
+    /// Fraction of the edges that match, 1 when solved
+    pub progress: f32,
+    /// Seconds it took to solve, None until solved
+    pub time: Option<f64>,
+}
+
+/// A race against a player on another machine: both get the same board,
+/// the first to solve it wins.
+/// The guest says hello until the host answers with its own and the state of its board,
+/// then both send theirs every so often, with the cells that changed
+pub struct Versus {
+    pub role: VersusRole,
+    /// The board of the race, picked by the host, known by the guest once it joined
+    pub seed_code: Option<SeedCode>,
+    /// The other player, the guest is known by the host once it joined.
+    /// The relay, when the players talk through one
+    peer: Option<SocketAddr>,
+    /// Both players are clients of a relay, the host too
+    relayed: bool,
+    /// Who the player is, sent in the hello
+    hello: Message,
+    /// The name and color of the other player, from its hello
+    pub opponent_profile: Option<(String, usize)>,
+    pub opponent: VersusStatus,
+    /// The last status sent
+    status: VersusStatus,
+    board: BoardSender,
+    /// The tiles on the board of the other player
+    pub opponent_board: BoardReceiver,
+    /// The last emote of the other player and when it arrived
+    pub opponent_emote: Option<(usize, f64)>,
+    last_emote: f64,
+    /// When the race started, None while waiting for the other player
+    pub started: Option<f64>,
+    /// The outcome is in the profile already
+    pub counted: bool,
+    last_sent: f64,
+    last_received: f64,
+}
+
+impl Versus {
+    /// Opens the server, the board is raced on once a guest joins
+    pub fn host(
+        server: &mut NetServer,
+        port: u16,
+        seed_code: SeedCode,
+        profile: &Profile,
+    ) -> Result<Self, String> {
+        server.open(port).map_err(|e| format!("{}", e))?;
+        Ok(Self::new(
+            VersusRole::Host,
+            Some(seed_code),
+            None,
+            false,
+            profile,
+        ))
+    }
+
+    /// Connects to the host, the race starts when its board arrives
+    pub fn join(
+        client: &mut NetClient,
+        address: SocketAddr,
+        profile: &Profile,
+    ) -> Result<Self, String> {
+        client.connect(address).map_err(|e| format!("{}", e))?;
+        Ok(Self::new(
+            VersusRole::Guest,
+            None,
+            Some(address),
+            false,
+            profile,
+        ))
+    }
+
+    /// Creates a room on the relay, the board is raced on once a guest joins it
+    pub fn host_room(
+        client: &mut NetClient,
+        relay: SocketAddr,
+        seed_code: SeedCode,
+        profile: &Profile,
+    ) -> Result<Self, String> {
+        client
+            .connect_relay(relay, None)
+            .map_err(|e| format!("{}", e))?;
+        Ok(Self::new(
+            VersusRole::Host,
+            Some(seed_code),
+            Some(relay),
+            true,
+            profile,
+        ))
+    }
+
+    /// Joins the room with the code on the relay, like `join`
+    pub fn join_room(
+        client: &mut NetClient,
+        relay: SocketAddr,
+        code: &str,
+        profile: &Profile,
+    ) -> Result<Self, String> {
+        client
+            .connect_relay(relay, Some(code))
+            .map_err(|e| format!("{}", e))?;
+        Ok(Self::new(
+            VersusRole::Guest,
+            None,
+            Some(relay),
+            true,
+            profile,
+        ))
+    }
+
+    fn new(
+        role: VersusRole,
+        seed_code: Option<SeedCode>,
+        peer: Option<SocketAddr>,
+        relayed: bool,
+        profile: &Profile,
+    ) -> Self {
+        Self {
+            role,
+            seed_code,
+            peer,
+            relayed,
+            hello: Message::Hello {
+                name: profile.name.clone(),
+                color: profile.color as u8,
+            },
+            opponent_profile: None,
+            opponent: VersusStatus::default(),
+            status: VersusStatus::default(),
+            board: BoardSender::default(),
+            opponent_board: BoardReceiver::default(),
+            opponent_emote: None,
+            last_emote: f64::MIN,
+            started: None,
+            counted: false,
+            last_sent: f64::MIN,
+            last_received: f64::MIN,
+        }
+    }
+
+    /// Reads the messages that arrived and answers the guest.
+    /// Returns the board to race on when the race starts
+    pub fn receive(
+        &mut self,
+        server: &mut NetServer,
+        client: &mut NetClient,
+        current_time: f64,
+    ) -> Option<SeedCode> {
+        loop {
+            let received = match self.is_server() {
+                true => server.receive_message(),
+                false => client.receive_message(),
+            };
+            let (message, address) = match received {
+                Ok(received) => received,
+                Err(NetError::Protocol(err)) => {
+                    eprintln!("Versus message dropped: {:?}", err);
+                    continue;
+                }
+                Err(_) => return None,
+            };
+            // the host races the first guest that joins
+            if self.role == VersusRole::Host
+                && self.peer.is_none()
+                && matches!(message, Message::Hello { .. })
+            {
+                self.peer = Some(address);
+            }
+            if Some(address) != self.peer {
+                continue;
+            }
+            self.last_received = current_time;
+            match (self.role, message) {
+                // answered every time, the first answers may be lost
+                (VersusRole::Host, Message::Hello { name, color }) => {
+                    self.opponent_profile = Some((name, color as usize));
+                    let delivery = match self.started {
+                        None => Delivery::Reliable,
+                        Some(_) => Delivery::Unreliable,
+                    };
+                    self.send(server, client, &self.hello, delivery);
+                    self.send_board_state(server, client, delivery);
+                    if self.started.is_none() {
+                        self.started = Some(current_time);
+                        return self.seed_code.clone();
+                    }
+                }
+                (
+                    _,
+                    Message::BoardState {
+                        code,
+                        progress,
+                        time,
+                    },
+                ) => {
+                    self.opponent = VersusStatus { progress, time };
+                    // the first board state the guest gets is the board of the race
+                    if self.role == VersusRole::Guest && self.started.is_none() {
+                        let Ok(seed_code) = SeedCode::decode(&code) else {
+                            continue;
+                        };
+                        self.seed_code = Some(seed_code.clone());
+                        self.started = Some(current_time);
+                        return Some(seed_code);
+                    }
+                }
+                (VersusRole::Guest, Message::Hello { name, color }) => {
+                    self.opponent_profile = Some((name, color as usize));
+                }
+                (_, Message::Emote { emote }) if (emote as usize) < EMOTES.len() => {
+                    self.opponent_emote = Some((emote as usize, current_time));
+                }
+                (_, Message::BoardAck { sequence }) => self.board.ack(sequence),
+                (_, message @ (Message::BoardSnapshot { .. } | Message::BoardDelta { .. })) => {
+                    if let Some(ack) = self.opponent_board.receive(&message) {
+                        self.send(server, client, &ack, Delivery::Unreliable);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Keeps the other player up to date, the guest says hello until the race starts.
+    /// `cells` is the board, empty until it's generated
+    pub fn send_status(
+        &mut self,
+        server: &mut NetServer,
+        client: &mut NetClient,
+        status: VersusStatus,
+        cells: &[CellState],
+        current_time: f64,
+    ) {
+        let interval = match self.started {
+            Some(_) => STATUS_INTERVAL,
+            None if self.role == VersusRole::Guest => JOIN_INTERVAL,
+            None => return,
+        };
+        if current_time - self.last_sent < interval {
+            return;
+        }
+        self.last_sent = current_time;
+        // the time of the solve can't be lost, the progress is sent again anyway
+        let delivery = match (self.status.time, status.time) {
+            (None, Some(_)) => Delivery::Reliable,
+            _ => Delivery::Unreliable,
+        };
+        self.status = status;
+        match self.started {
+            Some(_) => self.send_board_state(server, client, delivery),
+            None => self.send(server, client, &self.hello, Delivery::Unreliable),
+        }
+        if self.started.is_some() && !cells.is_empty() {
+            let message = self.board.message(cells, current_time);
+            self.send(server, client, &message, Delivery::Unreliable);
+        }
+    }
+
+    fn send_board_state(&self, server: &mut NetServer, client: &mut NetClient, delivery: Delivery) {
+        let Some(seed_code) = &self.seed_code else {
+            return;
+        };
+        let message = Message::BoardState {
+            code: seed_code.encode(),
+            progress: self.status.progress,
+            time: self.status.time,
+        };
+        self.send(server, client, &message, delivery);
+    }
+
+    fn send(
+        &self,
+        server: &mut NetServer,
+        client: &mut NetClient,
+        message: &Message,
+        delivery: Delivery,
+    ) {
+        let sent = match (self.is_server(), self.peer) {
+            (true, Some(peer)) => server.send_message(message, delivery, peer),
+            (false, _) => client.send_message(message, delivery),
+            _ => return,
+        };
+        if let Err(err) = sent {
+            eprintln!("Versus message not sent: {:?}", err);
+        }
+    }
+
+    /// Sends an emote once the race started, they can be lost. False if it's too soon after
+    /// the last one
+    pub fn send_emote(
+        &mut self,
+        server: &mut NetServer,
+        client: &mut NetClient,
+        emote: usize,
+        current_time: f64,
+    ) -> bool {
+        if self.started.is_none() || current_time - self.last_emote < EMOTE_COOLDOWN {
+            return false;
+        }
+        self.last_emote = current_time;
+        let message = Message::Emote { emote: emote as u8 };
+        self.send(server, client, &message, Delivery::Unreliable);
+        true
+    }
+
+    /// True when won, false when lost, None until the player solved the board and the other
+    /// one solved it too or left. The times decide, the other player may have started later
+    pub fn outcome(&self, solve_time: Option<f64>, current_time: f64) -> Option<bool> {
+        let time = solve_time?;
+        match self.opponent.time {
+            Some(opponent) => Some(time <= opponent),
+            None if self.is_opponent_gone(current_time) => Some(true),
+            None => None,
+        }
+    }
+
+    /// Nothing heard from the other player for a while since the race started
+    pub fn is_opponent_gone(&self, current_time: f64) -> bool {
+        self.started.is_some() && current_time - self.last_received > VERSUS_TIMEOUT
+    }
+
+    pub fn close(&self, server: &mut NetServer, client: &mut NetClient) {
+        match self.is_server() {
+            true => server.close(),
+            false => client.close(),
+        }
+    }
+
+    /// The host talks through its server, unless both talk through a relay
+    fn is_server(&self) -> bool {
+        self.role == VersusRole::Host && !self.relayed
+    }
+}
+
+/// The net commands of the console
+pub fn register_commands(registry: &mut CommandRegistry) {
+    registry.register("connect", "connect <ip>[:port]", |_, engine, args| {
+        let [address] = args else {
+            return Err("expected an address".to_string());
+        };
+        let address = parse_address(address, DEFAULT_PORT)?;
+        engine
+            .client
+            .connect(address)
+            .map_err(|e| format!("{}", e))?;
+        Ok(format!("connected to {}", address))
+    });
+    registry.register("host", "host [port]", |_, engine, args| {
+        let port = match args.first() {
+            Some(port) => port.parse().map_err(|e| format!("{}", e))?,
+            None => DEFAULT_PORT,
+        };
+        engine.server.open(port).map_err(|e| format!("{}", e))?;
+        Ok(format!("hosting on port {}", port))
+    });
+    registry.register(
+        "netsim",
+        "netsim <latency ms> [jitter ms] [loss %] | off",
+        |_, engine, args| {
+            if args == ["off"] {
+                engine.server.simulation = None;
+                engine.client.simulation = None;
+                return Ok("network simulation off".to_string());
+            }
+            if args.is_empty() || args.len() > 3 {
+                return Err("expected a latency, or off".to_string());
+            }
+            // the ones left out are 0
+            let mut numbers = args.iter().map(|arg| arg.parse::<f64>());
+            let mut next = || {
+                numbers
+                    .next()
+                    .unwrap_or(Ok(0.))
+                    .map_err(|e| format!("{}", e))
+            };
+            let (latency, jitter, loss) = (next()?, next()?, next()?);
+            if latency < 0. || jitter < 0. || !(0. ..=100.).contains(&loss) {
+                return Err("the times are positive, the loss goes from 0 to 100".to_string());
+            }
+            let conditions = NetConditions {
+                latency: latency / 1000.,
+                jitter: jitter / 1000.,
+                loss: (loss / 100.) as f32,
+            };
+            engine.server.simulation = Some(NetSimulation::new(conditions));
+            engine.client.simulation = Some(NetSimulation::new(conditions));
+            Ok(format!(
+                "{}ms of latency, up to {}ms of jitter and {}% of loss each way",
+                latency, jitter, loss
+            ))
+        },
+    );
+    registry.register("disconnect", "disconnect", |_, engine, _| {
+        engine.client.close();
+        engine.server.close();
+        Ok("disconnected".to_string())
+    });
+}